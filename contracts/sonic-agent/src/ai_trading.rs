@@ -1,16 +1,15 @@
-/**
+/*!
  * Sonic SVM AI Trading Contract
  * This is a Rust implementation of a trading contract for Sonic SVM
  * using Anchor framework
  */
 
  use anchor_lang::prelude::*;
- use anchor_spl::token::{self, TokenAccount, Token, Mint, Transfer};
- use pyth_solana_receiver_sdk::price_update::{PriceUpdateV2};
+ use anchor_spl::token::TokenAccount;
+ use solana_program::instruction::{AccountMeta, Instruction};
+ use solana_program::program::invoke;
+ use crate::price_alerts::{read_pyth_message, PYTH_RECEIVER_PROGRAM_ID};
  
- declare_id!("Ai8gzqrzgndFtswn9BXgHZcVaXZ5UHgqwBF8ZrgqNHZn");
- 
- #[program]
  pub mod sonic_ai_trading {
      use super::*;
  
@@ -22,7 +21,10 @@
          authority: Pubkey,
          max_position_size: u64,
          risk_level: u8,
+         max_price_age_secs: u64,
      ) -> Result<()> {
+         TradingState::check_max_price_age(max_price_age_secs)?;
+         
          let trading_state = &mut ctx.accounts.trading_state;
          trading_state.authority = authority;
          trading_state.max_position_size = max_position_size;
@@ -32,6 +34,22 @@
          trading_state.total_trades = 0;
          trading_state.successful_trades = 0;
          trading_state.total_profit_loss = 0;
+         trading_state.max_conf_bps = TradingState::DEFAULT_MAX_CONF_BPS;
+         trading_state.max_twap_deviation_bps = TradingState::DEFAULT_MAX_TWAP_DEVIATION_BPS;
+         trading_state.max_slippage_bps = TradingState::DEFAULT_MAX_SLIPPAGE_BPS;
+         trading_state.max_price_age_secs = max_price_age_secs;
+         trading_state.min_trade_interval_secs = 0;
+         trading_state.last_trade_at = 0;
+         trading_state.daily_loss_limit = 0;
+         trading_state.day_start_pl = 0;
+         trading_state.current_day = Clock::get()?.unix_timestamp / 86400;
+         trading_state.daily_loss_paused = false;
+         trading_state.settlement_authorities = vec![];
+         
+         // SOL/USD is allowed out of the box; other feeds are added by the authority
+         trading_state.allowed_feed_ids = vec![
+             get_feed_id_from_hex("0xef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d")?,
+         ];
          
          msg!("SonicAI Trading system initialized");
          msg!("Max position size: {}", max_position_size);
@@ -41,17 +59,28 @@
      }
  
      /**
-      * Execute a trade based on AI signal
+      * Execute a trade based on AI signal, swapping through a route program.
+      * `remaining_accounts` holds the route's accounts and `swap_data` is
+      * the route program's instruction data.
       */
-     pub fn execute_trade(
-         ctx: Context<ExecuteTrade>,
-         amount: u64,
-         side: TradeSide,
-         confidence: u8,
-         strategy_id: u8
+     pub fn execute_trade<'info>(
+         ctx: Context<'_, '_, '_, 'info, ExecuteTrade<'info>>,
+         params: ExecuteTradeParams,
      ) -> Result<()> {
+         let ExecuteTradeParams {
+             amount,
+             side,
+             confidence,
+             strategy_id,
+             min_confidence_override,
+             asset_feed_id,
+             min_output_amount,
+             swap_data,
+         } = params;
          let trading_state = &mut ctx.accounts.trading_state;
-         let price_update = &ctx.accounts.price_update;
+         
+         // A new day lifts a pause from yesterday's loss limit
+         trading_state.roll_day(Clock::get()?.unix_timestamp);
          
          // Ensure trading is not paused
          require!(!trading_state.paused, ErrorCode::TradingPaused);
@@ -62,15 +91,29 @@
              ErrorCode::Unauthorized
          );
          
-         // For simplicity, we'll use SOL/USD price feed ID
-         // In a real implementation, you'd validate the asset specifically
-         let sol_usd_feed_id = get_feed_id_from_hex("0xef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d")?;
+         // The pair config fixes which feed prices this market, and only a
+         // feed the authority has approved may price a trade
+         let pair_config = &mut ctx.accounts.pair_config;
+         trading_state.check_price_feed(pair_config, &asset_feed_id)?;
+         
+         // The source pays with this side's mint and the destination
+         // receives the other side of the pair
+         require!(
+             ctx.accounts.source_account.mint == side.input_mint(pair_config)
+                 && ctx.accounts.destination_account.mint == side.output_mint(pair_config),
+             ErrorCode::InvalidTradeMint
+         );
+         
+         // Throttle each market to one trade per interval
+         let now = Clock::get()?.unix_timestamp;
+         pair_config.check_cooldown(now, trading_state.min_trade_interval_secs)?;
          
-         // Get the price from Pyth with a maximum age of 30 seconds
-         let price_info = price_update.get_price_no_older_than(
-             &Clock::get()?, 
-             30,  // 30 seconds max age
-             &sol_usd_feed_id
+         // Get the price from Pyth, no older than the configured max age
+         let price_info = read_pyth_message(
+             &ctx.accounts.price_update,
+             &asset_feed_id,
+             now,
+             trading_state.max_price_age_secs as i64,
          )?;
          
          // Calculate scaled price (handle Pyth exponent)
@@ -81,61 +124,74 @@
          // Log the price information
          msg!("Current price: {} ± {} * 10^{}", price, confidence_interval, exponent);
          
+         // Refuse to trade while the oracle is uncertain about the price
+         trading_state.check_price_confidence(price, confidence_interval)?;
+         
+         // A spot price far from the EMA suggests a manipulated or stale print
+         let ema_price = price_info.ema_price;
+         trading_state.check_ema_deviation(price, ema_price)?;
+         
          // Validate the trade based on risk parameters
-         // Higher confidence should be required for higher risk trades
-         let min_confidence = match trading_state.risk_level {
-             1..=3 => 80,   // Low risk: require high confidence
-             4..=7 => 65,   // Medium risk
-             _ => 50,       // High risk: accept lower confidence
-         };
+         let risk_level = pair_config.risk_level.unwrap_or(trading_state.risk_level);
+         let min_confidence = required_confidence(risk_level, min_confidence_override);
          
          require!(
              confidence >= min_confidence,
              ErrorCode::InsufficientConfidence
          );
          
-         // Check if amount is within max position size
+         // Check that the position is within max position size, for both
+         // the pair and the account as a whole
+         let max_position_size = pair_config.max_position_size.min(trading_state.max_position_size);
          require!(
-             amount <= trading_state.max_position_size,
+             amount <= max_position_size,
              ErrorCode::PositionTooLarge
          );
          
+         // Tie the transfer to the quoted price before moving any tokens
+         let expected_output = expected_output_amount(amount, side, price, exponent)?;
+         require!(
+             expected_output >= min_output_amount,
+             ErrorCode::SlippageExceeded
+         );
+         
          // Record the trade
          trading_state.total_trades += 1;
+         trading_state.last_trade_at = now;
+         pair_config.last_trade_at = now;
          
-         // Execute the trade logic based on side
-         match side {
-             TradeSide::Buy => {
-                 msg!("Executing BUY trade for {} tokens", amount);
-                 // Transfer tokens from source to destination
-                 token::transfer(
-                     CpiContext::new(
-                         ctx.accounts.token_program.to_account_info(),
-                         Transfer {
-                             from: ctx.accounts.source_account.to_account_info(),
-                             to: ctx.accounts.destination_account.to_account_info(),
-                             authority: ctx.accounts.authority.to_account_info(),
-                         },
-                     ),
-                     amount,
-                 )?;
-             },
-             TradeSide::Sell => {
-                 msg!("Executing SELL trade for {} tokens", amount);
-                 // Transfer tokens from destination to source
-                 token::transfer(
-                     CpiContext::new(
-                         ctx.accounts.token_program.to_account_info(),
-                         Transfer {
-                             from: ctx.accounts.destination_account.to_account_info(),
-                             to: ctx.accounts.source_account.to_account_info(),
-                             authority: ctx.accounts.authority.to_account_info(),
-                         },
-                     ),
-                     amount,
-                 )?;
-             },
-         }
+         msg!("Executing {} trade for {} tokens", side.label(), amount);
+         
+         let source_before = ctx.accounts.source_account.amount;
+         let destination_before = ctx.accounts.destination_account.amount;
+         
+         let route_accounts = ctx.remaining_accounts;
+         let swap_ix = Instruction {
+             program_id: ctx.accounts.swap_program.key(),
+             accounts: route_accounts
+                 .iter()
+                 .map(|a| AccountMeta {
+                     pubkey: a.key(),
+                     is_signer: a.is_signer,
+                     is_writable: a.is_writable,
+                 })
+                 .collect(),
+             data: swap_data,
+         };
+         let mut swap_infos = route_accounts.to_vec();
+         swap_infos.push(ctx.accounts.swap_program.to_account_info());
+         invoke(&swap_ix, &swap_infos)?;
+         
+         // Measure what the swap actually moved and hold it to the quote
+         ctx.accounts.source_account.reload()?;
+         ctx.accounts.destination_account.reload()?;
+         let input_spent = source_before.saturating_sub(ctx.accounts.source_account.amount);
+         require!(input_spent <= amount, ErrorCode::SwapOverspent);
+         let actual_output = ctx.accounts.trading_state.check_swap_output(
+             (destination_before, ctx.accounts.destination_account.amount),
+             expected_output,
+             min_output_amount,
+         )?;
          
          // Store trade record in account
          let trade_record = &mut ctx.accounts.trade_record;
@@ -144,22 +200,76 @@
          trade_record.amount = amount;
          trade_record.side = side;
          trade_record.price = price;
+         trade_record.ema_price = ema_price;
          trade_record.confidence = confidence;
+         trade_record.confidence_required = min_confidence;
          trade_record.strategy_id = strategy_id;
+         trade_record.expected_output = expected_output;
+         trade_record.actual_output = actual_output;
+         trade_record.trading_state = ctx.accounts.trading_state.key();
+         trade_record.settled = false;
          
          msg!("Trade executed successfully");
          Ok(())
      }
  
      /**
-      * Update trading parameters
+      * Register a market (base/quote pair) with its own price feed, position
+      * limit and optional risk level override
       */
-     pub fn update_parameters(
-         ctx: Context<UpdateParameters>,
-         max_position_size: Option<u64>,
+     pub fn register_pair(
+         ctx: Context<RegisterPair>,
+         base_mint: Pubkey,
+         quote_mint: Pubkey,
+         feed_id: [u8; 32],
+         max_position_size: u64,
          risk_level: Option<u8>,
-         paused: Option<bool>,
      ) -> Result<()> {
+         let trading_state = &ctx.accounts.trading_state;
+         
+         // Ensure the caller is the authorized authority
+         require!(
+             ctx.accounts.authority.key() == trading_state.authority,
+             ErrorCode::Unauthorized
+         );
+         require!(
+             trading_state.allowed_feed_ids.contains(&feed_id),
+             ErrorCode::InvalidPriceFeed
+         );
+         if let Some(level) = risk_level {
+             require!((1..=10).contains(&level), ErrorCode::InvalidRiskLevel);
+         }
+         
+         let pair_config = &mut ctx.accounts.pair_config;
+         pair_config.trading_state = trading_state.key();
+         pair_config.base_mint = base_mint;
+         pair_config.quote_mint = quote_mint;
+         pair_config.feed_id = feed_id;
+         pair_config.max_position_size = max_position_size;
+         pair_config.risk_level = risk_level;
+         pair_config.last_trade_at = 0;
+         pair_config.bump = *ctx.bumps.get("pair_config").unwrap();
+         
+         msg!("Registered pair {}/{}", base_mint, quote_mint);
+         msg!("Max position size: {}", max_position_size);
+         Ok(())
+     }
+ 
+     /**
+      * Update trading parameters
+      */
+     pub fn update_parameters(ctx: Context<UpdateParameters>, update: TradingParametersUpdate) -> Result<()> {
+         let TradingParametersUpdate {
+             max_position_size,
+             risk_level,
+             paused,
+             max_conf_bps,
+             max_twap_deviation_bps,
+             daily_loss_limit,
+             max_price_age_secs,
+             min_trade_interval_secs,
+             max_slippage_bps,
+         } = update;
          let trading_state = &mut ctx.accounts.trading_state;
          
          // Ensure the caller is the authorized authority
@@ -181,15 +291,107 @@
          }
          
          if let Some(pause_state) = paused {
+             // A manual change overrides the daily loss pause, so the next day won't undo it
              trading_state.paused = pause_state;
+             trading_state.daily_loss_paused = false;
              msg!("Trading {} paused", if pause_state { "is now" } else { "is no longer" });
          }
          
+         if let Some(conf_bps) = max_conf_bps {
+             require!(conf_bps > 0 && conf_bps <= 10000, ErrorCode::InvalidConfidenceBound);
+             trading_state.max_conf_bps = conf_bps;
+             msg!("Updated max confidence interval: {} bps", conf_bps);
+         }
+         
+         if let Some(deviation_bps) = max_twap_deviation_bps {
+             require!(deviation_bps > 0 && deviation_bps <= 10000, ErrorCode::InvalidDeviationBound);
+             trading_state.max_twap_deviation_bps = deviation_bps;
+             msg!("Updated max spot/EMA deviation: {} bps", deviation_bps);
+         }
+         
+         if let Some(limit) = daily_loss_limit {
+             trading_state.daily_loss_limit = limit;
+             msg!("Updated daily loss limit: {} bps", limit);
+         }
+         
+         if let Some(max_age) = max_price_age_secs {
+             TradingState::check_max_price_age(max_age)?;
+             trading_state.max_price_age_secs = max_age;
+             msg!("Updated max price age: {} seconds", max_age);
+         }
+         
+         if let Some(interval) = min_trade_interval_secs {
+             trading_state.min_trade_interval_secs = interval;
+             msg!("Updated min trade interval: {} seconds", interval);
+         }
+         
+         if let Some(slippage_bps) = max_slippage_bps {
+             require!(slippage_bps <= 10000, ErrorCode::InvalidSlippageBound);
+             trading_state.max_slippage_bps = slippage_bps;
+             msg!("Updated max slippage from the oracle quote: {} bps", slippage_bps);
+         }
+         
+         Ok(())
+     }
+ 
+     /**
+      * Allow or disallow a Pyth price feed for trade pricing
+      */
+     pub fn set_price_feed_allowed(
+         ctx: Context<UpdateParameters>,
+         feed_id: [u8; 32],
+         allowed: bool,
+     ) -> Result<()> {
+         let trading_state = &mut ctx.accounts.trading_state;
+         
+         // Ensure the caller is the authorized authority
+         require!(
+             ctx.accounts.authority.key() == trading_state.authority,
+             ErrorCode::Unauthorized
+         );
+         
+         if allowed {
+             if !trading_state.allowed_feed_ids.contains(&feed_id) {
+                 require!(
+                     trading_state.allowed_feed_ids.len() < TradingState::MAX_PRICE_FEEDS,
+                     ErrorCode::TooManyPriceFeeds
+                 );
+                 trading_state.allowed_feed_ids.push(feed_id);
+             }
+         } else {
+             trading_state.allowed_feed_ids.retain(|id| *id != feed_id);
+         }
+         
+         msg!("Price feed {} {}", hex::encode(feed_id), if allowed { "allowed" } else { "removed" });
+         Ok(())
+     }
+     
+     /**
+      * Allow or disallow a settlement authority, which may settle trade
+      * outcomes alongside the main authority
+      */
+     pub fn set_settlement_authority(
+         ctx: Context<UpdateParameters>,
+         settlement_authority: Pubkey,
+         allowed: bool,
+     ) -> Result<()> {
+         let trading_state = &mut ctx.accounts.trading_state;
+         
+         // Ensure the caller is the authorized authority
+         require!(
+             ctx.accounts.authority.key() == trading_state.authority,
+             ErrorCode::Unauthorized
+         );
+         
+         trading_state.allow_settlement_authority(settlement_authority, allowed)?;
+         
+         msg!("Settlement authority {} {}", settlement_authority, if allowed { "allowed" } else { "removed" });
          Ok(())
      }
  
      /**
-      * Update trade outcome
+      * Update trade outcome. A trade settles once, under the trading state
+      * it was executed with
       */
      pub fn update_trade_outcome(
          ctx: Context<UpdateTradeOutcome>,
@@ -200,9 +402,9 @@
          let trading_state = &mut ctx.accounts.trading_state;
          let trade_record = &mut ctx.accounts.trade_record;
          
-         // Ensure the caller is the authorized authority
+         // Ensure the caller may settle outcomes
          require!(
-             ctx.accounts.authority.key() == trading_state.authority,
+             trading_state.can_settle(&ctx.accounts.authority.key()),
              ErrorCode::Unauthorized
          );
          
@@ -213,18 +415,72 @@
          );
          
          // Update trade success status and profit/loss
-         trade_record.successful = successful;
-         trade_record.profit_loss = profit_loss;
+         trade_record.settle(&trading_state.key(), successful, profit_loss)?;
          
          // Update trading state metrics
-         if successful {
-             trading_state.successful_trades += 1;
-         }
-         trading_state.total_profit_loss += profit_loss;
+         trading_state.record_outcomes(successful as u64, profit_loss, Clock::get()?.unix_timestamp)?;
          
          msg!("Updated trade outcome: successful={}, profit/loss={}", successful, profit_loss);
          Ok(())
      }
+ 
+     /**
+      * Settle several trades at once. Each outcome is (trade record key,
+      * successful, profit/loss) and its trade record is passed as the
+      * remaining account at the same position. Any mismatch or already
+      * settled trade fails the batch.
+      */
+     pub fn update_trade_outcomes_batch<'info>(
+         ctx: Context<'_, '_, '_, 'info, UpdateTradeOutcomesBatch<'info>>,
+         outcomes: Vec<(Pubkey, bool, i64)>,
+     ) -> Result<()> {
+         let trading_state = &mut ctx.accounts.trading_state;
+         
+         // Ensure the caller may settle outcomes
+         require!(
+             trading_state.can_settle(&ctx.accounts.authority.key()),
+             ErrorCode::Unauthorized
+         );
+         require!(
+             !outcomes.is_empty()
+                 && outcomes.len() <= TradingState::MAX_OUTCOME_BATCH
+                 && outcomes.len() == ctx.remaining_accounts.len(),
+             ErrorCode::InvalidBatchSize
+         );
+         
+         let mut successful_trades: u64 = 0;
+         let mut profit_loss_total: i64 = 0;
+         
+         for (index, ((trade_id, successful, profit_loss), info)) in
+             outcomes.iter().zip(ctx.remaining_accounts.iter()).enumerate()
+         {
+             // Ensure the trade record matches the provided ID and appears once
+             require!(info.key() == *trade_id, ErrorCode::InvalidTradeRecord);
+             require!(
+                 !outcomes[..index].iter().any(|(id, _, _)| id == trade_id),
+                 ErrorCode::InvalidTradeRecord
+             );
+             
+             let mut trade_record = Account::<TradeRecord>::try_from(info)?;
+             trade_record.settle(&trading_state.key(), *successful, *profit_loss)?;
+             trade_record.exit(ctx.program_id)?;
+             
+             successful_trades += *successful as u64;
+             profit_loss_total = profit_loss_total
+                 .checked_add(*profit_loss)
+                 .ok_or(ErrorCode::ArithmeticOverflow)?;
+         }
+         
+         trading_state.record_outcomes(successful_trades, profit_loss_total, Clock::get()?.unix_timestamp)?;
+         
+         msg!(
+             "Updated {} trade outcomes: successful={}, profit/loss={}",
+             outcomes.len(),
+             successful_trades,
+             profit_loss_total
+         );
+         Ok(())
+     }
  }
  
  /**
@@ -253,6 +509,19 @@
      #[account(mut)]
      pub trading_state: Account<'info, TradingState>,
      
+     /// Market being traded
+     #[account(
+         mut,
+         seeds = [
+             b"pair-config",
+             trading_state.key().as_ref(),
+             pair_config.base_mint.as_ref(),
+             pair_config.quote_mint.as_ref()
+         ],
+         bump = pair_config.bump
+     )]
+     pub pair_config: Account<'info, PairConfig>,
+     
      #[account(
          init,
          payer = authority,
@@ -260,19 +529,49 @@
      )]
      pub trade_record: Account<'info, TradeRecord>,
      
-     #[account(mut)]
+     #[account(mut, constraint = source_account.owner == authority.key() @ ErrorCode::Unauthorized)]
      pub source_account: Account<'info, TokenAccount>,
      
-     #[account(mut)]
+     #[account(mut, constraint = destination_account.owner == authority.key() @ ErrorCode::Unauthorized)]
      pub destination_account: Account<'info, TokenAccount>,
      
      #[account(mut)]
      pub authority: Signer<'info>,
      
-     /// Price update account from Pyth
-     pub price_update: Account<'info, PriceUpdateV2>,
+     /// CHECK: Pyth PriceUpdateV2 account, parsed and checked against the feed id in the handler
+     #[account(owner = PYTH_RECEIVER_PROGRAM_ID)]
+     pub price_update: UncheckedAccount<'info>,
+     
+     /// CHECK: Swap route program; must be executable and not this program
+     #[account(
+         executable,
+         constraint = swap_program.key() != crate::ID @ ErrorCode::InvalidSwapProgram
+     )]
+     pub swap_program: UncheckedAccount<'info>,
+     
+     pub system_program: Program<'info, System>,
+ }
+ 
+ /**
+  * Context for registering a trading pair
+  */
+ #[derive(Accounts)]
+ #[instruction(base_mint: Pubkey, quote_mint: Pubkey)]
+ pub struct RegisterPair<'info> {
+     pub trading_state: Account<'info, TradingState>,
+     
+     #[account(
+         init,
+         payer = authority,
+         space = 8 + PairConfig::LEN,
+         seeds = [b"pair-config", trading_state.key().as_ref(), base_mint.as_ref(), quote_mint.as_ref()],
+         bump
+     )]
+     pub pair_config: Account<'info, PairConfig>,
+     
+     #[account(mut)]
+     pub authority: Signer<'info>,
      
-     pub token_program: Program<'info, Token>,
      pub system_program: Program<'info, System>,
  }
  
@@ -307,6 +606,21 @@
      pub system_program: Program<'info, System>,
  }
  
+ /**
+  * Context for settling a batch of trade outcomes; the trade records are
+  * passed as remaining accounts
+  */
+ #[derive(Accounts)]
+ pub struct UpdateTradeOutcomesBatch<'info> {
+     #[account(mut)]
+     pub trading_state: Account<'info, TradingState>,
+     
+     #[account(mut)]
+     pub authority: Signer<'info>,
+     
+     pub system_program: Program<'info, System>,
+ }
+ 
  /**
   * Trading state account data structure
   */
@@ -320,10 +634,211 @@
      pub total_trades: u64,           // Total number of trades executed
      pub successful_trades: u64,      // Number of successful trades
      pub total_profit_loss: i64,      // Total profit/loss in basis points
+     pub allowed_feed_ids: Vec<[u8; 32]>, // Pyth feed ids trades may be priced against
+     pub max_conf_bps: u16,           // Widest oracle confidence interval accepted, in bps of price
+     pub max_twap_deviation_bps: u16, // Largest spot/EMA price gap accepted, in bps of the EMA
+     pub max_slippage_bps: u16,       // Largest shortfall of the swap output from the oracle quote, in bps
+     pub max_price_age_secs: u64,     // Oldest oracle price accepted for a trade
+     pub min_trade_interval_secs: u64, // Minimum time between trades on a pair
+     pub last_trade_at: i64,          // Time of the latest trade on any pair
+     pub daily_loss_limit: u64,       // Intraday loss in basis points that pauses trading (0 = disabled)
+     pub day_start_pl: i64,           // total_profit_loss at the start of current_day
+     pub current_day: i64,            // UTC day index day_start_pl was taken on
+     pub daily_loss_paused: bool,     // Paused by the daily loss limit, cleared on the next day
+     pub settlement_authorities: Vec<Pubkey>, // Also allowed to settle trade outcomes
  }
  
  impl TradingState {
-     pub const LEN: usize = 32 + 1 + 1 + 8 + 1 + 8 + 8 + 8;
+     pub const MAX_PRICE_FEEDS: usize = 8;
+     pub const DEFAULT_MAX_CONF_BPS: u16 = 100;
+     pub const DEFAULT_MAX_TWAP_DEVIATION_BPS: u16 = 200;
+     pub const DEFAULT_MAX_SLIPPAGE_BPS: u16 = 100;
+     pub const MAX_OUTCOME_BATCH: usize = 10;
+     pub const MAX_PRICE_AGE_LIMIT_SECS: u64 = 300;
+     pub const MAX_SETTLEMENT_AUTHORITIES: usize = 5;
+     pub const LEN: usize = 32 + 1 + 1 + 8 + 1 + 8 + 8 + 8 + (4 + 32 * Self::MAX_PRICE_FEEDS) + 2 + 2 + 2 + 8 + 8 + 8 + 8 + 8 + 8 + 1
+         + (4 + 32 * Self::MAX_SETTLEMENT_AUTHORITIES);
+     
+     /**
+      * Require a configured oracle freshness window of at least a second and
+      * at most MAX_PRICE_AGE_LIMIT_SECS
+      */
+     pub fn check_max_price_age(max_price_age_secs: u64) -> Result<()> {
+         require!(
+             (1..=Self::MAX_PRICE_AGE_LIMIT_SECS).contains(&max_price_age_secs),
+             ErrorCode::InvalidPriceAge
+         );
+         Ok(())
+     }
+     
+     /**
+      * Whether `key` may settle trade outcomes: the main authority or an
+      * allowed settlement authority
+      */
+     pub fn can_settle(&self, key: &Pubkey) -> bool {
+         *key == self.authority || self.settlement_authorities.contains(key)
+     }
+     
+     /**
+      * Add `key` to or remove it from the settlement authorities
+      */
+     pub fn allow_settlement_authority(&mut self, key: Pubkey, allowed: bool) -> Result<()> {
+         if allowed {
+             if !self.settlement_authorities.contains(&key) {
+                 require!(
+                     self.settlement_authorities.len() < Self::MAX_SETTLEMENT_AUTHORITIES,
+                     ErrorCode::TooManySettlementAuthorities
+                 );
+                 self.settlement_authorities.push(key);
+             }
+         } else {
+             self.settlement_authorities.retain(|k| *k != key);
+         }
+         Ok(())
+     }
+     
+     /**
+      * Require `feed_id` to be both the pair's configured feed and one the
+      * authority has allowed
+      */
+     pub fn check_price_feed(&self, pair_config: &PairConfig, feed_id: &[u8; 32]) -> Result<()> {
+         require!(
+             *feed_id == pair_config.feed_id && self.allowed_feed_ids.contains(feed_id),
+             ErrorCode::InvalidPriceFeed
+         );
+         Ok(())
+     }
+     
+     /**
+      * Require a positive oracle price whose confidence interval is within
+      * max_conf_bps of it
+      */
+     pub fn check_price_confidence(&self, price: i64, conf: u64) -> Result<()> {
+         require!(price > 0, ErrorCode::InvalidPrice);
+         let conf_bps = (conf as u128) * 10000 / (price as u128);
+         require!(
+             conf_bps <= self.max_conf_bps as u128,
+             ErrorCode::PriceTooUncertain
+         );
+         Ok(())
+     }
+     
+     /**
+      * Require the spot price to be within max_twap_deviation_bps of a
+      * positive EMA price
+      */
+     pub fn check_ema_deviation(&self, price: i64, ema_price: i64) -> Result<()> {
+         require!(ema_price > 0, ErrorCode::InvalidPrice);
+         let twap_deviation_bps = (price.abs_diff(ema_price) as u128) * 10000 / (ema_price as u128);
+         require!(
+             twap_deviation_bps <= self.max_twap_deviation_bps as u128,
+             ErrorCode::PriceManipulationDetected
+         );
+         Ok(())
+     }
+     
+     /**
+      * Output a swap delivered, measured as the increase of the destination
+      * balance from `destination_balances.0` to `.1`. It must reach the
+      * caller's minimum and come within max_slippage_bps of the oracle's
+      * expected output.
+      */
+     pub fn check_swap_output(
+         &self,
+         destination_balances: (u64, u64),
+         expected_output: u64,
+         min_output_amount: u64,
+     ) -> Result<u64> {
+         let actual_output = destination_balances.1.saturating_sub(destination_balances.0);
+         let oracle_bound = (expected_output as u128) * (10000 - self.max_slippage_bps as u128) / 10000;
+         require!(
+             actual_output >= min_output_amount && actual_output as u128 >= oracle_bound,
+             ErrorCode::SlippageExceeded
+         );
+         Ok(actual_output)
+     }
+     
+     /**
+      * Snapshot P/L at each UTC day boundary, lifting a pause set by the
+      * daily loss limit
+      */
+     pub fn roll_day(&mut self, now: i64) {
+         let today = now / 86400;
+         if self.current_day == today {
+             return;
+         }
+         
+         self.current_day = today;
+         self.day_start_pl = self.total_profit_loss;
+         if self.daily_loss_paused {
+             self.paused = false;
+             self.daily_loss_paused = false;
+             msg!("New trading day, daily loss pause lifted");
+         }
+     }
+     
+     /**
+      * Add settled trade outcomes to the metrics, pausing trading for the
+      * rest of the day once the daily loss limit is hit
+      */
+     pub fn record_outcomes(&mut self, successful_trades: u64, profit_loss: i64, now: i64) -> Result<()> {
+         self.roll_day(now);
+         self.successful_trades = self
+             .successful_trades
+             .checked_add(successful_trades)
+             .ok_or(ErrorCode::ArithmeticOverflow)?;
+         self.total_profit_loss = self
+             .total_profit_loss
+             .checked_add(profit_loss)
+             .ok_or(ErrorCode::ArithmeticOverflow)?;
+         
+         let intraday_pl = self
+             .total_profit_loss
+             .checked_sub(self.day_start_pl)
+             .ok_or(ErrorCode::ArithmeticOverflow)?;
+         if self.daily_loss_limit > 0 && !self.paused && intraday_pl <= -(self.daily_loss_limit as i64) {
+             self.paused = true;
+             self.daily_loss_paused = true;
+             msg!(
+                 "Daily loss limit reached: intraday P/L {} bps, limit {} bps. Trading paused until the next UTC day",
+                 intraday_pl,
+                 self.daily_loss_limit
+             );
+         }
+         
+         Ok(())
+     }
+ }
+ 
+ /**
+  * Per-market configuration under a trading state
+  */
+ #[account]
+ pub struct PairConfig {
+     pub trading_state: Pubkey,       // Trading state this market belongs to
+     pub base_mint: Pubkey,           // Asset being traded
+     pub quote_mint: Pubkey,          // Asset it is priced in
+     pub feed_id: [u8; 32],           // Pyth feed pricing base in quote
+     pub max_position_size: u64,      // Maximum position size in tokens for this market
+     pub risk_level: Option<u8>,      // Overrides the trading state's risk level
+     pub last_trade_at: i64,          // Time of the latest trade on this pair
+     pub bump: u8,                    // PDA bump
+ }
+ 
+ impl PairConfig {
+     pub const LEN: usize = 32 + 32 + 32 + 32 + 8 + 2 + 8 + 1;
+     
+     /**
+      * Require at least `min_trade_interval_secs` since the last trade on
+      * this pair
+      */
+     pub fn check_cooldown(&self, now: i64, min_trade_interval_secs: u64) -> Result<()> {
+         require!(
+             now - self.last_trade_at >= min_trade_interval_secs as i64,
+             ErrorCode::TradeCooldownActive
+         );
+         Ok(())
+     }
  }
  
  /**
@@ -336,14 +851,34 @@
      pub amount: u64,                 // Amount of tokens in the trade
      pub side: TradeSide,             // Buy or sell
      pub price: i64,                  // Price at execution
+     pub ema_price: i64,              // Oracle EMA price at execution
      pub confidence: u8,              // AI confidence level
+     pub confidence_required: u8,     // Minimum confidence enforced for this trade
      pub strategy_id: u8,             // ID of the strategy used
      pub successful: bool,            // Whether the trade was successful
      pub profit_loss: i64,            // Profit/loss from the trade in basis points
+     pub expected_output: u64,        // Output implied by the oracle price, checked against the caller's minimum
+     pub actual_output: u64,          // Output the swap delivered to the destination account
+     pub trading_state: Pubkey,       // Trading state the trade was executed under
+     pub settled: bool,               // Whether the outcome has been settled
  }
  
  impl TradeRecord {
-     pub const LEN: usize = 32 + 8 + 8 + 1 + 8 + 1 + 1 + 1 + 8;
+     pub const LEN: usize = 32 + 8 + 8 + 1 + 8 + 8 + 1 + 1 + 1 + 1 + 8 + 8 + 8 + 32 + 1;
+     
+     /**
+      * Set the trade's outcome, once, for a trade executed under
+      * `trading_state`
+      */
+     pub fn settle(&mut self, trading_state: &Pubkey, successful: bool, profit_loss: i64) -> Result<()> {
+         require!(self.trading_state == *trading_state, ErrorCode::InvalidTradeRecord);
+         require!(!self.settled, ErrorCode::TradeAlreadySettled);
+         
+         self.successful = successful;
+         self.profit_loss = profit_loss;
+         self.settled = true;
+         Ok(())
+     }
  }
  
  /**
@@ -355,6 +890,66 @@
      Sell,
  }
  
+ impl TradeSide {
+     fn label(&self) -> &'static str {
+         match self {
+             TradeSide::Buy => "BUY",
+             TradeSide::Sell => "SELL",
+         }
+     }
+     
+     /**
+      * Mint the trade pays with: the quote mint to buy, the base mint to sell
+      */
+     fn input_mint(&self, pair_config: &PairConfig) -> Pubkey {
+         match self {
+             TradeSide::Buy => pair_config.quote_mint,
+             TradeSide::Sell => pair_config.base_mint,
+         }
+     }
+     
+     /**
+      * Mint the trade receives: the other side of the pair
+      */
+     fn output_mint(&self, pair_config: &PairConfig) -> Pubkey {
+         match self {
+             TradeSide::Buy => pair_config.base_mint,
+             TradeSide::Sell => pair_config.quote_mint,
+         }
+     }
+ }
+ 
+ /**
+  * An AI signal to trade on; see `execute_trade`
+  */
+ #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+ pub struct ExecuteTradeParams {
+     pub amount: u64,
+     pub side: TradeSide,
+     pub confidence: u8,
+     pub strategy_id: u8,
+     pub min_confidence_override: Option<u8>,
+     pub asset_feed_id: [u8; 32],
+     pub min_output_amount: u64,
+     pub swap_data: Vec<u8>, // Route program instruction data
+ }
+ 
+ /**
+  * Optional trading parameter changes; see `update_parameters`
+  */
+ #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+ pub struct TradingParametersUpdate {
+     pub max_position_size: Option<u64>,
+     pub risk_level: Option<u8>,
+     pub paused: Option<bool>,
+     pub max_conf_bps: Option<u16>,
+     pub max_twap_deviation_bps: Option<u16>,
+     pub daily_loss_limit: Option<u64>,
+     pub max_price_age_secs: Option<u64>,
+     pub min_trade_interval_secs: Option<u64>,
+     pub max_slippage_bps: Option<u16>,
+ }
+ 
  /**
   * Error codes
   */
@@ -377,6 +972,96 @@
      
      #[msg("Invalid trade record")]
      InvalidTradeRecord,
+     
+     #[msg("Price feed is not allowed for trading")]
+     InvalidPriceFeed,
+     
+     #[msg("Too many allowed price feeds")]
+     TooManyPriceFeeds,
+     
+     #[msg("Oracle price must be positive")]
+     InvalidPrice,
+     
+     #[msg("Oracle confidence interval is too wide to trade")]
+     PriceTooUncertain,
+     
+     #[msg("Confidence bound must be between 1 and 10000 bps")]
+     InvalidConfidenceBound,
+     
+     #[msg("Output is below the caller's minimum or too far below the oracle quote")]
+     SlippageExceeded,
+     
+     #[msg("Arithmetic overflow")]
+     ArithmeticOverflow,
+     
+     #[msg("Spot price deviates too far from the EMA price")]
+     PriceManipulationDetected,
+     
+     #[msg("Deviation bound must be between 1 and 10000 bps")]
+     InvalidDeviationBound,
+     
+     #[msg("Batch must hold between 1 and 10 outcomes, one per trade record")]
+     InvalidBatchSize,
+     
+     #[msg("Max price age must be between 1 and 300 seconds")]
+     InvalidPriceAge,
+     
+     #[msg("Too soon since the last trade on this pair")]
+     TradeCooldownActive,
+     
+     #[msg("Too many settlement authorities")]
+     TooManySettlementAuthorities,
+     
+     #[msg("Token accounts do not hold the mint this side of the pair pays with")]
+     InvalidTradeMint,
+     
+     #[msg("Slippage bound must be at most 10000 bps")]
+     InvalidSlippageBound,
+     
+     #[msg("Swap program must be executable and not this program")]
+     InvalidSwapProgram,
+     
+     #[msg("Swap spent more than the trade amount")]
+     SwapOverspent,
+     
+     #[msg("Trade outcome has already been settled")]
+     TradeAlreadySettled,
+ }
+ 
+ /**
+  * Output implied by the oracle price: a sell converts `amount` at `price`,
+  * a buy spends `amount` at `price`. Price is scaled by 10^exponent.
+  */
+ fn expected_output_amount(amount: u64, side: TradeSide, price: i64, exponent: i32) -> Result<u64> {
+     let scale = 10u128.checked_pow(exponent.unsigned_abs());
+     let price = price as u128;
+     let amount = amount as u128;
+     
+     let output = scale.and_then(|scale| match (side, exponent < 0) {
+         (TradeSide::Sell, true) => amount.checked_mul(price)?.checked_div(scale),
+         (TradeSide::Sell, false) => amount.checked_mul(price)?.checked_mul(scale),
+         (TradeSide::Buy, true) => amount.checked_mul(scale)?.checked_div(price),
+         (TradeSide::Buy, false) => amount.checked_div(price.checked_mul(scale)?),
+     });
+     
+     output
+         .and_then(|output| u64::try_from(output).ok())
+         .ok_or_else(|| error!(ErrorCode::ArithmeticOverflow))
+ }
+ 
+ /**
+  * Signal confidence a trade needs at `risk_level`. Higher confidence is
+  * required for higher risk trades; a strategy may demand more confidence
+  * than its band, never less.
+  */
+ fn required_confidence(risk_level: u8, min_confidence_override: Option<u8>) -> u8 {
+     let band_min_confidence = match risk_level {
+         1..=3 => 80,   // Low risk: require high confidence
+         4..=7 => 65,   // Medium risk
+         _ => 50,       // High risk: accept lower confidence
+     };
+     
+     band_min_confidence.max(min_confidence_override.unwrap_or(0))
  }
  
  /**
@@ -396,4 +1081,300 @@
      }
      
      Ok(result)
- }
\ No newline at end of file
+ }
+ 
+ #[cfg(test)]
+ mod tests {
+     use super::*;
+     
+     fn test_trading_state(daily_loss_limit: u64) -> TradingState {
+         TradingState {
+             authority: Pubkey::new_unique(),
+             initialized: true,
+             paused: false,
+             max_position_size: u64::MAX,
+             risk_level: 5,
+             total_trades: 0,
+             successful_trades: 0,
+             total_profit_loss: 0,
+             allowed_feed_ids: vec![],
+             max_conf_bps: TradingState::DEFAULT_MAX_CONF_BPS,
+             max_twap_deviation_bps: TradingState::DEFAULT_MAX_TWAP_DEVIATION_BPS,
+             max_slippage_bps: TradingState::DEFAULT_MAX_SLIPPAGE_BPS,
+             max_price_age_secs: 60,
+             min_trade_interval_secs: 0,
+             last_trade_at: 0,
+             daily_loss_limit,
+             day_start_pl: 0,
+             current_day: 0,
+             daily_loss_paused: false,
+             settlement_authorities: vec![],
+         }
+     }
+     
+     fn test_pair_config(base_mint: Pubkey, quote_mint: Pubkey) -> PairConfig {
+         PairConfig {
+             trading_state: Pubkey::new_unique(),
+             base_mint,
+             quote_mint,
+             feed_id: [0; 32],
+             max_position_size: u64::MAX,
+             risk_level: None,
+             last_trade_at: 0,
+             bump: 0,
+         }
+     }
+     
+     #[test]
+     fn each_side_pays_with_its_pair_mint() {
+         let base_mint = Pubkey::new_unique();
+         let quote_mint = Pubkey::new_unique();
+         let pair_config = test_pair_config(base_mint, quote_mint);
+         
+         assert_eq!(TradeSide::Buy.input_mint(&pair_config), quote_mint);
+         assert_eq!(TradeSide::Sell.input_mint(&pair_config), base_mint);
+         assert_eq!(TradeSide::Buy.output_mint(&pair_config), base_mint);
+         assert_eq!(TradeSide::Sell.output_mint(&pair_config), quote_mint);
+     }
+     
+     #[test]
+     fn swap_output_is_measured_and_held_to_the_oracle_quote() {
+         // 1% below the quote of 1_000 is the oracle bound
+         let trading_state = test_trading_state(0);
+         
+         assert_eq!(trading_state.check_swap_output((500, 1_495), 1_000, 900).unwrap(), 995);
+         assert_eq!(trading_state.check_swap_output((500, 1_490), 1_000, 900).unwrap(), 990);
+         assert_eq!(
+             trading_state.check_swap_output((500, 1_489), 1_000, 900).unwrap_err(),
+             error!(ErrorCode::SlippageExceeded)
+         );
+         
+         // The caller's minimum binds when it is tighter than the oracle bound
+         assert_eq!(
+             trading_state.check_swap_output((500, 1_495), 1_000, 1_000).unwrap_err(),
+             error!(ErrorCode::SlippageExceeded)
+         );
+         
+         // A falling destination balance delivered nothing
+         assert_eq!(
+             trading_state.check_swap_output((500, 400), 1_000, 0).unwrap_err(),
+             error!(ErrorCode::SlippageExceeded)
+         );
+     }
+     
+     #[test]
+     fn expected_output_follows_the_oracle_price() {
+         // 2.50 quote per base
+         assert_eq!(expected_output_amount(1_000, TradeSide::Sell, 250, -2).unwrap(), 2_500);
+         assert_eq!(expected_output_amount(1_000, TradeSide::Buy, 250, -2).unwrap(), 400);
+         assert_eq!(expected_output_amount(1_000, TradeSide::Sell, 3, 1).unwrap(), 30_000);
+         assert_eq!(expected_output_amount(30_000, TradeSide::Buy, 3, 1).unwrap(), 1_000);
+     }
+     
+     #[test]
+     fn expected_output_overflow_is_an_error() {
+         assert_eq!(
+             expected_output_amount(u64::MAX, TradeSide::Sell, i64::MAX, 0).unwrap_err(),
+             error!(ErrorCode::ArithmeticOverflow)
+         );
+         assert_eq!(
+             expected_output_amount(1, TradeSide::Sell, 1, 40).unwrap_err(),
+             error!(ErrorCode::ArithmeticOverflow)
+         );
+     }
+     
+     #[test]
+     fn daily_loss_limit_pauses_until_the_next_day() {
+         let mut trading_state = test_trading_state(500);
+         
+         trading_state.record_outcomes(0, -300, 10).unwrap();
+         assert!(!trading_state.paused);
+         trading_state.record_outcomes(0, -200, 20).unwrap();
+         assert!(trading_state.paused && trading_state.daily_loss_paused);
+         
+         trading_state.roll_day(86_400);
+         assert!(!trading_state.paused && !trading_state.daily_loss_paused);
+         assert_eq!(trading_state.day_start_pl, -500);
+     }
+     
+     #[test]
+     fn outcome_overflow_is_an_error() {
+         let mut trading_state = test_trading_state(0);
+         trading_state.total_profit_loss = i64::MAX;
+         
+         assert_eq!(
+             trading_state.record_outcomes(0, 1, 0).unwrap_err(),
+             error!(ErrorCode::ArithmeticOverflow)
+         );
+     }
+     
+     #[test]
+     fn trade_outcome_settles_once_under_its_trading_state() {
+         let trading_state = Pubkey::new_unique();
+         let mut trade_record = TradeRecord {
+             authority: Pubkey::new_unique(),
+             timestamp: 0,
+             amount: 1_000,
+             side: TradeSide::Buy,
+             price: 250,
+             ema_price: 250,
+             confidence: 90,
+             confidence_required: 80,
+             strategy_id: 1,
+             successful: false,
+             profit_loss: 0,
+             expected_output: 400,
+             actual_output: 398,
+             trading_state,
+             settled: false,
+         };
+         
+         let mut data = Vec::new();
+         trade_record.try_serialize(&mut data).unwrap();
+         assert_eq!(data.len(), 8 + TradeRecord::LEN);
+         
+         assert_eq!(
+             trade_record.settle(&Pubkey::new_unique(), true, 50).unwrap_err(),
+             error!(ErrorCode::InvalidTradeRecord)
+         );
+         trade_record.settle(&trading_state, true, 50).unwrap();
+         assert!(trade_record.settled && trade_record.successful);
+         assert_eq!(
+             trade_record.settle(&trading_state, false, -50).unwrap_err(),
+             error!(ErrorCode::TradeAlreadySettled)
+         );
+         assert_eq!(trade_record.profit_loss, 50);
+     }
+     
+     #[test]
+     fn confidence_override_can_only_raise_the_band() {
+         assert_eq!(required_confidence(2, None), 80);
+         assert_eq!(required_confidence(5, None), 65);
+         assert_eq!(required_confidence(9, None), 50);
+         
+         assert_eq!(required_confidence(9, Some(90)), 90);
+         assert_eq!(required_confidence(2, Some(40)), 80);
+     }
+     
+     #[test]
+     fn trades_are_priced_only_by_the_pairs_allowed_feed() {
+         let mut trading_state = test_trading_state(0);
+         let mut pair_config = test_pair_config(Pubkey::new_unique(), Pubkey::new_unique());
+         let sol_usd = get_feed_id_from_hex(
+             "0xef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d"
+         ).unwrap();
+         pair_config.feed_id = sol_usd;
+     
+         // Matches the pair but hasn't been allowed yet
+         assert_eq!(
+             trading_state.check_price_feed(&pair_config, &sol_usd).unwrap_err(),
+             error!(ErrorCode::InvalidPriceFeed)
+         );
+     
+         trading_state.allowed_feed_ids.push(sol_usd);
+         assert!(trading_state.check_price_feed(&pair_config, &sol_usd).is_ok());
+     
+         // Allowed, but not the feed for this pair
+         let other_feed = [1u8; 32];
+         trading_state.allowed_feed_ids.push(other_feed);
+         assert_eq!(
+             trading_state.check_price_feed(&pair_config, &other_feed).unwrap_err(),
+             error!(ErrorCode::InvalidPriceFeed)
+         );
+     }
+     
+     #[test]
+     fn uncertain_oracle_prices_are_rejected() {
+         let mut trading_state = test_trading_state(0);
+         trading_state.max_conf_bps = 100;
+         
+         assert!(trading_state.check_price_confidence(10_000, 100).is_ok());
+         assert_eq!(
+             trading_state.check_price_confidence(10_000, 101).unwrap_err(),
+             error!(ErrorCode::PriceTooUncertain)
+         );
+         assert_eq!(
+             trading_state.check_price_confidence(0, 0).unwrap_err(),
+             error!(ErrorCode::InvalidPrice)
+         );
+         assert_eq!(
+             trading_state.check_price_confidence(-5, 0).unwrap_err(),
+             error!(ErrorCode::InvalidPrice)
+         );
+     }
+     
+     #[test]
+     fn spot_prices_far_from_the_ema_are_rejected() {
+         let mut trading_state = test_trading_state(0);
+         trading_state.max_twap_deviation_bps = 200;
+         
+         assert!(trading_state.check_ema_deviation(10_200, 10_000).is_ok());
+         assert!(trading_state.check_ema_deviation(9_800, 10_000).is_ok());
+         assert_eq!(
+             trading_state.check_ema_deviation(10_201, 10_000).unwrap_err(),
+             error!(ErrorCode::PriceManipulationDetected)
+         );
+         assert_eq!(
+             trading_state.check_ema_deviation(9_799, 10_000).unwrap_err(),
+             error!(ErrorCode::PriceManipulationDetected)
+         );
+         assert_eq!(
+             trading_state.check_ema_deviation(10_000, 0).unwrap_err(),
+             error!(ErrorCode::InvalidPrice)
+         );
+     }
+     
+     #[test]
+     fn max_price_age_must_be_within_the_limit() {
+         assert!(TradingState::check_max_price_age(1).is_ok());
+         assert!(TradingState::check_max_price_age(TradingState::MAX_PRICE_AGE_LIMIT_SECS).is_ok());
+         assert_eq!(
+             TradingState::check_max_price_age(0).unwrap_err(),
+             error!(ErrorCode::InvalidPriceAge)
+         );
+         assert_eq!(
+             TradingState::check_max_price_age(TradingState::MAX_PRICE_AGE_LIMIT_SECS + 1).unwrap_err(),
+             error!(ErrorCode::InvalidPriceAge)
+         );
+     }
+     
+     #[test]
+     fn pairs_trade_at_most_once_per_interval() {
+         let mut pair_config = test_pair_config(Pubkey::new_unique(), Pubkey::new_unique());
+         pair_config.last_trade_at = 1_000;
+         
+         assert_eq!(
+             pair_config.check_cooldown(1_059, 60).unwrap_err(),
+             error!(ErrorCode::TradeCooldownActive)
+         );
+         assert!(pair_config.check_cooldown(1_060, 60).is_ok());
+         // No interval configured
+         assert!(pair_config.check_cooldown(1_000, 0).is_ok());
+     }
+     
+     #[test]
+     fn settlement_authorities_settle_alongside_the_authority() {
+         let mut trading_state = test_trading_state(0);
+         let settler = Pubkey::new_unique();
+         
+         assert!(trading_state.can_settle(&trading_state.authority));
+         assert!(!trading_state.can_settle(&settler));
+         
+         trading_state.allow_settlement_authority(settler, true).unwrap();
+         trading_state.allow_settlement_authority(settler, true).unwrap();
+         assert_eq!(trading_state.settlement_authorities, vec![settler]);
+         assert!(trading_state.can_settle(&settler));
+         assert!(!trading_state.can_settle(&Pubkey::new_unique()));
+         
+         trading_state.allow_settlement_authority(settler, false).unwrap();
+         assert!(!trading_state.can_settle(&settler));
+         
+         for _ in 0..TradingState::MAX_SETTLEMENT_AUTHORITIES {
+             trading_state.allow_settlement_authority(Pubkey::new_unique(), true).unwrap();
+         }
+         assert_eq!(
+             trading_state.allow_settlement_authority(Pubkey::new_unique(), true).unwrap_err(),
+             error!(ErrorCode::TooManySettlementAuthorities)
+         );
+     }
+ }
@@ -2,7 +2,8 @@
 
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Token, TokenAccount, Transfer};
-use crate::notification_events::{emit_notification, NotificationEventType, NotificationPriority};
+use crate::notification_events::{emit_notification, Notification, NotificationCounter, NotificationEventType, NotificationPriority};
+pub use crate::errors::CommonError;
 use std::collections::HashMap;
 
 // Protocol types
@@ -96,9 +97,49 @@ pub struct DeFiStrategyRegistry {
     pub strategy_count: u64,
     pub protocol_fee_bps: u16,  // in basis points
     pub fee_recipient: Pubkey,
+    // Non-deprecated strategies per protocol type
+    pub lending_count: u32,
+    pub yield_farming_count: u32,
+    pub liquidity_providing_count: u32,
+    pub staking_count: u32,
+    pub options_count: u32,
+    // Non-deprecated strategies per risk level
+    pub conservative_count: u32,
+    pub moderate_count: u32,
+    pub aggressive_count: u32,
+    pub experimental_count: u32,
     pub bump: u8,
 }
 
+impl DeFiStrategyRegistry {
+    // Count a strategy into (or out of) its protocol and risk categories
+    pub fn adjust_category_counts(
+        &mut self,
+        protocol_type: &ProtocolType,
+        risk_level: &RiskLevel,
+        increment: bool,
+    ) {
+        let adjust = |count: &mut u32| {
+            *count = if increment { count.saturating_add(1) } else { count.saturating_sub(1) };
+        };
+        
+        adjust(match protocol_type {
+            ProtocolType::Lending => &mut self.lending_count,
+            ProtocolType::YieldFarming => &mut self.yield_farming_count,
+            ProtocolType::LiquidityProviding => &mut self.liquidity_providing_count,
+            ProtocolType::Staking => &mut self.staking_count,
+            ProtocolType::Options => &mut self.options_count,
+        });
+        
+        adjust(match risk_level {
+            RiskLevel::Conservative => &mut self.conservative_count,
+            RiskLevel::Moderate => &mut self.moderate_count,
+            RiskLevel::Aggressive => &mut self.aggressive_count,
+            RiskLevel::Experimental => &mut self.experimental_count,
+        });
+    }
+}
+
 // DeFi Strategy Definition
 #[account]
 pub struct DeFiStrategy {
@@ -121,6 +162,7 @@ pub struct DeFiStrategy {
     pub updated_at: i64,
     pub status: StrategyStatus,
     pub tags: Vec<String>,
+    pub early_exit_penalty_bps: u16, // Penalty for exiting at the start of the lockup; falls linearly to zero at its end
     pub bump: u8,
 }
 
@@ -137,9 +179,94 @@ pub struct DeFiSubscription {
     pub auto_compound: bool,
     pub active_position_ids: Vec<Pubkey>, // References to protocol-specific positions
     pub custom_settings: HashMap<String, Vec<u8>>, // Custom setting overrides
+    pub health_check: HealthCheckParams,
+    pub deleverage_pending: bool,         // Set by check_position_health for keepers to act on
+    pub compound_count: u32,
+    pub total_compounded_amount: u64,  // Total reward tokens folded back in, in their smallest units
+    pub cooldown_ends_at: i64,         // Set by initiate_unstake for Staking strategies, 0 if not started
+    pub entry_price_ratio: u64,        // LP pool price (reserve_b / reserve_a) at entry, scaled by PRICE_RATIO_SCALE
+    pub impermanent_loss_bps: u16,     // Latest IL versus holding, in basis points
+    pub pending_rewards: u64,          // Harvested rewards not yet claimed, before the performance fee
+    pub pending_reward_mint: Pubkey,   // Mint of pending_rewards
     pub bump: u8,
 }
 
+impl DeFiSubscription {
+    // Credit reinvested reward tokens to the holding of `mint` and track the
+    // compounding. Returns the new holding.
+    pub fn record_compound(&mut self, mint: Pubkey, amount: u64, now: i64) -> Result<u64> {
+        let new_holding = match self.investment_values.iter_mut().find(|i| i.mint == mint) {
+            Some(investment) => {
+                investment.amount = investment.amount.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+                investment.amount
+            }
+            None => {
+                self.investment_values.push(TokenInvestment {
+                    mint,
+                    amount,
+                    usd_value: 0,
+                });
+                amount
+            }
+        };
+        self.total_compounded_amount = self.total_compounded_amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.compound_count = self.compound_count.checked_add(1).ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.last_harvest_time = now;
+        
+        Ok(new_holding)
+    }
+    
+    // Accrue harvested rewards of `mint` for claim_rewards to pay out. Pending
+    // rewards are held in one mint at a time. Returns the new pending total.
+    pub fn accrue_rewards(&mut self, mint: Pubkey, amount: u64, now: i64) -> Result<u64> {
+        require!(
+            self.pending_rewards == 0 || self.pending_reward_mint == mint,
+            ErrorCode::RewardMintMismatch
+        );
+        self.pending_rewards = self.pending_rewards.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.pending_reward_mint = mint;
+        self.last_harvest_time = now;
+        
+        Ok(self.pending_rewards)
+    }
+    
+    // Zero the pending rewards for payout in `mint`, split into the user's
+    // share and a performance fee of `fee_bps`
+    pub fn take_pending_rewards(&mut self, mint: &Pubkey, fee_bps: u16) -> Result<(u64, u64)> {
+        require!(self.pending_rewards > 0, ErrorCode::NoPendingRewards);
+        require!(self.pending_reward_mint == *mint, ErrorCode::RewardMintMismatch);
+        
+        let rewards = self.pending_rewards;
+        let fee = (rewards as u128 * fee_bps.min(10000) as u128 / 10000) as u64;
+        self.pending_rewards = 0;
+        
+        Ok((rewards - fee, fee))
+    }
+    
+    // Growth over the initial investment since subscribing, annualized
+    pub fn effective_apy_bps(&self, now: i64) -> u64 {
+        let elapsed = (now - self.subscribed_at).max(0) as u128;
+        let principal = self.initial_investment_value as u128;
+        let growth = self.current_value.saturating_sub(self.initial_investment_value) as u128;
+        if elapsed > 0 && principal > 0 {
+            (growth * 10000 * 365 * 86400 / (principal * elapsed)) as u64
+        } else {
+            0
+        }
+    }
+}
+
+// Fixed-point scale for LP price ratios
+pub const PRICE_RATIO_SCALE: u128 = 1_000_000_000;
+
+// Impermanent loss above this warns users with IL protection enabled (5%)
+pub const IL_WARNING_THRESHOLD_BPS: u16 = 500;
+
+// Largest early exit penalty a strategy may charge (50%)
+pub const MAX_EARLY_EXIT_PENALTY_BPS: u16 = 5000;
+
 // Token investment in a strategy
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct TokenInvestment {
@@ -148,6 +275,21 @@ pub struct TokenInvestment {
     pub usd_value: u64,    // USD value in cents
 }
 
+// One swap within a rebalance: `amount` of `from_mint` for at least `min_out` of `to_mint`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RebalanceLeg {
+    pub from_mint: Pubkey,
+    pub to_mint: Pubkey,
+    pub amount: u64,       // in from_mint's smallest units
+    pub min_out: u64,      // in to_mint's smallest units
+}
+
+// Most legs a single rebalance may record
+pub const MAX_REBALANCE_LEGS: usize = 8;
+
+// Slippage limit for protocols whose config has none (1%)
+pub const DEFAULT_REBALANCE_SLIPPAGE_BPS: u16 = 100;
+
 // DCA (Dollar Cost Averaging) Setup
 #[account]
 pub struct DCAConfig {
@@ -163,6 +305,21 @@ pub struct DCAConfig {
     pub bump: u8,
 }
 
+impl DCAConfig {
+    // Resuming restarts the schedule from now rather than firing missed executions
+    pub fn set_active(&mut self, active: bool, now: i64) {
+        self.active = active;
+        if active && self.next_execution < now {
+            self.next_execution = now;
+        }
+    }
+    
+    // A paused schedule never comes due
+    pub fn is_due(&self, now: i64) -> bool {
+        self.active && self.next_execution <= now
+    }
+}
+
 // Position Health Check Parameters
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct HealthCheckParams {
@@ -173,6 +330,90 @@ pub struct HealthCheckParams {
     pub auto_deleverage_enabled: bool,
 }
 
+impl HealthCheckParams {
+    // At or above the warning band
+    pub fn is_healthy(&self, health_factor_bps: u16) -> bool {
+        health_factor_bps >= self.health_factor_threshold
+    }
+    
+    // Below the liquidation band
+    pub fn is_critical(&self, health_factor_bps: u16) -> bool {
+        health_factor_bps < self.liquidation_threshold
+    }
+}
+
+// Configuration of a new DeFi strategy; see create_defi_strategy
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CreateDeFiStrategyParams {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub protocol_type: ProtocolType,
+    pub risk_level: RiskLevel,
+    pub token_allocations: Vec<TokenAllocation>,
+    pub estimated_apy: u16,
+    pub creator_name: String,
+    pub protocol_config: ProtocolConfig,
+    pub fee_percentage: u16,
+    pub min_investment: u64,
+    pub tags: Vec<String>,
+}
+
+// Optional changes to an existing DeFi strategy; see update_defi_strategy
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct UpdateDeFiStrategyParams {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub risk_level: Option<RiskLevel>,
+    pub token_allocations: Option<Vec<TokenAllocation>>,
+    pub estimated_apy: Option<u16>,
+    pub fee_percentage: Option<u16>,
+    pub min_investment: Option<u64>,
+    pub status: Option<StrategyStatus>,
+    pub tags: Option<Vec<String>>,
+    pub early_exit_penalty_bps: Option<u16>,
+}
+
+// Emitted each time rewards are folded back into a subscription
+#[event]
+pub struct CompoundedEvent {
+    pub subscription: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub new_holding: u64, // Subscription's holding of `mint` after compounding
+    pub compound_count: u32,
+    pub timestamp: i64,
+}
+
+// Realized compounding performance versus the strategy's advertised APY
+#[event]
+pub struct CompoundingSummaryEvent {
+    pub subscription: Pubkey,
+    pub compound_count: u32,
+    pub total_compounded_amount: u64,
+    pub effective_apy_bps: u64,
+    pub advertised_apy_bps: u16,
+    pub timestamp: i64,
+}
+
+// Emitted when a staking subscription starts its unstake cooldown
+#[event]
+pub struct UnstakeInitiatedEvent {
+    pub subscription: Pubkey,
+    pub cooldown_ends_at: i64,
+    pub timestamp: i64,
+}
+
+// Emitted when a subscription exits before its lockup ends
+#[event]
+pub struct EmergencyExitEvent {
+    pub subscription: Pubkey,
+    pub payout: u64,
+    pub penalty: u64,
+    pub penalty_bps: u16,
+    pub timestamp: i64,
+}
+
 // Strategy Creation/Management Accounts
 #[derive(Accounts)]
 pub struct InitializeDeFiRegistry<'info> {
@@ -182,7 +423,7 @@ pub struct InitializeDeFiRegistry<'info> {
     #[account(
         init,
         payer = authority,
-        space = 8 + 32 + 8 + 2 + 32 + 1, // discriminator + authority + strategy_count + protocol_fee_bps + fee_recipient + bump
+        space = 8 + 32 + 8 + 2 + 32 + 4 * 9 + 1, // discriminator + authority + strategy_count + protocol_fee_bps + fee_recipient + category counters + bump
         seeds = [b"defi-registry"],
         bump
     )]
@@ -192,7 +433,7 @@ pub struct InitializeDeFiRegistry<'info> {
 }
 
 #[derive(Accounts)]
-#[instruction(id: String)]
+#[instruction(params: CreateDeFiStrategyParams)]
 pub struct CreateDeFiStrategy<'info> {
     #[account(mut)]
     pub creator: Signer<'info>,
@@ -207,13 +448,20 @@ pub struct CreateDeFiStrategy<'info> {
     #[account(
         init,
         payer = creator,
-        space = 8 + 64 + 100 + 255 + 1 + 1 + 100 + 2 + 8 + 4 + 32 + 50 + 1 + 500 + 2 + 8 + 8 + 8 + 1 + 200 + 1, // Approximate space
-        seeds = [b"defi-strategy", id.as_bytes()],
+        space = 8 + 64 + 100 + 255 + 1 + 1 + 100 + 2 + 8 + 4 + 32 + 50 + 1 + 500 + 2 + 8 + 8 + 8 + 1 + 200 + 2 + 1, // Approximate space
+        seeds = [b"defi-strategy", params.id.as_bytes()],
         bump
     )]
     pub strategy: Account<'info, DeFiStrategy>,
     
     pub system_program: Program<'info, System>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", creator.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
 }
 
 #[derive(Accounts)]
@@ -221,13 +469,27 @@ pub struct UpdateDeFiStrategy<'info> {
     #[account(mut)]
     pub creator: Signer<'info>,
     
+    #[account(
+        mut,
+        seeds = [b"defi-registry"],
+        bump = registry.bump
+    )]
+    pub registry: Account<'info, DeFiStrategyRegistry>,
+    
     #[account(
         mut,
         seeds = [b"defi-strategy", strategy.id.as_bytes()],
         bump = strategy.bump,
-        constraint = strategy.creator == creator.key() @ ErrorCode::Unauthorized
+        constraint = strategy.creator == creator.key() @ CommonError::Unauthorized
     )]
     pub strategy: Account<'info, DeFiStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", creator.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
 }
 
 #[derive(Accounts)]
@@ -238,7 +500,7 @@ pub struct VerifyDeFiStrategy<'info> {
     #[account(
         seeds = [b"defi-registry"],
         bump = registry.bump,
-        constraint = authority.key() == registry.authority @ ErrorCode::Unauthorized
+        constraint = authority.key() == registry.authority @ CommonError::Unauthorized
     )]
     pub registry: Account<'info, DeFiStrategyRegistry>,
     
@@ -255,35 +517,62 @@ pub struct SubscribeToDeFiStrategy<'info> {
         mut,
         seeds = [b"defi-strategy", strategy.id.as_bytes()],
         bump = strategy.bump,
-        constraint = strategy.status == StrategyStatus::Active @ ErrorCode::StrategyNotActive
+        constraint = strategy.status == StrategyStatus::Active @ CommonError::StrategyNotActive
     )]
     pub strategy: Account<'info, DeFiStrategy>,
     
     #[account(
         init,
         payer = user,
-        space = 8 + 32 + 32 + 200 + 8 + 8 + 8 + 8 + 1 + 200 + 100 + 1, // Approximate space
+        space = 8 + 32 + 32 + 200 + 8 + 8 + 8 + 8 + 1 + 200 + 100 + 6 + 1 + 4 + 8 + 8 + 8 + 2 + 8 + 32 + 1, // Approximate space
         seeds = [b"defi-subscription", strategy.key().as_ref(), user.key().as_ref()],
         bump
     )]
     pub subscription: Account<'info, DeFiSubscription>,
     
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = user_token_account.owner == user.key() @ CommonError::Unauthorized
+    )]
     pub user_token_account: Account<'info, TokenAccount>,
     
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = strategy_token_account.owner == strategy.key() @ CommonError::Unauthorized,
+        constraint = strategy_token_account.mint == user_token_account.mint @ CommonError::InvalidParameter
+    )]
     pub strategy_token_account: Account<'info, TokenAccount>,
     
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct InitiateUnstake<'info> {
+    pub user: Signer<'info>,
+    
+    #[account(
+        seeds = [b"defi-strategy", strategy.id.as_bytes()],
+        bump = strategy.bump
+    )]
+    pub strategy: Account<'info, DeFiStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"defi-subscription", strategy.key().as_ref(), user.key().as_ref()],
+        bump = subscription.bump,
+        constraint = subscription.user == user.key() @ CommonError::Unauthorized
+    )]
+    pub subscription: Account<'info, DeFiSubscription>,
+}
+
 #[derive(Accounts)]
 pub struct UnsubscribeFromDeFiStrategy<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
     
     #[account(
+        mut,
         seeds = [b"defi-strategy", strategy.id.as_bytes()],
         bump = strategy.bump
     )]
@@ -294,16 +583,74 @@ pub struct UnsubscribeFromDeFiStrategy<'info> {
         close = user,
         seeds = [b"defi-subscription", strategy.key().as_ref(), user.key().as_ref()],
         bump = subscription.bump,
-        constraint = subscription.user == user.key() @ ErrorCode::Unauthorized
+        constraint = subscription.user == user.key() @ CommonError::Unauthorized
     )]
     pub subscription: Account<'info, DeFiSubscription>,
     
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = user_token_account.owner == user.key() @ CommonError::Unauthorized
+    )]
     pub user_token_account: Account<'info, TokenAccount>,
     
+    #[account(
+        mut,
+        constraint = strategy_token_account.owner == strategy.key() @ CommonError::Unauthorized,
+        constraint = strategy_token_account.mint == user_token_account.mint @ CommonError::InvalidParameter
+    )]
+    pub strategy_token_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct EmergencyUnsubscribeFromDeFiStrategy<'info> {
     #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        seeds = [b"defi-registry"],
+        bump = registry.bump
+    )]
+    pub registry: Account<'info, DeFiStrategyRegistry>,
+    
+    #[account(
+        mut,
+        seeds = [b"defi-strategy", strategy.id.as_bytes()],
+        bump = strategy.bump
+    )]
+    pub strategy: Account<'info, DeFiStrategy>,
+    
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"defi-subscription", strategy.key().as_ref(), user.key().as_ref()],
+        bump = subscription.bump,
+        constraint = subscription.user == user.key() @ CommonError::Unauthorized
+    )]
+    pub subscription: Account<'info, DeFiSubscription>,
+    
+    #[account(
+        mut,
+        constraint = user_token_account.owner == user.key() @ CommonError::Unauthorized
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = strategy_token_account.owner == strategy.key() @ CommonError::Unauthorized,
+        constraint = strategy_token_account.mint == user_token_account.mint @ CommonError::InvalidParameter
+    )]
     pub strategy_token_account: Account<'info, TokenAccount>,
     
+    // The penalty goes to the registry's fee recipient
+    #[account(
+        mut,
+        constraint = fee_recipient_token_account.owner == registry.fee_recipient @ CommonError::Unauthorized,
+        constraint = fee_recipient_token_account.mint == user_token_account.mint @ CommonError::InvalidParameter
+    )]
+    pub fee_recipient_token_account: Account<'info, TokenAccount>,
+    
     pub token_program: Program<'info, Token>,
 }
 
@@ -322,16 +669,66 @@ pub struct HarvestRewards<'info> {
         mut,
         seeds = [b"defi-subscription", strategy.key().as_ref(), user.key().as_ref()],
         bump = subscription.bump,
-        constraint = subscription.user == user.key() @ ErrorCode::Unauthorized
+        constraint = subscription.user == user.key() @ CommonError::Unauthorized
     )]
     pub subscription: Account<'info, DeFiSubscription>,
     
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = reward_source.owner == user.key() @ CommonError::Unauthorized
+    )]
     pub reward_source: Account<'info, TokenAccount>,
     
-    #[account(mut)]
+    // Holds harvested rewards until they are claimed
+    #[account(
+        mut,
+        constraint = reward_vault.owner == strategy.key() @ CommonError::Unauthorized,
+        constraint = reward_vault.mint == reward_source.mint @ CommonError::InvalidParameter
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    pub user: Signer<'info>,
+    
+    #[account(
+        seeds = [b"defi-strategy", strategy.id.as_bytes()],
+        bump = strategy.bump
+    )]
+    pub strategy: Account<'info, DeFiStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"defi-subscription", strategy.key().as_ref(), user.key().as_ref()],
+        bump = subscription.bump,
+        constraint = subscription.user == user.key() @ CommonError::Unauthorized
+    )]
+    pub subscription: Account<'info, DeFiSubscription>,
+    
+    #[account(
+        mut,
+        constraint = reward_vault.owner == strategy.key() @ CommonError::Unauthorized
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = user_token_account.owner == user.key() @ CommonError::Unauthorized,
+        constraint = user_token_account.mint == reward_vault.mint @ CommonError::InvalidParameter
+    )]
     pub user_token_account: Account<'info, TokenAccount>,
     
+    // The performance fee goes to the strategy creator
+    #[account(
+        mut,
+        constraint = creator_token_account.owner == strategy.creator @ CommonError::Unauthorized,
+        constraint = creator_token_account.mint == reward_vault.mint @ CommonError::InvalidParameter
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+    
     pub token_program: Program<'info, Token>,
 }
 
@@ -350,9 +747,16 @@ pub struct RebalancePosition<'info> {
         mut,
         seeds = [b"defi-subscription", strategy.key().as_ref(), user.key().as_ref()],
         bump = subscription.bump,
-        constraint = subscription.user == user.key() @ ErrorCode::Unauthorized
+        constraint = subscription.user == user.key() @ CommonError::Unauthorized
     )]
     pub subscription: Account<'info, DeFiSubscription>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", user.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
 }
 
 #[derive(Accounts)]
@@ -363,7 +767,7 @@ pub struct SetupDCA<'info> {
     #[account(
         seeds = [b"defi-strategy", strategy.id.as_bytes()],
         bump = strategy.bump,
-        constraint = strategy.status == StrategyStatus::Active @ ErrorCode::StrategyNotActive
+        constraint = strategy.status == StrategyStatus::Active @ CommonError::StrategyNotActive
     )]
     pub strategy: Account<'info, DeFiStrategy>,
     
@@ -371,4 +775,1403 @@ pub struct SetupDCA<'info> {
         init,
         payer = user,
         space = 8 + 32 + 32 + 8 + 8 + 32 + 8 + 1 + 8 + 4 + 1, // Approximate space
-        seeds = [b"dca-config", strategy.key().as_ref(), user.key().
\ No newline at end of file
+        seeds = [b"dca-config", strategy.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub dca_config: Account<'info, DCAConfig>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ToggleDCA<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub strategy: Account<'info, DeFiStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"dca-config", strategy.key().as_ref(), user.key().as_ref()],
+        bump = dca_config.bump,
+        constraint = dca_config.user == user.key() @ CommonError::Unauthorized
+    )]
+    pub dca_config: Account<'info, DCAConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", user.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+}
+
+#[derive(Accounts)]
+pub struct CloseDCA<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub strategy: Account<'info, DeFiStrategy>,
+    
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"dca-config", strategy.key().as_ref(), user.key().as_ref()],
+        bump = dca_config.bump,
+        constraint = dca_config.user == user.key() @ CommonError::Unauthorized
+    )]
+    pub dca_config: Account<'info, DCAConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", user.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+}
+
+#[derive(Accounts)]
+pub struct SetHealthCheckParams<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub strategy: Account<'info, DeFiStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"defi-subscription", strategy.key().as_ref(), user.key().as_ref()],
+        bump = subscription.bump,
+        constraint = subscription.user == user.key() @ CommonError::Unauthorized
+    )]
+    pub subscription: Account<'info, DeFiSubscription>,
+}
+
+#[derive(Accounts)]
+pub struct CompoundingSummary<'info> {
+    pub strategy: Account<'info, DeFiStrategy>,
+    
+    #[account(
+        seeds = [b"defi-subscription", strategy.key().as_ref(), subscription.user.as_ref()],
+        bump = subscription.bump
+    )]
+    pub subscription: Account<'info, DeFiSubscription>,
+}
+
+#[derive(Accounts)]
+pub struct CheckPositionHealth<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"defi-registry"],
+        bump = registry.bump,
+        constraint = authority.key() == registry.authority @ CommonError::Unauthorized
+    )]
+    pub registry: Account<'info, DeFiStrategyRegistry>,
+    
+    pub strategy: Account<'info, DeFiStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"defi-subscription", strategy.key().as_ref(), subscription.user.as_ref()],
+        bump = subscription.bump
+    )]
+    pub subscription: Account<'info, DeFiSubscription>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", subscription.user.as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateLpValue<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"defi-registry"],
+        bump = registry.bump,
+        constraint = authority.key() == registry.authority @ CommonError::Unauthorized
+    )]
+    pub registry: Account<'info, DeFiStrategyRegistry>,
+    
+    pub strategy: Account<'info, DeFiStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"defi-subscription", strategy.key().as_ref(), subscription.user.as_ref()],
+        bump = subscription.bump
+    )]
+    pub subscription: Account<'info, DeFiSubscription>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", subscription.user.as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+}
+
+#[derive(Accounts)]
+pub struct CompoundPosition<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        seeds = [b"defi-strategy", strategy.id.as_bytes()],
+        bump = strategy.bump
+    )]
+    pub strategy: Account<'info, DeFiStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"defi-subscription", strategy.key().as_ref(), user.key().as_ref()],
+        bump = subscription.bump,
+        constraint = subscription.user == user.key() @ CommonError::Unauthorized
+    )]
+    pub subscription: Account<'info, DeFiSubscription>,
+    
+    // Accrued rewards waiting to be reinvested
+    #[account(
+        mut,
+        constraint = reward_token_account.owner == user.key() @ CommonError::Unauthorized
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+    
+    // Strategy-owned position account the rewards are reinvested into
+    #[account(
+        mut,
+        constraint = position_token_account.mint == reward_token_account.mint @ CommonError::InvalidParameter,
+        constraint = position_token_account.owner == strategy.key() @ CommonError::Unauthorized
+    )]
+    pub position_token_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[error_code(offset = 6400)]
+pub enum ErrorCode {
+    #[msg("Auto-compounding is disabled for this subscription")]
+    AutoCompoundDisabled,
+    
+    #[msg("Unstake cooldown has not finished")]
+    UnstakeCooldownActive,
+    
+    #[msg("Rebalance leg exceeds the strategy's slippage limit")]
+    RebalanceSlippageExceeded,
+    
+    #[msg("Rebalance changes the position's total value too much")]
+    RebalanceValueDrift,
+    
+    #[msg("Rebalance leg references a token the position does not hold")]
+    UnknownRebalanceToken,
+    
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+    
+    #[msg("Position still holds other tokens; rebalance them into the withdrawal mint first")]
+    OtherHoldingsRemain,
+    
+    #[msg("Liquidity position has no entry price ratio to measure impermanent loss against")]
+    LpEntryNotRecorded,
+    
+    #[msg("No harvested rewards to claim")]
+    NoPendingRewards,
+    
+    #[msg("Rewards are pending in a different mint")]
+    RewardMintMismatch,
+}
+
+// Create a new DeFi strategy
+pub fn create_defi_strategy(ctx: Context<CreateDeFiStrategy>, params: CreateDeFiStrategyParams) -> Result<()> {
+    let CreateDeFiStrategyParams {
+        id,
+        name,
+        description,
+        protocol_type,
+        risk_level,
+        token_allocations,
+        estimated_apy,
+        creator_name,
+        protocol_config,
+        fee_percentage,
+        min_investment,
+        tags,
+    } = params;
+    let strategy = &mut ctx.accounts.strategy;
+    let registry = &mut ctx.accounts.registry;
+    let now = Clock::get()?.unix_timestamp;
+    
+    // Validate inputs
+    require!(!id.is_empty() && id.len() <= 32, CommonError::InvalidParameter); // Used as a PDA seed
+    validate_token_allocations(&token_allocations)?;
+    
+    // Set strategy data
+    strategy.id = id;
+    strategy.name = name;
+    strategy.description = description;
+    strategy.protocol_type = protocol_type;
+    strategy.risk_level = risk_level;
+    strategy.token_allocations = token_allocations;
+    strategy.estimated_apy = estimated_apy;
+    strategy.tvl = 0;
+    strategy.user_count = 0;
+    strategy.creator = ctx.accounts.creator.key();
+    strategy.creator_name = creator_name;
+    strategy.verified = false;
+    strategy.protocol_config = protocol_config;
+    strategy.fee_percentage = fee_percentage;
+    strategy.min_investment = min_investment;
+    strategy.created_at = now;
+    strategy.updated_at = now;
+    strategy.status = StrategyStatus::Active;
+    strategy.tags = tags;
+    strategy.early_exit_penalty_bps = 0;
+    strategy.bump = *ctx.bumps.get("strategy").unwrap();
+    
+    // Increment strategy count in registry
+    registry.strategy_count = registry.strategy_count.checked_add(1).unwrap();
+    registry.adjust_category_counts(&strategy.protocol_type, &strategy.risk_level, true);
+    
+    emit_notification(
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
+        ctx.accounts.creator.key(),
+        Notification {
+            event_type: NotificationEventType::StrategyUpdated,
+            priority: NotificationPriority::Low,
+            title: "DeFi Strategy Created".to_string(),
+            message: format!("Your DeFi strategy '{}' has been created successfully", strategy.name),
+            data: Some(format!("{{\"strategyId\":\"{}\"}}", strategy.id)),
+            strategy_id: None,
+            token_address: None,
+            tx_signature: None,
+        },
+    );
+    
+    Ok(())
+}
+
+// Update an existing DeFi strategy
+pub fn update_defi_strategy(ctx: Context<UpdateDeFiStrategy>, params: UpdateDeFiStrategyParams) -> Result<()> {
+    let UpdateDeFiStrategyParams {
+        name,
+        description,
+        risk_level,
+        token_allocations,
+        estimated_apy,
+        fee_percentage,
+        min_investment,
+        status,
+        tags,
+        early_exit_penalty_bps,
+    } = params;
+    let strategy = &mut ctx.accounts.strategy;
+    let registry = &mut ctx.accounts.registry;
+    
+    // Deprecated strategies are not counted, so take this one out of its
+    // categories and add it back once category and status changes are applied
+    if strategy.status != StrategyStatus::Deprecated {
+        registry.adjust_category_counts(&strategy.protocol_type, &strategy.risk_level, false);
+    }
+    
+    if let Some(new_name) = name {
+        strategy.name = new_name;
+    }
+    
+    if let Some(new_desc) = description {
+        strategy.description = new_desc;
+    }
+    
+    if let Some(level) = risk_level {
+        strategy.risk_level = level;
+    }
+    
+    if let Some(allocations) = token_allocations {
+        validate_token_allocations(&allocations)?;
+        strategy.token_allocations = allocations;
+    }
+    
+    if let Some(apy) = estimated_apy {
+        strategy.estimated_apy = apy;
+    }
+    
+    if let Some(fee) = fee_percentage {
+        strategy.fee_percentage = fee;
+    }
+    
+    if let Some(min) = min_investment {
+        strategy.min_investment = min;
+    }
+    
+    if let Some(new_status) = status {
+        strategy.status = new_status;
+    }
+    
+    if let Some(new_tags) = tags {
+        strategy.tags = new_tags;
+    }
+    
+    if let Some(penalty_bps) = early_exit_penalty_bps {
+        require!(penalty_bps <= MAX_EARLY_EXIT_PENALTY_BPS, CommonError::InvalidParameter);
+        strategy.early_exit_penalty_bps = penalty_bps;
+    }
+    
+    if strategy.status != StrategyStatus::Deprecated {
+        registry.adjust_category_counts(&strategy.protocol_type, &strategy.risk_level, true);
+    }
+    
+    // Update the timestamp
+    strategy.updated_at = Clock::get()?.unix_timestamp;
+    
+    emit_notification(
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
+        ctx.accounts.creator.key(),
+        Notification {
+            event_type: NotificationEventType::StrategyUpdated,
+            priority: NotificationPriority::Low,
+            title: "DeFi Strategy Updated".to_string(),
+            message: format!("Your DeFi strategy '{}' has been updated successfully", strategy.name),
+            data: Some(format!("{{\"strategyId\":\"{}\"}}", strategy.id)),
+            strategy_id: None,
+            token_address: None,
+            tx_signature: None,
+        },
+    );
+    
+    Ok(())
+}
+
+// Pause or resume a DCA schedule
+pub fn toggle_dca(ctx: Context<ToggleDCA>, active: bool) -> Result<()> {
+    let dca_config = &mut ctx.accounts.dca_config;
+    let strategy = &ctx.accounts.strategy;
+    
+    dca_config.set_active(active, Clock::get()?.unix_timestamp);
+    
+    emit_notification(
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
+        ctx.accounts.user.key(),
+        Notification {
+            event_type: NotificationEventType::StrategyUpdated,
+            priority: NotificationPriority::Low,
+            title: if active { "DCA Resumed".to_string() } else { "DCA Paused".to_string() },
+            message: format!(
+                "Your DCA into '{}' has been {}",
+                strategy.name,
+                if active { "resumed" } else { "paused" }
+            ),
+            data: Some(format!("{{\"strategyId\":\"{}\", \"active\":{}}}", strategy.id, active)),
+            strategy_id: None,
+            token_address: Some(dca_config.source_token),
+            tx_signature: None,
+        },
+    );
+    
+    Ok(())
+}
+
+// Close a DCA schedule and reclaim its rent
+pub fn close_dca(ctx: Context<CloseDCA>) -> Result<()> {
+    let dca_config = &ctx.accounts.dca_config;
+    let strategy = &ctx.accounts.strategy;
+    
+    emit_notification(
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
+        ctx.accounts.user.key(),
+        Notification {
+            event_type: NotificationEventType::StrategyUpdated,
+            priority: NotificationPriority::Low,
+            title: "DCA Closed".to_string(),
+            message: format!(
+                "Your DCA into '{}' has been closed after {} executions",
+                strategy.name,
+                dca_config.execution_count
+            ),
+            data: Some(format!("{{\"strategyId\":\"{}\"}}", strategy.id)),
+            strategy_id: None,
+            token_address: Some(dca_config.source_token),
+            tx_signature: None,
+        },
+    );
+    
+    Ok(())
+}
+
+// Configure the health thresholds monitored for a subscription
+pub fn set_health_check_params(
+    ctx: Context<SetHealthCheckParams>,
+    params: HealthCheckParams
+) -> Result<()> {
+    // The liquidation band must sit below the warning band
+    require!(
+        params.liquidation_threshold < params.health_factor_threshold,
+        CommonError::InvalidParameter
+    );
+    
+    ctx.accounts.subscription.health_check = params;
+    
+    Ok(())
+}
+
+// Evaluate a position's health factor (in basis points) reported by a keeper
+pub fn check_position_health(
+    ctx: Context<CheckPositionHealth>,
+    health_factor_bps: u16
+) -> Result<()> {
+    let strategy = &ctx.accounts.strategy;
+    let subscription = &mut ctx.accounts.subscription;
+    let params = subscription.health_check.clone();
+    
+    // Healthy positions clear any pending deleverage and produce no notification
+    if params.is_healthy(health_factor_bps) {
+        subscription.deleverage_pending = false;
+        return Ok(());
+    }
+    
+    let critical = params.is_critical(health_factor_bps);
+    
+    if critical && params.auto_deleverage_enabled {
+        subscription.deleverage_pending = true;
+    }
+    
+    let notify = if critical {
+        params.critical_notification_enabled
+    } else {
+        params.warning_notification_enabled
+    };
+    
+    if notify {
+        emit_notification(
+            ctx.remaining_accounts,
+            &mut ctx.accounts.notification_counter,
+            subscription.user,
+            Notification {
+                event_type: NotificationEventType::HighExposureWarning,
+                priority: if critical { NotificationPriority::Critical } else { NotificationPriority::High },
+                title: if critical { "Position Near Liquidation".to_string() } else { "Position Health Warning".to_string() },
+                message: format!(
+                    "Your position in '{}' has a health factor of {}.{:02}",
+                    strategy.name,
+                    health_factor_bps / 1000,
+                    (health_factor_bps % 1000) / 10
+                ),
+                data: Some(format!(
+                    "{{\"strategyId\":\"{}\", \"healthFactorBps\":{}, \"deleveragePending\":{}}}",
+                    strategy.id,
+                    health_factor_bps,
+                    subscription.deleverage_pending
+                )),
+                strategy_id: None,
+                token_address: None,
+                tx_signature: None,
+            },
+        );
+    }
+    
+    Ok(())
+}
+
+// Record impermanent loss for a liquidity position from the pool's current reserves
+pub fn update_lp_value(
+    ctx: Context<UpdateLpValue>,
+    reserve_a: u64,
+    reserve_b: u64
+) -> Result<()> {
+    let strategy = &ctx.accounts.strategy;
+    let protection_enabled = match &strategy.protocol_config {
+        ProtocolConfig::LiquidityProviding { impermanent_loss_protection, .. } => *impermanent_loss_protection,
+        _ => return err!(CommonError::InvalidParameter),
+    };
+    
+    let price_ratio = lp_price_ratio(reserve_a, reserve_b)?;
+    let subscription = &mut ctx.accounts.subscription;
+    
+    // The HODL baseline is recorded at subscription time
+    require!(subscription.entry_price_ratio > 0, ErrorCode::LpEntryNotRecorded);
+    
+    let impermanent_loss_bps = calculate_impermanent_loss(subscription.entry_price_ratio, price_ratio);
+    subscription.impermanent_loss_bps = impermanent_loss_bps;
+    
+    if protection_enabled && impermanent_loss_bps > IL_WARNING_THRESHOLD_BPS {
+        emit_notification(
+            ctx.remaining_accounts,
+            &mut ctx.accounts.notification_counter,
+            subscription.user,
+            Notification {
+                event_type: NotificationEventType::HighExposureWarning,
+                priority: NotificationPriority::High,
+                title: "Impermanent Loss Warning".to_string(),
+                message: format!(
+                    "Your liquidity position in '{}' is down {}.{:02}% versus holding",
+                    strategy.name,
+                    impermanent_loss_bps / 100,
+                    impermanent_loss_bps % 100
+                ),
+                data: Some(format!(
+                    "{{\"strategyId\":\"{}\", \"impermanentLossBps\":{}}}",
+                    strategy.id,
+                    impermanent_loss_bps
+                )),
+                strategy_id: None,
+                token_address: None,
+                tx_signature: None,
+            },
+        );
+    }
+    
+    Ok(())
+}
+
+// When an unstake started at `now` may exit: the cooldown runs from now, but
+// never ends before the lockup does
+fn unstake_cooldown_end(
+    subscribed_at: i64,
+    now: i64,
+    lockup_period: Option<u64>,
+    unstake_cooldown: Option<u64>,
+) -> Result<i64> {
+    let cooldown_end = now
+        .checked_add(unstake_cooldown.unwrap_or(0) as i64)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    let lockup_end = subscribed_at
+        .checked_add(lockup_period.unwrap_or(0) as i64)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    Ok(cooldown_end.max(lockup_end))
+}
+
+// A staking position may only exit once initiate_unstake has run and its
+// cooldown has ended
+fn check_unstake_cooldown(cooldown_ends_at: i64, now: i64) -> Result<()> {
+    require!(
+        cooldown_ends_at != 0 && now >= cooldown_ends_at,
+        ErrorCode::UnstakeCooldownActive
+    );
+    Ok(())
+}
+
+// Pool price (reserve_b / reserve_a), scaled by PRICE_RATIO_SCALE
+fn lp_price_ratio(reserve_a: u64, reserve_b: u64) -> Result<u64> {
+    require!(reserve_a > 0 && reserve_b > 0, CommonError::InvalidParameter);
+    
+    u64::try_from(reserve_b as u128 * PRICE_RATIO_SCALE / reserve_a as u128)
+        .map_err(|_| error!(ErrorCode::ArithmeticOverflow))
+}
+
+// Impermanent loss of a 50/50 pool after its price ratio moves from entry to current:
+// IL = 1 - 2 * sqrt(r) / (1 + r), where r = current / entry
+fn calculate_impermanent_loss(entry_price_ratio: u64, current_price_ratio: u64) -> u16 {
+    if entry_price_ratio == 0 {
+        return 0;
+    }
+    
+    let r = current_price_ratio as f64 / entry_price_ratio as f64;
+    let loss = 1.0 - 2.0 * r.sqrt() / (1.0 + r);
+    
+    (loss.max(0.0) * 10000.0).round() as u16
+}
+
+// Token allocation percentages must add up to exactly 100
+fn validate_token_allocations(token_allocations: &[TokenAllocation]) -> Result<()> {
+    require!(!token_allocations.is_empty(), CommonError::InvalidParameter);
+    
+    let total: u16 = token_allocations
+        .iter()
+        .map(|a| a.allocation_percentage as u16)
+        .sum();
+    require!(total == 100, CommonError::InvalidParameter);
+    
+    Ok(())
+}
+
+// Report realized compounding performance for a subscription
+pub fn compounding_summary(ctx: Context<CompoundingSummary>) -> Result<()> {
+    let strategy = &ctx.accounts.strategy;
+    let subscription = &ctx.accounts.subscription;
+    let now = Clock::get()?.unix_timestamp;
+    
+    emit!(CompoundingSummaryEvent {
+        subscription: subscription.key(),
+        compound_count: subscription.compound_count,
+        total_compounded_amount: subscription.total_compounded_amount,
+        effective_apy_bps: subscription.effective_apy_bps(now),
+        advertised_apy_bps: strategy.estimated_apy,
+        timestamp: now,
+    });
+    
+    Ok(())
+}
+
+// Reinvest accrued rewards into an auto-compounding position
+pub fn compound_position(ctx: Context<CompoundPosition>) -> Result<()> {
+    require!(ctx.accounts.subscription.auto_compound, ErrorCode::AutoCompoundDisabled);
+    
+    let amount = ctx.accounts.reward_token_account.amount;
+    let reward_mint = ctx.accounts.reward_token_account.mint;
+    
+    // Yield farms only reinvest once rewards clear their threshold
+    let threshold = match &ctx.accounts.strategy.protocol_config {
+        ProtocolConfig::YieldFarming { reinvest_threshold, .. } => *reinvest_threshold,
+        _ => 0,
+    };
+    if amount == 0 || amount < threshold {
+        msg!("Accrued rewards {} below reinvest threshold {}, skipping", amount, threshold);
+        return Ok(());
+    }
+    
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.reward_token_account.to_account_info(),
+                to: ctx.accounts.position_token_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+    
+    compound_rewards(&mut ctx.accounts.subscription, reward_mint, amount, Clock::get()?.unix_timestamp)
+}
+
+// Move the rewards in reward_source into the strategy's reward vault and
+// accrue them as pending. They are paid out by claim_rewards, apart from
+// the position's value.
+pub fn harvest_rewards(ctx: Context<HarvestRewards>) -> Result<()> {
+    let amount = ctx.accounts.reward_source.amount;
+    if amount == 0 {
+        msg!("No rewards to harvest");
+        return Ok(());
+    }
+    
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.reward_source.to_account_info(),
+                to: ctx.accounts.reward_vault.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+    
+    let pending = ctx.accounts.subscription.accrue_rewards(
+        ctx.accounts.reward_source.mint,
+        amount,
+        Clock::get()?.unix_timestamp,
+    )?;
+    msg!("Harvested {} rewards, {} pending", amount, pending);
+    
+    Ok(())
+}
+
+// Pay out the pending rewards, less the strategy's performance fee, which
+// goes to the strategy creator
+pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
+    let (user_reward, fee) = ctx.accounts.subscription.take_pending_rewards(
+        &ctx.accounts.reward_vault.mint,
+        ctx.accounts.strategy.fee_percentage,
+    )?;
+    
+    transfer_from_strategy(
+        &ctx.accounts.strategy,
+        &ctx.accounts.token_program,
+        &ctx.accounts.reward_vault,
+        &ctx.accounts.user_token_account,
+        user_reward,
+    )?;
+    transfer_from_strategy(
+        &ctx.accounts.strategy,
+        &ctx.accounts.token_program,
+        &ctx.accounts.reward_vault,
+        &ctx.accounts.creator_token_account,
+        fee,
+    )?;
+    
+    msg!("Claimed {} rewards ({} performance fee)", user_reward, fee);
+    Ok(())
+}
+
+// Record a rebalance of the position's token holdings. Each leg is valued at
+// the position's recorded prices, so both mints must already be held. A leg
+// whose minimum output is worth less than its input by more than the
+// protocol's max slippage is rejected, as is a rebalance whose total value
+// drifts by more than that.
+pub fn rebalance_position(ctx: Context<RebalancePosition>, legs: Vec<RebalanceLeg>) -> Result<()> {
+    require!(!legs.is_empty() && legs.len() <= MAX_REBALANCE_LEGS, CommonError::InvalidParameter);
+    
+    let max_slippage_bps = rebalance_slippage_bps(&ctx.accounts.strategy.protocol_config);
+    let subscription = &mut ctx.accounts.subscription;
+    let (value_before, value_after) = apply_rebalance_legs(&mut subscription.investment_values, &legs, max_slippage_bps)?;
+    
+    let strategy = &ctx.accounts.strategy;
+    emit_notification(
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
+        subscription.user,
+        Notification {
+            event_type: NotificationEventType::PortfolioRebalanced,
+            priority: NotificationPriority::Low,
+            title: "Position Rebalanced".to_string(),
+            message: format!(
+                "Your position in '{}' was rebalanced across {} swaps",
+                strategy.name,
+                legs.len()
+            ),
+            data: Some(format!(
+                "{{\"strategyId\":\"{}\", \"legs\":[{}], \"valueBefore\":{}, \"valueAfter\":{}}}",
+                strategy.id,
+                legs.iter()
+                    .map(|leg| format!(
+                        "{{\"from\":\"{}\", \"to\":\"{}\", \"amount\":{}, \"minOut\":{}}}",
+                        leg.from_mint,
+                        leg.to_mint,
+                        leg.amount,
+                        leg.min_out
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(","),
+                value_before,
+                value_after
+            )),
+            strategy_id: None,
+            token_address: None,
+            tx_signature: None,
+        },
+    );
+    
+    Ok(())
+}
+
+// Apply rebalance legs to a position's holdings, checking each leg's slippage
+// and the total value drift against `max_slippage_bps`. Returns the total
+// value before and after.
+fn apply_rebalance_legs(
+    investment_values: &mut [TokenInvestment],
+    legs: &[RebalanceLeg],
+    max_slippage_bps: u16,
+) -> Result<(u128, u128)> {
+    let max_slippage_bps = max_slippage_bps as u128;
+    let value_before: u128 = investment_values.iter().map(|i| i.usd_value as u128).sum();
+    
+    for leg in legs {
+        require!(leg.amount > 0 && leg.from_mint != leg.to_mint, CommonError::InvalidParameter);
+        
+        let (from_amount, from_value) = investment_holding(investment_values, &leg.from_mint)?;
+        let (to_amount, to_value) = investment_holding(investment_values, &leg.to_mint)?;
+        require!(leg.amount <= from_amount, CommonError::InvalidParameter);
+        
+        // Value both sides at the recorded price per unit
+        let value_in = leg.amount as u128 * from_value as u128 / from_amount as u128;
+        let value_out = leg.min_out as u128 * to_value as u128 / to_amount as u128;
+        if value_out < value_in {
+            let slippage_bps = (value_in - value_out) * 10000 / value_in.max(1);
+            require!(slippage_bps <= max_slippage_bps, ErrorCode::RebalanceSlippageExceeded);
+        }
+        
+        for investment in investment_values.iter_mut() {
+            if investment.mint == leg.from_mint {
+                investment.amount -= leg.amount;
+                investment.usd_value = investment.usd_value.saturating_sub(value_in as u64);
+            } else if investment.mint == leg.to_mint {
+                investment.amount = investment.amount.checked_add(leg.min_out).ok_or(ErrorCode::ArithmeticOverflow)?;
+                investment.usd_value = investment.usd_value.checked_add(value_out as u64).ok_or(ErrorCode::ArithmeticOverflow)?;
+            }
+        }
+    }
+    
+    let value_after: u128 = investment_values.iter().map(|i| i.usd_value as u128).sum();
+    let drift_bps = value_before.abs_diff(value_after) * 10000 / value_before.max(1);
+    require!(drift_bps <= max_slippage_bps, ErrorCode::RebalanceValueDrift);
+    
+    Ok((value_before, value_after))
+}
+
+// Max slippage a protocol allows for rebalance swaps, in basis points
+fn rebalance_slippage_bps(protocol_config: &ProtocolConfig) -> u16 {
+    match protocol_config {
+        ProtocolConfig::YieldFarming { max_slippage, .. } => *max_slippage as u16,
+        ProtocolConfig::LiquidityProviding { max_slippage, .. } => *max_slippage,
+        _ => DEFAULT_REBALANCE_SLIPPAGE_BPS,
+    }
+}
+
+// Amount and USD value held of `mint`; both must be non-zero to price it
+fn investment_holding(investment_values: &[TokenInvestment], mint: &Pubkey) -> Result<(u64, u64)> {
+    let investment = investment_values
+        .iter()
+        .find(|i| i.mint == *mint && i.amount > 0 && i.usd_value > 0)
+        .ok_or(ErrorCode::UnknownRebalanceToken)?;
+    Ok((investment.amount, investment.usd_value))
+}
+
+// Fold rewards into a subscription's value and track the compounding
+// Credit reinvested reward tokens to the subscription's holding of `mint`.
+// The amount is in the reward token's units, so current_value (lamports) is
+// left for the next valuation to pick up.
+fn compound_rewards(
+    subscription: &mut Account<DeFiSubscription>,
+    mint: Pubkey,
+    amount: u64,
+    now: i64,
+) -> Result<()> {
+    let new_holding = subscription.record_compound(mint, amount, now)?;
+    
+    emit!(CompoundedEvent {
+        subscription: subscription.key(),
+        mint,
+        amount,
+        new_holding,
+        compound_count: subscription.compound_count,
+        timestamp: now,
+    });
+    
+    Ok(())
+}
+
+// Start the unstake cooldown for a staking subscription
+// Subscribe with a deposit of user_token_account's mint. Liquidity providing
+// strategies also take the pool's reserves at entry, which become the
+// baseline update_lp_value measures impermanent loss against.
+pub fn subscribe_to_defi_strategy(
+    ctx: Context<SubscribeToDeFiStrategy>,
+    amount: u64,
+    usd_value: u64,
+    entry_reserves: Option<(u64, u64)>
+) -> Result<()> {
+    let strategy = &mut ctx.accounts.strategy;
+    let mint = ctx.accounts.user_token_account.mint;
+    let now = Clock::get()?.unix_timestamp;
+    
+    require!(amount > 0 && amount >= strategy.min_investment, CommonError::InvalidParameter);
+    require!(
+        strategy.token_allocations.iter().any(|allocation| allocation.mint == mint),
+        CommonError::InvalidParameter
+    );
+    
+    let entry_price_ratio = match (&strategy.protocol_config, entry_reserves) {
+        (ProtocolConfig::LiquidityProviding { .. }, Some((reserve_a, reserve_b))) => lp_price_ratio(reserve_a, reserve_b)?,
+        (ProtocolConfig::LiquidityProviding { .. }, None) => return err!(ErrorCode::LpEntryNotRecorded),
+        _ => 0,
+    };
+    
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.user_token_account.to_account_info(),
+                to: ctx.accounts.strategy_token_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+    
+    let subscription = &mut ctx.accounts.subscription;
+    subscription.user = ctx.accounts.user.key();
+    subscription.strategy = strategy.key();
+    subscription.investment_values = vec![TokenInvestment { mint, amount, usd_value }];
+    subscription.initial_investment_value = amount;
+    subscription.current_value = amount;
+    subscription.last_harvest_time = now;
+    subscription.subscribed_at = now;
+    subscription.auto_compound = false;
+    subscription.active_position_ids = Vec::new();
+    subscription.custom_settings = HashMap::new();
+    subscription.health_check = HealthCheckParams {
+        health_factor_threshold: 0,
+        liquidation_threshold: 0,
+        warning_notification_enabled: false,
+        critical_notification_enabled: false,
+        auto_deleverage_enabled: false,
+    };
+    subscription.deleverage_pending = false;
+    subscription.compound_count = 0;
+    subscription.total_compounded_amount = 0;
+    subscription.cooldown_ends_at = 0;
+    subscription.entry_price_ratio = entry_price_ratio;
+    subscription.impermanent_loss_bps = 0;
+    subscription.pending_rewards = 0;
+    subscription.pending_reward_mint = Pubkey::default();
+    subscription.bump = *ctx.bumps.get("subscription").unwrap();
+    
+    strategy.tvl = strategy.tvl.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+    strategy.user_count = strategy.user_count.checked_add(1).ok_or(ErrorCode::ArithmeticOverflow)?;
+    
+    Ok(())
+}
+
+pub fn initiate_unstake(ctx: Context<InitiateUnstake>) -> Result<()> {
+    let (lockup_period, unstake_cooldown) = match &ctx.accounts.strategy.protocol_config {
+        ProtocolConfig::Staking { lockup_period, unstake_cooldown, .. } => (*lockup_period, *unstake_cooldown),
+        _ => return err!(CommonError::InvalidParameter),
+    };
+    
+    let subscription = &mut ctx.accounts.subscription;
+    require!(subscription.cooldown_ends_at == 0, CommonError::InvalidParameter);
+    
+    let now = Clock::get()?.unix_timestamp;
+    subscription.cooldown_ends_at = unstake_cooldown_end(
+        subscription.subscribed_at,
+        now,
+        lockup_period,
+        unstake_cooldown,
+    )?;
+    
+    emit!(UnstakeInitiatedEvent {
+        subscription: subscription.key(),
+        cooldown_ends_at: subscription.cooldown_ends_at,
+        timestamp: now,
+    });
+    
+    Ok(())
+}
+
+// Withdraw a subscription's position and close it. Only the holding in
+// user_token_account's mint is paid out, so the position must hold nothing
+// else; rebalance other tokens into it first. Unclaimed rewards are
+// forfeited, so claim them first.
+pub fn unsubscribe_from_defi_strategy(ctx: Context<UnsubscribeFromDeFiStrategy>) -> Result<()> {
+    let strategy = &mut ctx.accounts.strategy;
+    let subscription = &ctx.accounts.subscription;
+    
+    // Staking positions can only exit once their cooldown has run out. This
+    // keys on the protocol config, as initiate_unstake does.
+    if matches!(strategy.protocol_config, ProtocolConfig::Staking { .. }) {
+        check_unstake_cooldown(subscription.cooldown_ends_at, Clock::get()?.unix_timestamp)?;
+    }
+    
+    let amount = exit_amount(subscription, &ctx.accounts.user_token_account.mint)?;
+    log_forfeited_rewards(subscription);
+    
+    strategy.tvl = strategy.tvl.saturating_sub(subscription.current_value);
+    strategy.user_count = strategy.user_count.saturating_sub(1);
+    
+    transfer_from_strategy(
+        strategy,
+        &ctx.accounts.token_program,
+        &ctx.accounts.strategy_token_account,
+        &ctx.accounts.user_token_account,
+        amount,
+    )
+}
+
+// Withdraw and close a subscription without waiting for its lockup or
+// unstake cooldown. The strategy's early exit penalty, pro-rated to the
+// lockup time remaining, goes to the registry's fee recipient. Unclaimed
+// rewards are forfeited, as with unsubscribe_from_defi_strategy.
+pub fn emergency_unsubscribe_from_defi_strategy(ctx: Context<EmergencyUnsubscribeFromDeFiStrategy>) -> Result<()> {
+    let strategy = &mut ctx.accounts.strategy;
+    let subscription = &ctx.accounts.subscription;
+    let now = Clock::get()?.unix_timestamp;
+    
+    let amount = exit_amount(subscription, &ctx.accounts.user_token_account.mint)?;
+    log_forfeited_rewards(subscription);
+    let lockup_period = match &strategy.protocol_config {
+        ProtocolConfig::Staking { lockup_period, .. } => *lockup_period,
+        _ => None,
+    };
+    let penalty_bps = early_exit_penalty_bps(
+        strategy.early_exit_penalty_bps,
+        subscription.subscribed_at,
+        lockup_period,
+        now,
+    );
+    let (payout, penalty) = split_early_exit(amount, penalty_bps);
+    
+    strategy.tvl = strategy.tvl.saturating_sub(subscription.current_value);
+    strategy.user_count = strategy.user_count.saturating_sub(1);
+    
+    transfer_from_strategy(
+        strategy,
+        &ctx.accounts.token_program,
+        &ctx.accounts.strategy_token_account,
+        &ctx.accounts.user_token_account,
+        payout,
+    )?;
+    transfer_from_strategy(
+        strategy,
+        &ctx.accounts.token_program,
+        &ctx.accounts.strategy_token_account,
+        &ctx.accounts.fee_recipient_token_account,
+        penalty,
+    )?;
+    
+    emit!(EmergencyExitEvent {
+        subscription: subscription.key(),
+        payout,
+        penalty,
+        penalty_bps,
+        timestamp: now,
+    });
+    
+    Ok(())
+}
+
+// Holding of `mint` a subscription exits with. Only that holding is paid
+// out, so the position must hold nothing else.
+fn exit_amount(subscription: &DeFiSubscription, mint: &Pubkey) -> Result<u64> {
+    require!(
+        subscription.investment_values.iter().all(|i| i.mint == *mint || i.amount == 0),
+        ErrorCode::OtherHoldingsRemain
+    );
+    Ok(subscription.investment_values
+        .iter()
+        .find(|i| i.mint == *mint)
+        .map(|i| i.amount)
+        .unwrap_or(0))
+}
+
+// Rewards left pending when a subscription closes stay in the reward vault
+fn log_forfeited_rewards(subscription: &DeFiSubscription) {
+    if subscription.pending_rewards > 0 {
+        msg!("Forfeited {} unclaimed rewards", subscription.pending_rewards);
+    }
+}
+
+// Early exit penalty in basis points: the strategy's full penalty at
+// subscription, falling linearly to zero when the lockup ends
+fn early_exit_penalty_bps(max_penalty_bps: u16, subscribed_at: i64, lockup_period: Option<u64>, now: i64) -> u16 {
+    let lockup = lockup_period.unwrap_or(0) as i128;
+    let remaining = subscribed_at as i128 + lockup - now as i128;
+    if lockup == 0 || remaining <= 0 {
+        return 0;
+    }
+    
+    (max_penalty_bps as i128 * remaining.min(lockup) / lockup) as u16
+}
+
+// Split an exiting amount into the user's payout and the penalty
+fn split_early_exit(amount: u64, penalty_bps: u16) -> (u64, u64) {
+    let penalty = (amount as u128 * penalty_bps.min(10000) as u128 / 10000) as u64;
+    (amount - penalty, penalty)
+}
+
+// Pay `amount` out of a strategy-owned token account, signing as the strategy
+fn transfer_from_strategy<'info>(
+    strategy: &Account<'info, DeFiStrategy>,
+    token_program: &Program<'info, Token>,
+    from: &Account<'info, TokenAccount>,
+    to: &Account<'info, TokenAccount>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    
+    let bump = [strategy.bump];
+    let seeds = &[b"defi-strategy".as_ref(), strategy.id.as_bytes(), &bump];
+    let signer = &[&seeds[..]];
+    
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: from.to_account_info(),
+                to: to.to_account_info(),
+                authority: strategy.to_account_info(),
+            },
+            signer,
+        ),
+        amount,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn token_allocations_must_sum_to_100() {
+        let allocation = |allocation_percentage| TokenAllocation {
+            mint: Pubkey::new_unique(),
+            allocation_percentage,
+        };
+        
+        assert!(validate_token_allocations(&[allocation(60), allocation(40)]).is_ok());
+        assert_eq!(
+            validate_token_allocations(&[allocation(60), allocation(30)]).unwrap_err(),
+            error!(CommonError::InvalidParameter)
+        );
+        assert_eq!(
+            validate_token_allocations(&[allocation(100), allocation(100), allocation(100)]).unwrap_err(),
+            error!(CommonError::InvalidParameter)
+        );
+        assert_eq!(validate_token_allocations(&[]).unwrap_err(), error!(CommonError::InvalidParameter));
+    }
+    
+    #[test]
+    fn entry_price_ratio_comes_from_reserves() {
+        assert_eq!(lp_price_ratio(1_000, 2_000).unwrap(), 2 * PRICE_RATIO_SCALE as u64);
+        assert_eq!(lp_price_ratio(0, 2_000).unwrap_err(), error!(CommonError::InvalidParameter));
+        assert_eq!(lp_price_ratio(1, u64::MAX).unwrap_err(), error!(ErrorCode::ArithmeticOverflow));
+    }
+    
+    #[test]
+    fn impermanent_loss_grows_with_price_divergence() {
+        let entry = lp_price_ratio(1_000, 1_000).unwrap();
+        
+        // A 4x move loses 20% versus holding
+        assert_eq!(calculate_impermanent_loss(entry, lp_price_ratio(1_000, 4_000).unwrap()), 2000);
+        // A stable pair has no loss
+        assert_eq!(calculate_impermanent_loss(entry, lp_price_ratio(1_000, 1_001).unwrap()), 0);
+    }
+    
+    #[test]
+    fn health_factor_bands() {
+        let params = HealthCheckParams {
+            health_factor_threshold: 1200,
+            liquidation_threshold: 1050,
+            warning_notification_enabled: true,
+            critical_notification_enabled: true,
+            auto_deleverage_enabled: true,
+        };
+        
+        assert!(params.is_healthy(1200));
+        assert!(!params.is_healthy(1199));
+        assert!(!params.is_critical(1050));
+        assert!(params.is_critical(1049));
+    }
+    
+    #[test]
+    fn category_counts_follow_strategy_lifecycle() {
+        let mut registry = DeFiStrategyRegistry::default();
+        
+        registry.adjust_category_counts(&ProtocolType::Staking, &RiskLevel::Conservative, true);
+        registry.adjust_category_counts(&ProtocolType::Staking, &RiskLevel::Aggressive, true);
+        assert_eq!(registry.staking_count, 2);
+        assert_eq!(registry.conservative_count, 1);
+        assert_eq!(registry.aggressive_count, 1);
+        
+        registry.adjust_category_counts(&ProtocolType::Staking, &RiskLevel::Conservative, false);
+        assert_eq!(registry.staking_count, 1);
+        assert_eq!(registry.conservative_count, 0);
+        
+        // Counts never wrap below zero
+        registry.adjust_category_counts(&ProtocolType::Lending, &RiskLevel::Conservative, false);
+        assert_eq!(registry.lending_count, 0);
+        assert_eq!(registry.conservative_count, 0);
+    }
+    
+    #[test]
+    fn unstaking_waits_for_the_cooldown_and_lockup() {
+        // Cooldown from the unstake request
+        assert_eq!(unstake_cooldown_end(0, 1_000, None, Some(500)).unwrap(), 1_500);
+        // A longer lockup holds the exit back further
+        assert_eq!(unstake_cooldown_end(0, 1_000, Some(86_400), Some(500)).unwrap(), 86_400);
+        assert_eq!(
+            unstake_cooldown_end(0, i64::MAX, None, Some(1)).unwrap_err(),
+            error!(ErrorCode::ArithmeticOverflow)
+        );
+        
+        assert!(check_unstake_cooldown(1_500, 1_500).is_ok());
+        assert_eq!(
+            check_unstake_cooldown(1_500, 1_499).unwrap_err(),
+            error!(ErrorCode::UnstakeCooldownActive)
+        );
+        // Exiting without initiating an unstake is refused
+        assert_eq!(
+            check_unstake_cooldown(0, 1_000).unwrap_err(),
+            error!(ErrorCode::UnstakeCooldownActive)
+        );
+    }
+    
+    fn test_holdings(mints: &[Pubkey]) -> Vec<TokenInvestment> {
+        // Every token is worth 10 per unit
+        vec![
+            TokenInvestment { mint: mints[0], amount: 1_000, usd_value: 10_000 },
+            TokenInvestment { mint: mints[1], amount: 500, usd_value: 5_000 },
+            TokenInvestment { mint: mints[2], amount: 100, usd_value: 1_000 },
+        ]
+    }
+    
+    #[test]
+    fn two_leg_rebalances_update_the_holdings() {
+        let mints = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut holdings = test_holdings(&mints);
+        let legs = [
+            RebalanceLeg { from_mint: mints[0], to_mint: mints[1], amount: 100, min_out: 99 },
+            RebalanceLeg { from_mint: mints[1], to_mint: mints[2], amount: 50, min_out: 50 },
+        ];
+        
+        assert_eq!(apply_rebalance_legs(&mut holdings, &legs, 100).unwrap(), (16_000, 15_990));
+        let amounts: Vec<(u64, u64)> = holdings.iter().map(|i| (i.amount, i.usd_value)).collect();
+        assert_eq!(amounts, vec![(900, 9_000), (549, 5_490), (150, 1_500)]);
+    }
+    
+    #[test]
+    fn rebalance_legs_over_the_slippage_limit_are_rejected() {
+        let mints = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut holdings = test_holdings(&mints);
+        
+        // 100 units worth 1_000 for at least 98 worth 980 is 2% slippage
+        let legs = [RebalanceLeg { from_mint: mints[0], to_mint: mints[1], amount: 100, min_out: 98 }];
+        assert_eq!(
+            apply_rebalance_legs(&mut holdings, &legs, DEFAULT_REBALANCE_SLIPPAGE_BPS).unwrap_err(),
+            error!(ErrorCode::RebalanceSlippageExceeded)
+        );
+        
+        let unknown = [RebalanceLeg { from_mint: mints[0], to_mint: Pubkey::new_unique(), amount: 100, min_out: 100 }];
+        assert_eq!(
+            apply_rebalance_legs(&mut holdings, &unknown, DEFAULT_REBALANCE_SLIPPAGE_BPS).unwrap_err(),
+            error!(ErrorCode::UnknownRebalanceToken)
+        );
+    }
+    
+    #[test]
+    fn paused_dca_resumes_from_now() {
+        let mut dca_config = DCAConfig {
+            user: Pubkey::new_unique(),
+            strategy: Pubkey::new_unique(),
+            amount: 1_000,
+            frequency: 3_600,
+            source_token: Pubkey::new_unique(),
+            next_execution: 1_000,
+            active: true,
+            last_execution: 0,
+            execution_count: 3,
+            bump: 255,
+        };
+        assert!(dca_config.is_due(1_000));
+        
+        dca_config.set_active(false, 1_000);
+        assert!(!dca_config.is_due(1_000));
+        assert!(!dca_config.is_due(50_000));
+        
+        // Missed executions are skipped rather than fired in a burst
+        dca_config.set_active(true, 50_000);
+        assert_eq!(dca_config.next_execution, 50_000);
+        assert!(dca_config.is_due(50_000));
+        
+        // A schedule already in the future is left alone
+        dca_config.set_active(false, 50_000);
+        dca_config.next_execution = 60_000;
+        dca_config.set_active(true, 50_000);
+        assert_eq!(dca_config.next_execution, 60_000);
+        assert!(!dca_config.is_due(50_000));
+    }
+    
+    fn test_subscription(mint: Pubkey) -> DeFiSubscription {
+        DeFiSubscription {
+            user: Pubkey::new_unique(),
+            strategy: Pubkey::new_unique(),
+            investment_values: vec![TokenInvestment { mint, amount: 1_000, usd_value: 10_000 }],
+            initial_investment_value: 1_000_000,
+            current_value: 1_000_000,
+            last_harvest_time: 0,
+            subscribed_at: 0,
+            auto_compound: true,
+            active_position_ids: vec![],
+            custom_settings: HashMap::new(),
+            health_check: HealthCheckParams {
+                health_factor_threshold: 1200,
+                liquidation_threshold: 1050,
+                warning_notification_enabled: true,
+                critical_notification_enabled: true,
+                auto_deleverage_enabled: false,
+            },
+            deleverage_pending: false,
+            compound_count: 0,
+            total_compounded_amount: 0,
+            cooldown_ends_at: 0,
+            entry_price_ratio: 0,
+            impermanent_loss_bps: 0,
+            pending_rewards: 0,
+            pending_reward_mint: Pubkey::default(),
+            bump: 255,
+        }
+    }
+    
+    #[test]
+    fn compounding_is_tracked_and_annualized() {
+        let mint = Pubkey::new_unique();
+        let mut subscription = test_subscription(mint);
+        
+        assert_eq!(subscription.record_compound(mint, 100, 1_000).unwrap(), 1_100);
+        assert_eq!(subscription.record_compound(mint, 200, 2_000).unwrap(), 1_300);
+        assert_eq!(subscription.record_compound(mint, 300, 3_000).unwrap(), 1_600);
+        assert_eq!(subscription.compound_count, 3);
+        assert_eq!(subscription.total_compounded_amount, 600);
+        assert_eq!(subscription.last_harvest_time, 3_000);
+        
+        // 10% growth over half a year is 20% a year
+        let half_year = 365 * 86400 / 2;
+        subscription.current_value = 1_100_000;
+        assert_eq!(subscription.effective_apy_bps(half_year), 2000);
+        assert_eq!(subscription.effective_apy_bps(0), 0);
+    }
+    
+    #[test]
+    fn early_exit_penalty_is_prorated_over_the_lockup() {
+        let day = 86_400;
+        let lockup = Some(10 * day as u64);
+        
+        // Halfway through the lockup, half of the 10% penalty is charged
+        let penalty_bps = early_exit_penalty_bps(1_000, 0, lockup, 5 * day);
+        assert_eq!(penalty_bps, 500);
+        assert_eq!(split_early_exit(20_000, penalty_bps), (19_000, 1_000));
+        
+        assert_eq!(early_exit_penalty_bps(1_000, 0, lockup, 0), 1_000);
+        assert_eq!(early_exit_penalty_bps(1_000, day, lockup, 6 * day), 500);
+    }
+    
+    #[test]
+    fn exits_after_the_lockup_pay_no_penalty() {
+        let day = 86_400;
+        
+        assert_eq!(early_exit_penalty_bps(1_000, 0, Some(10 * day as u64), 10 * day), 0);
+        assert_eq!(early_exit_penalty_bps(1_000, 0, Some(10 * day as u64), 30 * day), 0);
+        assert_eq!(early_exit_penalty_bps(1_000, 0, None, 0), 0);
+        assert_eq!(split_early_exit(20_000, 0), (20_000, 0));
+    }
+    
+    #[test]
+    fn harvested_rewards_accrue_until_claimed_once() {
+        let reward_mint = Pubkey::new_unique();
+        let mut subscription = test_subscription(Pubkey::new_unique());
+        
+        assert_eq!(subscription.accrue_rewards(reward_mint, 400, 1_000).unwrap(), 400);
+        assert_eq!(subscription.accrue_rewards(reward_mint, 600, 2_000).unwrap(), 1_000);
+        assert_eq!(subscription.last_harvest_time, 2_000);
+        
+        // Harvesting leaves the position's value alone
+        assert_eq!(subscription.current_value, 1_000_000);
+        assert_eq!(subscription.investment_values[0].amount, 1_000);
+        
+        // The combined total is paid once, less a 2% performance fee
+        assert_eq!(subscription.take_pending_rewards(&reward_mint, 200).unwrap(), (980, 20));
+        assert_eq!(subscription.pending_rewards, 0);
+        assert_eq!(
+            subscription.take_pending_rewards(&reward_mint, 200).unwrap_err(),
+            error!(ErrorCode::NoPendingRewards)
+        );
+    }
+    
+    #[test]
+    fn pending_rewards_stay_in_one_mint() {
+        let reward_mint = Pubkey::new_unique();
+        let other_mint = Pubkey::new_unique();
+        let mut subscription = test_subscription(Pubkey::new_unique());
+        
+        subscription.accrue_rewards(reward_mint, 400, 1_000).unwrap();
+        assert_eq!(
+            subscription.accrue_rewards(other_mint, 100, 2_000).unwrap_err(),
+            error!(ErrorCode::RewardMintMismatch)
+        );
+        assert_eq!(
+            subscription.take_pending_rewards(&other_mint, 0).unwrap_err(),
+            error!(ErrorCode::RewardMintMismatch)
+        );
+        
+        // Once claimed, rewards may accrue in another mint
+        assert_eq!(subscription.take_pending_rewards(&reward_mint, 0).unwrap(), (400, 0));
+        assert_eq!(subscription.accrue_rewards(other_mint, 100, 2_000).unwrap(), 100);
+    }
+}
@@ -0,0 +1,54 @@
+// contracts/sonic-agent/src/errors.rs
+
+use anchor_lang::prelude::*;
+
+// Error variants shared by every Anchor module in this program.
+//
+// These keep the default 6000 range so clients see the same code for e.g.
+// `Unauthorized` no matter which instruction raised it. Module-specific
+// variants stay in each module's own `ErrorCode`, which starts at the
+// offset in its `#[error_code(offset = ..)]` so codes never collide. Keep
+// this table in step with those attributes:
+//
+//   6000-6099  CommonError
+//   6100-6299  sonic_agent (lib.rs)
+//   6300-6399  strategy_manager
+//   6400-6499  defi_strategy_manager
+//   6500-6599  price_alerts
+//
+// ai_trading and defi_strategy are separate programs with their own codes.
+#[error_code]
+pub enum CommonError {
+    #[msg("Unauthorized access")]
+    Unauthorized,
+    
+    #[msg("Invalid parameter")]
+    InvalidParameter,
+    
+    #[msg("Strategy not active")]
+    StrategyNotActive,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{defi_strategy_manager, price_alerts, strategy_manager};
+    
+    #[test]
+    fn module_error_codes_stay_in_their_ranges() {
+        assert_eq!(u32::from(CommonError::Unauthorized), 6000);
+        assert!(u32::from(CommonError::StrategyNotActive) < 6100);
+        
+        assert_eq!(u32::from(crate::ErrorCode::InvalidName), 6100);
+        assert!(u32::from(crate::ErrorCode::InvalidPoolReserve) < 6300);
+        
+        assert_eq!(u32::from(strategy_manager::ErrorCode::BelowMinimumInvestment), 6300);
+        assert!(u32::from(strategy_manager::ErrorCode::InvalidReferrer) < 6400);
+        
+        assert_eq!(u32::from(defi_strategy_manager::ErrorCode::AutoCompoundDisabled), 6400);
+        assert!(u32::from(defi_strategy_manager::ErrorCode::LpEntryNotRecorded) < 6500);
+        
+        assert_eq!(u32::from(price_alerts::ErrorCode::MaxAlertsExceeded), 6500);
+        assert!(u32::from(price_alerts::ErrorCode::InvalidEmaPeriods) < 6600);
+    }
+}
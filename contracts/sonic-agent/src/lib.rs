@@ -6,14 +6,28 @@
 //! various trading strategies.
 
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_spl::token::{Mint, TokenAccount};
+use serde::Deserialize;
+use solana_program::hash::{hash, hashv};
+use solana_program::instruction::{get_stack_height, AccountMeta, Instruction, TRANSACTION_LEVEL_STACK_HEIGHT};
 use solana_program::program::{invoke, invoke_signed};
 use solana_program::system_instruction;
+use solana_program::sysvar::instructions::get_instruction_relative;
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
-use std::mem::size_of;
+
+pub mod ai_trading;
+pub mod defi_strategy_manager;
+pub mod errors;
+pub mod notification_events;
+pub mod price_alerts;
+pub mod strategy_manager;
+
+pub use errors::CommonError;
+use notification_events::{emit_notification, Notification, NotificationCounter, NotificationEventType, NotificationPriority};
 
 // Declare program ID
-declare_id!("Sonicxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx");
+declare_id!("SonicAgent111111111111111111111111111111111");
 
 /// Risk profile types
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
@@ -29,6 +43,25 @@ pub enum AgentStatus {
     Inactive,
     Active,
     Paused,
+    CloseOnly, // Only trades back into the quote mint are allowed
+}
+
+/// Trade direction relative to the agent's quote mint
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TradeDirection {
+    Buy, // Pays the quote mint to open or add to a position
+    Sell, // Receives the quote mint to close or reduce a position
+    Rebalance, // Swaps between two non-quote tokens
+}
+
+/// Why a trade was made
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TradeReason {
+    StrategySignal,
+    Rebalance,
+    StopLoss,
+    ManualOverride,
+    DollarCostAverage,
 }
 
 /// Strategy types
@@ -50,6 +83,37 @@ pub struct GasSettings {
     pub max_retries: u8,
 }
 
+/// Default priority fee and compute units a new agent starts with
+pub struct GasProfileDefaults {
+    pub priority_fee: u64, // Micro-lamports per compute unit; 0 leaves it to the client
+    pub compute_units: u32,
+}
+
+impl GasProfileDefaults {
+    pub const CONSERVATIVE: Self = Self { priority_fee: 0, compute_units: 200_000 };
+    pub const MODERATE: Self = Self { priority_fee: 10_000, compute_units: 300_000 };
+    pub const AGGRESSIVE: Self = Self { priority_fee: 100_000, compute_units: 400_000 };
+    
+    /// Defaults for a risk profile; riskier agents pay more to land trades first
+    pub fn for_risk_profile(risk_profile: &RiskProfile) -> Self {
+        match risk_profile {
+            RiskProfile::Conservative => Self::CONSERVATIVE,
+            RiskProfile::Moderate => Self::MODERATE,
+            RiskProfile::Aggressive => Self::AGGRESSIVE,
+        }
+    }
+    
+    /// Gas settings a new agent starts with
+    pub fn gas_settings(&self) -> GasSettings {
+        GasSettings {
+            priority_fee: self.priority_fee,
+            compute_units: self.compute_units,
+            retry_on_fail: true,
+            max_retries: 3,
+        }
+    }
+}
+
 /// Trading strategy
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct Strategy {
@@ -60,8 +124,38 @@ pub struct Strategy {
     pub parameters: Vec<u8>, // JSON encoded strategy parameters
     pub last_executed_at: i64,
     pub execution_count: u64,
+    pub interval_secs: u64, // 0 means the strategy is not scheduled
+    pub next_run_at: i64,
+}
+
+impl Strategy {
+    /// Largest serialized size of a strategy (50-byte name, 1024-byte parameters)
+    pub const MAX_SIZE: usize = 16 + (4 + 50) + 1 + 1 + (4 + 1024) + 8 + 8 + 8 + 8;
+    
+    /// Whether an active, scheduled strategy has reached its next run
+    pub fn is_due(&self, now: i64) -> bool {
+        self.is_active && self.interval_secs > 0 && self.next_run_at <= now
+    }
+    
+    /// Record a run at `now` and schedule the next one. A late run skips the
+    /// slots it missed rather than leaving the strategy due for each of them.
+    pub fn mark_executed(&mut self, now: i64) -> Result<()> {
+        require!(self.interval_secs > 0, ErrorCode::StrategyNotScheduled);
+        require!(self.next_run_at <= now, ErrorCode::StrategyNotDue);
+        
+        let interval = self.interval_secs as i64;
+        let missed = (now - self.next_run_at) / interval;
+        self.next_run_at += (missed + 1) * interval;
+        self.last_executed_at = now;
+        self.execution_count += 1;
+        
+        Ok(())
+    }
 }
 
+/// Upper bound on `max_strategies` for any agent
+pub const MAX_STRATEGIES: u8 = 32;
+
 /// Trading rule
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct TradingRule {
@@ -71,12 +165,36 @@ pub struct TradingRule {
     pub allowed_tokens: Vec<Pubkey>,
     pub excluded_tokens: Vec<Pubkey>,
     pub max_slippage_bps: u16,
+    pub min_pool_liquidity: u64, // 0 disables the liquidity check
+    pub min_trade_amount: u64, // Smallest input amount recorded; 0 disables the dust filter
+}
+
+impl TradingRule {
+    /// Serialized size with both token lists full
+    pub const MAX_SIZE: usize = 16 + 8 + 1 + 2 * (4 + 32 * MAX_TOKEN_RULES) + 2 + 8 + 8;
 }
 
+/// Maximum agent name length in bytes
+pub const MAX_AGENT_NAME_LEN: usize = 50;
+
+/// Maximum agent description length in bytes
+pub const MAX_AGENT_DESCRIPTION_LEN: usize = 200;
+
+/// Maximum number of target allocations
+pub const MAX_ALLOCATIONS: usize = 20;
+
+/// Maximum number of allocation groups (baskets)
+pub const MAX_ALLOCATION_GROUPS: usize = 5;
+
+/// Maximum number of programs allowed to record trades for an agent
+pub const MAX_AUTHORIZED_PROGRAMS: usize = 5;
+
 /// Agent Configuration
 #[account]
 pub struct AgentConfig {
     pub owner: Pubkey,
+    pub creator: Pubkey, // Wallet that created the agent
+    pub agent_id: [u8; 16], // Client-chosen id the PDA is derived from
     pub name: String,
     pub description: String,
     pub risk_profile: RiskProfile,
@@ -85,15 +203,101 @@ pub struct AgentConfig {
     pub updated_at: i64,
     pub auto_rebalance: bool,
     pub rebalance_threshold_bps: u16,
+    pub min_rebalance_interval_secs: u64, // Minimum time between recorded rebalances
+    pub last_rebalance_at: i64,
     pub auto_trade: bool,
     pub trading_budget: u64,
     pub strategies: Vec<Strategy>,
+    pub max_strategies: u8, // The account is sized to hold this many strategies
     pub trading_rules: TradingRule,
     pub gas_settings: GasSettings,
     pub target_allocations: Vec<TokenAllocation>,
+    pub allocation_groups: Vec<AllocationGroup>, // Baskets that allocations with a group_id belong to
     pub total_executed_trades: u64,
     pub total_trade_volume: u64,
+    pub trade_day: i64, // Unix day index of trades_today
+    pub trades_today: u8,
+    pub approvers: Vec<Pubkey>, // When non-empty, rule changes need approvals
+    pub required_approvals: u8,
+    pub quote_mint: Pubkey, // Asset positions are bought and sold against; default disables cost basis
+    pub max_drawdown_bps: u16, // Auto-pause below this drawdown from peak; 0 disables
+    pub max_single_token_bps: u16, // Largest share of the portfolio one token may reach by buying; 10000 disables
+    pub max_consecutive_failures: u16, // Auto-pause after this many failed trades in a row; 0 disables
+    pub authorized_programs: Vec<Pubkey>, // Programs that may record trades by direct CPI, besides the owner
+    pub last_trade_hash: [u8; 32], // Head of the trade hash chain
     pub bump: u8,
+    pub schema_version: u8, // Layout version; 0 on accounts created before versioning
+}
+
+impl AgentConfig {
+    /// Serialized account size, including the discriminator, with every
+    /// vector filled to the given capacity
+    pub fn space(max_strategies: usize, max_allocations: usize, name_len: usize, desc_len: usize) -> usize {
+        8 // discriminator
+            + 32 + 32 + 16 // owner, creator, agent_id
+            + 4 + name_len
+            + 4 + desc_len
+            + 1 + 1 // risk_profile, status
+            + 8 + 8 // created_at, updated_at
+            + 1 + 2 // auto_rebalance, rebalance_threshold_bps
+            + 8 + 8 // min_rebalance_interval_secs, last_rebalance_at
+            + 1 + 8 // auto_trade, trading_budget
+            + 4 + max_strategies * Strategy::MAX_SIZE
+            + 1 // max_strategies
+            + TradingRule::MAX_SIZE
+            + 8 + 4 + 1 + 1 // gas_settings
+            + 4 + max_allocations * TokenAllocation::SIZE
+            + 4 + MAX_ALLOCATION_GROUPS * AllocationGroup::SIZE
+            + 8 + 8 // total_executed_trades, total_trade_volume
+            + 8 + 1 // trade_day, trades_today
+            + 4 + 32 * MAX_RULE_APPROVERS + 1 // approvers, required_approvals
+            + 32 + 2 // quote_mint, max_drawdown_bps
+            + 2 + 2 // max_single_token_bps, max_consecutive_failures
+            + 4 + 32 * MAX_AUTHORIZED_PROGRAMS
+            + 32 // last_trade_hash
+            + 1 // bump
+            + 1 // schema_version
+    }
+    
+    /// Fail when every one of the agent's `max_strategies` slots is taken
+    pub fn check_strategy_slot(&self) -> Result<()> {
+        require!(
+            self.strategies.len() < self.max_strategies as usize,
+            ErrorCode::TooManyStrategies
+        );
+        Ok(())
+    }
+    
+    /// Whether `key` is the agent's current owner
+    pub fn is_owned_by(&self, key: &Pubkey) -> bool {
+        self.owner == *key
+    }
+    
+    /// Whether the agent's own status lets it trade; close-only agents may
+    /// still sell back into the quote mint
+    pub fn accepts_trades(&self) -> bool {
+        matches!(self.status, AgentStatus::Active | AgentStatus::CloseOnly)
+    }
+    
+    /// Hand the agent and its stats to `new_owner`, returning the previous owner
+    pub fn transfer_ownership(&mut self, agent_stats: &mut AgentStats, new_owner: Pubkey, now: i64) -> Result<Pubkey> {
+        let previous_owner = self.owner;
+        require!(new_owner != previous_owner, CommonError::InvalidParameter);
+        
+        self.owner = new_owner;
+        self.updated_at = now;
+        agent_stats.owner = new_owner;
+        
+        Ok(previous_owner)
+    }
+    
+    /// Record a rebalance at `now`, failing until `min_rebalance_interval_secs`
+    /// has passed since the last one
+    pub fn record_rebalance(&mut self, now: i64) -> Result<()> {
+        check_rebalance_interval(self, now)?;
+        self.last_rebalance_at = now;
+        Ok(())
+    }
 }
 
 /// Token allocation for portfolio balancing
@@ -102,6 +306,47 @@ pub struct TokenAllocation {
     pub mint: Pubkey,
     pub target_percentage: u16, // Basis points (e.g., 2500 = 25%)
     pub max_deviation_bps: u16, // Maximum allowed deviation in basis points
+    pub decimals: u8, // Mint decimals, checked against the mint account
+    pub usd_price: u64, // Price snapshot in micro-USD per whole token
+    pub group_id: u16, // Basket this allocation belongs to; 0 if none
+}
+
+impl TokenAllocation {
+    pub const SIZE: usize = 32 + 2 + 2 + 1 + 8 + 2;
+}
+
+/// A basket of allocations held as one portfolio weight.
+/// Its members' targets are portfolio weights that sum to the group's target.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct AllocationGroup {
+    pub group_id: u16, // Non-zero
+    pub target_bps: u16,
+    pub max_deviation_bps: u16,
+}
+
+impl AllocationGroup {
+    pub const SIZE: usize = 2 + 2 + 2;
+}
+
+/// One allocation's current weight against its target
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct AllocationDeviation {
+    pub mint: Pubkey,
+    pub group_id: u16,
+    pub current_bps: u16,
+    pub target_bps: u16,
+    pub deviation_bps: u16,
+    pub needs_rebalance: bool,
+}
+
+/// One allocation group's current weight against its target
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct GroupDeviation {
+    pub group_id: u16,
+    pub current_bps: u16,
+    pub target_bps: u16,
+    pub deviation_bps: u16,
+    pub needs_rebalance: bool,
 }
 
 /// Trade action record
@@ -119,10 +364,93 @@ pub struct TradeAction {
     pub transaction_signature: [u8; 64],
     pub success: bool,
     pub price_impact_bps: u16,
+    pub reason: String, // Optional free-text detail
+    pub reason_code: TradeReason,
+    pub prev_trade_hash: [u8; 32], // Chain head before this trade; see `trade_chain_hash`
+    pub direction: TradeDirection,
+    pub bump: u8,
+}
+
+impl TradeAction {
+    /// Serialized account size, including the discriminator, for a trade
+    /// whose `reason` is `reason_len` bytes
+    pub fn space(reason_len: usize) -> usize {
+        8 // discriminator
+            + 32 + 32 + 16 // agent, owner, strategy_id
+            + 32 + 32 // input_mint, output_mint
+            + 8 + 8 + 2 // input_amount, output_amount, slippage_bps
+            + 8 + 64 // executed_at, transaction_signature
+            + 1 + 2 // success, price_impact_bps
+            + 4 + reason_len
+            + 1 + 32 + 1 // reason_code, prev_trade_hash, direction
+            + 1 // bump
+    }
+}
+
+/// A trade reported to `record_trade`, `record_rebalance_trade` or `validate_trade`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct RecordTradeParams {
+    pub strategy_id: [u8; 16],
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub input_amount: u64,
+    pub output_amount: u64,
+    pub slippage_bps: u16,
+    pub transaction_signature: [u8; 64],
+    pub success: bool,
+    pub price_impact_bps: u16,
+    pub reason: String, // Optional free-text detail
+    pub attempt: u8, // 0 for the first try, counting up on each retry
+    pub expected_output: u64,
+    pub client_trade_id: [u8; 16], // Part of the trade account's address
+    pub fee_amount: u64, // Network and protocol fees paid, in lamports
+}
+
+/// A swap for `execute_and_record_trade` to route and record
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ExecuteAndRecordTradeParams {
+    pub strategy_id: [u8; 16],
+    pub input_amount: u64,
+    pub expected_output: u64,
+    pub swap_data: Vec<u8>, // Route program instruction data
+    pub reason: String,
+    pub direction: Option<TradeDirection>, // See `trade_direction`
+    pub reason_code: TradeReason,
+    pub client_trade_id: [u8; 16], // Part of the trade account's address, as in `record_trade`
+}
+
+/// Input for one trade in a batch
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct TradeRecordInput {
+    pub strategy_id: [u8; 16],
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub input_amount: u64,
+    pub output_amount: u64,
+    pub slippage_bps: u16,
+    pub transaction_signature: [u8; 64],
+    pub success: bool,
+    pub price_impact_bps: u16,
     pub reason: String,
     pub bump: u8,
+    pub expected_output: u64,
+    pub direction: Option<TradeDirection>, // See `trade_direction`
+    pub reason_code: TradeReason,
+    pub fee_amount: u64, // Network and protocol fees paid, in lamports
+    pub attempt: u8, // 0 for the first try, counting up on each retry
+    pub client_trade_id: [u8; 16], // Part of the trade account's address, as in `record_trade`
 }
 
+/// Maximum number of trades in a single batch
+pub const MAX_BATCH_TRADES: usize = 8;
+
+/// Highest priority fee an agent may set, in micro-lamports per compute unit.
+/// At the 1.4M compute unit maximum this costs at most 0.014 SOL per trade.
+pub const MAX_PRIORITY_FEE: u64 = 10_000_000;
+
+/// Maximum number of mints in each of the allowed and excluded token lists
+pub const MAX_TOKEN_RULES: usize = 20;
+
 /// Agent statistics and performance metrics
 #[account]
 pub struct AgentStats {
@@ -137,7 +465,186 @@ pub struct AgentStats {
     pub created_at: i64,
     pub last_updated_at: i64,
     pub performance_data: Vec<PerformancePoint>,
+    pub cost_basis: Vec<CostBasisEntry>,
+    pub peak_portfolio_value: u64,
+    pub retry_count: u64, // Trades recorded on a retry attempt
+    pub consecutive_failures: u16, // Failed trades since the last success
+    pub valuation_mint: Pubkey, // Currency portfolio values and P/L are denominated in
+    pub valuation_decimals: u8, // Decimals of valuation_mint
     pub bump: u8,
+    pub schema_version: u8, // Layout version; 0 on accounts created before versioning
+}
+
+impl AgentStats {
+    /// Serialized account size, including the discriminator, keeping
+    /// `retention` performance points and a full cost basis table
+    pub fn space(retention: usize) -> usize {
+        8 // discriminator
+            + 32 + 32 // agent, owner
+            + 8 * 6 // trade counts, volume, fees paid, profit_loss
+            + 8 + 8 // created_at, last_updated_at
+            + 4 + retention * PerformancePoint::SIZE
+            + 4 + MAX_COST_BASIS_ENTRIES * CostBasisEntry::SIZE
+            + 8 + 8 // peak_portfolio_value, retry_count
+            + 2 // consecutive_failures
+            + 32 + 1 // valuation_mint, valuation_decimals
+            + 1 // bump
+            + 1 // schema_version
+    }
+    
+    /// Share of recorded trades that succeeded, in basis points
+    pub fn win_rate_bps(&self) -> u16 {
+        // Guard against division by zero when no trades have been recorded
+        if self.total_trades > 0 {
+            (self.successful_trades as u128 * 10000 / self.total_trades as u128) as u16
+        } else {
+            0
+        }
+    }
+    
+    /// Average input amount of a successful trade
+    pub fn avg_trade_volume(&self) -> u64 {
+        self.total_volume.checked_div(self.successful_trades).unwrap_or(0)
+    }
+    
+    /// Summed daily P/L of the performance points in the last `seconds`
+    pub fn profit_loss_since(&self, now: i64, seconds: i64) -> i64 {
+        self.performance_data
+            .iter()
+            .filter(|p| p.timestamp >= now - seconds)
+            .map(|p| p.daily_profit_loss)
+            .sum()
+    }
+    
+    /// Add `trades` recorded trades, `successful` of which moved `volume`, to
+    /// the running totals
+    pub fn record_trades(&mut self, trades: u64, successful: u64, volume: u64) -> Result<()> {
+        self.total_trades = self.total_trades
+            .checked_add(trades)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.successful_trades = self.successful_trades
+            .checked_add(successful)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.failed_trades = self.failed_trades
+            .checked_add(trades - successful)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_volume = self.total_volume
+            .checked_add(volume)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+    
+    /// Count a trade recorded on `attempt`; any attempt after the first is a retry
+    pub fn record_attempt(&mut self, attempt: u8) -> Result<()> {
+        if attempt > 0 {
+            self.retry_count = self.retry_count
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+        Ok(())
+    }
+    
+    /// Zero the trade and performance totals, keeping `created_at`, the cost
+    /// basis and the valuation settings
+    pub fn reset(&mut self, now: i64) {
+        self.total_trades = 0;
+        self.successful_trades = 0;
+        self.failed_trades = 0;
+        self.total_volume = 0;
+        self.total_fees_paid = 0;
+        self.profit_loss = 0;
+        self.performance_data = vec![];
+        self.peak_portfolio_value = 0;
+        self.last_updated_at = now;
+    }
+    
+    /// Add a recorded trade's network and protocol fees to the running total
+    pub fn record_fee(&mut self, fee_amount: u64) -> Result<()> {
+        self.total_fees_paid = self.total_fees_paid
+            .checked_add(fee_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+    
+    /// Reported figures must be expressed in the valuation mint's units
+    pub fn check_valuation_units(&self, valuation_decimals: u8) -> Result<()> {
+        require!(
+            valuation_decimals == self.valuation_decimals,
+            ErrorCode::ValuationUnitsMismatch
+        );
+        Ok(())
+    }
+    
+    /// Convert a micro-USD value into valuation mint units at `valuation_price`
+    /// (micro-USD per whole token). A price that rounded down to zero can't
+    /// value anything.
+    pub fn valuation_amount(&self, usd_value: u128, valuation_price: u64) -> Result<u64> {
+        require!(valuation_price > 0, ErrorCode::InvalidValuationPrice);
+        usd_value
+            .checked_mul(10u128.pow(self.valuation_decimals as u32))
+            .and_then(|scaled| u64::try_from(scaled / valuation_price as u128).ok())
+            .ok_or_else(|| error!(ErrorCode::ArithmeticOverflow))
+    }
+}
+
+/// Current layout version of `AgentConfig` and `AgentStats`.
+///
+/// v2 appended the `schema_version` byte itself, and `migrate_agent_v2` only
+/// upgrades accounts written at the otherwise identical layout just before
+/// it. Accounts from earlier releases, including the original v1 layout, had
+/// fields inserted in the middle since and cannot be migrated in place: close
+/// them and create the agent again. Fields added from now on go after
+/// `schema_version`, together with a migration that grows the account and
+/// sets their defaults.
+pub const AGENT_SCHEMA_VERSION: u8 = 2;
+
+/// Bytes `migrate_agent_v2` adds to an account with no room for the fields
+/// introduced in v2
+pub const AGENT_SCHEMA_V2_GROWTH: usize = 1;
+
+/// Maximum number of token balances `snapshot_portfolio` values at once
+pub const MAX_SNAPSHOT_TOKENS: usize = 8;
+
+/// Number of performance points kept in `AgentStats`
+pub const PERFORMANCE_RETENTION: usize = 30;
+
+/// Agent state returned by `get_agent_snapshot`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct AgentSnapshot {
+    pub agent: Pubkey,
+    pub status: AgentStatus,
+    pub trading_budget: u64,
+    pub num_strategies: u8,
+    pub total_trades: u64,
+    pub profit_loss: i64,
+    pub peak_portfolio_value: u64, // In the agent's valuation units
+}
+
+impl AgentSnapshot {
+    pub const SIZE: usize = 32 + 1 + 8 + 1 + 8 + 8 + 8;
+    
+    /// Snapshot of the agent at `agent` from its config and stats
+    pub fn new(agent: Pubkey, agent_config: &AgentConfig, agent_stats: &AgentStats) -> Self {
+        Self {
+            agent,
+            status: agent_config.status.clone(),
+            trading_budget: agent_config.trading_budget,
+            num_strategies: agent_config.strategies.len() as u8,
+            total_trades: agent_stats.total_trades,
+            profit_loss: agent_stats.profit_loss,
+            peak_portfolio_value: agent_stats.peak_portfolio_value,
+        }
+    }
+}
+
+// Return data is capped at 1024 bytes
+const _: () = assert!(AgentSnapshot::SIZE <= solana_program::program::MAX_RETURN_DATA);
+
+/// Compute budget returned by `get_compute_budget`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ComputeBudgetRequest {
+    pub compute_unit_limit: u32, // For SetComputeUnitLimit
+    pub compute_unit_price: u64, // Micro-lamports for SetComputeUnitPrice; 0 leaves it to the client
 }
 
 /// Performance data point
@@ -148,115 +655,287 @@ pub struct PerformancePoint {
     pub daily_profit_loss: i64, // Can be negative
 }
 
+impl PerformancePoint {
+    pub const SIZE: usize = 8 + 8 + 8;
+}
+
+/// Average-cost position in one token, with cost in quote units
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct CostBasisEntry {
+    pub mint: Pubkey,
+    pub total_amount: u64,
+    pub total_cost: u64,
+}
+
+impl CostBasisEntry {
+    pub const SIZE: usize = 32 + 8 + 8;
+}
+
+/// Maximum number of tokens with a tracked cost basis
+pub const MAX_COST_BASIS_ENTRIES: usize = 10;
+
+/// Ring buffer of an agent's most recent trades, in insertion order
+#[account]
+pub struct AgentTradeIndex {
+    pub agent: Pubkey,
+    pub head: u16, // Next slot to overwrite once the ring is full
+    pub trade_nonce: u64, // Nonce the next recorded trade will get
+    pub entries: Vec<TradeIndexEntry>,
+    pub bump: u8,
+}
+
+/// Trade index entry
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct TradeIndexEntry {
+    pub nonce: u64,
+    pub trade: Pubkey,
+    pub executed_at: i64,
+    pub success: bool,
+}
+
+/// Maximum number of entries kept in an agent's trade index
+pub const TRADE_INDEX_CAPACITY: usize = 64;
+
+impl AgentTradeIndex {
+    pub const SPACE: usize = 8 + 32 + 2 + 8 + 4 + TRADE_INDEX_CAPACITY * (8 + 32 + 8 + 1) + 1;
+    
+    /// Append a trade under the next nonce, overwriting the oldest entry once
+    /// the ring is full
+    pub fn push(&mut self, trade: Pubkey, executed_at: i64, success: bool) {
+        let entry = TradeIndexEntry {
+            nonce: self.trade_nonce,
+            trade,
+            executed_at,
+            success,
+        };
+        self.trade_nonce += 1;
+        
+        if self.entries.len() < TRADE_INDEX_CAPACITY {
+            self.entries.push(entry);
+        } else {
+            self.entries[self.head as usize] = entry;
+        }
+        self.head = ((self.head as usize + 1) % TRADE_INDEX_CAPACITY) as u16;
+    }
+    
+    /// Entries sorted by `executed_at` descending. Trades with equal timestamps
+    /// keep newest-first insertion order.
+    pub fn recent(&self, successful_only: bool) -> Vec<TradeIndexEntry> {
+        let len = self.entries.len();
+        if len == 0 {
+            return vec![];
+        }
+        
+        // Walk the ring from the newest entry back to the oldest
+        let newest = (self.head as usize + len - 1) % len;
+        let mut trades: Vec<TradeIndexEntry> = (0..len)
+            .map(|i| self.entries[(newest + len - i) % len].clone())
+            .filter(|entry| !successful_only || entry.success)
+            .collect();
+        
+        // sort_by_key is stable
+        trades.sort_by_key(|t| std::cmp::Reverse(t.executed_at));
+        trades
+    }
+    
+    /// Up to `limit` entries with a nonce below `before_nonce`, newest first.
+    /// Nonces are sequential, so the page is read straight out of the ring.
+    pub fn page(&self, before_nonce: u64, limit: usize) -> Vec<TradeIndexEntry> {
+        let len = self.entries.len() as u64;
+        let oldest = self.trade_nonce - len;
+        let end = before_nonce.min(self.trade_nonce);
+        if end <= oldest {
+            return vec![];
+        }
+        
+        // The entry for nonce n sits at slot n % capacity
+        (oldest..end)
+            .rev()
+            .take(limit)
+            .map(|nonce| self.entries[(nonce % TRADE_INDEX_CAPACITY as u64) as usize].clone())
+            .collect()
+    }
+}
+
+/// Maximum number of approvers for trading rule changes
+pub const MAX_RULE_APPROVERS: usize = 5;
+
+/// A set of optional trading rule changes
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct TradingRulesUpdate {
+    pub max_amount_per_trade: Option<u64>,
+    pub max_trades_per_day: Option<u8>,
+    pub allowed_tokens: Option<Vec<Pubkey>>,
+    pub excluded_tokens: Option<Vec<Pubkey>>,
+    pub max_slippage_bps: Option<u16>,
+    pub min_pool_liquidity: Option<u64>,
+    pub min_trade_amount: Option<u64>,
+}
+
+impl TradingRulesUpdate {
+    pub const MAX_SIZE: usize = 9 + 2 + 2 * (1 + 4 + 32 * MAX_TOKEN_RULES) + 3 + 9 + 9;
+}
+
+/// A set of optional agent configuration changes; see `update_agent_config`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct AgentConfigUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub risk_profile: Option<RiskProfile>,
+    pub auto_rebalance: Option<bool>,
+    pub rebalance_threshold_bps: Option<u16>,
+    pub auto_trade: Option<bool>,
+    pub trading_budget: Option<u64>,
+    pub quote_mint: Option<Pubkey>,
+    pub max_drawdown_bps: Option<u16>,
+    pub max_single_token_bps: Option<u16>,
+    pub min_rebalance_interval_secs: Option<u64>,
+    pub max_consecutive_failures: Option<u16>,
+}
+
+/// Identity and capacity of a new agent; see `initialize_agent`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct NewAgentParams {
+    pub agent_id: [u8; 16], // Client-chosen id the agent PDA is derived from
+    pub name: String,
+    pub description: String,
+    pub risk_profile: RiskProfile,
+    pub max_strategies: u8,
+}
+
+/// Trading rule change awaiting approval on a multi-approver agent
+#[account]
+pub struct PendingRulesChange {
+    pub agent: Pubkey,
+    pub update: TradingRulesUpdate,
+    pub rules_hash: [u8; 32], // Hash of the serialized update that approvers sign off on
+    pub approvals: Vec<Pubkey>,
+    pub proposed_at: i64,
+    pub bump: u8,
+}
+
+impl PendingRulesChange {
+    pub const SPACE: usize = 8 + 32 + TradingRulesUpdate::MAX_SIZE + 32 + 4 + 32 * MAX_RULE_APPROVERS + 8 + 1;
+}
+
+/// Program-wide configuration controlled by the program admin
+#[account]
+pub struct ProgramConfig {
+    pub admin: Pubkey,
+    pub globally_paused: bool, // Emergency switch that halts trading for all agents
+    pub bump: u8,
+}
+
+impl ProgramConfig {
+    /// Whether any agent may trade, regardless of its own status
+    pub fn trading_allowed(&self) -> bool {
+        !self.globally_paused
+    }
+}
+
+/// Metaplex token metadata program
+pub const METADATA_PROGRAM_ID: Pubkey = solana_program::pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+/// Maximum symbol length stored by Metaplex metadata
+pub const TOKEN_SYMBOL_LEN: usize = 10;
+
+/// Cached mint decimals and symbol, keyed by mint
+#[account]
+pub struct TokenMeta {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub symbol: [u8; TOKEN_SYMBOL_LEN], // Zero-padded; empty when no metadata was supplied
+    pub last_updated: i64,
+    pub bump: u8,
+}
+
+impl TokenMeta {
+    pub const SPACE: usize = 8 + 32 + 1 + TOKEN_SYMBOL_LEN + 8 + 1;
+}
+
 /// SonicAgent program
 #[program]
 pub mod sonic_agent {
     use super::*;
 
-    /// Initialize a new agent with a configuration
-    pub fn initialize_agent(
-        ctx: Context<InitializeAgent>,
-        name: String,
-        description: String,
-        risk_profile: RiskProfile,
-        bump: u8,
-    ) -> Result<()> {
-        let agent_config = &mut ctx.accounts.agent_config;
-        let owner = ctx.accounts.owner.key();
-        let clock = Clock::get()?;
-        
-        // Validate inputs
-        require!(!name.is_empty(), ErrorCode::InvalidName);
-        require!(name.len() <= 50, ErrorCode::NameTooLong);
-        require!(description.len() <= 200, ErrorCode::DescriptionTooLong);
-        
-        // Configure default trading rule
-        let trading_rule = TradingRule {
-            id: [0; 16], // Will be set by update_trading_rules instruction
-            max_amount_per_trade: 1000_000_000, // 1000 USDC in smallest units
-            max_trades_per_day: 5,
-            allowed_tokens: vec![], // Empty means all tokens allowed
-            excluded_tokens: vec![], // No excluded tokens by default
-            max_slippage_bps: 100, // 1% max slippage
-        };
-        
-        // Configure default gas settings
-        let gas_settings = GasSettings {
-            priority_fee: 0, // Auto priority fee
-            compute_units: 200_000, // Default compute unit limit
-            retry_on_fail: true,
-            max_retries: 3,
-        };
+    /// Initialize a new agent with a configuration.
+    /// The agent account is sized for `max_strategies` strategies. New accounts
+    /// are capped at 10 KiB, which leaves room for about five; use
+    /// `expand_strategy_capacity` to grow beyond that.
+    pub fn initialize_agent(ctx: Context<InitializeAgent>, params: NewAgentParams) -> Result<()> {
+        let accounts = ctx.accounts;
+        init_agent_accounts(
+            &mut accounts.agent_config,
+            &mut accounts.agent_stats,
+            &mut accounts.trade_index,
+            &accounts.valuation_mint,
+            accounts.owner.key(),
+            params,
+            &ctx.bumps,
+        )
+    }
+    
+    /// Create a user's agent (config, stats and trade index), price alerts and
+    /// notification counter in one instruction, so onboarding either completes
+    /// or leaves nothing. Fails if any of the accounts already exists.
+    pub fn onboard_user(ctx: Context<OnboardUser>, params: NewAgentParams, max_alerts: u8) -> Result<()> {
+        let price_alerts_bump = *ctx.bumps.get("price_alerts").unwrap();
+        let notification_counter_bump = *ctx.bumps.get("notification_counter").unwrap();
+        let accounts = ctx.accounts;
+        init_agent_accounts(
+            &mut accounts.agent_config,
+            &mut accounts.agent_stats,
+            &mut accounts.trade_index,
+            &accounts.valuation_mint,
+            accounts.owner.key(),
+            params,
+            &ctx.bumps,
+        )?;
         
-        // Initialize agent configuration
-        agent_config.owner = owner;
-        agent_config.name = name;
-        agent_config.description = description;
-        agent_config.risk_profile = risk_profile;
-        agent_config.status = AgentStatus::Inactive;
-        agent_config.created_at = clock.unix_timestamp;
-        agent_config.updated_at = clock.unix_timestamp;
-        agent_config.auto_rebalance = false;
-        agent_config.rebalance_threshold_bps = 500; // 5% threshold
-        agent_config.auto_trade = false;
-        agent_config.trading_budget = 0;
-        agent_config.strategies = vec![];
-        agent_config.trading_rules = trading_rule;
-        agent_config.gas_settings = gas_settings;
-        agent_config.target_allocations = vec![];
-        agent_config.total_executed_trades = 0;
-        agent_config.total_trade_volume = 0;
-        agent_config.bump = bump;
-        
-        // Initialize agent stats
-        let agent_stats = &mut ctx.accounts.agent_stats;
-        agent_stats.agent = agent_config.key();
-        agent_stats.owner = owner;
-        agent_stats.total_trades = 0;
-        agent_stats.successful_trades = 0;
-        agent_stats.failed_trades = 0;
-        agent_stats.total_volume = 0;
-        agent_stats.total_fees_paid = 0;
-        agent_stats.profit_loss = 0;
-        agent_stats.created_at = clock.unix_timestamp;
-        agent_stats.last_updated_at = clock.unix_timestamp;
-        agent_stats.performance_data = vec![];
-        agent_stats.bump = bump;
+        price_alerts::init_price_alerts(
+            &mut accounts.price_alerts,
+            accounts.owner.key(),
+            max_alerts,
+            price_alerts_bump,
+        )?;
         
-        emit!(AgentInitializedEvent {
-            agent: agent_config.key(),
-            owner,
-            name: agent_config.name.clone(),
-            risk_profile: agent_config.risk_profile.clone(),
-            timestamp: clock.unix_timestamp,
-        });
+        notification_events::init_notification_counter(
+            &mut accounts.notification_counter,
+            accounts.owner.key(),
+            notification_counter_bump,
+        );
         
         Ok(())
     }
     
     /// Update agent configuration
-    pub fn update_agent_config(
-        ctx: Context<UpdateAgentConfig>,
-        name: Option<String>,
-        description: Option<String>,
-        risk_profile: Option<RiskProfile>,
-        auto_rebalance: Option<bool>,
-        rebalance_threshold_bps: Option<u16>,
-        auto_trade: Option<bool>,
-        trading_budget: Option<u64>,
-    ) -> Result<()> {
+    pub fn update_agent_config(ctx: Context<UpdateAgentConfig>, update: AgentConfigUpdate) -> Result<()> {
+        let AgentConfigUpdate {
+            name,
+            description,
+            risk_profile,
+            auto_rebalance,
+            rebalance_threshold_bps,
+            auto_trade,
+            trading_budget,
+            quote_mint,
+            max_drawdown_bps,
+            max_single_token_bps,
+            min_rebalance_interval_secs,
+            max_consecutive_failures,
+        } = update;
         let agent_config = &mut ctx.accounts.agent_config;
         let clock = Clock::get()?;
         
         // Update fields if provided
         if let Some(name) = name {
             require!(!name.is_empty(), ErrorCode::InvalidName);
-            require!(name.len() <= 50, ErrorCode::NameTooLong);
+            require!(name.len() <= MAX_AGENT_NAME_LEN, ErrorCode::NameTooLong);
             agent_config.name = name;
         }
         
         if let Some(description) = description {
-            require!(description.len() <= 200, ErrorCode::DescriptionTooLong);
+            require!(description.len() <= MAX_AGENT_DESCRIPTION_LEN, ErrorCode::DescriptionTooLong);
             agent_config.description = description;
         }
         
@@ -281,8 +960,33 @@ pub mod sonic_agent {
             agent_config.trading_budget = trading_budget;
         }
         
-        // Update timestamp
-        agent_config.updated_at = clock.unix_timestamp;
+        if let Some(quote_mint) = quote_mint {
+            agent_config.quote_mint = quote_mint;
+        }
+        
+        if let Some(max_drawdown_bps) = max_drawdown_bps {
+            require!(max_drawdown_bps <= 10000, ErrorCode::InvalidDrawdownThreshold);
+            agent_config.max_drawdown_bps = max_drawdown_bps;
+        }
+        
+        if let Some(max_single_token_bps) = max_single_token_bps {
+            require!(
+                max_single_token_bps > 0 && max_single_token_bps <= 10000,
+                ErrorCode::InvalidConcentrationLimit
+            );
+            agent_config.max_single_token_bps = max_single_token_bps;
+        }
+        
+        if let Some(min_rebalance_interval_secs) = min_rebalance_interval_secs {
+            agent_config.min_rebalance_interval_secs = min_rebalance_interval_secs;
+        }
+        
+        if let Some(max_consecutive_failures) = max_consecutive_failures {
+            agent_config.max_consecutive_failures = max_consecutive_failures;
+        }
+        
+        // Update timestamp
+        agent_config.updated_at = clock.unix_timestamp;
         
         emit!(AgentUpdatedEvent {
             agent: agent_config.key(),
@@ -294,62 +998,125 @@ pub mod sonic_agent {
     }
     
     /// Update trading rules
-    pub fn update_trading_rules(
+    pub fn update_trading_rules(ctx: Context<UpdateAgentConfig>, update: TradingRulesUpdate) -> Result<()> {
+        let agent_config = &mut ctx.accounts.agent_config;
+        
+        // Agents with approvers must go through propose/approve/apply
+        require!(agent_config.approvers.is_empty(), ErrorCode::RulesChangeRequiresApproval);
+        
+        apply_trading_rules_update(agent_config, update)
+    }
+    
+    /// Set the approvers required for trading rule changes.
+    /// Approvers can only be set once, on an agent that has none yet; rules
+    /// changes cover trading rules only and cannot rotate the approver set.
+    pub fn set_rule_approvers(
         ctx: Context<UpdateAgentConfig>,
-        max_amount_per_trade: Option<u64>,
-        max_trades_per_day: Option<u8>,
-        allowed_tokens: Option<Vec<Pubkey>>,
-        excluded_tokens: Option<Vec<Pubkey>>,
-        max_slippage_bps: Option<u16>,
+        approvers: Vec<Pubkey>,
+        required_approvals: u8,
     ) -> Result<()> {
         let agent_config = &mut ctx.accounts.agent_config;
-        let clock = Clock::get()?;
-        
-        // Update trading rules if provided
-        if let Some(max_amount) = max_amount_per_trade {
-            agent_config.trading_rules.max_amount_per_trade = max_amount;
-        }
         
-        if let Some(max_trades) = max_trades_per_day {
-            require!(max_trades <= 100, ErrorCode::InvalidTradeLimit);
-            agent_config.trading_rules.max_trades_per_day = max_trades;
-        }
+        require!(agent_config.approvers.is_empty(), ErrorCode::RulesChangeRequiresApproval);
+        require!(approvers.len() <= MAX_RULE_APPROVERS, ErrorCode::InvalidApprovalThreshold);
+        require!(
+            required_approvals > 0 && required_approvals as usize <= approvers.len(),
+            ErrorCode::InvalidApprovalThreshold
+        );
         
-        if let Some(allowed) = allowed_tokens {
-            // Validate token mints
-            for mint in &allowed {
-                require!(is_valid_token_mint(mint), ErrorCode::InvalidTokenMint);
-            }
-            agent_config.trading_rules.allowed_tokens = allowed;
-        }
+        agent_config.approvers = approvers;
+        agent_config.required_approvals = required_approvals;
+        agent_config.updated_at = Clock::get()?.unix_timestamp;
         
-        if let Some(excluded) = excluded_tokens {
-            // Validate token mints
-            for mint in &excluded {
-                require!(is_valid_token_mint(mint), ErrorCode::InvalidTokenMint);
-            }
-            agent_config.trading_rules.excluded_tokens = excluded;
-        }
+        Ok(())
+    }
+    
+    /// Let the given programs record trades for the agent by direct CPI,
+    /// alongside the owner. An empty list leaves only the owner.
+    pub fn set_authorized_programs(
+        ctx: Context<UpdateAgentConfig>,
+        programs: Vec<Pubkey>,
+    ) -> Result<()> {
+        let agent_config = &mut ctx.accounts.agent_config;
         
-        if let Some(slippage) = max_slippage_bps {
-            require!(slippage <= 1000, ErrorCode::SlippageTooHigh); // Max 10%
-            agent_config.trading_rules.max_slippage_bps = slippage;
-        }
+        require!(programs.len() <= MAX_AUTHORIZED_PROGRAMS, ErrorCode::TooManyAuthorizedPrograms);
         
-        // Update timestamp
-        agent_config.updated_at = clock.unix_timestamp;
+        agent_config.authorized_programs = programs;
+        agent_config.updated_at = Clock::get()?.unix_timestamp;
         
-        emit!(TradingRulesUpdatedEvent {
+        emit!(AgentUpdatedEvent {
             agent: agent_config.key(),
             owner: agent_config.owner,
-            max_amount_per_trade: agent_config.trading_rules.max_amount_per_trade,
-            max_slippage_bps: agent_config.trading_rules.max_slippage_bps,
-            timestamp: clock.unix_timestamp,
+            timestamp: agent_config.updated_at,
         });
         
         Ok(())
     }
     
+    /// Propose a trading rules change for approval
+    pub fn propose_rules_change(
+        ctx: Context<ProposeRulesChange>,
+        update: TradingRulesUpdate,
+    ) -> Result<()> {
+        let pending_change = &mut ctx.accounts.pending_change;
+        
+        pending_change.agent = ctx.accounts.agent_config.key();
+        pending_change.rules_hash = hash(&update.try_to_vec()?).to_bytes();
+        pending_change.update = update;
+        pending_change.approvals = vec![];
+        pending_change.proposed_at = Clock::get()?.unix_timestamp;
+        pending_change.bump = *ctx.bumps.get("pending_change").unwrap();
+        
+        Ok(())
+    }
+    
+    /// Approve a pending trading rules change.
+    /// The hash must match the proposal, so an approver signs off on exact values.
+    pub fn approve_rules_change(
+        ctx: Context<ApproveRulesChange>,
+        rules_hash: [u8; 32],
+    ) -> Result<()> {
+        let approver = ctx.accounts.approver.key();
+        let pending_change = &mut ctx.accounts.pending_change;
+        
+        require!(
+            ctx.accounts.agent_config.approvers.contains(&approver),
+            ErrorCode::NotAnApprover
+        );
+        require!(pending_change.rules_hash == rules_hash, ErrorCode::RulesChangeHashMismatch);
+        
+        if !pending_change.approvals.contains(&approver) {
+            pending_change.approvals.push(approver);
+        }
+        
+        Ok(())
+    }
+    
+    /// Apply a trading rules change once enough approvers have signed off
+    pub fn apply_rules_change(ctx: Context<ApplyRulesChange>) -> Result<()> {
+        let agent_config = &mut ctx.accounts.agent_config;
+        let pending_change = &ctx.accounts.pending_change;
+        
+        // Count only approvals from the current approver set
+        let approvals = pending_change
+            .approvals
+            .iter()
+            .filter(|a| agent_config.approvers.contains(a))
+            .count();
+        require!(
+            approvals >= agent_config.required_approvals as usize,
+            ErrorCode::InsufficientApprovals
+        );
+        
+        apply_trading_rules_update(agent_config, pending_change.update.clone())
+    }
+    
+    /// Cancel a pending trading rules change and reclaim its rent.
+    /// Lets the owner replace a proposal that approvers won't sign off on.
+    pub fn cancel_rules_change(_ctx: Context<CancelRulesChange>) -> Result<()> {
+        Ok(())
+    }
+    
     /// Update gas settings
     pub fn update_gas_settings(
         ctx: Context<UpdateAgentConfig>,
@@ -362,23 +1129,13 @@ pub mod sonic_agent {
         let clock = Clock::get()?;
         
         // Update gas settings if provided
-        if let Some(fee) = priority_fee {
-            agent_config.gas_settings.priority_fee = fee;
-        }
-        
-        if let Some(units) = compute_units {
-            require!(units >= 100_000 && units <= 1_400_000, ErrorCode::InvalidComputeUnits);
-            agent_config.gas_settings.compute_units = units;
-        }
-        
-        if let Some(retry) = retry_on_fail {
-            agent_config.gas_settings.retry_on_fail = retry;
-        }
-        
-        if let Some(retries) = max_retries {
-            require!(retries <= 10, ErrorCode::TooManyRetries);
-            agent_config.gas_settings.max_retries = retries;
-        }
+        apply_gas_settings_update(
+            &mut agent_config.gas_settings,
+            priority_fee,
+            compute_units,
+            retry_on_fail,
+            max_retries,
+        )?;
         
         // Update timestamp
         agent_config.updated_at = clock.unix_timestamp;
@@ -400,6 +1157,7 @@ pub mod sonic_agent {
         strategy_type: StrategyType,
         parameters: Vec<u8>, // JSON encoded strategy parameters
         is_active: bool,
+        interval_secs: u64,
     ) -> Result<()> {
         let agent_config = &mut ctx.accounts.agent_config;
         let clock = Clock::get()?;
@@ -408,6 +1166,7 @@ pub mod sonic_agent {
         require!(!name.is_empty(), ErrorCode::InvalidName);
         require!(name.len() <= 50, ErrorCode::NameTooLong);
         require!(parameters.len() <= 1024, ErrorCode::ParametersTooLarge);
+        validate_strategy_parameters(&strategy_type, &parameters)?;
         
         // Check if strategy with this ID already exists
         let strategy_index = agent_config.strategies.iter().position(|s| s.id == strategy_id);
@@ -419,10 +1178,20 @@ pub mod sonic_agent {
             strategy.strategy_type = strategy_type;
             strategy.is_active = is_active;
             strategy.parameters = parameters;
+            
+            // Reschedule from the last run, but never into the past
+            if strategy.interval_secs != interval_secs {
+                strategy.interval_secs = interval_secs;
+                strategy.next_run_at = if strategy.execution_count > 0 {
+                    (strategy.last_executed_at + interval_secs as i64).max(clock.unix_timestamp)
+                } else {
+                    clock.unix_timestamp
+                };
+            }
         } else {
             // Add new strategy
             // Limit the number of strategies
-            require!(agent_config.strategies.len() < 10, ErrorCode::TooManyStrategies);
+            agent_config.check_strategy_slot()?;
             
             let strategy = Strategy {
                 id: strategy_id,
@@ -432,6 +1201,8 @@ pub mod sonic_agent {
                 parameters,
                 last_executed_at: 0,
                 execution_count: 0,
+                interval_secs,
+                next_run_at: clock.unix_timestamp, // Due as soon as it is added
             };
             
             agent_config.strategies.push(strategy);
@@ -451,6 +1222,109 @@ pub mod sonic_agent {
         Ok(())
     }
     
+    /// Change some of a strategy's parameters without resending all of them.
+    /// `patch` is a JSON object merged over the stored parameters: its keys
+    /// replace existing ones and `null` removes a key. Nested values are
+    /// replaced whole.
+    pub fn patch_strategy_parameters(
+        ctx: Context<UpdateAgentConfig>,
+        strategy_id: [u8; 16],
+        patch: Vec<u8>,
+    ) -> Result<()> {
+        let agent_config = &mut ctx.accounts.agent_config;
+        let clock = Clock::get()?;
+        
+        let strategy = agent_config
+            .strategies
+            .iter_mut()
+            .find(|s| s.id == strategy_id)
+            .ok_or(ErrorCode::StrategyNotFound)?;
+        
+        let parameters = patch_parameters(&strategy.parameters, &patch)?;
+        validate_strategy_parameters(&strategy.strategy_type, &parameters)?;
+        
+        strategy.parameters = parameters;
+        let is_active = strategy.is_active;
+        agent_config.updated_at = clock.unix_timestamp;
+        
+        emit!(StrategyUpdatedEvent {
+            agent: agent_config.key(),
+            owner: agent_config.owner,
+            strategy_id,
+            is_active,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    /// Grow the agent account so it can hold `max_strategies` strategies.
+    /// The owner pays the extra rent. Each call can grow the account by at most
+    /// 10 KiB, so large increases take several calls.
+    pub fn expand_strategy_capacity(
+        ctx: Context<ExpandStrategyCapacity>,
+        max_strategies: u8,
+    ) -> Result<()> {
+        let agent_config = &mut ctx.accounts.agent_config;
+        
+        require!(
+            max_strategies > agent_config.max_strategies && max_strategies <= MAX_STRATEGIES,
+            ErrorCode::InvalidMaxStrategies
+        );
+        
+        agent_config.max_strategies = max_strategies;
+        let data_len = agent_config.to_account_info().data_len();
+        check_strategy_capacity(agent_config, data_len)?;
+        
+        agent_config.updated_at = Clock::get()?.unix_timestamp;
+        
+        emit!(AgentUpdatedEvent {
+            agent: agent_config.key(),
+            owner: agent_config.owner,
+            timestamp: agent_config.updated_at,
+        });
+        
+        Ok(())
+    }
+    
+    /// Mark a scheduled strategy as executed and schedule its next run.
+    /// Runs missed while late are skipped rather than queued up.
+    pub fn mark_strategy_executed(
+        ctx: Context<UpdateAgentConfig>,
+        strategy_id: [u8; 16],
+    ) -> Result<()> {
+        let agent_config = &mut ctx.accounts.agent_config;
+        let now = Clock::get()?.unix_timestamp;
+        
+        agent_config
+            .strategies
+            .iter_mut()
+            .find(|s| s.id == strategy_id)
+            .ok_or(ErrorCode::StrategyNotFound)?
+            .mark_executed(now)
+    }
+    
+    /// Emit the ids of active scheduled strategies that are due to run
+    pub fn due_strategies(ctx: Context<DueStrategies>) -> Result<()> {
+        let agent_config = &ctx.accounts.agent_config;
+        let now = Clock::get()?.unix_timestamp;
+        
+        let strategy_ids = agent_config
+            .strategies
+            .iter()
+            .filter(|s| s.is_due(now))
+            .map(|s| s.id)
+            .collect();
+        
+        emit!(DueStrategiesEvent {
+            agent: agent_config.key(),
+            strategy_ids,
+            timestamp: now,
+        });
+        
+        Ok(())
+    }
+    
     /// Remove a trading strategy
     pub fn remove_strategy(
         ctx: Context<UpdateAgentConfig>,
@@ -482,17 +1356,43 @@ pub mod sonic_agent {
         Ok(())
     }
     
-    /// Set target allocations for portfolio rebalancing
+    /// Cache a mint's decimals and symbol. Anyone may call this; the symbol
+    /// is read from the Metaplex metadata account when one is passed as the
+    /// first remaining account.
+    pub fn cache_token_meta(ctx: Context<CacheTokenMeta>) -> Result<()> {
+        let token_meta = &mut ctx.accounts.token_meta;
+        
+        token_meta.mint = ctx.accounts.mint.key();
+        token_meta.bump = *ctx.bumps.get("token_meta").unwrap();
+        fill_token_meta(token_meta, &ctx.accounts.mint, ctx.remaining_accounts.first())
+    }
+    
+    /// Refresh a cached mint's decimals and symbol
+    pub fn refresh_token_meta(ctx: Context<RefreshTokenMeta>) -> Result<()> {
+        fill_token_meta(
+            &mut ctx.accounts.token_meta,
+            &ctx.accounts.mint,
+            ctx.remaining_accounts.first(),
+        )
+    }
+    
+    /// Set target allocations for portfolio rebalancing.
+    /// For each allocation, in order, pass either its `TokenMeta` cache or its
+    /// mint account as a remaining account. With `strict_token_meta` only
+    /// cached mints are accepted. Allocations with a `group_id` must belong to
+    /// one of `groups` and sum to its target.
     pub fn set_target_allocations(
         ctx: Context<UpdateAgentConfig>,
         allocations: Vec<TokenAllocation>,
+        strict_token_meta: bool,
+        groups: Vec<AllocationGroup>,
     ) -> Result<()> {
         let agent_config = &mut ctx.accounts.agent_config;
         let clock = Clock::get()?;
         
         // Validate allocations
         require!(!allocations.is_empty(), ErrorCode::EmptyAllocations);
-        require!(allocations.len() <= 20, ErrorCode::TooManyAllocations);
+        require!(allocations.len() <= MAX_ALLOCATIONS, ErrorCode::TooManyAllocations);
         
         // Check that allocations sum to 10000 (100%)
         let total: u16 = allocations.iter().map(|a| a.target_percentage).sum();
@@ -504,9 +1404,18 @@ pub mod sonic_agent {
             require!(allocation.target_percentage > 0, ErrorCode::InvalidAllocation);
             require!(allocation.max_deviation_bps <= 2000, ErrorCode::DeviationTooHigh); // Max 20% deviation
         }
+        check_allocation_groups(&allocations, &groups)?;
+        
+        // Decimals come from the mint, so values in different units compare correctly
+        require!(ctx.remaining_accounts.len() == allocations.len(), ErrorCode::AllocationMintMismatch);
+        for (allocation, info) in allocations.iter().zip(ctx.remaining_accounts.iter()) {
+            let decimals = allocation_decimals(&allocation.mint, info, strict_token_meta)?;
+            require!(decimals == allocation.decimals, ErrorCode::AllocationMintMismatch);
+        }
         
         // Set allocations
         agent_config.target_allocations = allocations;
+        agent_config.allocation_groups = groups;
         
         // Update timestamp
         agent_config.updated_at = clock.unix_timestamp;
@@ -520,6 +1429,137 @@ pub mod sonic_agent {
         Ok(())
     }
     
+    /// Refresh the USD price snapshots used for rebalance math, in allocation order
+    pub fn update_allocation_prices(
+        ctx: Context<UpdateAgentConfig>,
+        usd_prices: Vec<u64>,
+    ) -> Result<()> {
+        let agent_config = &mut ctx.accounts.agent_config;
+        
+        require!(
+            usd_prices.len() == agent_config.target_allocations.len(),
+            ErrorCode::AllocationLengthMismatch
+        );
+        
+        for (allocation, usd_price) in agent_config.target_allocations.iter_mut().zip(usd_prices) {
+            allocation.usd_price = usd_price;
+        }
+        agent_config.updated_at = Clock::get()?.unix_timestamp;
+        
+        Ok(())
+    }
+    
+    /// Emit how far each allocation has drifted from its target.
+    /// Balances are raw token amounts in allocation order; they are converted to
+    /// USD with each allocation's decimals and price snapshot before comparing.
+    pub fn compute_rebalance_needs(
+        ctx: Context<ComputeRebalanceNeeds>,
+        balances: Vec<u64>,
+    ) -> Result<()> {
+        let agent_config = &ctx.accounts.agent_config;
+        let allocations = &agent_config.target_allocations;
+        
+        require!(balances.len() == allocations.len(), ErrorCode::AllocationLengthMismatch);
+        
+        let usd_values = allocation_usd_values(allocations, &balances)?;
+        let total: u128 = usd_values.iter().sum();
+        let (deviations, groups) = rebalance_deviations(allocations, &agent_config.allocation_groups, &usd_values);
+        
+        emit!(RebalanceNeedsEvent {
+            agent: agent_config.key(),
+            total_usd_value: u64::try_from(total).map_err(|_| error!(ErrorCode::ArithmeticOverflow))?,
+            allocations: deviations,
+            groups,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    /// Record that the agent rebalanced.
+    /// Rejects rebalances closer together than `min_rebalance_interval_secs`,
+    /// whatever the allocation drift.
+    pub fn record_rebalance(ctx: Context<RecordRebalance>) -> Result<()> {
+        let agent_config = &mut ctx.accounts.agent_config;
+        let clock = Clock::get()?;
+        
+        agent_config.record_rebalance(clock.unix_timestamp)?;
+        
+        emit!(RebalanceRecordedEvent {
+            agent: agent_config.key(),
+            owner: agent_config.owner,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    /// Record a trade made to move the portfolio toward its target allocations.
+    /// Takes the same trade and accounts as `record_trade`, plus
+    /// `balances`: the raw balances of each target allocation before the trade,
+    /// in allocation order. Both mints must be target allocations, and a
+    /// successful trade must leave the portfolio closer to its targets than the
+    /// snapshot was. The trade is recorded with `TradeReason::Rebalance`.
+    /// Like `record_rebalance`, it fails until `min_rebalance_interval_secs`
+    /// has passed since the last rebalance.
+    pub fn record_rebalance_trade(ctx: Context<RecordTrade>, trade: RecordTradeParams, balances: Vec<u64>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let RecordTradeParams { input_mint, output_mint, input_amount, output_amount, slippage_bps, success, .. } = trade;
+        
+        // Trades over the slippage limit are recorded as failed and move nothing
+        let rebalanced = {
+            let agent_config = &ctx.accounts.agent_config;
+            check_rebalance_interval(agent_config, now)?;
+            
+            let allocations = &agent_config.target_allocations;
+            require!(balances.len() == allocations.len(), ErrorCode::AllocationLengthMismatch);
+            
+            let input_index = allocations
+                .iter()
+                .position(|a| a.mint == input_mint)
+                .ok_or(ErrorCode::RebalanceTokenNotAllocated)?;
+            let output_index = allocations
+                .iter()
+                .position(|a| a.mint == output_mint)
+                .ok_or(ErrorCode::RebalanceTokenNotAllocated)?;
+            
+            let succeeded = success && slippage_bps <= agent_config.trading_rules.max_slippage_bps;
+            if succeeded {
+                let mut balances_after = balances.clone();
+                balances_after[input_index] = balances_after[input_index]
+                    .checked_sub(input_amount)
+                    .ok_or(ErrorCode::InvalidAmount)?;
+                balances_after[output_index] = balances_after[output_index]
+                    .checked_add(output_amount)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+                
+                let deviation_before = total_allocation_deviation(
+                    allocations,
+                    &allocation_usd_values(allocations, &balances)?,
+                );
+                let deviation_after = total_allocation_deviation(
+                    allocations,
+                    &allocation_usd_values(allocations, &balances_after)?,
+                );
+                require!(deviation_after < deviation_before, ErrorCode::RebalanceIncreasesDeviation);
+            }
+            succeeded
+        };
+        
+        if rebalanced {
+            let agent_config = &mut ctx.accounts.agent_config;
+            agent_config.last_rebalance_at = now;
+            
+            emit!(RebalanceRecordedEvent {
+                agent: agent_config.key(),
+                owner: agent_config.owner,
+                timestamp: now,
+            });
+        }
+        
+        record_trade(ctx, trade, None, TradeReason::Rebalance)
+    }
+    
     /// Activate agent
     pub fn activate_agent(ctx: Context<UpdateAgentStatus>) -> Result<()> {
         let agent_config = &mut ctx.accounts.agent_config;
@@ -583,35 +1623,272 @@ pub mod sonic_agent {
         Ok(())
     }
     
-    /// Record a trade action
+    /// Put the agent into close-only mode, where it may only sell back into
+    /// its quote mint
+    pub fn close_only_agent(ctx: Context<UpdateAgentStatus>) -> Result<()> {
+        let agent_config = &mut ctx.accounts.agent_config;
+        let clock = Clock::get()?;
+        
+        require!(agent_config.quote_mint != Pubkey::default(), ErrorCode::QuoteMintNotSet);
+        
+        // Set status to close-only
+        agent_config.status = AgentStatus::CloseOnly;
+        
+        // Update timestamp
+        agent_config.updated_at = clock.unix_timestamp;
+        
+        emit!(AgentStatusChangedEvent {
+            agent: agent_config.key(),
+            owner: agent_config.owner,
+            status: AgentStatus::CloseOnly,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    /// Hand an agent to a new owner.
+    /// The agent PDA is derived from `agent_id`, so its address does not change.
+    pub fn transfer_agent_ownership(
+        ctx: Context<TransferAgentOwnership>,
+        new_owner: Pubkey,
+    ) -> Result<()> {
+        let agent_config = &mut ctx.accounts.agent_config;
+        let clock = Clock::get()?;
+        let previous_owner = agent_config.transfer_ownership(
+            &mut ctx.accounts.agent_stats,
+            new_owner,
+            clock.unix_timestamp,
+        )?;
+        
+        let agent = agent_config.key();
+        let agent_name = agent_config.name.clone();
+        let data = format!(
+            "{{\"agent\":\"{}\", \"previousOwner\":\"{}\", \"newOwner\":\"{}\"}}",
+            agent,
+            previous_owner,
+            new_owner
+        );
+        
+        emit_notification(
+            &[ctx.accounts.notification_prefs.to_account_info()],
+            &mut ctx.accounts.notification_counter,
+            previous_owner,
+            Notification {
+                event_type: NotificationEventType::PermissionsChanged,
+                priority: NotificationPriority::High,
+                title: "Agent Transferred".to_string(),
+                message: format!("Agent '{}' has been transferred to {}", agent_name, new_owner),
+                data: Some(data.clone()),
+                strategy_id: None,
+                token_address: None,
+                tx_signature: None,
+            },
+        );
+        
+        emit_notification(
+            &[ctx.accounts.new_owner_notification_prefs.to_account_info()],
+            &mut ctx.accounts.new_owner_notification_counter,
+            new_owner,
+            Notification {
+                event_type: NotificationEventType::PermissionsChanged,
+                priority: NotificationPriority::High,
+                title: "Agent Received".to_string(),
+                message: format!("You are now the owner of agent '{}'", agent_name),
+                data: Some(data),
+                strategy_id: None,
+                token_address: None,
+                tx_signature: None,
+            },
+        );
+        
+        emit!(AgentOwnershipTransferredEvent {
+            agent,
+            previous_owner,
+            new_owner,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    /// Upgrade an agent's config and stats accounts to the current layout.
+    /// Accounts too small to hold the v2 fields are grown first, with the owner
+    /// paying any extra rent, and new fields start at their defaults. Calling
+    /// it again on a migrated agent does nothing. Agents from before the
+    /// layout just preceding v2 fail to load; see `AGENT_SCHEMA_VERSION`.
+    pub fn migrate_agent_v2(ctx: Context<MigrateAgentV2>, _agent_id: [u8; 16]) -> Result<()> {
+        let owner = ctx.accounts.owner.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        
+        let mut agent_config: Account<AgentConfig> = load_for_migration(
+            &ctx.accounts.agent_config.to_account_info(),
+            &owner,
+            &system_program,
+            AGENT_SCHEMA_V2_GROWTH,
+        )?;
+        require!(agent_config.is_owned_by(&owner.key()), CommonError::Unauthorized);
+        
+        let mut agent_stats: Account<AgentStats> = load_for_migration(
+            &ctx.accounts.agent_stats.to_account_info(),
+            &owner,
+            &system_program,
+            AGENT_SCHEMA_V2_GROWTH,
+        )?;
+        
+        let config_upgraded = upgrade_schema(&mut agent_config.schema_version);
+        let stats_upgraded = upgrade_schema(&mut agent_stats.schema_version);
+        if !config_upgraded && !stats_upgraded {
+            msg!("Agent is already at schema version {}", AGENT_SCHEMA_VERSION);
+            return Ok(());
+        }
+        
+        let now = Clock::get()?.unix_timestamp;
+        
+        if config_upgraded {
+            agent_config.updated_at = now;
+            agent_config.exit(ctx.program_id)?;
+        }
+        
+        if stats_upgraded {
+            agent_stats.exit(ctx.program_id)?;
+        }
+        
+        emit!(AgentUpdatedEvent {
+            agent: agent_config.key(),
+            owner: agent_config.owner,
+            timestamp: now,
+        });
+        
+        Ok(())
+    }
+    
+    /// Dry-run the checks `record_trade` performs without recording anything.
+    /// Fails with the same error `record_trade` would, given the same trade and
+    /// `pool_reserve` account, for a trade reported as successful. Returns
+    /// whether it would be recorded as successful: like `record_trade`, a trade
+    /// over the slippage limit is not an error but counts as failed.
+    pub fn validate_trade(ctx: Context<ValidateTrade>, trade: RecordTradeParams) -> Result<bool> {
+        let RecordTradeParams {
+            strategy_id,
+            input_mint,
+            output_mint,
+            input_amount,
+            output_amount,
+            expected_output,
+            slippage_bps,
+            reason,
+            ..
+        } = trade;
+        let agent_config = &ctx.accounts.agent_config;
+        let clock = Clock::get()?;
+        
+        check_trade_limits(
+            agent_config,
+            &ctx.accounts.agent_stats,
+            ctx.accounts.pool_reserve.as_deref(),
+            &input_mint,
+            &output_mint,
+            input_amount,
+            &reason,
+        )?;
+        check_output_consistency(output_amount, expected_output, slippage_bps)?;
+        check_daily_trade(agent_config, clock.unix_timestamp)?;
+        require!(
+            agent_config.strategies.iter().any(|s| s.id == strategy_id),
+            ErrorCode::StrategyNotFound
+        );
+        
+        Ok(slippage_bps <= agent_config.trading_rules.max_slippage_bps)
+    }
+    
+    /// Record a trade action. The owner records trades directly unless the
+    /// agent has authorized programs, which must then make the call by CPI.
+    /// When `min_pool_liquidity` is set, `pool_reserve` must be the traded
+    /// pool's reserve of the input or output mint. `attempt` is 0 for the first try
+    /// and counts up on each retry. For successful trades, `output_amount` must
+    /// be within `slippage_bps` of the quoted `expected_output`.
+    /// `client_trade_id` is part of the trade account's address, so recording
+    /// the same id twice fails instead of creating a duplicate.
+    /// `direction` is inferred from the quote mint when possible; see
+    /// `trade_direction`. `reason_code` classifies the trade, with `reason`
+    /// as optional free-text detail. `fee_amount` is the network and protocol
+    /// fees the trade paid, counted whether or not it succeeded.
     pub fn record_trade(
         ctx: Context<RecordTrade>,
-        strategy_id: [u8; 16],
-        input_mint: Pubkey,
-        output_mint: Pubkey,
-        input_amount: u64,
-        output_amount: u64,
-        slippage_bps: u16,
-        transaction_signature: [u8; 64],
-        success: bool,
-        price_impact_bps: u16,
-        reason: String,
-        bump: u8,
+        trade: RecordTradeParams,
+        direction: Option<TradeDirection>,
+        reason_code: TradeReason,
     ) -> Result<()> {
+        let RecordTradeParams {
+            strategy_id,
+            input_mint,
+            output_mint,
+            input_amount,
+            output_amount,
+            slippage_bps,
+            transaction_signature,
+            success,
+            price_impact_bps,
+            reason,
+            attempt,
+            expected_output,
+            client_trade_id: _, // Only used in the trade account's seeds
+            fee_amount,
+        } = trade;
+        let trade_bump = *ctx.bumps.get("trade_action").unwrap();
+        
         let trade_action = &mut ctx.accounts.trade_action;
         let agent_config = &mut ctx.accounts.agent_config;
         let agent_stats = &mut ctx.accounts.agent_stats;
         let clock = Clock::get()?;
         
         // Validate inputs
-        require!(is_valid_token_mint(&input_mint), ErrorCode::InvalidTokenMint);
-        require!(is_valid_token_mint(&output_mint), ErrorCode::InvalidTokenMint);
-        require!(input_amount > 0, ErrorCode::InvalidAmount);
-        require!(reason.len() <= 200, ErrorCode::ReasonTooLong);
+        check_trade_recorder(agent_config, &ctx.accounts.authority.key(), &ctx.accounts.instructions_sysvar)?;
+        check_trade_limits(
+            agent_config,
+            agent_stats,
+            ctx.accounts.pool_reserve.as_deref(),
+            &input_mint,
+            &output_mint,
+            input_amount,
+            &reason,
+        )?;
+        let direction = trade_direction(&agent_config.quote_mint, &input_mint, &output_mint, direction)?;
+        check_retry_attempt(&agent_config.gas_settings, attempt)?;
+        if success {
+            check_output_consistency(output_amount, expected_output, slippage_bps)?;
+        }
+        consume_daily_trade(agent_config, clock.unix_timestamp)?;
+        
+        // Enforce the slippage limit. The trade is recorded as failed rather than
+        // aborting the instruction, so the notification isn't rolled back with it
+        let max_slippage_bps = agent_config.trading_rules.max_slippage_bps;
+        let success = settled_success(success, slippage_bps, max_slippage_bps);
+        
+        if let Some((title, message, data)) = slippage_notice(slippage_bps, max_slippage_bps) {
+            emit_notification(
+                &[ctx.accounts.notification_prefs.to_account_info()],
+                &mut ctx.accounts.notification_counter,
+                agent_config.owner,
+                Notification {
+                    event_type: NotificationEventType::SlippageExceeded,
+                    priority: NotificationPriority::Medium,
+                    title,
+                    message,
+                    data: Some(data),
+                    strategy_id: None,
+                    token_address: Some(output_mint),
+                    tx_signature: None,
+                },
+            );
+        }
         
         // Check if strategy exists
-        let strategy = agent_config.strategies.iter_mut().find(|s| s.id == strategy_id);
-        require!(strategy.is_some(), ErrorCode::StrategyNotFound);
+        let strategy_index = agent_config.strategies
+            .iter()
+            .position(|s| s.id == strategy_id)
+            .ok_or(ErrorCode::StrategyNotFound)?;
         
         // Record trade action
         trade_action.agent = agent_config.key();
@@ -627,27 +1904,58 @@ pub mod sonic_agent {
         trade_action.success = success;
         trade_action.price_impact_bps = price_impact_bps;
         trade_action.reason = reason;
-        trade_action.bump = bump;
+        trade_action.reason_code = reason_code;
+        trade_action.prev_trade_hash = agent_config.last_trade_hash;
+        trade_action.direction = direction;
+        trade_action.bump = trade_bump;
+        let trade_hash = trade_chain_hash(trade_action)?;
+        agent_config.last_trade_hash = trade_hash;
         
         // Update agent statistics
-        agent_stats.total_trades += 1;
         if success {
-            agent_stats.successful_trades += 1;
-            agent_stats.total_volume += input_amount;
-            agent_config.total_trade_volume += input_amount;
-            agent_config.total_executed_trades += 1;
+            agent_stats.record_trades(1, 1, input_amount)?;
+            agent_config.total_trade_volume = agent_config.total_trade_volume
+                .checked_add(input_amount)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            agent_config.total_executed_trades = agent_config.total_executed_trades
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            
+            update_cost_basis(
+                agent_stats,
+                &agent_config.quote_mint,
+                &input_mint,
+                &output_mint,
+                input_amount,
+                output_amount,
+                clock.unix_timestamp,
+            )?;
             
             // Update strategy execution stats
-            if let Some(s) = strategy {
-                s.last_executed_at = clock.unix_timestamp;
-                s.execution_count += 1;
-            }
+            let s = &mut agent_config.strategies[strategy_index];
+            s.last_executed_at = clock.unix_timestamp;
+            s.execution_count = s.execution_count.checked_add(1).ok_or(ErrorCode::ArithmeticOverflow)?;
         } else {
-            agent_stats.failed_trades += 1;
+            agent_stats.record_trades(1, 0, 0)?;
         }
         
+        agent_stats.record_attempt(attempt)?;
+        
+        agent_stats.record_fee(fee_amount)?;
+        
         agent_stats.last_updated_at = clock.unix_timestamp;
         
+        if track_consecutive_failures(agent_config, agent_stats, success, clock.unix_timestamp) {
+            notify_failure_pause(
+                &[ctx.accounts.notification_prefs.to_account_info()],
+                &mut ctx.accounts.notification_counter,
+                agent_config,
+                agent_stats.consecutive_failures,
+            );
+        }
+        
+        ctx.accounts.trade_index.push(trade_action.key(), clock.unix_timestamp, success);
+        
         emit!(TradeExecutedEvent {
             agent: agent_config.key(),
             owner: agent_config.owner,
@@ -657,56 +1965,619 @@ pub mod sonic_agent {
             input_amount,
             output_amount,
             success,
+            direction,
+            reason_code,
+            fee_amount,
             trade_record: trade_action.key(),
+            trade_hash,
             timestamp: clock.unix_timestamp,
         });
         
         Ok(())
     }
     
-    /// Record portfolio performance data point
-    pub fn record_performance(
-        ctx: Context<RecordPerformance>,
-        portfolio_value: u64,
-        daily_profit_loss: i64,
+    /// Swap through a route program and record the trade from the measured
+    /// token balance changes rather than caller-supplied amounts.
+    /// `remaining_accounts` holds the route's accounts; when `min_pool_liquidity`
+    /// is set, `pool_reserve` must be one of them. `swap_data` is the
+    /// route program's instruction data. The whole swap reverts if the
+    /// measured output breaks the agent's slippage limit. `direction`,
+    /// `reason_code` and `client_trade_id` are handled as in `record_trade`.
+    pub fn execute_and_record_trade<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteAndRecordTrade<'info>>,
+        trade: ExecuteAndRecordTradeParams,
     ) -> Result<()> {
-        let agent_stats = &mut ctx.accounts.agent_stats;
+        let ExecuteAndRecordTradeParams {
+            strategy_id,
+            input_amount,
+            expected_output,
+            swap_data,
+            reason,
+            direction,
+            reason_code,
+            client_trade_id: _, // Only used in the trade account's seeds
+        } = trade;
+        let trade_bump = *ctx.bumps.get("trade_action").unwrap();
         let clock = Clock::get()?;
+        let input_mint = ctx.accounts.source_token_account.mint;
+        let output_mint = ctx.accounts.destination_token_account.mint;
         
-        // Create new performance data point
-        let data_point = PerformancePoint {
+        // Validate inputs
+        let direction = {
+            let agent_config = &mut ctx.accounts.agent_config;
+            check_trade_recorder(agent_config, &ctx.accounts.owner.key(), &ctx.accounts.instructions_sysvar)?;
+            check_trade_limits(
+                agent_config,
+                &ctx.accounts.agent_stats,
+                ctx.accounts.pool_reserve.as_deref(),
+                &input_mint,
+                &output_mint,
+                input_amount,
+                &reason,
+            )?;
+            require!(
+                agent_config.strategies.iter().any(|s| s.id == strategy_id),
+                ErrorCode::StrategyNotFound
+            );
+            consume_daily_trade(agent_config, clock.unix_timestamp)?;
+            trade_direction(&agent_config.quote_mint, &input_mint, &output_mint, direction)?
+        };
+        
+        // The reserve must belong to the pool the swap actually goes through
+        let route_accounts = ctx.remaining_accounts;
+        if let Some(pool_reserve) = &ctx.accounts.pool_reserve {
+            require!(
+                route_accounts.iter().any(|a| a.key() == pool_reserve.key()),
+                ErrorCode::InvalidPoolReserve
+            );
+        }
+        
+        let input_before = ctx.accounts.source_token_account.amount;
+        let output_before = ctx.accounts.destination_token_account.amount;
+        
+        let swap_ix = Instruction {
+            program_id: ctx.accounts.swap_program.key(),
+            accounts: route_accounts
+                .iter()
+                .map(|a| AccountMeta {
+                    pubkey: a.key(),
+                    is_signer: a.is_signer,
+                    is_writable: a.is_writable,
+                })
+                .collect(),
+            data: swap_data,
+        };
+        let mut swap_infos = route_accounts.to_vec();
+        swap_infos.push(ctx.accounts.swap_program.to_account_info());
+        invoke(&swap_ix, &swap_infos)?;
+        
+        // Measure what the swap actually moved
+        ctx.accounts.source_token_account.reload()?;
+        ctx.accounts.destination_token_account.reload()?;
+        let (input_spent, output_amount, slippage_bps) = measure_swap(
+            &ctx.accounts.agent_config.trading_rules,
+            (input_before, ctx.accounts.source_token_account.amount),
+            (output_before, ctx.accounts.destination_token_account.amount),
+            input_amount,
+            expected_output,
+        )?;
+        
+        let trade_action = &mut ctx.accounts.trade_action;
+        let agent_config = &mut ctx.accounts.agent_config;
+        let agent_stats = &mut ctx.accounts.agent_stats;
+        
+        // Record trade action
+        trade_action.agent = agent_config.key();
+        trade_action.owner = agent_config.owner;
+        trade_action.strategy_id = strategy_id;
+        trade_action.input_mint = input_mint;
+        trade_action.output_mint = output_mint;
+        trade_action.input_amount = input_spent;
+        trade_action.output_amount = output_amount;
+        trade_action.slippage_bps = slippage_bps;
+        trade_action.executed_at = clock.unix_timestamp;
+        trade_action.transaction_signature = [0; 64]; // Not known on chain; the trade is part of this transaction
+        trade_action.success = true;
+        trade_action.price_impact_bps = 0;
+        trade_action.reason = reason;
+        trade_action.reason_code = reason_code;
+        trade_action.prev_trade_hash = agent_config.last_trade_hash;
+        trade_action.direction = direction;
+        trade_action.bump = trade_bump;
+        let trade_hash = trade_chain_hash(trade_action)?;
+        agent_config.last_trade_hash = trade_hash;
+        
+        // Update agent statistics
+        agent_stats.record_trades(1, 1, input_spent)?;
+        agent_config.total_trade_volume = agent_config.total_trade_volume
+            .checked_add(input_spent)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        agent_config.total_executed_trades = agent_config.total_executed_trades
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        
+        update_cost_basis(
+            agent_stats,
+            &agent_config.quote_mint,
+            &input_mint,
+            &output_mint,
+            input_spent,
+            output_amount,
+            clock.unix_timestamp,
+        )?;
+        
+        // Update strategy execution stats
+        if let Some(s) = agent_config.strategies.iter_mut().find(|s| s.id == strategy_id) {
+            s.last_executed_at = clock.unix_timestamp;
+            s.execution_count = s.execution_count.checked_add(1).ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+        
+        agent_stats.last_updated_at = clock.unix_timestamp;
+        track_consecutive_failures(agent_config, agent_stats, true, clock.unix_timestamp);
+        
+        ctx.accounts.trade_index.push(trade_action.key(), clock.unix_timestamp, true);
+        
+        emit!(TradeExecutedEvent {
+            agent: agent_config.key(),
+            owner: agent_config.owner,
+            strategy_id,
+            input_mint,
+            output_mint,
+            input_amount: input_spent,
+            output_amount,
+            success: true,
+            direction,
+            reason_code,
+            fee_amount: 0, // Fees paid by this transaction aren't visible on chain
+            trade_record: trade_action.key(),
+            trade_hash,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    /// Record several trades at once.
+    /// Every trade is validated before anything is written, so the batch
+    /// either records completely or not at all. When `min_pool_liquidity` is
+    /// set, the trade accounts are followed by one pool reserve per trade.
+    /// Authorization, retry limits and slippage work as in `record_trade`: a
+    /// trade over the slippage limit is recorded as failed.
+    pub fn record_trades_batch<'info>(
+        ctx: Context<'_, '_, '_, 'info, RecordTradesBatch<'info>>,
+        trades: Vec<TradeRecordInput>,
+    ) -> Result<()> {
+        let agent_config = &mut ctx.accounts.agent_config;
+        let agent_stats = &mut ctx.accounts.agent_stats;
+        let clock = Clock::get()?;
+        let agent_key = agent_config.key();
+        
+        check_trade_recorder(agent_config, &ctx.accounts.authority.key(), &ctx.accounts.instructions_sysvar)?;
+        require!(!trades.is_empty() && trades.len() <= MAX_BATCH_TRADES, ErrorCode::InvalidBatchSize);
+        let reserves_required = agent_config.trading_rules.min_pool_liquidity > 0;
+        let account_count = if reserves_required { trades.len() * 2 } else { trades.len() };
+        require!(ctx.remaining_accounts.len() == account_count, ErrorCode::InvalidBatchSize);
+        let (trade_accounts, reserve_accounts) = ctx.remaining_accounts.split_at(trades.len());
+        
+        // Validate every trade first
+        for (index, trade) in trades.iter().enumerate() {
+            check_trading_rules(
+                &agent_config.trading_rules,
+                &trade.input_mint,
+                &trade.output_mint,
+                trade.input_amount,
+            )?;
+            check_close_only(agent_config, &trade.output_mint)?;
+            let pool_reserve = reserve_accounts
+                .get(index)
+                .map(Account::<TokenAccount>::try_from)
+                .transpose()?;
+            check_pool_liquidity(
+                &agent_config.trading_rules,
+                pool_reserve.as_deref(),
+                &trade.input_mint,
+                &trade.output_mint,
+            )?;
+            check_concentration(
+                agent_config,
+                agent_stats,
+                &trade.input_mint,
+                &trade.output_mint,
+                trade.input_amount,
+            )?;
+            require!(trade.reason.len() <= 200, ErrorCode::ReasonTooLong);
+            if trade.success {
+                check_output_consistency(trade.output_amount, trade.expected_output, trade.slippage_bps)?;
+            }
+            trade_direction(&agent_config.quote_mint, &trade.input_mint, &trade.output_mint, trade.direction)?;
+            check_retry_attempt(&agent_config.gas_settings, trade.attempt)?;
+            require!(
+                agent_config.strategies.iter().any(|s| s.id == trade.strategy_id),
+                ErrorCode::StrategyNotFound
+            );
+            consume_daily_trade(agent_config, clock.unix_timestamp)?;
+        }
+        
+        let mut successful_trades: u64 = 0;
+        let mut volume: u64 = 0;
+        let mut fees_paid: u64 = 0;
+        let mut retries: u64 = 0;
+        let mut breaker_tripped = false;
+        let max_slippage_bps = agent_config.trading_rules.max_slippage_bps;
+        
+        for (trade, account) in trades.iter().zip(trade_accounts.iter()) {
+            // A leg over the slippage limit is recorded as failed, as in `record_trade`
+            let success = settled_success(trade.success, trade.slippage_bps, max_slippage_bps);
+            if let Some((title, message, data)) = slippage_notice(trade.slippage_bps, max_slippage_bps) {
+                emit_notification(
+                    &[ctx.accounts.notification_prefs.to_account_info()],
+                    &mut ctx.accounts.notification_counter,
+                    agent_config.owner,
+                    Notification {
+                        event_type: NotificationEventType::SlippageExceeded,
+                        priority: NotificationPriority::Medium,
+                        title,
+                        message,
+                        data: Some(data),
+                        strategy_id: None,
+                        token_address: Some(trade.output_mint),
+                        tx_signature: None,
+                    },
+                );
+            }
+            
+            // Same address as `record_trade` gives the trade, so an id is recorded at most once
+            let expected = trade_address(&agent_key, &trade.strategy_id, &trade.client_trade_id, trade.bump)?;
+            let bump_bytes = [trade.bump];
+            let seeds: &[&[u8]] = &[
+                b"trade",
+                agent_key.as_ref(),
+                &trade.strategy_id,
+                &trade.client_trade_id,
+                &bump_bytes,
+            ];
+            require!(account.key() == expected, ErrorCode::InvalidTradeAccount);
+            
+            // Create the trade account
+            let space = TradeAction::space(trade.reason.len());
+            let lamports = Rent::get()?.minimum_balance(space);
+            invoke_signed(
+                &system_instruction::create_account(
+                    &ctx.accounts.authority.key(),
+                    &expected,
+                    lamports,
+                    space as u64,
+                    ctx.program_id,
+                ),
+                &[
+                    ctx.accounts.authority.to_account_info(),
+                    account.clone(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+                &[seeds],
+            )?;
+            
+            let direction = trade_direction(
+                &agent_config.quote_mint,
+                &trade.input_mint,
+                &trade.output_mint,
+                trade.direction,
+            )?;
+            let trade_action = TradeAction {
+                agent: agent_key,
+                owner: agent_config.owner,
+                strategy_id: trade.strategy_id,
+                input_mint: trade.input_mint,
+                output_mint: trade.output_mint,
+                input_amount: trade.input_amount,
+                output_amount: trade.output_amount,
+                slippage_bps: trade.slippage_bps,
+                executed_at: clock.unix_timestamp,
+                transaction_signature: trade.transaction_signature,
+                success,
+                price_impact_bps: trade.price_impact_bps,
+                reason: trade.reason.clone(),
+                reason_code: trade.reason_code,
+                prev_trade_hash: agent_config.last_trade_hash,
+                direction,
+                bump: trade.bump,
+            };
+            let trade_hash = trade_chain_hash(&trade_action)?;
+            agent_config.last_trade_hash = trade_hash;
+            let mut data = account.try_borrow_mut_data()?;
+            let mut writer: &mut [u8] = &mut data;
+            trade_action.try_serialize(&mut writer)?;
+            
+            ctx.accounts.trade_index.push(expected, clock.unix_timestamp, success);
+            breaker_tripped |= track_consecutive_failures(agent_config, agent_stats, success, clock.unix_timestamp);
+            fees_paid = fees_paid.checked_add(trade.fee_amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+            if trade.attempt > 0 {
+                retries += 1;
+            }
+            
+            if success {
+                successful_trades += 1;
+                volume = volume.checked_add(trade.input_amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+                
+                update_cost_basis(
+                    agent_stats,
+                    &agent_config.quote_mint,
+                    &trade.input_mint,
+                    &trade.output_mint,
+                    trade.input_amount,
+                    trade.output_amount,
+                    clock.unix_timestamp,
+                )?;
+                
+                // Update strategy execution stats
+                if let Some(s) = agent_config.strategies.iter_mut().find(|s| s.id == trade.strategy_id) {
+                    s.last_executed_at = clock.unix_timestamp;
+                    s.execution_count = s.execution_count.checked_add(1).ok_or(ErrorCode::ArithmeticOverflow)?;
+                }
+            }
+            
+            emit!(TradeExecutedEvent {
+                agent: agent_key,
+                owner: agent_config.owner,
+                strategy_id: trade.strategy_id,
+                input_mint: trade.input_mint,
+                output_mint: trade.output_mint,
+                input_amount: trade.input_amount,
+                output_amount: trade.output_amount,
+                success,
+                direction,
+                reason_code: trade.reason_code,
+                fee_amount: trade.fee_amount,
+                trade_record: expected,
+                trade_hash,
+                timestamp: clock.unix_timestamp,
+            });
+        }
+        
+        // Update agent statistics once with the aggregate totals
+        let trade_count = trades.len() as u64;
+        agent_stats.record_trades(trade_count, successful_trades, volume)?;
+        agent_stats.record_fee(fees_paid)?;
+        agent_stats.retry_count = agent_stats.retry_count
+            .checked_add(retries)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        agent_config.total_trade_volume = agent_config.total_trade_volume
+            .checked_add(volume)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        agent_config.total_executed_trades = agent_config.total_executed_trades
+            .checked_add(successful_trades)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        agent_stats.last_updated_at = clock.unix_timestamp;
+        
+        if breaker_tripped {
+            notify_failure_pause(
+                &[ctx.accounts.notification_prefs.to_account_info()],
+                &mut ctx.accounts.notification_counter,
+                agent_config,
+                agent_stats.consecutive_failures,
+            );
+        }
+        
+        Ok(())
+    }
+    
+    /// Initialize the program-wide configuration
+    pub fn init_program_config(ctx: Context<InitProgramConfig>) -> Result<()> {
+        let program_config = &mut ctx.accounts.program_config;
+        
+        program_config.admin = ctx.accounts.admin.key();
+        program_config.globally_paused = false;
+        program_config.bump = *ctx.bumps.get("program_config").unwrap();
+        
+        Ok(())
+    }
+    
+    /// Pause or resume trading for every agent (admin only)
+    pub fn set_global_pause(ctx: Context<SetGlobalPause>, paused: bool) -> Result<()> {
+        let program_config = &mut ctx.accounts.program_config;
+        let clock = Clock::get()?;
+        
+        program_config.globally_paused = paused;
+        
+        emit!(GlobalPauseChangedEvent {
+            admin: program_config.admin,
+            paused,
             timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    /// Record portfolio performance data point. Only the agent owner may
+    /// report a value, since a low one can trigger the drawdown pause.
+    /// `valuation_decimals` states the units the figures are in and must match
+    /// the agent's valuation mint.
+    pub fn record_performance(
+        mut ctx: Context<RecordPerformance>,
+        portfolio_value: u64,
+        daily_profit_loss: i64,
+        valuation_decimals: u8,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        
+        // Values must be expressed in the agent's valuation units
+        ctx.accounts.agent_stats.check_valuation_units(valuation_decimals)?;
+        
+        let accounts = &mut ctx.accounts;
+        record_performance_point(
+            &mut accounts.agent_config,
+            &mut accounts.agent_stats,
+            &mut accounts.notification_counter,
+            &[accounts.notification_prefs.to_account_info()],
             portfolio_value,
             daily_profit_loss,
+            clock.unix_timestamp,
+        )
+    }
+    
+    /// Value the owner's token balances on chain and record the result as a
+    /// performance point, instead of trusting a caller-supplied value.
+    /// `remaining_accounts` holds, for each token, the owner's associated token
+    /// account, its mint, the mint's `PriceFeedMapping` and the Pyth price
+    /// update. Values are
+    /// converted to the valuation mint at its own Pyth price, and daily P/L is
+    /// the change since the previous point. Only the owner may take a snapshot,
+    /// since leaving tokens out would understate the value and could trigger
+    /// the drawdown pause.
+    pub fn snapshot_portfolio<'info>(
+        mut ctx: Context<'_, '_, '_, 'info, SnapshotPortfolio<'info>>,
+        token_count: u8,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let token_count = token_count as usize;
+        require!(
+            token_count > 0 && token_count <= MAX_SNAPSHOT_TOKENS,
+            ErrorCode::InvalidSnapshotAccounts
+        );
+        require!(
+            ctx.remaining_accounts.len() == token_count * 4,
+            ErrorCode::InvalidSnapshotAccounts
+        );
+        
+        let usd_value = token_balances_usd_value(
+            ctx.remaining_accounts,
+            &ctx.accounts.agent_config.owner,
+            clock.unix_timestamp,
+        )?;
+        
+        let (valuation_price, _) = price_alerts::read_pyth_price(
+            &ctx.accounts.valuation_price_update,
+            &ctx.accounts.valuation_price_feed.feed_id,
+            clock.unix_timestamp,
+        )?;
+        let portfolio_value = ctx.accounts.agent_stats.valuation_amount(usd_value, valuation_price)?;
+        
+        let daily_profit_loss = match ctx.accounts.agent_stats.performance_data.last() {
+            Some(previous) => value_change(previous.portfolio_value, portfolio_value)?,
+            None => 0,
         };
         
-        // Add data point
-        agent_stats.performance_data.push(data_point);
+        let accounts = &mut ctx.accounts;
+        record_performance_point(
+            &mut accounts.agent_config,
+            &mut accounts.agent_stats,
+            &mut accounts.notification_counter,
+            &[accounts.notification_prefs.to_account_info()],
+            portfolio_value,
+            daily_profit_loss,
+            clock.unix_timestamp,
+        )
+    }
+    
+    /// Emit the agent's most recent trades, newest first
+    pub fn get_recent_trades(
+        ctx: Context<GetRecentTrades>,
+        limit: u8,
+        successful_only: bool,
+    ) -> Result<()> {
+        let trade_index = &ctx.accounts.trade_index;
+        let clock = Clock::get()?;
+        
+        let mut trades = trade_index.recent(successful_only);
+        trades.truncate(limit as usize);
         
-        // Limit the size of performance history (keep the last 30 days)
-        if agent_stats.performance_data.len() > 30 {
-            agent_stats.performance_data.remove(0);
-        }
+        emit!(RecentTradesEvent {
+            agent: trade_index.agent,
+            trades,
+            timestamp: clock.unix_timestamp,
+        });
         
-        // Update profit/loss
-        agent_stats.profit_loss += daily_profit_loss;
-        agent_stats.last_updated_at = clock.unix_timestamp;
+        Ok(())
+    }
+    
+    /// Emit a page of the agent's trade history, newest first, starting below
+    /// `before_nonce`. Pass `u64::MAX` for the first page and the returned
+    /// cursor for the next; history older than the index capacity is not kept.
+    pub fn get_trade_page(
+        ctx: Context<GetRecentTrades>,
+        before_nonce: u64,
+        limit: u8,
+    ) -> Result<()> {
+        let trade_index = &ctx.accounts.trade_index;
+        
+        let trades = trade_index.page(before_nonce, limit as usize);
+        let next_cursor = trades.last().map(|entry| entry.nonce);
+        
+        emit!(TradePageEvent {
+            agent: trade_index.agent,
+            head_nonce: trade_index.trade_nonce,
+            trades,
+            next_cursor,
+        });
+        
+        Ok(())
+    }
+    
+    /// Emit a summary of the agent's computed performance metrics
+    pub fn compute_agent_summary(ctx: Context<ComputeAgentSummary>) -> Result<()> {
+        let agent_stats = &ctx.accounts.agent_stats;
+        let clock = Clock::get()?;
         
-        emit!(PerformanceRecordedEvent {
+        emit!(AgentSummaryEvent {
             agent: agent_stats.agent,
             owner: agent_stats.owner,
-            portfolio_value,
-            daily_profit_loss,
+            total_trades: agent_stats.total_trades,
+            win_rate_bps: agent_stats.win_rate_bps(),
+            avg_trade_volume: agent_stats.avg_trade_volume(),
+            profit_loss_7d: agent_stats.profit_loss_since(clock.unix_timestamp, 7 * 86400),
+            profit_loss_30d: agent_stats.profit_loss_since(clock.unix_timestamp, 30 * 86400),
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+    
+    /// Return a compact view of the agent's config and stats as return data,
+    /// so a single simulated call gives clients the whole picture
+    pub fn get_agent_snapshot(ctx: Context<ComputeAgentSummary>) -> Result<AgentSnapshot> {
+        Ok(AgentSnapshot::new(
+            ctx.accounts.agent_config.key(),
+            &ctx.accounts.agent_config,
+            &ctx.accounts.agent_stats,
+        ))
+    }
+    
+    /// Return the compute unit limit and price the agent's gas settings ask
+    /// for, so clients can add the matching compute budget instructions to
+    /// the transactions that record its trades
+    pub fn get_compute_budget(ctx: Context<GetComputeBudget>) -> Result<ComputeBudgetRequest> {
+        compute_budget_request(&ctx.accounts.agent_config.gas_settings)
+    }
+    
+    /// Zero the agent's trade and performance stats to start fresh, keeping
+    /// its configuration, open cost basis positions and `created_at`. The
+    /// pre-reset totals are emitted for archival. The portfolio peak is
+    /// cleared too, so drawdown is measured from the next recorded value.
+    pub fn reset_agent_stats(ctx: Context<ResetAgentStats>) -> Result<()> {
+        let agent_stats = &mut ctx.accounts.agent_stats;
+        let clock = Clock::get()?;
+        
+        emit!(StatsResetEvent {
+            agent: agent_stats.agent,
+            owner: agent_stats.owner,
+            total_trades: agent_stats.total_trades,
+            successful_trades: agent_stats.successful_trades,
+            failed_trades: agent_stats.failed_trades,
+            total_volume: agent_stats.total_volume,
+            total_fees_paid: agent_stats.total_fees_paid,
+            profit_loss: agent_stats.profit_loss,
             timestamp: clock.unix_timestamp,
         });
         
+        agent_stats.reset(clock.unix_timestamp);
+        
         Ok(())
     }
 }
 
 /// Accounts for initializing an agent
 #[derive(Accounts)]
-#[instruction(name: String, description: String, risk_profile: RiskProfile, bump: u8)]
+#[instruction(params: NewAgentParams)]
 pub struct InitializeAgent<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
@@ -714,21 +2585,101 @@ pub struct InitializeAgent<'info> {
     #[account(
         init,
         payer = owner,
-        space = 8 + size_of::<AgentConfig>() + 200, // Extra space for vectors
-        seeds = [b"agent", owner.key().as_ref()],
-        bump = bump
+        space = AgentConfig::space(
+            params.max_strategies as usize,
+            MAX_ALLOCATIONS,
+            MAX_AGENT_NAME_LEN,
+            MAX_AGENT_DESCRIPTION_LEN
+        ),
+        seeds = [b"agent", params.agent_id.as_ref()],
+        bump
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+    
+    #[account(
+        init,
+        payer = owner,
+        space = AgentStats::space(PERFORMANCE_RETENTION),
+        seeds = [b"stats", agent_config.key().as_ref()],
+        bump
+    )]
+    pub agent_stats: Account<'info, AgentStats>,
+    
+    #[account(
+        init,
+        payer = owner,
+        space = AgentTradeIndex::SPACE,
+        seeds = [b"trade-index", agent_config.key().as_ref()],
+        bump
+    )]
+    pub trade_index: Account<'info, AgentTradeIndex>,
+    
+    /// Mint portfolio values and P/L are denominated in, e.g. USDC
+    pub valuation_mint: Account<'info, Mint>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for onboarding a new user
+#[derive(Accounts)]
+#[instruction(params: NewAgentParams, max_alerts: u8)]
+pub struct OnboardUser<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = owner,
+        space = AgentConfig::space(
+            params.max_strategies as usize,
+            MAX_ALLOCATIONS,
+            MAX_AGENT_NAME_LEN,
+            MAX_AGENT_DESCRIPTION_LEN
+        ),
+        seeds = [b"agent", params.agent_id.as_ref()],
+        bump
     )]
     pub agent_config: Account<'info, AgentConfig>,
     
     #[account(
         init,
         payer = owner,
-        space = 8 + size_of::<AgentStats>() + 200, // Extra space for performance data
+        space = AgentStats::space(PERFORMANCE_RETENTION),
         seeds = [b"stats", agent_config.key().as_ref()],
-        bump = bump
+        bump
     )]
     pub agent_stats: Account<'info, AgentStats>,
     
+    #[account(
+        init,
+        payer = owner,
+        space = AgentTradeIndex::SPACE,
+        seeds = [b"trade-index", agent_config.key().as_ref()],
+        bump
+    )]
+    pub trade_index: Account<'info, AgentTradeIndex>,
+    
+    #[account(
+        init,
+        payer = owner,
+        space = price_alerts::UserPriceAlerts::space(max_alerts as usize),
+        seeds = [b"price_alerts", owner.key().as_ref()],
+        bump
+    )]
+    pub price_alerts: Account<'info, price_alerts::UserPriceAlerts>,
+    
+    #[account(
+        init,
+        payer = owner,
+        space = NotificationCounter::SPACE,
+        seeds = [b"notification-counter", owner.key().as_ref()],
+        bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+    
+    /// Mint portfolio values and P/L are denominated in, e.g. USDC
+    pub valuation_mint: Account<'info, Mint>,
+    
     pub system_program: Program<'info, System>,
 }
 
@@ -740,107 +2691,3790 @@ pub struct UpdateAgentConfig<'info> {
     
     #[account(
         mut,
-        seeds = [b"agent", owner.key().as_ref()],
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
         bump = agent_config.bump,
-        constraint = agent_config.owner == owner.key() @ ErrorCode::Unauthorized
+        constraint = agent_config.is_owned_by(&owner.key()) @ CommonError::Unauthorized
     )]
     pub agent_config: Account<'info, AgentConfig>,
     
     pub system_program: Program<'info, System>,
 }
 
-/// Accounts for updating agent status
+/// Accounts for growing an agent's strategy capacity
 #[derive(Accounts)]
-pub struct UpdateAgentStatus<'info> {
+#[instruction(max_strategies: u8)]
+pub struct ExpandStrategyCapacity<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
     
     #[account(
         mut,
-        seeds = [b"agent", owner.key().as_ref()],
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
         bump = agent_config.bump,
-        constraint = agent_config.owner == owner.key() @ ErrorCode::Unauthorized
+        constraint = agent_config.is_owned_by(&owner.key()) @ CommonError::Unauthorized,
+        realloc = agent_config.to_account_info().data_len()
+            + max_strategies.saturating_sub(agent_config.max_strategies) as usize * Strategy::MAX_SIZE,
+        realloc::payer = owner,
+        realloc::zero = false
     )]
     pub agent_config: Account<'info, AgentConfig>,
     
     pub system_program: Program<'info, System>,
 }
 
-/// Accounts for recording a trade
+/// Accounts for proposing a trading rules change
 #[derive(Accounts)]
-#[instruction(
-    strategy_id: [u8; 16],
-    input_mint: Pubkey,
-    output_mint: Pubkey,
-    input_amount: u64,
-    output_amount: u64,
-    slippage_bps: u16,
-    transaction_signature: [u8; 64],
-    success: bool,
-    price_impact_bps: u16,
-    reason: String,
-    bump: u8
-)]
-pub struct RecordTrade<'info> {
+pub struct ProposeRulesChange<'info> {
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub owner: Signer<'info>,
     
     #[account(
-        mut,
-        seeds = [b"agent", agent_config.owner.as_ref()],
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
         bump = agent_config.bump,
-        constraint = agent_config.status == AgentStatus::Active @ ErrorCode::AgentNotActive
+        constraint = agent_config.is_owned_by(&owner.key()) @ CommonError::Unauthorized,
+        constraint = !agent_config.approvers.is_empty() @ CommonError::InvalidParameter
     )]
     pub agent_config: Account<'info, AgentConfig>,
     
     #[account(
-        mut,
-        seeds = [b"stats", agent_config.key().as_ref()],
-        bump = agent_stats.bump,
-        constraint = agent_stats.agent == agent_config.key() @ ErrorCode::InvalidAgentStats
+        init,
+        payer = owner,
+        space = PendingRulesChange::SPACE,
+        seeds = [b"rules-change", agent_config.key().as_ref()],
+        bump
     )]
-    pub agent_stats: Account<'info, AgentStats>,
+    pub pending_change: Account<'info, PendingRulesChange>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for approving a trading rules change
+#[derive(Accounts)]
+pub struct ApproveRulesChange<'info> {
+    pub approver: Signer<'info>,
     
     #[account(
-        init,
-        payer = authority,
-        space = 8 + size_of::<TradeAction>() + reason.len(),
-        seeds = [
-            b"trade",
-            agent_config.key().as_ref(),
-            &strategy_id,
-            &Clock::get()?.unix_timestamp.to_le_bytes()
-        ],
-        bump = bump
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump
     )]
-    pub trade_action: Account<'info, TradeAction>,
+    pub agent_config: Account<'info, AgentConfig>,
     
-    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"rules-change", agent_config.key().as_ref()],
+        bump = pending_change.bump
+    )]
+    pub pending_change: Account<'info, PendingRulesChange>,
 }
 
-/// Accounts for recording performance
+/// Accounts for applying an approved trading rules change
 #[derive(Accounts)]
-pub struct RecordPerformance<'info> {
+pub struct ApplyRulesChange<'info> {
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub owner: Signer<'info>,
     
     #[account(
         mut,
-        seeds = [b"agent", agent_stats.owner.as_ref()],
-        bump,
-        constraint = agent_config.status == AgentStatus::Active @ ErrorCode::AgentNotActive
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump,
+        constraint = agent_config.is_owned_by(&owner.key()) @ CommonError::Unauthorized
     )]
     pub agent_config: Account<'info, AgentConfig>,
     
     #[account(
         mut,
-        seeds = [b"stats", agent_config.key().as_ref()],
-        bump = agent_stats.bump,
-        constraint = agent_stats.agent == agent_config.key() @ ErrorCode::InvalidAgentStats
+        close = owner,
+        seeds = [b"rules-change", agent_config.key().as_ref()],
+        bump = pending_change.bump
     )]
-    pub agent_stats: Account<'info, AgentStats>,
+    pub pending_change: Account<'info, PendingRulesChange>,
+}
+
+/// Accounts for cancelling a pending trading rules change
+#[derive(Accounts)]
+pub struct CancelRulesChange<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
     
-    pub system_program: Program<'info, System>,
+    #[account(
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump,
+        constraint = agent_config.is_owned_by(&owner.key()) @ CommonError::Unauthorized
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+    
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"rules-change", agent_config.key().as_ref()],
+        bump = pending_change.bump
+    )]
+    pub pending_change: Account<'info, PendingRulesChange>,
 }
 
-///
\ No newline at end of file
+/// Accounts for updating agent status
+#[derive(Accounts)]
+pub struct UpdateAgentStatus<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump,
+        constraint = agent_config.is_owned_by(&owner.key()) @ CommonError::Unauthorized
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for transferring agent ownership
+#[derive(Accounts)]
+#[instruction(new_owner: Pubkey)]
+pub struct TransferAgentOwnership<'info> {
+    pub owner: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump,
+        constraint = agent_config.is_owned_by(&owner.key()) @ CommonError::Unauthorized
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"stats", agent_config.key().as_ref()],
+        bump = agent_stats.bump,
+        constraint = agent_stats.agent == agent_config.key() @ ErrorCode::InvalidAgentStats
+    )]
+    pub agent_stats: Account<'info, AgentStats>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", owner.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", new_owner.as_ref()],
+        bump = new_owner_notification_counter.bump
+    )]
+    pub new_owner_notification_counter: Account<'info, NotificationCounter>,
+    
+    /// CHECK: Owner's notification preferences; when not created yet, every channel is used
+    #[account(seeds = [b"notification-prefs", owner.key().as_ref()], bump)]
+    pub notification_prefs: UncheckedAccount<'info>,
+    
+    /// CHECK: New owner's notification preferences; when not created yet, every channel is used
+    #[account(seeds = [b"notification-prefs", new_owner.as_ref()], bump)]
+    pub new_owner_notification_prefs: UncheckedAccount<'info>,
+}
+
+/// Accounts for upgrading an agent to the current schema version
+#[derive(Accounts)]
+#[instruction(agent_id: [u8; 16])]
+pub struct MigrateAgentV2<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    /// CHECK: May predate the current layout; loaded and owner-checked in `migrate_agent_v2`
+    #[account(
+        mut,
+        seeds = [b"agent", agent_id.as_ref()],
+        bump
+    )]
+    pub agent_config: UncheckedAccount<'info>,
+    
+    /// CHECK: May predate the current layout; loaded in `migrate_agent_v2`
+    #[account(
+        mut,
+        seeds = [b"stats", agent_config.key().as_ref()],
+        bump
+    )]
+    pub agent_stats: UncheckedAccount<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for dry-running a trade against the agent's rules
+#[derive(Accounts)]
+pub struct ValidateTrade<'info> {
+    #[account(
+        seeds = [b"program-config"],
+        bump = program_config.bump,
+        constraint = program_config.trading_allowed() @ ErrorCode::GloballyPaused
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    
+    #[account(
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump,
+        constraint = agent_config.accepts_trades() @ ErrorCode::AgentNotActive
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+    
+    #[account(
+        seeds = [b"stats", agent_config.key().as_ref()],
+        bump = agent_stats.bump,
+        constraint = agent_stats.agent == agent_config.key() @ ErrorCode::InvalidAgentStats
+    )]
+    pub agent_stats: Account<'info, AgentStats>,
+    
+    /// Pool reserve of a traded token, required when `min_pool_liquidity` is set
+    pub pool_reserve: Option<Account<'info, TokenAccount>>,
+}
+
+/// Accounts for recording a trade
+#[derive(Accounts)]
+#[instruction(trade: RecordTradeParams)]
+pub struct RecordTrade<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"program-config"],
+        bump = program_config.bump,
+        constraint = program_config.trading_allowed() @ ErrorCode::GloballyPaused
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump,
+        constraint = agent_config.accepts_trades() @ ErrorCode::AgentNotActive
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"stats", agent_config.key().as_ref()],
+        bump = agent_stats.bump,
+        constraint = agent_stats.agent == agent_config.key() @ ErrorCode::InvalidAgentStats
+    )]
+    pub agent_stats: Account<'info, AgentStats>,
+    
+    #[account(
+        mut,
+        seeds = [b"trade-index", agent_config.key().as_ref()],
+        bump = trade_index.bump
+    )]
+    pub trade_index: Account<'info, AgentTradeIndex>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = TradeAction::space(trade.reason.len()),
+        seeds = [
+            b"trade",
+            agent_config.key().as_ref(),
+            &trade.strategy_id,
+            &trade.client_trade_id
+        ],
+        bump
+    )]
+    pub trade_action: Account<'info, TradeAction>,
+    
+    /// Pool reserve of a traded token, required when `min_pool_liquidity` is set
+    pub pool_reserve: Option<Account<'info, TokenAccount>>,
+    
+    pub system_program: Program<'info, System>,
+    
+    /// CHECK: Address is checked against the instructions sysvar ID
+    #[account(address = solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", agent_config.owner.as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+    
+    /// CHECK: Owner's notification preferences; when not created yet, every channel is used
+    #[account(seeds = [b"notification-prefs", agent_config.owner.as_ref()], bump)]
+    pub notification_prefs: UncheckedAccount<'info>,
+}
+
+/// Accounts for swapping through a route program and recording the result
+#[derive(Accounts)]
+#[instruction(trade: ExecuteAndRecordTradeParams)]
+pub struct ExecuteAndRecordTrade<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    #[account(
+        seeds = [b"program-config"],
+        bump = program_config.bump,
+        constraint = program_config.trading_allowed() @ ErrorCode::GloballyPaused
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump,
+        constraint = agent_config.is_owned_by(&owner.key()) @ CommonError::Unauthorized,
+        constraint = agent_config.accepts_trades() @ ErrorCode::AgentNotActive
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"stats", agent_config.key().as_ref()],
+        bump = agent_stats.bump,
+        constraint = agent_stats.agent == agent_config.key() @ ErrorCode::InvalidAgentStats
+    )]
+    pub agent_stats: Account<'info, AgentStats>,
+    
+    #[account(
+        mut,
+        seeds = [b"trade-index", agent_config.key().as_ref()],
+        bump = trade_index.bump
+    )]
+    pub trade_index: Account<'info, AgentTradeIndex>,
+    
+    #[account(
+        init,
+        payer = owner,
+        space = TradeAction::space(trade.reason.len()),
+        seeds = [
+            b"trade",
+            agent_config.key().as_ref(),
+            &trade.strategy_id,
+            &trade.client_trade_id
+        ],
+        bump
+    )]
+    pub trade_action: Account<'info, TradeAction>,
+    
+    /// Token account the swap spends from
+    #[account(mut, constraint = source_token_account.owner == owner.key() @ CommonError::Unauthorized)]
+    pub source_token_account: Account<'info, TokenAccount>,
+    
+    /// Token account the swap pays into
+    #[account(mut, constraint = destination_token_account.owner == owner.key() @ CommonError::Unauthorized)]
+    pub destination_token_account: Account<'info, TokenAccount>,
+    
+    /// CHECK: Swap route program (e.g. Jupiter); must be executable and not this program
+    #[account(
+        executable,
+        constraint = swap_program.key() != crate::ID @ ErrorCode::InvalidSwapProgram
+    )]
+    pub swap_program: UncheckedAccount<'info>,
+    
+    /// Pool reserve of a traded token, required when `min_pool_liquidity` is set
+    pub pool_reserve: Option<Account<'info, TokenAccount>>,
+    
+    pub system_program: Program<'info, System>,
+    
+    /// CHECK: Address is checked against the instructions sysvar ID
+    #[account(address = solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+/// Accounts for recording performance
+#[derive(Accounts)]
+pub struct RecordPerformance<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"program-config"],
+        bump = program_config.bump,
+        constraint = program_config.trading_allowed() @ ErrorCode::GloballyPaused
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump,
+        constraint = agent_config.accepts_trades() @ ErrorCode::AgentNotActive,
+        constraint = agent_config.is_owned_by(&authority.key()) @ CommonError::Unauthorized
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"stats", agent_config.key().as_ref()],
+        bump = agent_stats.bump,
+        constraint = agent_stats.agent == agent_config.key() @ ErrorCode::InvalidAgentStats
+    )]
+    pub agent_stats: Account<'info, AgentStats>,
+    
+    pub system_program: Program<'info, System>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", agent_config.owner.as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+    
+    /// CHECK: Owner's notification preferences; when not created yet, every channel is used
+    #[account(seeds = [b"notification-prefs", agent_config.owner.as_ref()], bump)]
+    pub notification_prefs: UncheckedAccount<'info>,
+}
+
+/// Accounts for recording an on-chain portfolio valuation
+#[derive(Accounts)]
+pub struct SnapshotPortfolio<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"program-config"],
+        bump = program_config.bump,
+        constraint = program_config.trading_allowed() @ ErrorCode::GloballyPaused
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump,
+        constraint = agent_config.accepts_trades() @ ErrorCode::AgentNotActive,
+        constraint = agent_config.is_owned_by(&authority.key()) @ CommonError::Unauthorized
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"stats", agent_config.key().as_ref()],
+        bump = agent_stats.bump,
+        constraint = agent_stats.agent == agent_config.key() @ ErrorCode::InvalidAgentStats
+    )]
+    pub agent_stats: Account<'info, AgentStats>,
+    
+    /// Price feed of the agent's valuation mint
+    #[account(
+        seeds = [b"price-feed", agent_stats.valuation_mint.as_ref()],
+        bump = valuation_price_feed.bump
+    )]
+    pub valuation_price_feed: Account<'info, price_alerts::PriceFeedMapping>,
+    
+    /// CHECK: Owner, discriminator and feed id are checked when the price is read
+    #[account(owner = price_alerts::PYTH_RECEIVER_PROGRAM_ID)]
+    pub valuation_price_update: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", agent_config.owner.as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+    
+    /// CHECK: Owner's notification preferences; when not created yet, every channel is used
+    #[account(seeds = [b"notification-prefs", agent_config.owner.as_ref()], bump)]
+    pub notification_prefs: UncheckedAccount<'info>,
+}
+
+/// Accounts for recording a batch of trades.
+/// One uninitialized `TradeAction` PDA per trade is passed via `remaining_accounts`,
+/// followed by one pool reserve per trade when `min_pool_liquidity` is set.
+#[derive(Accounts)]
+pub struct RecordTradesBatch<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"program-config"],
+        bump = program_config.bump,
+        constraint = program_config.trading_allowed() @ ErrorCode::GloballyPaused
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump,
+        constraint = agent_config.accepts_trades() @ ErrorCode::AgentNotActive
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"stats", agent_config.key().as_ref()],
+        bump = agent_stats.bump,
+        constraint = agent_stats.agent == agent_config.key() @ ErrorCode::InvalidAgentStats
+    )]
+    pub agent_stats: Account<'info, AgentStats>,
+    
+    #[account(
+        mut,
+        seeds = [b"trade-index", agent_config.key().as_ref()],
+        bump = trade_index.bump
+    )]
+    pub trade_index: Account<'info, AgentTradeIndex>,
+    
+    pub system_program: Program<'info, System>,
+    
+    /// CHECK: Address is checked against the instructions sysvar ID
+    #[account(address = solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", agent_config.owner.as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+    
+    /// CHECK: Owner's notification preferences; when not created yet, every channel is used
+    #[account(seeds = [b"notification-prefs", agent_config.owner.as_ref()], bump)]
+    pub notification_prefs: UncheckedAccount<'info>,
+}
+
+/// Accounts for initializing the program configuration
+#[derive(Accounts)]
+pub struct InitProgramConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + 1 + 1, // discriminator + admin + globally_paused + bump
+        seeds = [b"program-config"],
+        bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for toggling the global pause
+#[derive(Accounts)]
+pub struct SetGlobalPause<'info> {
+    pub admin: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"program-config"],
+        bump = program_config.bump,
+        constraint = program_config.admin == admin.key() @ CommonError::Unauthorized
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+}
+
+/// Accounts for caching a mint's metadata
+#[derive(Accounts)]
+pub struct CacheTokenMeta<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = payer,
+        space = TokenMeta::SPACE,
+        seeds = [b"token-meta", mint.key().as_ref()],
+        bump
+    )]
+    pub token_meta: Account<'info, TokenMeta>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for refreshing a mint's cached metadata
+#[derive(Accounts)]
+pub struct RefreshTokenMeta<'info> {
+    pub mint: Account<'info, Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"token-meta", mint.key().as_ref()],
+        bump = token_meta.bump
+    )]
+    pub token_meta: Account<'info, TokenMeta>,
+}
+
+/// Accounts for querying recent trades
+#[derive(Accounts)]
+pub struct GetRecentTrades<'info> {
+    #[account(
+        seeds = [b"trade-index", trade_index.agent.as_ref()],
+        bump = trade_index.bump
+    )]
+    pub trade_index: Account<'info, AgentTradeIndex>,
+}
+
+/// Accounts for computing rebalance needs
+#[derive(Accounts)]
+pub struct ComputeRebalanceNeeds<'info> {
+    #[account(
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+}
+
+/// Accounts for recording a rebalance
+#[derive(Accounts)]
+pub struct RecordRebalance<'info> {
+    pub owner: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump,
+        constraint = agent_config.is_owned_by(&owner.key()) @ CommonError::Unauthorized
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+}
+
+/// Accounts for listing due strategies
+#[derive(Accounts)]
+pub struct DueStrategies<'info> {
+    #[account(
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+}
+
+/// Accounts for reading an agent's compute budget
+#[derive(Accounts)]
+pub struct GetComputeBudget<'info> {
+    #[account(
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+}
+
+/// Accounts for computing an agent summary
+#[derive(Accounts)]
+pub struct ComputeAgentSummary<'info> {
+    #[account(
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+    
+    #[account(
+        seeds = [b"stats", agent_config.key().as_ref()],
+        bump = agent_stats.bump,
+        constraint = agent_stats.agent == agent_config.key() @ ErrorCode::InvalidAgentStats
+    )]
+    pub agent_stats: Account<'info, AgentStats>,
+}
+
+/// Accounts for resetting an agent's stats
+#[derive(Accounts)]
+pub struct ResetAgentStats<'info> {
+    pub owner: Signer<'info>,
+    
+    #[account(
+        seeds = [b"agent", agent_config.agent_id.as_ref()],
+        bump = agent_config.bump,
+        constraint = agent_config.is_owned_by(&owner.key()) @ CommonError::Unauthorized
+    )]
+    pub agent_config: Account<'info, AgentConfig>,
+    
+    #[account(
+        mut,
+        seeds = [b"stats", agent_config.key().as_ref()],
+        bump = agent_stats.bump,
+        constraint = agent_stats.agent == agent_config.key() @ ErrorCode::InvalidAgentStats
+    )]
+    pub agent_stats: Account<'info, AgentStats>,
+}
+
+/// Event emitted when an agent is initialized
+#[event]
+pub struct AgentInitializedEvent {
+    pub agent: Pubkey,
+    pub owner: Pubkey,
+    pub name: String,
+    pub risk_profile: RiskProfile,
+    pub timestamp: i64,
+}
+
+/// Event emitted when an agent configuration is updated
+#[event]
+pub struct AgentUpdatedEvent {
+    pub agent: Pubkey,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+/// Event emitted when trading rules are updated
+#[event]
+pub struct TradingRulesUpdatedEvent {
+    pub agent: Pubkey,
+    pub owner: Pubkey,
+    pub max_amount_per_trade: u64,
+    pub max_slippage_bps: u16,
+    pub timestamp: i64,
+}
+
+/// Event emitted when gas settings are updated
+#[event]
+pub struct GasSettingsUpdatedEvent {
+    pub agent: Pubkey,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+/// Event emitted when a strategy is added or updated
+#[event]
+pub struct StrategyUpdatedEvent {
+    pub agent: Pubkey,
+    pub owner: Pubkey,
+    pub strategy_id: [u8; 16],
+    pub is_active: bool,
+    pub timestamp: i64,
+}
+
+/// Event emitted when a strategy is removed
+#[event]
+pub struct StrategyRemovedEvent {
+    pub agent: Pubkey,
+    pub owner: Pubkey,
+    pub strategy_id: [u8; 16],
+    pub timestamp: i64,
+}
+
+/// Event emitted when target allocations are updated
+#[event]
+pub struct AllocationsUpdatedEvent {
+    pub agent: Pubkey,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+/// Event emitted with each allocation's drift from its target
+#[event]
+pub struct RebalanceNeedsEvent {
+    pub agent: Pubkey,
+    pub total_usd_value: u64, // In micro-USD
+    pub allocations: Vec<AllocationDeviation>,
+    pub groups: Vec<GroupDeviation>,
+    pub timestamp: i64,
+}
+
+/// Event emitted when the agent status changes
+#[event]
+pub struct AgentStatusChangedEvent {
+    pub agent: Pubkey,
+    pub owner: Pubkey,
+    pub status: AgentStatus,
+    pub timestamp: i64,
+}
+
+/// Event emitted when an agent's stats are reset, with the totals before the reset
+#[event]
+pub struct StatsResetEvent {
+    pub agent: Pubkey,
+    pub owner: Pubkey,
+    pub total_trades: u64,
+    pub successful_trades: u64,
+    pub failed_trades: u64,
+    pub total_volume: u64,
+    pub total_fees_paid: u64,
+    pub profit_loss: i64,
+    pub timestamp: i64,
+}
+
+/// Event emitted when a trade is recorded
+#[event]
+pub struct TradeExecutedEvent {
+    pub agent: Pubkey,
+    pub owner: Pubkey,
+    pub strategy_id: [u8; 16],
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub input_amount: u64,
+    pub output_amount: u64,
+    pub success: bool,
+    pub direction: TradeDirection,
+    pub reason_code: TradeReason,
+    pub fee_amount: u64,
+    pub trade_record: Pubkey,
+    pub trade_hash: [u8; 32], // New head of the agent's trade hash chain
+    pub timestamp: i64,
+}
+
+/// Event emitted when a performance data point is recorded
+#[event]
+pub struct PerformanceRecordedEvent {
+    pub agent: Pubkey,
+    pub owner: Pubkey,
+    pub portfolio_value: u64,
+    pub daily_profit_loss: i64,
+    pub valuation_mint: Pubkey,
+    pub valuation_decimals: u8,
+    pub timestamp: i64,
+}
+
+/// Event emitted with an agent's recent trades
+#[event]
+pub struct RecentTradesEvent {
+    pub agent: Pubkey,
+    pub trades: Vec<TradeIndexEntry>,
+    pub timestamp: i64,
+}
+
+/// Event emitted with one page of an agent's trade history
+#[event]
+pub struct TradePageEvent {
+    pub agent: Pubkey,
+    pub head_nonce: u64,
+    pub trades: Vec<TradeIndexEntry>,
+    pub next_cursor: Option<u64>, // Pass as `before_nonce` to fetch the next page
+}
+
+/// Event emitted when a rebalance is recorded
+#[event]
+pub struct RebalanceRecordedEvent {
+    pub agent: Pubkey,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+/// Event emitted when the global pause is toggled
+#[event]
+pub struct GlobalPauseChangedEvent {
+    pub admin: Pubkey,
+    pub paused: bool,
+    pub timestamp: i64,
+}
+
+/// Event emitted when a mint's metadata is cached
+#[event]
+pub struct TokenMetaCachedEvent {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub symbol: [u8; TOKEN_SYMBOL_LEN],
+    pub timestamp: i64,
+}
+
+/// Event emitted when a sell realizes a gain or loss against cost basis
+#[event]
+pub struct RealizedGainEvent {
+    pub agent: Pubkey,
+    pub mint: Pubkey,
+    pub amount_sold: u64,
+    pub proceeds: u64,
+    pub cost_basis: u64,
+    pub realized_profit_loss: i64,
+    pub timestamp: i64,
+}
+
+/// Event emitted with the strategies that are due to run
+#[event]
+pub struct DueStrategiesEvent {
+    pub agent: Pubkey,
+    pub strategy_ids: Vec<[u8; 16]>,
+    pub timestamp: i64,
+}
+
+/// Event emitted with an agent's computed performance summary
+#[event]
+pub struct AgentSummaryEvent {
+    pub agent: Pubkey,
+    pub owner: Pubkey,
+    pub total_trades: u64,
+    pub win_rate_bps: u16,
+    pub avg_trade_volume: u64,
+    pub profit_loss_7d: i64,
+    pub profit_loss_30d: i64,
+    pub timestamp: i64,
+}
+
+/// Event emitted when an agent changes owner
+#[event]
+pub struct AgentOwnershipTransferredEvent {
+    pub agent: Pubkey,
+    pub previous_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+/// Error codes specific to the sonic_agent program (shared ones are in `CommonError`)
+#[error_code(offset = 6100)]
+pub enum ErrorCode {
+    #[msg("Name cannot be empty")]
+    InvalidName,
+    #[msg("Name is too long (max 50 characters)")]
+    NameTooLong,
+    #[msg("Description is too long (max 200 characters)")]
+    DescriptionTooLong,
+    #[msg("Rebalance threshold must be between 1 and 5000 basis points")]
+    InvalidThreshold,
+    #[msg("Max trades per day must be at most 100")]
+    InvalidTradeLimit,
+    #[msg("Invalid token mint")]
+    InvalidTokenMint,
+    #[msg("Slippage is too high (max 10%)")]
+    SlippageTooHigh,
+    #[msg("Compute units must be between 100,000 and 1,400,000")]
+    InvalidComputeUnits,
+    #[msg("Too many retries (max 10)")]
+    TooManyRetries,
+    #[msg("Strategy parameters are too large (max 1024 bytes)")]
+    ParametersTooLarge,
+    #[msg("Agent is at its strategy capacity")]
+    TooManyStrategies,
+    #[msg("Strategy not found")]
+    StrategyNotFound,
+    #[msg("Allocations cannot be empty")]
+    EmptyAllocations,
+    #[msg("Too many allocations (max 20)")]
+    TooManyAllocations,
+    #[msg("Allocations must sum to 100%")]
+    AllocationsMustSumTo100,
+    #[msg("Invalid allocation")]
+    InvalidAllocation,
+    #[msg("Deviation is too high (max 20%)")]
+    DeviationTooHigh,
+    #[msg("Invalid amount")]
+    InvalidAmount,
+    #[msg("Reason is too long (max 200 characters)")]
+    ReasonTooLong,
+    #[msg("Agent is not active")]
+    AgentNotActive,
+    #[msg("Invalid agent stats account")]
+    InvalidAgentStats,
+    #[msg("Trade amount exceeds the maximum per trade")]
+    TradeAmountTooLarge,
+    #[msg("Token is not allowed by the trading rules")]
+    TokenNotAllowed,
+    #[msg("Daily trade limit exceeded")]
+    DailyTradeLimitExceeded,
+    #[msg("Slippage exceeded")]
+    SlippageExceeded,
+    #[msg("Batch is empty or too large")]
+    InvalidBatchSize,
+    #[msg("Trade account does not match the expected address")]
+    InvalidTradeAccount,
+    #[msg("Pool liquidity is below the configured minimum")]
+    InsufficientLiquidity,
+    #[msg("Strategy parameters are missing or invalid for the strategy type")]
+    InvalidStrategyParameters,
+    #[msg("Trading is globally paused")]
+    GloballyPaused,
+    #[msg("A token cannot be both allowed and excluded")]
+    ConflictingTokenRules,
+    #[msg("Too many tokens in the allowed or excluded list (max 20)")]
+    TooManyTokenRules,
+    #[msg("Trading rule changes on this agent require approval")]
+    RulesChangeRequiresApproval,
+    #[msg("Approval threshold must be between 1 and the number of approvers (max 5)")]
+    InvalidApprovalThreshold,
+    #[msg("Signer is not an approver for this agent")]
+    NotAnApprover,
+    #[msg("Approval does not match the pending rules change")]
+    RulesChangeHashMismatch,
+    #[msg("Not enough approvals to apply the rules change")]
+    InsufficientApprovals,
+    #[msg("Strategy has no execution interval")]
+    StrategyNotScheduled,
+    #[msg("Strategy is not due to run yet")]
+    StrategyNotDue,
+    #[msg("No position held in the token being sold")]
+    NoPositionToSell,
+    #[msg("Sell amount exceeds the tracked position")]
+    InsufficientPosition,
+    #[msg("Too many tracked token positions (max 10)")]
+    TooManyPositions,
+    #[msg("Agent is close-only: trades must sell back into the quote mint")]
+    CloseOnlyMode,
+    #[msg("Agent has no quote mint configured")]
+    QuoteMintNotSet,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+    #[msg("Max drawdown must be at most 10000 basis points")]
+    InvalidDrawdownThreshold,
+    #[msg("Allocation does not match the mint account passed for it")]
+    AllocationMintMismatch,
+    #[msg("Expected one value per target allocation")]
+    AllocationLengthMismatch,
+    #[msg("Allocation has no USD price snapshot")]
+    MissingAllocationPrice,
+    #[msg("Trade attempt exceeds the configured retry limit")]
+    RetryLimitExceeded,
+    #[msg("Output amount implies more slippage than recorded")]
+    InconsistentTradeRecord,
+    #[msg("Invalid strategy capacity")]
+    InvalidMaxStrategies,
+    #[msg("Agent account is too small for its strategy capacity")]
+    InsufficientAccountSpace,
+    #[msg("Invalid swap program")]
+    InvalidSwapProgram,
+    #[msg("Concentration limit must be between 1 and 10000 basis points")]
+    InvalidConcentrationLimit,
+    #[msg("Trade would over-concentrate the portfolio in one token")]
+    ConcentrationLimitExceeded,
+    #[msg("Too many authorized programs (max 5)")]
+    TooManyAuthorizedPrograms,
+    #[msg("Trades for this agent must be recorded by an authorized program")]
+    UnauthorizedCaller,
+    #[msg("Invalid token metadata account")]
+    InvalidTokenMetadata,
+    #[msg("Token metadata is not cached for this mint")]
+    TokenMetaNotCached,
+    #[msg("Rebalance interval has not elapsed")]
+    RebalanceTooSoon,
+    #[msg("Values are not in the agent's valuation units")]
+    ValuationUnitsMismatch,
+    #[msg("Invalid allocation group")]
+    InvalidAllocationGroup,
+    #[msg("Group members must sum to the group's target")]
+    GroupAllocationMismatch,
+    #[msg("Trade direction contradicts the agent's quote mint")]
+    TradeDirectionMismatch,
+    #[msg("Priority fee is above the allowed maximum")]
+    PriorityFeeTooHigh,
+    #[msg("Invalid token, mint or price accounts for the snapshot")]
+    InvalidSnapshotAccounts,
+    #[msg("Trade amount is below the minimum trade size")]
+    TradeTooSmall,
+    #[msg("Rebalance trade tokens must be target allocations")]
+    RebalanceTokenNotAllocated,
+    #[msg("Rebalance trade would move the portfolio further from its targets")]
+    RebalanceIncreasesDeviation,
+    #[msg("Pool reserve does not hold a traded token or is not part of the swap route")]
+    InvalidPoolReserve,
+    #[msg("Valuation price must be positive")]
+    InvalidValuationPrice,
+}
+
+/// USD value of each allocation's raw balance, using its decimals and price snapshot
+fn allocation_usd_values(allocations: &[TokenAllocation], balances: &[u64]) -> Result<Vec<u128>> {
+    let mut usd_values = Vec::with_capacity(allocations.len());
+    for (allocation, balance) in allocations.iter().zip(balances.iter()) {
+        require!(allocation.usd_price > 0, ErrorCode::MissingAllocationPrice);
+        usd_values.push(
+            *balance as u128 * allocation.usd_price as u128 / 10u128.pow(allocation.decimals as u32)
+        );
+    }
+    Ok(usd_values)
+}
+
+/// Check that `min_rebalance_interval_secs` has passed since the last rebalance
+fn check_rebalance_interval(agent_config: &AgentConfig, now: i64) -> Result<()> {
+    let next_allowed = agent_config
+        .last_rebalance_at
+        .saturating_add(i64::try_from(agent_config.min_rebalance_interval_secs).unwrap_or(i64::MAX));
+    require!(now >= next_allowed, ErrorCode::RebalanceTooSoon);
+    
+    Ok(())
+}
+
+/// Summed distance of every allocation from its target weight, in millionths
+/// of a basis point so that small trades still register
+fn total_allocation_deviation(allocations: &[TokenAllocation], usd_values: &[u128]) -> u128 {
+    const SCALE: u128 = 1_000_000;
+    let total: u128 = usd_values.iter().sum();
+    
+    allocations
+        .iter()
+        .zip(usd_values.iter())
+        .map(|(allocation, value)| {
+            let current = (value * 10000 * SCALE).checked_div(total).unwrap_or(0);
+            current.abs_diff(allocation.target_percentage as u128 * SCALE)
+        })
+        .sum()
+}
+
+/// Each allocation's and each group's current weight against its target,
+/// given the allocations' USD values in allocation order
+fn rebalance_deviations(
+    allocations: &[TokenAllocation],
+    groups: &[AllocationGroup],
+    usd_values: &[u128],
+) -> (Vec<AllocationDeviation>, Vec<GroupDeviation>) {
+    let total: u128 = usd_values.iter().sum();
+    
+    let deviations = allocations
+        .iter()
+        .zip(usd_values.iter())
+        .map(|(allocation, value)| {
+            let current_bps = (value * 10000).checked_div(total).unwrap_or(0) as u16;
+            let deviation_bps = current_bps.abs_diff(allocation.target_percentage);
+            AllocationDeviation {
+                mint: allocation.mint,
+                group_id: allocation.group_id,
+                current_bps,
+                target_bps: allocation.target_percentage,
+                deviation_bps,
+                needs_rebalance: deviation_bps > allocation.max_deviation_bps,
+            }
+        })
+        .collect();
+    
+    // A group drifts when its members' combined weight does
+    let groups = groups
+        .iter()
+        .map(|group| {
+            let value: u128 = allocations
+                .iter()
+                .zip(usd_values.iter())
+                .filter(|(allocation, _)| allocation.group_id == group.group_id)
+                .map(|(_, value)| value)
+                .sum();
+            let current_bps = (value * 10000).checked_div(total).unwrap_or(0) as u16;
+            let deviation_bps = current_bps.abs_diff(group.target_bps);
+            GroupDeviation {
+                group_id: group.group_id,
+                current_bps,
+                target_bps: group.target_bps,
+                deviation_bps,
+                needs_rebalance: deviation_bps > group.max_deviation_bps,
+            }
+        })
+        .collect();
+    
+    (deviations, groups)
+}
+
+/// Check that allocation groups are well formed and that each group's members
+/// sum to its target
+fn check_allocation_groups(allocations: &[TokenAllocation], groups: &[AllocationGroup]) -> Result<()> {
+    require!(groups.len() <= MAX_ALLOCATION_GROUPS, ErrorCode::InvalidAllocationGroup);
+    
+    for (index, group) in groups.iter().enumerate() {
+        require!(group.group_id != 0, ErrorCode::InvalidAllocationGroup);
+        require!(
+            !groups[..index].iter().any(|g| g.group_id == group.group_id),
+            ErrorCode::InvalidAllocationGroup
+        );
+        require!(group.max_deviation_bps <= 2000, ErrorCode::DeviationTooHigh);
+        
+        let members: u32 = allocations
+            .iter()
+            .filter(|a| a.group_id == group.group_id)
+            .map(|a| a.target_percentage as u32)
+            .sum();
+        require!(
+            group.target_bps > 0 && members == group.target_bps as u32,
+            ErrorCode::GroupAllocationMismatch
+        );
+    }
+    
+    // Every grouped allocation needs its group
+    for allocation in allocations {
+        require!(
+            allocation.group_id == 0 || groups.iter().any(|g| g.group_id == allocation.group_id),
+            ErrorCode::InvalidAllocationGroup
+        );
+    }
+    
+    Ok(())
+}
+
+/// Check that a token mint is valid
+fn is_valid_token_mint(mint: &Pubkey) -> bool {
+    *mint != Pubkey::default()
+}
+
+/// Required parameters for DollarCostAverage strategies
+#[derive(Deserialize)]
+struct DollarCostAverageParameters {
+    interval_secs: u64,
+    amount: u64,
+}
+
+/// Required parameters for MomentumTrading strategies
+#[derive(Deserialize)]
+struct MomentumTradingParameters {
+    lookback: u32,
+    threshold_bps: u16,
+}
+
+/// Required parameters for MeanReversion strategies
+#[derive(Deserialize)]
+struct MeanReversionParameters {
+    lookback: u32,
+    deviation_bps: u16,
+}
+
+/// Required parameters for TrendFollowing strategies
+#[derive(Deserialize)]
+struct TrendFollowingParameters {
+    short_window: u32,
+    long_window: u32,
+}
+
+/// Check that the JSON parameters carry the keys their strategy type requires.
+/// Custom strategies are not checked.
+fn validate_strategy_parameters(strategy_type: &StrategyType, parameters: &[u8]) -> Result<()> {
+    let valid = match strategy_type {
+        StrategyType::DollarCostAverage => {
+            serde_json_core::from_slice::<DollarCostAverageParameters>(parameters)
+                .is_ok_and(|(p, _)| p.interval_secs > 0 && p.amount > 0)
+        }
+        StrategyType::MomentumTrading => {
+            serde_json_core::from_slice::<MomentumTradingParameters>(parameters)
+                .is_ok_and(|(p, _)| p.lookback > 0 && p.threshold_bps > 0)
+        }
+        StrategyType::MeanReversion => {
+            serde_json_core::from_slice::<MeanReversionParameters>(parameters)
+                .is_ok_and(|(p, _)| p.lookback > 0 && p.deviation_bps > 0)
+        }
+        StrategyType::TrendFollowing => {
+            serde_json_core::from_slice::<TrendFollowingParameters>(parameters)
+                .is_ok_and(|(p, _)| p.short_window > 0 && p.short_window < p.long_window)
+        }
+        StrategyType::Custom => true,
+    };
+    require!(valid, ErrorCode::InvalidStrategyParameters);
+    
+    Ok(())
+}
+
+/// Merge `patch` over `parameters`, keeping the result within the 1024-byte cap
+fn patch_parameters(parameters: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    let parameters = merge_json_parameters(parameters, patch)
+        .ok_or(ErrorCode::InvalidStrategyParameters)?;
+    require!(parameters.len() <= 1024, ErrorCode::ParametersTooLarge);
+    Ok(parameters)
+}
+
+/// Merge a JSON object patch over a JSON object, returning the merged object.
+/// Returns None if either input is not a JSON object.
+fn merge_json_parameters(parameters: &[u8], patch: &[u8]) -> Option<Vec<u8>> {
+    let mut members = json_object_members(parameters)?;
+    
+    for (key, value) in json_object_members(patch)? {
+        let existing = members.iter().position(|(k, _)| *k == key);
+        match (existing, value == b"null") {
+            (Some(index), true) => {
+                members.remove(index);
+            }
+            (Some(index), false) => members[index].1 = value,
+            (None, true) => {}
+            (None, false) => members.push((key, value)),
+        }
+    }
+    
+    let mut merged = vec![b'{'];
+    for (i, (key, value)) in members.iter().enumerate() {
+        if i > 0 {
+            merged.push(b',');
+        }
+        merged.extend_from_slice(key);
+        merged.push(b':');
+        merged.extend_from_slice(value);
+    }
+    merged.push(b'}');
+    
+    Some(merged)
+}
+
+/// Split a JSON object into its raw (quoted key, value) members
+fn json_object_members(json: &[u8]) -> Option<Vec<(&[u8], &[u8])>> {
+    let mut members = Vec::new();
+    let mut i = skip_json_whitespace(json, 0);
+    if json.get(i) != Some(&b'{') {
+        return None;
+    }
+    i = skip_json_whitespace(json, i + 1);
+    
+    if json.get(i) == Some(&b'}') {
+        i += 1;
+    } else {
+        loop {
+            if json.get(i) != Some(&b'"') {
+                return None;
+            }
+            let key_end = scan_json_value(json, i)?;
+            let key = &json[i..key_end];
+            
+            i = skip_json_whitespace(json, key_end);
+            if json.get(i) != Some(&b':') {
+                return None;
+            }
+            i = skip_json_whitespace(json, i + 1);
+            
+            let value_end = scan_json_value(json, i)?;
+            members.push((key, &json[i..value_end]));
+            
+            i = skip_json_whitespace(json, value_end);
+            match json.get(i) {
+                Some(b',') => i = skip_json_whitespace(json, i + 1),
+                Some(b'}') => {
+                    i += 1;
+                    break;
+                }
+                _ => return None,
+            }
+        }
+    }
+    
+    // Nothing may follow the object
+    if skip_json_whitespace(json, i) != json.len() {
+        return None;
+    }
+    
+    Some(members)
+}
+
+/// Index of the first non-whitespace byte at or after `i`
+fn skip_json_whitespace(json: &[u8], mut i: usize) -> usize {
+    while matches!(json.get(i), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+        i += 1;
+    }
+    i
+}
+
+/// Index just past the JSON value starting at `start`
+fn scan_json_value(json: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut i = start;
+    
+    while let Some(&byte) = json.get(i) {
+        if in_string {
+            match byte {
+                b'\\' => i += 1, // Skip the escaped byte
+                b'"' => {
+                    in_string = false;
+                    if depth == 0 {
+                        return Some(i + 1);
+                    }
+                }
+                _ => {}
+            }
+        } else {
+            match byte {
+                b'"' => in_string = true,
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' if depth > 0 => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(i + 1);
+                    }
+                }
+                b',' | b'}' | b']' | b' ' | b'\t' | b'\n' | b'\r' if depth == 0 => {
+                    return if i > start { Some(i) } else { None };
+                }
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+    
+    // A scalar may run to the end of the input, but strings and containers must close
+    if !in_string && depth == 0 && i > start {
+        Some(i)
+    } else {
+        None
+    }
+}
+
+/// Validate and apply the provided gas settings fields
+fn apply_gas_settings_update(
+    gas_settings: &mut GasSettings,
+    priority_fee: Option<u64>,
+    compute_units: Option<u32>,
+    retry_on_fail: Option<bool>,
+    max_retries: Option<u8>,
+) -> Result<()> {
+    if let Some(fee) = priority_fee {
+        require!(fee <= MAX_PRIORITY_FEE, ErrorCode::PriorityFeeTooHigh);
+        gas_settings.priority_fee = fee;
+    }
+    
+    if let Some(units) = compute_units {
+        require!((100_000..=1_400_000).contains(&units), ErrorCode::InvalidComputeUnits);
+        gas_settings.compute_units = units;
+    }
+    
+    if let Some(retry) = retry_on_fail {
+        gas_settings.retry_on_fail = retry;
+    }
+    
+    if let Some(retries) = max_retries {
+        require!(retries <= 10, ErrorCode::TooManyRetries);
+        gas_settings.max_retries = retries;
+    }
+    
+    Ok(())
+}
+
+/// Validate and apply a set of trading rule changes
+fn apply_trading_rules_update(
+    agent_config: &mut Account<AgentConfig>,
+    update: TradingRulesUpdate,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    
+    // Update trading rules if provided
+    if let Some(max_amount) = update.max_amount_per_trade {
+        agent_config.trading_rules.max_amount_per_trade = max_amount;
+    }
+    
+    if let Some(max_trades) = update.max_trades_per_day {
+        require!(max_trades <= 100, ErrorCode::InvalidTradeLimit);
+        agent_config.trading_rules.max_trades_per_day = max_trades;
+    }
+    
+    if let Some(allowed) = update.allowed_tokens {
+        // Validate token mints
+        require!(allowed.len() <= MAX_TOKEN_RULES, ErrorCode::TooManyTokenRules);
+        for mint in &allowed {
+            require!(is_valid_token_mint(mint), ErrorCode::InvalidTokenMint);
+        }
+        agent_config.trading_rules.allowed_tokens = allowed;
+    }
+    
+    if let Some(excluded) = update.excluded_tokens {
+        // Validate token mints
+        require!(excluded.len() <= MAX_TOKEN_RULES, ErrorCode::TooManyTokenRules);
+        for mint in &excluded {
+            require!(is_valid_token_mint(mint), ErrorCode::InvalidTokenMint);
+        }
+        agent_config.trading_rules.excluded_tokens = excluded;
+    }
+    
+    // Check the combined lists, since either side may have been updated alone
+    check_token_rule_conflicts(&agent_config.trading_rules)?;
+    
+    if let Some(slippage) = update.max_slippage_bps {
+        require!(slippage <= 1000, ErrorCode::SlippageTooHigh); // Max 10%
+        agent_config.trading_rules.max_slippage_bps = slippage;
+    }
+    
+    if let Some(liquidity) = update.min_pool_liquidity {
+        agent_config.trading_rules.min_pool_liquidity = liquidity;
+    }
+    
+    if let Some(min_amount) = update.min_trade_amount {
+        agent_config.trading_rules.min_trade_amount = min_amount;
+    }
+    
+    // Check the combined limits, since either side may have been updated alone
+    require!(
+        agent_config.trading_rules.min_trade_amount <= agent_config.trading_rules.max_amount_per_trade,
+        ErrorCode::InvalidAmount
+    );
+    
+    // Update timestamp
+    agent_config.updated_at = clock.unix_timestamp;
+    
+    emit!(TradingRulesUpdatedEvent {
+        agent: agent_config.key(),
+        owner: agent_config.owner,
+        max_amount_per_trade: agent_config.trading_rules.max_amount_per_trade,
+        max_slippage_bps: agent_config.trading_rules.max_slippage_bps,
+        timestamp: clock.unix_timestamp,
+    });
+    
+    Ok(())
+}
+
+/// Update average-cost positions for a successful trade.
+/// Trades paying the quote mint are buys, trades receiving it are sells;
+/// anything else, or an agent with no quote mint, is not tracked.
+fn update_cost_basis(
+    agent_stats: &mut AgentStats,
+    quote_mint: &Pubkey,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+    input_amount: u64,
+    output_amount: u64,
+    timestamp: i64,
+) -> Result<()> {
+    if *quote_mint == Pubkey::default() {
+        return Ok(());
+    }
+    
+    if input_mint == quote_mint {
+        // Buy: add the tokens received at the quote amount paid
+        match agent_stats.cost_basis.iter_mut().find(|e| e.mint == *output_mint) {
+            Some(entry) => {
+                entry.total_amount = entry.total_amount
+                    .checked_add(output_amount)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+                entry.total_cost = entry.total_cost
+                    .checked_add(input_amount)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+            }
+            None => {
+                require!(
+                    agent_stats.cost_basis.len() < MAX_COST_BASIS_ENTRIES,
+                    ErrorCode::TooManyPositions
+                );
+                agent_stats.cost_basis.push(CostBasisEntry {
+                    mint: *output_mint,
+                    total_amount: output_amount,
+                    total_cost: input_amount,
+                });
+            }
+        }
+    } else if output_mint == quote_mint {
+        // Sell: release cost at the position's average and realize the difference
+        let index = agent_stats
+            .cost_basis
+            .iter()
+            .position(|e| e.mint == *input_mint)
+            .ok_or(ErrorCode::NoPositionToSell)?;
+        let entry = &mut agent_stats.cost_basis[index];
+        require!(input_amount <= entry.total_amount, ErrorCode::InsufficientPosition);
+        
+        let cost_basis = (entry.total_cost as u128 * input_amount as u128
+            / entry.total_amount as u128) as u64;
+        entry.total_amount -= input_amount;
+        entry.total_cost -= cost_basis;
+        if entry.total_amount == 0 {
+            agent_stats.cost_basis.remove(index);
+        }
+        
+        emit!(RealizedGainEvent {
+            agent: agent_stats.agent,
+            mint: *input_mint,
+            amount_sold: input_amount,
+            proceeds: output_amount,
+            cost_basis,
+            realized_profit_loss: value_change(cost_basis, output_amount)?,
+            timestamp,
+        });
+    }
+    
+    Ok(())
+}
+
+/// Classify a trade against the quote mint: paying it is a buy, receiving it a
+/// sell. When neither side is the quote mint (or none is configured) the
+/// caller's `explicit` direction is used, defaulting to a rebalance. An
+/// explicit direction that contradicts the quote mint is rejected.
+fn trade_direction(
+    quote_mint: &Pubkey,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+    explicit: Option<TradeDirection>,
+) -> Result<TradeDirection> {
+    let inferred = if *quote_mint == Pubkey::default() {
+        None
+    } else if input_mint == quote_mint {
+        Some(TradeDirection::Buy)
+    } else if output_mint == quote_mint {
+        Some(TradeDirection::Sell)
+    } else {
+        None
+    };
+    
+    match (inferred, explicit) {
+        (Some(inferred), Some(explicit)) => {
+            require!(inferred == explicit, ErrorCode::TradeDirectionMismatch);
+            Ok(inferred)
+        }
+        (Some(direction), None) | (None, Some(direction)) => Ok(direction),
+        (None, None) => Ok(TradeDirection::Rebalance),
+    }
+}
+
+/// In close-only mode, reject any trade that does not exit into the quote mint
+fn check_close_only(agent_config: &AgentConfig, output_mint: &Pubkey) -> Result<()> {
+    if agent_config.status == AgentStatus::CloseOnly {
+        require!(*output_mint == agent_config.quote_mint, ErrorCode::CloseOnlyMode);
+    }
+    Ok(())
+}
+
+/// The compute budget a client should request for the agent's trades. The
+/// runtime only honours compute budget instructions at the top level of a
+/// transaction, so the program can't apply it itself.
+fn compute_budget_request(gas_settings: &GasSettings) -> Result<ComputeBudgetRequest> {
+    let units = gas_settings.compute_units;
+    require!((100_000..=1_400_000).contains(&units), ErrorCode::InvalidComputeUnits);
+    
+    Ok(ComputeBudgetRequest {
+        compute_unit_limit: units,
+        compute_unit_price: gas_settings.priority_fee,
+    })
+}
+
+/// Check that no token is both allowed and excluded
+fn check_token_rule_conflicts(rules: &TradingRule) -> Result<()> {
+    require!(
+        !rules.allowed_tokens.iter().any(|mint| rules.excluded_tokens.contains(mint)),
+        ErrorCode::ConflictingTokenRules
+    );
+    
+    Ok(())
+}
+
+/// What a swap actually moved, from the source and destination balances
+/// `(before, after)` it: the input spent, the output received and its
+/// slippage against `expected_output`. The fill must respect the requested
+/// `input_amount` and the agent's trading rules.
+fn measure_swap(
+    rules: &TradingRule,
+    source_balances: (u64, u64),
+    destination_balances: (u64, u64),
+    input_amount: u64,
+    expected_output: u64,
+) -> Result<(u64, u64, u16)> {
+    let input_spent = source_balances.0
+        .checked_sub(source_balances.1)
+        .ok_or(ErrorCode::InvalidAmount)?;
+    let output_amount = destination_balances.1
+        .checked_sub(destination_balances.0)
+        .ok_or(ErrorCode::InvalidAmount)?;
+    require!(input_spent > 0 && input_spent <= input_amount, ErrorCode::TradeAmountTooLarge);
+    require!(input_spent >= rules.min_trade_amount, ErrorCode::TradeTooSmall);
+    require!(expected_output > 0, ErrorCode::InvalidAmount);
+    
+    let slippage_bps = (expected_output.saturating_sub(output_amount) as u128 * 10000
+        / expected_output as u128) as u16;
+    require!(slippage_bps <= rules.max_slippage_bps, ErrorCode::SlippageExceeded);
+    
+    Ok((input_spent, output_amount, slippage_bps))
+}
+
+/// Checks shared by validate_trade and every instruction that records a
+/// trade: trading rules, close-only mode, reason length, pool liquidity and
+/// position concentration
+fn check_trade_limits(
+    agent_config: &AgentConfig,
+    agent_stats: &AgentStats,
+    pool_reserve: Option<&TokenAccount>,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+    input_amount: u64,
+    reason: &str,
+) -> Result<()> {
+    check_trading_rules(&agent_config.trading_rules, input_mint, output_mint, input_amount)?;
+    check_close_only(agent_config, output_mint)?;
+    require!(reason.len() <= 200, ErrorCode::ReasonTooLong);
+    check_pool_liquidity(&agent_config.trading_rules, pool_reserve, input_mint, output_mint)?;
+    check_concentration(agent_config, agent_stats, input_mint, output_mint, input_amount)
+}
+
+/// Check a prospective trade against the agent's trading rules
+fn check_trading_rules(
+    rules: &TradingRule,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+    input_amount: u64,
+) -> Result<()> {
+    require!(is_valid_token_mint(input_mint), ErrorCode::InvalidTokenMint);
+    require!(is_valid_token_mint(output_mint), ErrorCode::InvalidTokenMint);
+    require!(input_amount > 0, ErrorCode::InvalidAmount);
+    require!(input_amount <= rules.max_amount_per_trade, ErrorCode::TradeAmountTooLarge);
+    require!(input_amount >= rules.min_trade_amount, ErrorCode::TradeTooSmall);
+    
+    for mint in [input_mint, output_mint] {
+        // An empty allow list means all tokens are allowed
+        require!(
+            rules.allowed_tokens.is_empty() || rules.allowed_tokens.contains(mint),
+            ErrorCode::TokenNotAllowed
+        );
+        require!(!rules.excluded_tokens.contains(mint), ErrorCode::TokenNotAllowed);
+    }
+    
+    Ok(())
+}
+
+/// Check that the pool reserve holds at least `min_pool_liquidity` of one of
+/// the traded tokens
+fn check_pool_liquidity(
+    rules: &TradingRule,
+    pool_reserve: Option<&TokenAccount>,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+) -> Result<()> {
+    if rules.min_pool_liquidity == 0 {
+        return Ok(());
+    }
+    
+    let reserve = pool_reserve.ok_or(ErrorCode::InsufficientLiquidity)?;
+    require!(
+        reserve.mint == *input_mint || reserve.mint == *output_mint,
+        ErrorCode::InvalidPoolReserve
+    );
+    require!(reserve.amount >= rules.min_pool_liquidity, ErrorCode::InsufficientLiquidity);
+    
+    Ok(())
+}
+
+/// Check that `output_amount` is no further below `expected_output` than
+/// `slippage_bps` allows. Output above the quote is always consistent.
+fn check_output_consistency(output_amount: u64, expected_output: u64, slippage_bps: u16) -> Result<()> {
+    require!(expected_output > 0, ErrorCode::InvalidAmount);
+    
+    let min_output = expected_output as u128 * (10000 - slippage_bps.min(10000) as u128) / 10000;
+    require!(output_amount as u128 >= min_output, ErrorCode::InconsistentTradeRecord);
+    
+    Ok(())
+}
+
+/// Whether a trade reported as `success` counts as one: fills over the
+/// slippage limit are recorded as failed
+fn settled_success(success: bool, slippage_bps: u16, max_slippage_bps: u16) -> bool {
+    success && slippage_bps <= max_slippage_bps
+}
+
+/// Title, message and data of the SlippageExceeded notification, or None
+/// when `slippage_bps` is within `max_slippage_bps`
+fn slippage_notice(slippage_bps: u16, max_slippage_bps: u16) -> Option<(String, String, String)> {
+    if slippage_bps <= max_slippage_bps {
+        return None;
+    }
+    
+    Some((
+        "Slippage Limit Exceeded".to_string(),
+        format!(
+            "Trade slippage of {} bps exceeded your limit of {} bps",
+            slippage_bps,
+            max_slippage_bps
+        ),
+        format!(
+            "{{\"requestedSlippageBps\":{}, \"allowedSlippageBps\":{}}}",
+            slippage_bps,
+            max_slippage_bps
+        ),
+    ))
+}
+
+/// Check that an agent account of `data_len` bytes has room to fill its
+/// remaining strategy slots
+fn check_strategy_capacity(agent_config: &AgentConfig, data_len: usize) -> Result<()> {
+    let used = 8 + agent_config.try_to_vec()?.len();
+    let free_slots = (agent_config.max_strategies as usize).saturating_sub(agent_config.strategies.len());
+    
+    require!(
+        used + free_slots * Strategy::MAX_SIZE <= data_len,
+        ErrorCode::InsufficientAccountSpace
+    );
+    
+    Ok(())
+}
+
+/// Check that a buy doesn't push one token above `max_single_token_bps` of the
+/// portfolio. Position size is the token's cost basis plus the quote spent;
+/// portfolio size is the latest performance snapshot, or the trading budget
+/// before any snapshot has been recorded.
+fn check_concentration(
+    agent_config: &AgentConfig,
+    agent_stats: &AgentStats,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+    input_amount: u64,
+) -> Result<()> {
+    let quote_mint = &agent_config.quote_mint;
+    if agent_config.max_single_token_bps >= 10000 || input_mint != quote_mint || output_mint == quote_mint {
+        return Ok(());
+    }
+    
+    let portfolio_value = agent_stats
+        .performance_data
+        .last()
+        .map(|p| p.portfolio_value)
+        .unwrap_or(agent_config.trading_budget);
+    require!(portfolio_value > 0, ErrorCode::ConcentrationLimitExceeded);
+    
+    let position_cost = agent_stats
+        .cost_basis
+        .iter()
+        .find(|e| e.mint == *output_mint)
+        .map(|e| e.total_cost)
+        .unwrap_or(0);
+    let position_after = position_cost as u128 + input_amount as u128;
+    
+    require!(
+        position_after * 10000 <= portfolio_value as u128 * agent_config.max_single_token_bps as u128,
+        ErrorCode::ConcentrationLimitExceeded
+    );
+    
+    Ok(())
+}
+
+/// Validate the user-supplied fields of a new agent
+fn check_agent_params(name: &str, description: &str, max_strategies: u8) -> Result<()> {
+    require!(!name.is_empty(), ErrorCode::InvalidName);
+    require!(name.len() <= MAX_AGENT_NAME_LEN, ErrorCode::NameTooLong);
+    require!(description.len() <= MAX_AGENT_DESCRIPTION_LEN, ErrorCode::DescriptionTooLong);
+    require!(max_strategies <= MAX_STRATEGIES, ErrorCode::InvalidMaxStrategies);
+    Ok(())
+}
+
+/// Initialize a new agent's config, stats and trade index, with their PDA
+/// bumps taken from `bumps`
+fn init_agent_accounts(
+    agent_config: &mut Account<AgentConfig>,
+    agent_stats: &mut Account<AgentStats>,
+    trade_index: &mut Account<AgentTradeIndex>,
+    valuation_mint: &Account<Mint>,
+    owner: Pubkey,
+    params: NewAgentParams,
+    bumps: &BTreeMap<String, u8>,
+) -> Result<()> {
+    let NewAgentParams { agent_id, name, description, risk_profile, max_strategies } = params;
+    let bump = bumps["agent_config"];
+    let stats_bump = bumps["agent_stats"];
+    let trade_index_bump = bumps["trade_index"];
+    let clock = Clock::get()?;
+    check_agent_params(&name, &description, max_strategies)?;
+    
+    // Configure default trading rule
+    let trading_rule = TradingRule {
+        id: [0; 16], // Will be set by update_trading_rules instruction
+        max_amount_per_trade: 1_000_000_000, // 1000 USDC in smallest units
+        max_trades_per_day: 5,
+        allowed_tokens: vec![], // Empty means all tokens allowed
+        excluded_tokens: vec![], // No excluded tokens by default
+        max_slippage_bps: 100, // 1% max slippage
+        min_pool_liquidity: 0, // Liquidity check disabled
+        min_trade_amount: 0, // Dust filter disabled
+    };
+    
+    // Configure default gas settings for the risk profile
+    let gas_settings = GasProfileDefaults::for_risk_profile(&risk_profile).gas_settings();
+    
+    // Initialize agent configuration
+    agent_config.owner = owner;
+    agent_config.creator = owner;
+    agent_config.agent_id = agent_id;
+    agent_config.name = name;
+    agent_config.description = description;
+    agent_config.risk_profile = risk_profile;
+    agent_config.status = AgentStatus::Inactive;
+    agent_config.created_at = clock.unix_timestamp;
+    agent_config.updated_at = clock.unix_timestamp;
+    agent_config.auto_rebalance = false;
+    agent_config.rebalance_threshold_bps = 500; // 5% threshold
+    agent_config.min_rebalance_interval_secs = 3600; // At most one rebalance per hour
+    agent_config.last_rebalance_at = 0;
+    agent_config.auto_trade = false;
+    agent_config.trading_budget = 0;
+    agent_config.strategies = vec![];
+    agent_config.max_strategies = max_strategies;
+    agent_config.trading_rules = trading_rule;
+    agent_config.gas_settings = gas_settings;
+    agent_config.target_allocations = vec![];
+    agent_config.allocation_groups = vec![];
+    agent_config.total_executed_trades = 0;
+    agent_config.total_trade_volume = 0;
+    agent_config.trade_day = 0;
+    agent_config.trades_today = 0;
+    agent_config.approvers = vec![];
+    agent_config.required_approvals = 0;
+    agent_config.quote_mint = Pubkey::default();
+    agent_config.max_drawdown_bps = 0;
+    agent_config.max_single_token_bps = 10000; // Concentration check disabled
+    agent_config.max_consecutive_failures = 0; // Circuit breaker disabled
+    agent_config.authorized_programs = vec![];
+    agent_config.last_trade_hash = [0; 32];
+    agent_config.bump = bump;
+    agent_config.schema_version = AGENT_SCHEMA_VERSION;
+    let data_len = agent_config.to_account_info().data_len();
+    check_strategy_capacity(agent_config, data_len)?;
+    
+    // Initialize agent stats
+    agent_stats.agent = agent_config.key();
+    agent_stats.owner = owner;
+    agent_stats.total_trades = 0;
+    agent_stats.successful_trades = 0;
+    agent_stats.failed_trades = 0;
+    agent_stats.total_volume = 0;
+    agent_stats.total_fees_paid = 0;
+    agent_stats.profit_loss = 0;
+    agent_stats.created_at = clock.unix_timestamp;
+    agent_stats.last_updated_at = clock.unix_timestamp;
+    agent_stats.performance_data = vec![];
+    agent_stats.cost_basis = vec![];
+    agent_stats.peak_portfolio_value = 0;
+    agent_stats.retry_count = 0;
+    agent_stats.consecutive_failures = 0;
+    agent_stats.valuation_mint = valuation_mint.key();
+    agent_stats.valuation_decimals = valuation_mint.decimals;
+    agent_stats.bump = stats_bump;
+    agent_stats.schema_version = AGENT_SCHEMA_VERSION;
+    
+    // Initialize the trade index
+    trade_index.agent = agent_config.key();
+    trade_index.head = 0;
+    trade_index.trade_nonce = 0;
+    trade_index.entries = vec![];
+    trade_index.bump = trade_index_bump;
+    
+    emit!(AgentInitializedEvent {
+        agent: agent_config.key(),
+        owner,
+        name: agent_config.name.clone(),
+        risk_profile: agent_config.risk_profile.clone(),
+        timestamp: clock.unix_timestamp,
+    });
+    
+    Ok(())
+}
+
+/// Check that trades are recorded by an authenticated recorder, since they
+/// drive the stats and the consecutive failure breaker. An authorized
+/// program may record for any authority when it invoked this instruction
+/// directly; otherwise only the owner may record.
+fn check_trade_recorder(agent_config: &AgentConfig, authority: &Pubkey, instructions_sysvar: &AccountInfo) -> Result<()> {
+    let caller = cpi_caller(instructions_sysvar, get_stack_height())?;
+    check_recorder(agent_config, authority, caller)
+}
+
+/// The program that invoked this instruction, when it was invoked by CPI
+/// straight from a top-level instruction. The instructions sysvar only
+/// exposes top-level instructions, so deeper callers can't be identified.
+fn cpi_caller(instructions_sysvar: &AccountInfo, stack_height: usize) -> Result<Option<Pubkey>> {
+    if stack_height != TRANSACTION_LEVEL_STACK_HEIGHT + 1 {
+        return Ok(None);
+    }
+    Ok(Some(get_instruction_relative(0, instructions_sysvar)?.program_id))
+}
+
+/// Allow an authorized program calling directly, else only the owner
+fn check_recorder(agent_config: &AgentConfig, authority: &Pubkey, caller: Option<Pubkey>) -> Result<()> {
+    if caller.is_some_and(|caller| agent_config.authorized_programs.contains(&caller)) {
+        return Ok(());
+    }
+    if *authority == agent_config.owner {
+        return Ok(());
+    }
+    
+    if agent_config.authorized_programs.is_empty() {
+        err!(CommonError::Unauthorized)
+    } else {
+        err!(ErrorCode::UnauthorizedCaller)
+    }
+}
+
+/// Write a mint's decimals and, when a Metaplex metadata account is given,
+/// its symbol into the cache
+fn fill_token_meta(
+    token_meta: &mut Account<TokenMeta>,
+    mint: &Account<Mint>,
+    metadata: Option<&AccountInfo>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    
+    token_meta.decimals = mint.decimals;
+    if let Some(metadata) = metadata {
+        token_meta.symbol = read_metaplex_symbol(&mint.key(), metadata)?;
+    }
+    token_meta.last_updated = clock.unix_timestamp;
+    
+    emit!(TokenMetaCachedEvent {
+        mint: token_meta.mint,
+        decimals: token_meta.decimals,
+        symbol: token_meta.symbol,
+        timestamp: clock.unix_timestamp,
+    });
+    
+    Ok(())
+}
+
+/// Read the symbol from a Metaplex metadata account.
+/// Layout: key (1), update authority (32), mint (32), name (4 + len), symbol (4 + len).
+fn read_metaplex_symbol(mint: &Pubkey, metadata: &AccountInfo) -> Result<[u8; TOKEN_SYMBOL_LEN]> {
+    let (expected, _) = Pubkey::find_program_address(
+        &[b"metadata", METADATA_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &METADATA_PROGRAM_ID,
+    );
+    require!(
+        metadata.key() == expected && *metadata.owner == METADATA_PROGRAM_ID,
+        ErrorCode::InvalidTokenMetadata
+    );
+    
+    let data = metadata.try_borrow_data()?;
+    let read_len = |offset: usize| -> Result<usize> {
+        let bytes = data.get(offset..offset + 4).ok_or(ErrorCode::InvalidTokenMetadata)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    };
+    
+    let name_offset = 1 + 32 + 32;
+    let symbol_offset = name_offset + 4 + read_len(name_offset)?;
+    let symbol_len = read_len(symbol_offset)?;
+    let raw = data
+        .get(symbol_offset + 4..symbol_offset + 4 + symbol_len)
+        .ok_or(ErrorCode::InvalidTokenMetadata)?;
+    
+    // Metaplex pads symbols with trailing zero bytes
+    let trimmed = match raw.iter().rposition(|b| *b != 0) {
+        Some(end) => &raw[..=end],
+        None => &raw[..0],
+    };
+    require!(trimmed.len() <= TOKEN_SYMBOL_LEN, ErrorCode::InvalidTokenMetadata);
+    
+    let mut symbol = [0u8; TOKEN_SYMBOL_LEN];
+    symbol[..trimmed.len()].copy_from_slice(trimmed);
+    Ok(symbol)
+}
+
+/// Resolve an allocation's decimals from its `TokenMeta` cache or, unless
+/// strict, its mint account
+fn allocation_decimals(mint: &Pubkey, info: &AccountInfo, strict: bool) -> Result<u8> {
+    if *info.owner == crate::ID {
+        let token_meta = Account::<TokenMeta>::try_from(info)?;
+        let expected = Pubkey::create_program_address(
+            &[b"token-meta", mint.as_ref(), &[token_meta.bump]],
+            &crate::ID,
+        )
+        .map_err(|_| ErrorCode::AllocationMintMismatch)?;
+        require!(info.key() == expected && token_meta.mint == *mint, ErrorCode::AllocationMintMismatch);
+        return Ok(token_meta.decimals);
+    }
+    
+    require!(!strict, ErrorCode::TokenMetaNotCached);
+    require!(info.key() == *mint, ErrorCode::AllocationMintMismatch);
+    let mint_account = Account::<Mint>::try_from(info)?;
+    Ok(mint_account.decimals)
+}
+
+/// Address of the trade account for `client_trade_id`, as derived by `record_trade`
+fn trade_address(agent: &Pubkey, strategy_id: &[u8; 16], client_trade_id: &[u8; 16], bump: u8) -> Result<Pubkey> {
+    Pubkey::create_program_address(
+        &[b"trade", agent.as_ref(), strategy_id, client_trade_id, &[bump]],
+        &crate::ID,
+    )
+    .map_err(|_| error!(ErrorCode::InvalidTradeAccount))
+}
+
+/// Link a trade into the agent's hash chain.
+/// The hash is `sha256(prev_trade_hash || account data)`, where the account
+/// data is the Borsh-serialized `TradeAction` without its discriminator, so
+/// the chain can be recomputed off chain from the stored trade accounts.
+fn trade_chain_hash(trade_action: &TradeAction) -> Result<[u8; 32]> {
+    Ok(hashv(&[&trade_action.prev_trade_hash, &trade_action.try_to_vec()?]).to_bytes())
+}
+
+/// Check that a retry attempt is allowed by the agent's gas settings
+fn check_retry_attempt(gas_settings: &GasSettings, attempt: u8) -> Result<()> {
+    let max_attempt = if gas_settings.retry_on_fail { gas_settings.max_retries } else { 0 };
+    require!(attempt <= max_attempt, ErrorCode::RetryLimitExceeded);
+    Ok(())
+}
+
+/// Check that the agent has room for another trade today without counting it
+fn check_daily_trade(agent_config: &AgentConfig, now: i64) -> Result<()> {
+    let trades_today = if agent_config.trade_day == now / 86400 {
+        agent_config.trades_today
+    } else {
+        0
+    };
+    
+    require!(
+        trades_today < agent_config.trading_rules.max_trades_per_day,
+        ErrorCode::DailyTradeLimitExceeded
+    );
+    
+    Ok(())
+}
+
+/// Count consecutive failed trades, resetting on success, and pause the agent
+/// when `max_consecutive_failures` is reached. Returns true if it was paused.
+fn track_consecutive_failures(
+    agent_config: &mut AgentConfig,
+    agent_stats: &mut AgentStats,
+    success: bool,
+    now: i64,
+) -> bool {
+    if success {
+        agent_stats.consecutive_failures = 0;
+        return false;
+    }
+    
+    agent_stats.consecutive_failures = agent_stats.consecutive_failures.saturating_add(1);
+    let limit = agent_config.max_consecutive_failures;
+    if limit == 0 || agent_stats.consecutive_failures < limit || agent_config.status == AgentStatus::Paused {
+        return false;
+    }
+    
+    agent_config.status = AgentStatus::Paused;
+    agent_config.updated_at = now;
+    true
+}
+
+/// Tell the owner the agent was paused by the consecutive failure breaker
+fn notify_failure_pause(
+    accounts: &[AccountInfo],
+    notification_counter: &mut Account<NotificationCounter>,
+    agent_config: &Account<AgentConfig>,
+    consecutive_failures: u16,
+) {
+    emit!(AgentStatusChangedEvent {
+        agent: agent_config.key(),
+        owner: agent_config.owner,
+        status: AgentStatus::Paused,
+        timestamp: agent_config.updated_at,
+    });
+    
+    emit_notification(
+        accounts,
+        notification_counter,
+        agent_config.owner,
+        Notification {
+            event_type: NotificationEventType::TradeFailed,
+            priority: NotificationPriority::High,
+            title: "Agent Paused After Failed Trades".to_string(),
+            message: format!(
+                "{} trades failed in a row, reaching your limit of {}. The agent has been paused.",
+                consecutive_failures,
+                agent_config.max_consecutive_failures
+            ),
+            data: Some(format!(
+                "{{\"pauseReason\":\"consecutiveFailures\", \"consecutiveFailures\":{}}}",
+                consecutive_failures
+            )),
+            strategy_id: None,
+            token_address: None,
+            tx_signature: None,
+        },
+    );
+}
+
+/// Bring an account's layout version up to date, returning whether it changed.
+/// v2 adds only `schema_version` itself; later versions set their new fields'
+/// defaults here before bumping the version.
+fn upgrade_schema(schema_version: &mut u8) -> bool {
+    if *schema_version >= AGENT_SCHEMA_VERSION {
+        return false;
+    }
+    *schema_version = AGENT_SCHEMA_VERSION;
+    true
+}
+
+/// Load a program account that may predate the current layout. If it cannot be
+/// deserialized as is, it is grown by `growth` zeroed bytes, topped up to rent
+/// exemption by `payer`, and loaded again.
+fn load_for_migration<'info, T>(
+    info: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    growth: usize,
+) -> Result<Account<'info, T>>
+where
+    T: AccountSerialize + AccountDeserialize + Owner + Clone,
+{
+    require_keys_eq!(*info.owner, crate::ID, anchor_lang::error::ErrorCode::AccountOwnedByWrongProgram);
+    
+    if let Ok(account) = Account::<T>::try_from(info) {
+        return Ok(account);
+    }
+    
+    let new_len = info.data_len() + growth;
+    info.realloc(new_len, true)?;
+    
+    let shortfall = Rent::get()?.minimum_balance(new_len).saturating_sub(info.lamports());
+    if shortfall > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, info.key, shortfall),
+            &[payer.clone(), info.clone(), system_program.clone()],
+        )?;
+    }
+    
+    Account::<T>::try_from(info)
+}
+
+/// Append a performance point, track the portfolio peak and pause the agent
+/// once it falls past its drawdown limit
+fn record_performance_point(
+    agent_config: &mut Account<AgentConfig>,
+    agent_stats: &mut Account<AgentStats>,
+    notification_counter: &mut Account<NotificationCounter>,
+    accounts: &[AccountInfo],
+    portfolio_value: u64,
+    daily_profit_loss: i64,
+    now: i64,
+) -> Result<()> {
+    // Update profit/loss
+    agent_stats.profit_loss = agent_stats.profit_loss
+        .checked_add(daily_profit_loss)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    
+    // Create new performance data point
+    let data_point = PerformancePoint {
+        timestamp: now,
+        portfolio_value,
+        daily_profit_loss,
+    };
+    
+    // Add data point
+    agent_stats.performance_data.push(data_point);
+    
+    // Limit the size of performance history (keep the last 30 days)
+    if agent_stats.performance_data.len() > PERFORMANCE_RETENTION {
+        agent_stats.performance_data.remove(0);
+    }
+    
+    agent_stats.last_updated_at = now;
+    agent_stats.peak_portfolio_value = agent_stats.peak_portfolio_value.max(portfolio_value);
+    
+    emit!(PerformanceRecordedEvent {
+        agent: agent_stats.agent,
+        owner: agent_stats.owner,
+        portfolio_value,
+        daily_profit_loss,
+        valuation_mint: agent_stats.valuation_mint,
+        valuation_decimals: agent_stats.valuation_decimals,
+        timestamp: now,
+    });
+    
+    // Stop the agent once it has fallen too far below its peak
+    let peak = agent_stats.peak_portfolio_value;
+    let max_drawdown_bps = agent_config.max_drawdown_bps;
+    if max_drawdown_bps > 0 && peak > 0 {
+        let drawdown_bps = ((peak - portfolio_value) as u128 * 10000 / peak as u128) as u16;
+        
+        if drawdown_bps > max_drawdown_bps {
+            agent_config.status = AgentStatus::Paused;
+            agent_config.updated_at = now;
+            
+            emit!(AgentStatusChangedEvent {
+                agent: agent_config.key(),
+                owner: agent_config.owner,
+                status: AgentStatus::Paused,
+                timestamp: now,
+            });
+            
+            emit_notification(
+                accounts,
+                notification_counter,
+                agent_config.owner,
+                Notification {
+                    event_type: NotificationEventType::PositionLiquidated,
+                    priority: NotificationPriority::Critical,
+                    title: "Agent Paused on Drawdown".to_string(),
+                    message: format!(
+                        "Portfolio is {} bps below its peak, past your {} bps limit. The agent has been paused.",
+                        drawdown_bps,
+                        max_drawdown_bps
+                    ),
+                    data: Some(format!(
+                        "{{\"peakValue\":{}, \"portfolioValue\":{}, \"drawdownBps\":{}}}",
+                        peak,
+                        portfolio_value,
+                        drawdown_bps
+                    )),
+                    strategy_id: None,
+                    token_address: None,
+                    tx_signature: None,
+                },
+            );
+        }
+    }
+    
+    Ok(())
+}
+
+/// `current - previous` as a signed change, failing if it doesn't fit an i64
+fn value_change(previous: u64, current: u64) -> Result<i64> {
+    i64::try_from(current as i128 - previous as i128).map_err(|_| error!(ErrorCode::ArithmeticOverflow))
+}
+
+/// Value token balances in micro-USD. `accounts` holds one group per token:
+/// the owner's associated token account, its mint, the mint's
+/// `PriceFeedMapping` and a Pyth price update for that feed.
+fn token_balances_usd_value<'info>(
+    accounts: &[AccountInfo<'info>],
+    owner: &Pubkey,
+    now: i64,
+) -> Result<u128> {
+    let mut seen_mints: Vec<Pubkey> = Vec::with_capacity(accounts.len() / 4);
+    let mut total: u128 = 0;
+    
+    for group in accounts.chunks(4) {
+        let token_account = Account::<TokenAccount>::try_from(&group[0])?;
+        let mint = Account::<Mint>::try_from(&group[1])?;
+        let price_feed = Account::<price_alerts::PriceFeedMapping>::try_from(&group[2])?;
+        require_keys_eq!(
+            *group[3].owner,
+            price_alerts::PYTH_RECEIVER_PROGRAM_ID,
+            ErrorCode::InvalidSnapshotAccounts
+        );
+        
+        // Only the owner's associated account for each mint, counted once
+        require!(
+            token_account.key()
+                == spl_associated_token_account::get_associated_token_address(owner, &mint.key()),
+            ErrorCode::InvalidSnapshotAccounts
+        );
+        require!(
+            price_feed.mint == mint.key() && !seen_mints.contains(&mint.key()),
+            ErrorCode::InvalidSnapshotAccounts
+        );
+        seen_mints.push(mint.key());
+        
+        let (price, _) = price_alerts::read_pyth_price(&group[3], &price_feed.feed_id, now)?;
+        total = total
+            .checked_add(token_account.amount as u128 * price as u128 / 10u128.pow(mint.decimals as u32))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+    }
+    
+    Ok(total)
+}
+
+/// Count a trade against the agent's daily limit, rolling over at each UTC day
+fn consume_daily_trade(agent_config: &mut AgentConfig, now: i64) -> Result<()> {
+    check_daily_trade(agent_config, now)?;
+    
+    let day = now / 86400;
+    if agent_config.trade_day != day {
+        agent_config.trade_day = day;
+        agent_config.trades_today = 0;
+    }
+    agent_config.trades_today += 1;
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification_events::{resolve_channels, ALL_CHANNELS};
+    
+    fn test_agent_config(owner: Pubkey) -> AgentConfig {
+        AgentConfig {
+            owner,
+            creator: owner,
+            agent_id: [0; 16],
+            name: String::new(),
+            description: String::new(),
+            risk_profile: RiskProfile::Moderate,
+            status: AgentStatus::Active,
+            created_at: 0,
+            updated_at: 0,
+            auto_rebalance: false,
+            rebalance_threshold_bps: 0,
+            min_rebalance_interval_secs: 0,
+            last_rebalance_at: 0,
+            auto_trade: false,
+            trading_budget: 0,
+            strategies: vec![],
+            max_strategies: 0,
+            trading_rules: TradingRule {
+                id: [0; 16],
+                max_amount_per_trade: u64::MAX,
+                max_trades_per_day: u8::MAX,
+                allowed_tokens: vec![],
+                excluded_tokens: vec![],
+                max_slippage_bps: 10000,
+                min_pool_liquidity: 0,
+                min_trade_amount: 0,
+            },
+            gas_settings: GasSettings {
+                priority_fee: 0,
+                compute_units: 0,
+                retry_on_fail: false,
+                max_retries: 0,
+            },
+            target_allocations: vec![],
+            allocation_groups: vec![],
+            total_executed_trades: 0,
+            total_trade_volume: 0,
+            trade_day: 0,
+            trades_today: 0,
+            approvers: vec![],
+            required_approvals: 0,
+            quote_mint: Pubkey::default(),
+            max_drawdown_bps: 0,
+            max_single_token_bps: 10000,
+            max_consecutive_failures: 0,
+            authorized_programs: vec![],
+            last_trade_hash: [0; 32],
+            bump: 0,
+            schema_version: AGENT_SCHEMA_VERSION,
+        }
+    }
+    
+    fn test_agent_stats(agent: Pubkey, owner: Pubkey) -> AgentStats {
+        AgentStats {
+            agent,
+            owner,
+            total_trades: 0,
+            successful_trades: 0,
+            failed_trades: 0,
+            total_volume: 0,
+            total_fees_paid: 0,
+            profit_loss: 0,
+            created_at: 0,
+            last_updated_at: 0,
+            performance_data: vec![],
+            cost_basis: vec![],
+            peak_portfolio_value: 0,
+            retry_count: 0,
+            consecutive_failures: 0,
+            valuation_mint: Pubkey::default(),
+            valuation_decimals: 6,
+            bump: 0,
+            schema_version: AGENT_SCHEMA_VERSION,
+        }
+    }
+    
+    #[test]
+    fn only_the_owner_records_without_authorized_programs() {
+        let owner = Pubkey::new_unique();
+        let agent_config = test_agent_config(owner);
+        let sysvar_key = solana_program::sysvar::instructions::ID;
+        let mut lamports = 0;
+        let mut data = vec![];
+        let instructions_sysvar = AccountInfo::new(&sysvar_key, false, false, &mut lamports, &mut data, &sysvar_key, false, 0);
+        
+        assert!(check_trade_recorder(&agent_config, &owner, &instructions_sysvar).is_ok());
+        assert_eq!(
+            check_trade_recorder(&agent_config, &Pubkey::new_unique(), &instructions_sysvar).unwrap_err(),
+            Error::from(CommonError::Unauthorized)
+        );
+    }
+    
+    #[test]
+    fn consecutive_failures_pause_the_agent_at_the_limit() {
+        let owner = Pubkey::new_unique();
+        let mut agent_config = test_agent_config(owner);
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), owner);
+        agent_config.max_consecutive_failures = 2;
+        
+        assert!(!track_consecutive_failures(&mut agent_config, &mut agent_stats, false, 1));
+        assert!(!track_consecutive_failures(&mut agent_config, &mut agent_stats, true, 2));
+        assert_eq!(agent_stats.consecutive_failures, 0);
+        
+        assert!(!track_consecutive_failures(&mut agent_config, &mut agent_stats, false, 3));
+        assert!(track_consecutive_failures(&mut agent_config, &mut agent_stats, false, 4));
+        assert_eq!(agent_config.status, AgentStatus::Paused);
+        assert_eq!(agent_config.updated_at, 4);
+    }
+    
+    fn test_token_account(mint: Pubkey, amount: u64) -> TokenAccount {
+        use anchor_spl::token::spl_token;
+        use solana_program::program_pack::Pack;
+        
+        let account = spl_token::state::Account {
+            mint,
+            owner: Pubkey::new_unique(),
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        };
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account::pack(account, &mut data).unwrap();
+        TokenAccount::try_deserialize_unchecked(&mut data.as_slice()).unwrap()
+    }
+    
+    #[test]
+    fn pool_reserve_must_hold_a_traded_token() {
+        let mut rules = test_agent_config(Pubkey::new_unique()).trading_rules;
+        let (input_mint, output_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        
+        // No minimum means no reserve is needed
+        assert!(check_pool_liquidity(&rules, None, &input_mint, &output_mint).is_ok());
+        
+        rules.min_pool_liquidity = 1_000;
+        assert_eq!(
+            check_pool_liquidity(&rules, None, &input_mint, &output_mint).unwrap_err(),
+            error!(ErrorCode::InsufficientLiquidity)
+        );
+        
+        let unrelated = test_token_account(Pubkey::new_unique(), 1_000_000);
+        assert_eq!(
+            check_pool_liquidity(&rules, Some(&unrelated), &input_mint, &output_mint).unwrap_err(),
+            error!(ErrorCode::InvalidPoolReserve)
+        );
+        
+        let shallow = test_token_account(output_mint, 999);
+        assert_eq!(
+            check_pool_liquidity(&rules, Some(&shallow), &input_mint, &output_mint).unwrap_err(),
+            error!(ErrorCode::InsufficientLiquidity)
+        );
+        
+        let deep = test_token_account(input_mint, 1_000);
+        assert!(check_pool_liquidity(&rules, Some(&deep), &input_mint, &output_mint).is_ok());
+    }
+    
+    #[test]
+    fn retries_are_limited_by_the_gas_settings() {
+        let mut gas_settings = test_agent_config(Pubkey::new_unique()).gas_settings;
+        gas_settings.max_retries = 2;
+        
+        assert_eq!(
+            check_retry_attempt(&gas_settings, 1).unwrap_err(),
+            error!(ErrorCode::RetryLimitExceeded)
+        );
+        
+        gas_settings.retry_on_fail = true;
+        assert!(check_retry_attempt(&gas_settings, 2).is_ok());
+        assert_eq!(
+            check_retry_attempt(&gas_settings, 3).unwrap_err(),
+            error!(ErrorCode::RetryLimitExceeded)
+        );
+    }
+    
+    #[test]
+    fn daily_trade_limit_resets_each_day() {
+        let mut agent_config = test_agent_config(Pubkey::new_unique());
+        agent_config.trading_rules.max_trades_per_day = 2;
+        
+        assert!(consume_daily_trade(&mut agent_config, 86400).is_ok());
+        assert!(consume_daily_trade(&mut agent_config, 86401).is_ok());
+        assert_eq!(
+            consume_daily_trade(&mut agent_config, 86402).unwrap_err(),
+            error!(ErrorCode::DailyTradeLimitExceeded)
+        );
+        assert_eq!(agent_config.trades_today, 2);
+        
+        assert!(consume_daily_trade(&mut agent_config, 2 * 86400).is_ok());
+        assert_eq!(agent_config.trades_today, 1);
+    }
+    
+    #[test]
+    fn rebalances_wait_for_the_minimum_interval() {
+        let mut agent_config = test_agent_config(Pubkey::new_unique());
+        agent_config.last_rebalance_at = 1_000;
+        agent_config.min_rebalance_interval_secs = 3_600;
+        
+        assert_eq!(
+            check_rebalance_interval(&agent_config, 4_599).unwrap_err(),
+            error!(ErrorCode::RebalanceTooSoon)
+        );
+        assert!(check_rebalance_interval(&agent_config, 4_600).is_ok());
+    }
+    
+    #[test]
+    fn deviation_shrinks_as_the_portfolio_nears_its_targets() {
+        let allocation = |target_percentage| TokenAllocation {
+            mint: Pubkey::new_unique(),
+            target_percentage,
+            max_deviation_bps: 500,
+            decimals: 6,
+            usd_price: 1_000_000,
+            group_id: 0,
+        };
+        let allocations = vec![allocation(5000), allocation(5000)];
+        
+        let skewed = allocation_usd_values(&allocations, &[900_000, 100_000]).unwrap();
+        let closer = allocation_usd_values(&allocations, &[600_000, 400_000]).unwrap();
+        let balanced = allocation_usd_values(&allocations, &[500_000, 500_000]).unwrap();
+        
+        assert_eq!(total_allocation_deviation(&allocations, &balanced), 0);
+        assert!(
+            total_allocation_deviation(&allocations, &closer)
+                < total_allocation_deviation(&allocations, &skewed)
+        );
+    }
+    
+    #[test]
+    fn trading_rules_apply_to_every_trade() {
+        let mut rules = test_agent_config(Pubkey::new_unique()).trading_rules;
+        let (input_mint, output_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        rules.max_amount_per_trade = 1_000;
+        
+        assert!(check_trading_rules(&rules, &input_mint, &output_mint, 1_000).is_ok());
+        assert_eq!(
+            check_trading_rules(&rules, &Pubkey::default(), &output_mint, 1_000).unwrap_err(),
+            error!(ErrorCode::InvalidTokenMint)
+        );
+        assert_eq!(
+            check_trading_rules(&rules, &input_mint, &output_mint, 0).unwrap_err(),
+            error!(ErrorCode::InvalidAmount)
+        );
+        assert_eq!(
+            check_trading_rules(&rules, &input_mint, &output_mint, 1_001).unwrap_err(),
+            error!(ErrorCode::TradeAmountTooLarge)
+        );
+        
+        rules.allowed_tokens = vec![input_mint];
+        assert_eq!(
+            check_trading_rules(&rules, &input_mint, &output_mint, 1_000).unwrap_err(),
+            error!(ErrorCode::TokenNotAllowed)
+        );
+        
+        rules.allowed_tokens = vec![];
+        rules.excluded_tokens = vec![output_mint];
+        assert_eq!(
+            check_trading_rules(&rules, &input_mint, &output_mint, 1_000).unwrap_err(),
+            error!(ErrorCode::TokenNotAllowed)
+        );
+    }
+    
+    #[test]
+    fn summary_handles_agents_without_trades() {
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), Pubkey::new_unique());
+        
+        assert_eq!(agent_stats.win_rate_bps(), 0);
+        assert_eq!(agent_stats.avg_trade_volume(), 0);
+        assert_eq!(agent_stats.profit_loss_since(86400, 86400), 0);
+        
+        agent_stats.total_trades = 4;
+        agent_stats.successful_trades = 3;
+        agent_stats.total_volume = 900;
+        agent_stats.performance_data = vec![
+            PerformancePoint { timestamp: 0, portfolio_value: 1_000, daily_profit_loss: -50 },
+            PerformancePoint { timestamp: 86400, portfolio_value: 1_100, daily_profit_loss: 100 },
+        ];
+        
+        assert_eq!(agent_stats.win_rate_bps(), 7500);
+        assert_eq!(agent_stats.avg_trade_volume(), 300);
+        assert_eq!(agent_stats.profit_loss_since(86400, 3600), 100);
+        assert_eq!(agent_stats.profit_loss_since(86400, 86400), 50);
+    }
+    
+    #[test]
+    fn strategy_parameters_match_their_type() {
+        assert!(validate_strategy_parameters(
+            &StrategyType::DollarCostAverage,
+            br#"{"interval_secs":86400,"amount":100}"#
+        )
+        .is_ok());
+        assert_eq!(
+            validate_strategy_parameters(&StrategyType::DollarCostAverage, br#"{"amount":100}"#).unwrap_err(),
+            error!(ErrorCode::InvalidStrategyParameters)
+        );
+        assert_eq!(
+            validate_strategy_parameters(
+                &StrategyType::TrendFollowing,
+                br#"{"short_window":50,"long_window":20}"#
+            )
+            .unwrap_err(),
+            error!(ErrorCode::InvalidStrategyParameters)
+        );
+        assert!(validate_strategy_parameters(&StrategyType::Custom, b"anything").is_ok());
+    }
+    
+    #[test]
+    fn a_token_cannot_be_both_allowed_and_excluded() {
+        let mut rules = test_agent_config(Pubkey::new_unique()).trading_rules;
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        
+        rules.allowed_tokens = vec![a];
+        rules.excluded_tokens = vec![b];
+        assert!(check_token_rule_conflicts(&rules).is_ok());
+        
+        rules.excluded_tokens.push(a);
+        assert_eq!(
+            check_token_rule_conflicts(&rules).unwrap_err(),
+            error!(ErrorCode::ConflictingTokenRules)
+        );
+    }
+    
+    fn test_trade_index() -> AgentTradeIndex {
+        AgentTradeIndex {
+            agent: Pubkey::new_unique(),
+            head: 0,
+            trade_nonce: 0,
+            entries: vec![],
+            bump: 0,
+        }
+    }
+    
+    #[test]
+    fn recent_trades_are_sorted_newest_first() {
+        let mut trade_index = test_trade_index();
+        assert!(trade_index.recent(false).is_empty());
+        
+        trade_index.push(Pubkey::new_unique(), 200, true);
+        trade_index.push(Pubkey::new_unique(), 100, false);
+        trade_index.push(Pubkey::new_unique(), 300, true);
+        
+        let executed_at: Vec<i64> = trade_index.recent(false).iter().map(|e| e.executed_at).collect();
+        assert_eq!(executed_at, vec![300, 200, 100]);
+        
+        let successful: Vec<i64> = trade_index.recent(true).iter().map(|e| e.executed_at).collect();
+        assert_eq!(successful, vec![300, 200]);
+    }
+    
+    #[test]
+    fn trade_index_keeps_the_latest_entries_once_full() {
+        let mut trade_index = test_trade_index();
+        for i in 0..TRADE_INDEX_CAPACITY as i64 + 3 {
+            trade_index.push(Pubkey::new_unique(), i, true);
+        }
+        
+        let recent = trade_index.recent(false);
+        assert_eq!(recent.len(), TRADE_INDEX_CAPACITY);
+        assert_eq!(recent.first().unwrap().executed_at, TRADE_INDEX_CAPACITY as i64 + 2);
+        assert_eq!(recent.last().unwrap().executed_at, 3);
+    }
+    
+    #[test]
+    fn sells_realize_against_the_average_cost() {
+        let quote = Pubkey::new_unique();
+        let token = Pubkey::new_unique();
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), Pubkey::new_unique());
+        
+        // Two buys: 100 tokens for 1_000, then 100 more for 3_000
+        update_cost_basis(&mut agent_stats, &quote, &quote, &token, 1_000, 100, 1).unwrap();
+        update_cost_basis(&mut agent_stats, &quote, &quote, &token, 3_000, 100, 2).unwrap();
+        assert_eq!(agent_stats.cost_basis.len(), 1);
+        assert_eq!(agent_stats.cost_basis[0].total_amount, 200);
+        assert_eq!(agent_stats.cost_basis[0].total_cost, 4_000);
+        
+        // Selling half releases half the cost; the position closes at zero
+        update_cost_basis(&mut agent_stats, &quote, &token, &quote, 100, 2_500, 3).unwrap();
+        assert_eq!(agent_stats.cost_basis[0].total_amount, 100);
+        assert_eq!(agent_stats.cost_basis[0].total_cost, 2_000);
+        assert_eq!(
+            update_cost_basis(&mut agent_stats, &quote, &token, &quote, 101, 0, 4).unwrap_err(),
+            error!(ErrorCode::InsufficientPosition)
+        );
+        update_cost_basis(&mut agent_stats, &quote, &token, &quote, 100, 1_500, 5).unwrap();
+        assert!(agent_stats.cost_basis.is_empty());
+        
+        assert_eq!(
+            update_cost_basis(&mut agent_stats, &quote, &token, &quote, 1, 1, 6).unwrap_err(),
+            error!(ErrorCode::NoPositionToSell)
+        );
+    }
+    
+    #[test]
+    fn cost_basis_is_untracked_without_a_quote_mint() {
+        let token = Pubkey::new_unique();
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), Pubkey::new_unique());
+        
+        update_cost_basis(&mut agent_stats, &Pubkey::default(), &Pubkey::default(), &token, 1_000, 100, 1).unwrap();
+        // Token-to-token swaps are neither buys nor sells
+        let quote = Pubkey::new_unique();
+        update_cost_basis(&mut agent_stats, &quote, &Pubkey::new_unique(), &token, 1_000, 100, 1).unwrap();
+        assert!(agent_stats.cost_basis.is_empty());
+    }
+    
+    #[test]
+    fn trade_pages_follow_the_nonce_cursor() {
+        let mut trade_index = test_trade_index();
+        for i in 0..TRADE_INDEX_CAPACITY as i64 + 10 {
+            trade_index.push(Pubkey::new_unique(), i, true);
+        }
+        
+        let nonces = |page: Vec<TradeIndexEntry>| page.iter().map(|e| e.nonce).collect::<Vec<u64>>();
+        let newest = TRADE_INDEX_CAPACITY as u64 + 9;
+        assert_eq!(nonces(trade_index.page(u64::MAX, 3)), vec![newest, newest - 1, newest - 2]);
+        assert_eq!(nonces(trade_index.page(newest - 2, 2)), vec![newest - 3, newest - 4]);
+        
+        // Nonces that fell out of the ring are no longer paged
+        assert_eq!(nonces(trade_index.page(12, 5)), vec![11, 10]);
+        assert!(trade_index.page(10, 5).is_empty());
+    }
+    
+    #[test]
+    fn close_only_agents_can_only_exit_to_the_quote_mint() {
+        let mut agent_config = test_agent_config(Pubkey::new_unique());
+        agent_config.quote_mint = Pubkey::new_unique();
+        let other_mint = Pubkey::new_unique();
+        
+        assert!(check_close_only(&agent_config, &other_mint).is_ok());
+        
+        agent_config.status = AgentStatus::CloseOnly;
+        assert!(check_close_only(&agent_config, &agent_config.quote_mint).is_ok());
+        assert_eq!(
+            check_close_only(&agent_config, &other_mint).unwrap_err(),
+            error!(ErrorCode::CloseOnlyMode)
+        );
+    }
+    
+    #[test]
+    fn allocation_values_normalize_mint_decimals() {
+        let allocation = |decimals, usd_price| TokenAllocation {
+            mint: Pubkey::new_unique(),
+            target_percentage: 5000,
+            max_deviation_bps: 500,
+            decimals,
+            usd_price,
+            group_id: 0,
+        };
+        // One whole token each: 9-decimal SOL at $150, 6-decimal USDC at $1
+        let allocations = vec![allocation(9, 150_000_000), allocation(6, 1_000_000)];
+        
+        assert_eq!(
+            allocation_usd_values(&allocations, &[1_000_000_000, 1_000_000]).unwrap(),
+            vec![150_000_000, 1_000_000]
+        );
+        
+        let unpriced = vec![allocation(9, 0)];
+        assert_eq!(
+            allocation_usd_values(&unpriced, &[1]).unwrap_err(),
+            error!(ErrorCode::MissingAllocationPrice)
+        );
+    }
+    
+    #[test]
+    fn trade_limits_report_the_first_violated_rule() {
+        let owner = Pubkey::new_unique();
+        let mut agent_config = test_agent_config(owner);
+        let agent_stats = test_agent_stats(Pubkey::new_unique(), owner);
+        let (input_mint, output_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        agent_config.trading_rules.max_amount_per_trade = 1_000;
+        
+        let check = |agent_config: &AgentConfig, input_amount, reason: &str| {
+            check_trade_limits(agent_config, &agent_stats, None, &input_mint, &output_mint, input_amount, reason)
+        };
+        assert!(check(&agent_config, 1_000, "rebalance").is_ok());
+        assert_eq!(
+            check(&agent_config, 1_001, "rebalance").unwrap_err(),
+            error!(ErrorCode::TradeAmountTooLarge)
+        );
+        assert_eq!(
+            check(&agent_config, 1_000, &"x".repeat(201)).unwrap_err(),
+            error!(ErrorCode::ReasonTooLong)
+        );
+        
+        agent_config.status = AgentStatus::CloseOnly;
+        agent_config.quote_mint = Pubkey::new_unique();
+        assert_eq!(
+            check(&agent_config, 1_000, "rebalance").unwrap_err(),
+            error!(ErrorCode::CloseOnlyMode)
+        );
+        
+        // Without a pool reserve the liquidity rule can't be met
+        agent_config.status = AgentStatus::Active;
+        agent_config.trading_rules.min_pool_liquidity = 1;
+        assert_eq!(
+            check(&agent_config, 1_000, "rebalance").unwrap_err(),
+            error!(ErrorCode::InsufficientLiquidity)
+        );
+    }
+    
+    #[test]
+    fn compute_budget_returns_the_configured_limit_and_price() {
+        let mut gas_settings = test_agent_config(Pubkey::new_unique()).gas_settings;
+        gas_settings.compute_units = 200_000;
+        gas_settings.priority_fee = 5_000;
+        
+        let request = compute_budget_request(&gas_settings).unwrap();
+        assert_eq!(request.compute_unit_limit, 200_000);
+        assert_eq!(request.compute_unit_price, 5_000);
+        
+        gas_settings.compute_units = 1_400_001;
+        assert_eq!(
+            compute_budget_request(&gas_settings).unwrap_err(),
+            error!(ErrorCode::InvalidComputeUnits)
+        );
+        gas_settings.compute_units = 99_999;
+        assert_eq!(
+            compute_budget_request(&gas_settings).unwrap_err(),
+            error!(ErrorCode::InvalidComputeUnits)
+        );
+    }
+    
+    #[test]
+    fn only_retried_trades_count_as_retries() {
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), Pubkey::new_unique());
+        
+        agent_stats.record_attempt(0).unwrap();
+        assert_eq!(agent_stats.retry_count, 0);
+        
+        agent_stats.record_attempt(2).unwrap();
+        assert_eq!(agent_stats.retry_count, 1);
+        
+        agent_stats.retry_count = u64::MAX;
+        assert_eq!(
+            agent_stats.record_attempt(1).unwrap_err(),
+            error!(ErrorCode::ArithmeticOverflow)
+        );
+    }
+    
+    #[test]
+    fn outputs_must_match_the_claimed_slippage() {
+        // 1% slippage on an expected 10_000
+        assert!(check_output_consistency(9_900, 10_000, 100).is_ok());
+        assert!(check_output_consistency(10_050, 10_000, 100).is_ok());
+        
+        // An output implying double the claimed slippage
+        assert_eq!(
+            check_output_consistency(9_800, 10_000, 100).unwrap_err(),
+            error!(ErrorCode::InconsistentTradeRecord)
+        );
+        assert_eq!(
+            check_output_consistency(9_800, 0, 100).unwrap_err(),
+            error!(ErrorCode::InvalidAmount)
+        );
+    }
+    
+    fn test_strategy(id: u8) -> Strategy {
+        Strategy {
+            id: [id; 16],
+            name: String::new(),
+            strategy_type: StrategyType::Custom,
+            is_active: true,
+            parameters: vec![],
+            last_executed_at: 0,
+            execution_count: 0,
+            interval_secs: 0,
+            next_run_at: 0,
+        }
+    }
+    
+    #[test]
+    fn agents_stop_adding_strategies_at_their_cap() {
+        let mut agent_config = test_agent_config(Pubkey::new_unique());
+        agent_config.max_strategies = 2;
+        
+        agent_config.check_strategy_slot().unwrap();
+        agent_config.strategies.push(test_strategy(1));
+        agent_config.check_strategy_slot().unwrap();
+        agent_config.strategies.push(test_strategy(2));
+        assert_eq!(
+            agent_config.check_strategy_slot().unwrap_err(),
+            error!(ErrorCode::TooManyStrategies)
+        );
+    }
+    
+    #[test]
+    fn raising_the_cap_needs_room_in_the_account() {
+        let mut agent_config = test_agent_config(Pubkey::new_unique());
+        agent_config.max_strategies = 2;
+        // Exactly enough room for two strategies
+        let data_len = 8 + agent_config.try_to_vec().unwrap().len() + 2 * Strategy::MAX_SIZE;
+        check_strategy_capacity(&agent_config, data_len).unwrap();
+        
+        agent_config.max_strategies = 3;
+        assert_eq!(
+            check_strategy_capacity(&agent_config, data_len).unwrap_err(),
+            error!(ErrorCode::InsufficientAccountSpace)
+        );
+        
+        // expand_strategy_capacity reallocs one Strategy::MAX_SIZE per new slot
+        check_strategy_capacity(&agent_config, data_len + Strategy::MAX_SIZE).unwrap();
+        agent_config.strategies.push(test_strategy(1));
+        agent_config.strategies.push(test_strategy(2));
+        agent_config.strategies.push(test_strategy(3));
+        assert_eq!(
+            agent_config.check_strategy_slot().unwrap_err(),
+            error!(ErrorCode::TooManyStrategies)
+        );
+    }
+    
+    #[test]
+    fn full_agent_accounts_fit_their_declared_space() {
+        let mut agent_config = test_agent_config(Pubkey::new_unique());
+        agent_config.name = "n".repeat(MAX_AGENT_NAME_LEN);
+        agent_config.description = "d".repeat(MAX_AGENT_DESCRIPTION_LEN);
+        agent_config.max_strategies = MAX_STRATEGIES;
+        agent_config.strategies = (0..MAX_STRATEGIES)
+            .map(|id| Strategy {
+                name: "s".repeat(50),
+                parameters: vec![0; 1024],
+                ..test_strategy(id)
+            })
+            .collect();
+        agent_config.trading_rules.allowed_tokens = vec![Pubkey::new_unique(); MAX_TOKEN_RULES];
+        agent_config.trading_rules.excluded_tokens = vec![Pubkey::new_unique(); MAX_TOKEN_RULES];
+        agent_config.target_allocations = vec![
+            TokenAllocation {
+                mint: Pubkey::new_unique(),
+                target_percentage: 0,
+                max_deviation_bps: 0,
+                decimals: 0,
+                usd_price: 0,
+                group_id: 0,
+            };
+            MAX_ALLOCATIONS
+        ];
+        agent_config.allocation_groups = vec![
+            AllocationGroup { group_id: 1, target_bps: 0, max_deviation_bps: 0 };
+            MAX_ALLOCATION_GROUPS
+        ];
+        agent_config.approvers = vec![Pubkey::new_unique(); MAX_RULE_APPROVERS];
+        agent_config.authorized_programs = vec![Pubkey::new_unique(); MAX_AUTHORIZED_PROGRAMS];
+        
+        assert_eq!(
+            8 + agent_config.try_to_vec().unwrap().len(),
+            AgentConfig::space(MAX_STRATEGIES as usize, MAX_ALLOCATIONS, MAX_AGENT_NAME_LEN, MAX_AGENT_DESCRIPTION_LEN)
+        );
+        
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), Pubkey::new_unique());
+        agent_stats.performance_data = vec![
+            PerformancePoint { timestamp: 0, portfolio_value: 0, daily_profit_loss: 0 };
+            PERFORMANCE_RETENTION
+        ];
+        agent_stats.cost_basis = vec![
+            CostBasisEntry { mint: Pubkey::new_unique(), total_amount: 0, total_cost: 0 };
+            MAX_COST_BASIS_ENTRIES
+        ];
+        
+        assert_eq!(
+            8 + agent_stats.try_to_vec().unwrap().len(),
+            AgentStats::space(PERFORMANCE_RETENTION)
+        );
+    }
+    
+    #[test]
+    fn swaps_are_recorded_from_the_balances_they_moved() {
+        let mut rules = test_agent_config(Pubkey::new_unique()).trading_rules;
+        rules.max_slippage_bps = 100;
+        
+        // Spent 1_000 of a 1_000 budget and got 990 of an expected 1_000
+        assert_eq!(
+            measure_swap(&rules, (5_000, 4_000), (0, 990), 1_000, 1_000).unwrap(),
+            (1_000, 990, 100)
+        );
+        assert_eq!(
+            measure_swap(&rules, (5_000, 4_000), (0, 989), 1_000, 1_000).unwrap_err(),
+            error!(ErrorCode::SlippageExceeded)
+        );
+        // The route spent more than the trade allowed
+        assert_eq!(
+            measure_swap(&rules, (5_000, 3_999), (0, 1_000), 1_000, 1_000).unwrap_err(),
+            error!(ErrorCode::TradeAmountTooLarge)
+        );
+        // A balance that went the wrong way
+        assert_eq!(
+            measure_swap(&rules, (5_000, 4_000), (1_000, 990), 1_000, 1_000).unwrap_err(),
+            error!(ErrorCode::InvalidAmount)
+        );
+    }
+    
+    #[test]
+    fn buys_cannot_over_concentrate_one_token() {
+        let owner = Pubkey::new_unique();
+        let mut agent_config = test_agent_config(owner);
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), owner);
+        let (quote, token) = (Pubkey::new_unique(), Pubkey::new_unique());
+        agent_config.quote_mint = quote;
+        agent_config.max_single_token_bps = 2500;
+        agent_stats.performance_data.push(PerformancePoint {
+            timestamp: 0,
+            portfolio_value: 10_000,
+            daily_profit_loss: 0,
+        });
+        agent_stats.cost_basis.push(CostBasisEntry { mint: token, total_amount: 10, total_cost: 1_000 });
+        
+        // 1_000 held plus 1_500 bought is exactly a quarter of the portfolio
+        assert!(check_concentration(&agent_config, &agent_stats, &quote, &token, 1_500).is_ok());
+        assert_eq!(
+            check_concentration(&agent_config, &agent_stats, &quote, &token, 1_501).unwrap_err(),
+            error!(ErrorCode::ConcentrationLimitExceeded)
+        );
+        
+        // Sells and a disabled limit are never restricted
+        assert!(check_concentration(&agent_config, &agent_stats, &token, &quote, 1_000_000).is_ok());
+        agent_config.max_single_token_bps = 10000;
+        assert!(check_concentration(&agent_config, &agent_stats, &quote, &token, 1_000_000).is_ok());
+    }
+    
+    #[test]
+    fn strict_allocations_require_cached_token_meta() {
+        let mint = Pubkey::new_unique();
+        let (meta_key, bump) = Pubkey::find_program_address(&[b"token-meta", mint.as_ref()], &crate::ID);
+        let token_meta = TokenMeta {
+            mint,
+            decimals: 6,
+            symbol: [0; TOKEN_SYMBOL_LEN],
+            last_updated: 0,
+            bump,
+        };
+        let mut data = Vec::new();
+        token_meta.try_serialize(&mut data).unwrap();
+        let mut lamports = 0;
+        let program_id = crate::ID;
+        let info = AccountInfo::new(&meta_key, false, false, &mut lamports, &mut data, &program_id, false, 0);
+        assert_eq!(allocation_decimals(&mint, &info, true).unwrap(), 6);
+        
+        // Metadata cached for another mint is rejected
+        assert_eq!(
+            allocation_decimals(&Pubkey::new_unique(), &info, false).unwrap_err(),
+            error!(ErrorCode::AllocationMintMismatch)
+        );
+        
+        let token_program = anchor_spl::token::ID;
+        let mut lamports = 0;
+        let mut data = vec![];
+        let mint_info = AccountInfo::new(&mint, false, false, &mut lamports, &mut data, &token_program, false, 0);
+        assert_eq!(
+            allocation_decimals(&mint, &mint_info, true).unwrap_err(),
+            error!(ErrorCode::TokenMetaNotCached)
+        );
+    }
+    
+    #[test]
+    fn trade_hashes_chain_onto_the_previous_head() {
+        let mut trade_action = TradeAction {
+            agent: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            strategy_id: [0; 16],
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            input_amount: 1_000,
+            output_amount: 990,
+            slippage_bps: 50,
+            executed_at: 100,
+            transaction_signature: [0; 64],
+            success: true,
+            price_impact_bps: 10,
+            reason: String::new(),
+            reason_code: TradeReason::StrategySignal,
+            prev_trade_hash: [0; 32],
+            direction: TradeDirection::Buy,
+            bump: 0,
+        };
+        let first = trade_chain_hash(&trade_action).unwrap();
+        assert_eq!(first, trade_chain_hash(&trade_action).unwrap());
+        
+        // Linking onto the first hash or tampering with a field both change the head
+        trade_action.prev_trade_hash = first;
+        let second = trade_chain_hash(&trade_action).unwrap();
+        assert_ne!(first, second);
+        trade_action.output_amount = 991;
+        assert_ne!(second, trade_chain_hash(&trade_action).unwrap());
+    }
+    
+    #[test]
+    fn new_agents_reject_out_of_range_fields() {
+        let name = "a".repeat(MAX_AGENT_NAME_LEN);
+        let description = "d".repeat(MAX_AGENT_DESCRIPTION_LEN);
+        assert!(check_agent_params(&name, &description, MAX_STRATEGIES).is_ok());
+        
+        assert_eq!(check_agent_params("", "", 1).unwrap_err(), error!(ErrorCode::InvalidName));
+        assert_eq!(
+            check_agent_params(&name, "", MAX_STRATEGIES + 1).unwrap_err(),
+            error!(ErrorCode::InvalidMaxStrategies)
+        );
+        assert_eq!(
+            check_agent_params(&format!("{}a", name), "", 1).unwrap_err(),
+            error!(ErrorCode::NameTooLong)
+        );
+        assert_eq!(
+            check_agent_params(&name, &format!("{}d", description), 1).unwrap_err(),
+            error!(ErrorCode::DescriptionTooLong)
+        );
+    }
+    
+    #[test]
+    fn usdc_valued_figures_keep_their_decimals() {
+        let usdc = Pubkey::new_unique();
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), Pubkey::new_unique());
+        agent_stats.valuation_mint = usdc;
+        agent_stats.valuation_decimals = 6;
+        
+        assert!(agent_stats.check_valuation_units(6).is_ok());
+        assert_eq!(
+            agent_stats.check_valuation_units(9).unwrap_err(),
+            error!(ErrorCode::ValuationUnitsMismatch)
+        );
+        
+        // $2.50 at a $1.00 USDC price is 2.5 USDC
+        let portfolio_value = agent_stats.valuation_amount(2_500_000, 1_000_000).unwrap();
+        assert_eq!(portfolio_value, 2_500_000);
+        agent_stats.performance_data.push(PerformancePoint {
+            timestamp: 0,
+            portfolio_value,
+            daily_profit_loss: 0,
+        });
+        
+        let mut data = Vec::new();
+        agent_stats.try_serialize(&mut data).unwrap();
+        let restored = AgentStats::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!(restored.valuation_mint, usdc);
+        assert_eq!(restored.valuation_decimals, 6);
+        assert_eq!(restored.performance_data[0].portfolio_value, 2_500_000);
+    }
+    
+    #[test]
+    fn valuation_amounts_scale_by_the_mint_decimals() {
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), Pubkey::new_unique());
+        agent_stats.valuation_decimals = 9;
+        
+        // $2.50 at $2.00 per token is 1.25 tokens
+        assert_eq!(agent_stats.valuation_amount(2_500_000, 2_000_000).unwrap(), 1_250_000_000);
+        assert_eq!(
+            agent_stats.valuation_amount(u64::MAX as u128, 1).unwrap_err(),
+            error!(ErrorCode::ArithmeticOverflow)
+        );
+        assert_eq!(
+            agent_stats.valuation_amount(u128::MAX, 1).unwrap_err(),
+            error!(ErrorCode::ArithmeticOverflow)
+        );
+        
+        // A sub-micro-USD price rounds to zero and is rejected rather than divided by
+        assert_eq!(
+            agent_stats.valuation_amount(2_500_000, 0).unwrap_err(),
+            error!(ErrorCode::InvalidValuationPrice)
+        );
+        assert_eq!(agent_stats.valuation_amount(0, 0).unwrap_err(), error!(ErrorCode::InvalidValuationPrice));
+    }
+    
+    #[test]
+    fn patches_merge_single_keys_into_parameters() {
+        let parameters = br#"{"lookback": 14, "threshold": {"buy": 1, "sell": 2}, "tokens": ["a"]}"#;
+        
+        assert_eq!(
+            patch_parameters(parameters, br#"{"lookback": 21}"#).unwrap(),
+            br#"{"lookback":21,"threshold":{"buy": 1, "sell": 2},"tokens":["a"]}"#.to_vec()
+        );
+        assert_eq!(
+            patch_parameters(parameters, br#"{"tokens": null, "window": 3}"#).unwrap(),
+            br#"{"lookback":14,"threshold":{"buy": 1, "sell": 2},"window":3}"#.to_vec()
+        );
+        assert_eq!(
+            patch_parameters(parameters, b"[1]").unwrap_err(),
+            error!(ErrorCode::InvalidStrategyParameters)
+        );
+    }
+    
+    #[test]
+    fn patches_cannot_overflow_the_parameter_cap() {
+        let parameters = format!(r#"{{"notes":"{}"}}"#, "x".repeat(1000));
+        let patch = format!(r#"{{"more":"{}"}}"#, "y".repeat(20));
+        
+        assert_eq!(
+            patch_parameters(parameters.as_bytes(), patch.as_bytes()).unwrap_err(),
+            error!(ErrorCode::ParametersTooLarge)
+        );
+    }
+    
+    #[test]
+    fn client_trade_ids_fix_the_trade_address() {
+        let agent = Pubkey::new_unique();
+        let strategy_id = [1; 16];
+        let (client_trade_id, other_id) = ([7; 16], [8; 16]);
+        let (address, bump) = Pubkey::find_program_address(
+            &[b"trade", agent.as_ref(), &strategy_id, &client_trade_id],
+            &crate::ID,
+        );
+        
+        // A retry with the same id lands on the account already recorded
+        assert_eq!(trade_address(&agent, &strategy_id, &client_trade_id, bump).unwrap(), address);
+        let (other_address, other_bump) = Pubkey::find_program_address(
+            &[b"trade", agent.as_ref(), &strategy_id, &other_id],
+            &crate::ID,
+        );
+        assert_eq!(trade_address(&agent, &strategy_id, &other_id, other_bump).unwrap(), other_address);
+        assert_ne!(address, other_address);
+    }
+    
+    #[test]
+    fn each_risk_profile_seeds_its_gas_defaults() {
+        let conservative = GasProfileDefaults::for_risk_profile(&RiskProfile::Conservative).gas_settings();
+        let moderate = GasProfileDefaults::for_risk_profile(&RiskProfile::Moderate).gas_settings();
+        let aggressive = GasProfileDefaults::for_risk_profile(&RiskProfile::Aggressive).gas_settings();
+        
+        assert_eq!((conservative.priority_fee, conservative.compute_units), (0, 200_000));
+        assert_eq!((moderate.priority_fee, moderate.compute_units), (10_000, 300_000));
+        assert_eq!((aggressive.priority_fee, aggressive.compute_units), (100_000, 400_000));
+        assert!(conservative.retry_on_fail && conservative.max_retries == 3);
+        
+        // Every default builds a valid compute budget
+        for gas_settings in [&conservative, &moderate, &aggressive] {
+            assert!(compute_budget_request(gas_settings).is_ok());
+        }
+    }
+    
+    #[test]
+    fn basket_members_must_sum_to_their_group() {
+        let allocation = |target_percentage, group_id| TokenAllocation {
+            mint: Pubkey::new_unique(),
+            target_percentage,
+            max_deviation_bps: 500,
+            decimals: 6,
+            usd_price: 1_000_000,
+            group_id,
+        };
+        let group = |group_id, target_bps| AllocationGroup { group_id, target_bps, max_deviation_bps: 300 };
+        let mut allocations = vec![
+            allocation(2000, 1),
+            allocation(2000, 1),
+            allocation(1000, 1),
+            allocation(1500, 2),
+            allocation(1500, 2),
+            allocation(2000, 0),
+        ];
+        let groups = vec![group(1, 5000), group(2, 3000)];
+        assert!(check_allocation_groups(&allocations, &groups).is_ok());
+        
+        // Group and member drift are both reported
+        let usd_values = [2500, 2000, 1000, 1000, 1500, 2000];
+        let (deviations, group_deviations) = rebalance_deviations(&allocations, &groups, &usd_values);
+        assert_eq!((deviations[0].current_bps, deviations[0].deviation_bps), (2500, 500));
+        assert!(!deviations[0].needs_rebalance);
+        assert_eq!((group_deviations[0].current_bps, group_deviations[0].deviation_bps), (5500, 500));
+        assert_eq!((group_deviations[1].current_bps, group_deviations[1].deviation_bps), (2500, 500));
+        assert!(group_deviations.iter().all(|g| g.needs_rebalance));
+        
+        allocations[2].target_percentage = 900;
+        assert_eq!(
+            check_allocation_groups(&allocations, &groups).unwrap_err(),
+            error!(ErrorCode::GroupAllocationMismatch)
+        );
+        
+        allocations[2].target_percentage = 1000;
+        allocations[5].group_id = 3;
+        assert_eq!(
+            check_allocation_groups(&allocations, &groups).unwrap_err(),
+            error!(ErrorCode::InvalidAllocationGroup)
+        );
+    }
+    
+    #[test]
+    fn snapshots_decode_to_the_underlying_accounts() {
+        let (agent, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut agent_config = test_agent_config(owner);
+        let mut agent_stats = test_agent_stats(agent, owner);
+        agent_config.status = AgentStatus::Active;
+        agent_config.trading_budget = 5_000;
+        agent_config.strategies = vec![test_strategy(1), test_strategy(2)];
+        agent_stats.total_trades = 12;
+        agent_stats.profit_loss = -300;
+        agent_stats.peak_portfolio_value = 9_000;
+        
+        // Return data is the Borsh encoding of the snapshot
+        let data = AgentSnapshot::new(agent, &agent_config, &agent_stats).try_to_vec().unwrap();
+        assert_eq!(data.len(), AgentSnapshot::SIZE);
+        
+        let snapshot = AgentSnapshot::try_from_slice(&data).unwrap();
+        assert_eq!(snapshot.agent, agent);
+        assert_eq!(snapshot.status, AgentStatus::Active);
+        assert_eq!((snapshot.trading_budget, snapshot.num_strategies), (5_000, 2));
+        assert_eq!((snapshot.total_trades, snapshot.profit_loss, snapshot.peak_portfolio_value), (12, -300, 9_000));
+    }
+    
+    #[test]
+    fn unversioned_accounts_load_once_grown_and_migrate_once() {
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), Pubkey::new_unique());
+        agent_stats.total_trades = 42;
+        let mut data = Vec::new();
+        agent_stats.try_serialize(&mut data).unwrap();
+        
+        // An account from just before v2 has no schema_version byte, so it only loads once grown
+        data.truncate(data.len() - AGENT_SCHEMA_V2_GROWTH);
+        assert!(AgentStats::try_deserialize(&mut &data[..]).is_err());
+        data.resize(data.len() + AGENT_SCHEMA_V2_GROWTH, 0);
+        let mut migrated = AgentStats::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!((migrated.schema_version, migrated.total_trades), (0, 42));
+        
+        assert!(upgrade_schema(&mut migrated.schema_version));
+        assert_eq!(migrated.schema_version, AGENT_SCHEMA_VERSION);
+        assert!(!upgrade_schema(&mut migrated.schema_version));
+        assert_eq!(migrated.schema_version, AGENT_SCHEMA_VERSION);
+    }
+    
+    #[test]
+    fn trades_are_classified_against_the_quote_mint() {
+        let (quote, token, other) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        
+        assert_eq!(trade_direction(&quote, &quote, &token, None).unwrap(), TradeDirection::Buy);
+        assert_eq!(trade_direction(&quote, &token, &quote, None).unwrap(), TradeDirection::Sell);
+        assert_eq!(trade_direction(&quote, &token, &other, None).unwrap(), TradeDirection::Rebalance);
+        assert_eq!(
+            trade_direction(&quote, &token, &quote, Some(TradeDirection::Buy)).unwrap_err(),
+            error!(ErrorCode::TradeDirectionMismatch)
+        );
+        
+        // Without a quote mint only the caller's direction is known
+        let unset = Pubkey::default();
+        assert_eq!(trade_direction(&unset, &token, &other, None).unwrap(), TradeDirection::Rebalance);
+        assert_eq!(trade_direction(&unset, &token, &other, Some(TradeDirection::Sell)).unwrap(), TradeDirection::Sell);
+    }
+    
+    #[test]
+    fn priority_fees_are_capped() {
+        let mut gas_settings = test_agent_config(Pubkey::new_unique()).gas_settings;
+        
+        assert!(apply_gas_settings_update(&mut gas_settings, Some(MAX_PRIORITY_FEE), None, None, None).is_ok());
+        assert_eq!(gas_settings.priority_fee, MAX_PRIORITY_FEE);
+        assert_eq!(
+            apply_gas_settings_update(&mut gas_settings, Some(MAX_PRIORITY_FEE + 1), None, None, None).unwrap_err(),
+            error!(ErrorCode::PriorityFeeTooHigh)
+        );
+        assert_eq!(gas_settings.priority_fee, MAX_PRIORITY_FEE);
+    }
+    
+    #[test]
+    fn snapshots_value_token_balances_at_pyth_prices() {
+        use solana_program::program_pack::Pack;
+        use spl_token::state::{Account as SplTokenAccount, AccountState, Mint as SplMint};
+        
+        let owner = Pubkey::new_unique();
+        let owners = [anchor_spl::token::ID, anchor_spl::token::ID, crate::ID, price_alerts::PYTH_RECEIVER_PROGRAM_ID];
+        
+        // (decimals, balance, price at exponent -8): 2 tokens at $1.50 and half a token at $100
+        let tokens = [(6, 2_000_000, 150_000_000), (9, 500_000_000, 10_000_000_000)];
+        let mut keys = Vec::new();
+        let mut datas = Vec::new();
+        for (index, (decimals, amount, price)) in tokens.into_iter().enumerate() {
+            let mint = Pubkey::new_unique();
+            let feed_id = [index as u8 + 1; 32];
+            
+            let mut token_data = vec![0; SplTokenAccount::LEN];
+            let token_account = SplTokenAccount { mint, owner, amount, state: AccountState::Initialized, ..SplTokenAccount::default() };
+            SplTokenAccount::pack(token_account, &mut token_data).unwrap();
+            let mut mint_data = vec![0; SplMint::LEN];
+            SplMint::pack(SplMint { decimals, is_initialized: true, ..SplMint::default() }, &mut mint_data).unwrap();
+            let mut feed_data = Vec::new();
+            price_alerts::PriceFeedMapping { mint, feed_id, bump: 0 }.try_serialize(&mut feed_data).unwrap();
+            
+            keys.extend([
+                spl_associated_token_account::get_associated_token_address(&owner, &mint),
+                mint,
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+            ]);
+            datas.extend([
+                token_data,
+                mint_data,
+                feed_data,
+                price_alerts::tests::price_update_data(&[1], feed_id, price, -8, 1_000),
+            ]);
+        }
+        let mut lamports = [0; 8];
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(datas.iter_mut())
+            .zip(lamports.iter_mut())
+            .enumerate()
+            .map(|(i, ((key, data), lamports))| AccountInfo::new(key, false, false, lamports, data, &owners[i % 4], false, 0))
+            .collect();
+        
+        // $3 plus $50, in micro-USD
+        assert_eq!(token_balances_usd_value(&accounts, &owner, 1_000).unwrap(), 53_000_000);
+        
+        // A token can only be counted once
+        let doubled: Vec<AccountInfo> = accounts[..4].iter().chain(accounts[..4].iter()).cloned().collect();
+        assert_eq!(
+            token_balances_usd_value(&doubled, &owner, 1_000).unwrap_err(),
+            error!(ErrorCode::InvalidSnapshotAccounts)
+        );
+    }
+    
+    #[test]
+    fn dust_trades_are_filtered() {
+        let mut rules = test_agent_config(Pubkey::new_unique()).trading_rules;
+        let (input_mint, output_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        
+        // Disabled by default
+        assert!(check_trading_rules(&rules, &input_mint, &output_mint, 1).is_ok());
+        
+        rules.min_trade_amount = 100;
+        assert_eq!(
+            check_trading_rules(&rules, &input_mint, &output_mint, 99).unwrap_err(),
+            error!(ErrorCode::TradeTooSmall)
+        );
+        assert!(check_trading_rules(&rules, &input_mint, &output_mint, 100).is_ok());
+        assert!(check_trading_rules(&rules, &input_mint, &output_mint, 101).is_ok());
+    }
+    
+    #[test]
+    fn every_reason_code_is_stored_and_emitted() {
+        let reasons = [
+            TradeReason::StrategySignal,
+            TradeReason::Rebalance,
+            TradeReason::StopLoss,
+            TradeReason::ManualOverride,
+            TradeReason::DollarCostAverage,
+        ];
+        for reason_code in reasons {
+            let event = TradeExecutedEvent {
+                agent: Pubkey::new_unique(),
+                owner: Pubkey::new_unique(),
+                strategy_id: [0; 16],
+                input_mint: Pubkey::new_unique(),
+                output_mint: Pubkey::new_unique(),
+                input_amount: 1,
+                output_amount: 1,
+                success: true,
+                direction: TradeDirection::Buy,
+                reason_code,
+                fee_amount: 0,
+                trade_record: Pubkey::new_unique(),
+                trade_hash: [0; 32],
+                timestamp: 0,
+            };
+            let data = event.try_to_vec().unwrap();
+            assert_eq!(TradeExecutedEvent::try_from_slice(&data).unwrap().reason_code, reason_code);
+            assert_eq!(TradeReason::try_from_slice(&reason_code.try_to_vec().unwrap()).unwrap(), reason_code);
+        }
+        
+        // Instruction data with an unknown code doesn't decode
+        assert!(TradeReason::try_from_slice(&[reasons.len() as u8]).is_err());
+    }
+    
+    #[test]
+    fn recorded_fees_accumulate_across_trades() {
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), Pubkey::new_unique());
+        for fee_amount in [5_000, 0, 12_500] {
+            agent_stats.record_fee(fee_amount).unwrap();
+        }
+        assert_eq!(agent_stats.total_fees_paid, 17_500);
+        
+        assert_eq!(
+            agent_stats.record_fee(u64::MAX).unwrap_err(),
+            error!(ErrorCode::ArithmeticOverflow)
+        );
+        assert_eq!(agent_stats.total_fees_paid, 17_500);
+    }
+    
+    #[test]
+    fn resetting_stats_zeroes_totals_but_keeps_history_markers() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), owner);
+        agent_stats.created_at = 10;
+        agent_stats.total_trades = 8;
+        agent_stats.successful_trades = 6;
+        agent_stats.failed_trades = 2;
+        agent_stats.total_volume = 50_000;
+        agent_stats.total_fees_paid = 700;
+        agent_stats.profit_loss = -1_200;
+        agent_stats.peak_portfolio_value = 90_000;
+        agent_stats.performance_data.push(PerformancePoint { timestamp: 20, portfolio_value: 90_000, daily_profit_loss: 0 });
+        agent_stats.cost_basis.push(CostBasisEntry { mint, total_amount: 10, total_cost: 1_000 });
+        
+        agent_stats.reset(500);
+        assert_eq!(
+            (agent_stats.total_trades, agent_stats.successful_trades, agent_stats.failed_trades),
+            (0, 0, 0)
+        );
+        assert_eq!((agent_stats.total_volume, agent_stats.total_fees_paid, agent_stats.profit_loss), (0, 0, 0));
+        assert_eq!(agent_stats.peak_portfolio_value, 0);
+        assert!(agent_stats.performance_data.is_empty());
+        assert_eq!((agent_stats.created_at, agent_stats.last_updated_at), (10, 500));
+        assert_eq!(agent_stats.cost_basis.len(), 1);
+        assert_eq!((agent_stats.owner, agent_stats.valuation_decimals), (owner, 6));
+    }
+    
+    #[test]
+    fn slippage_over_the_limit_raises_a_notice() {
+        assert_eq!(slippage_notice(100, 100), None);
+        
+        let (title, message, data) = slippage_notice(150, 100).unwrap();
+        assert_eq!(title, "Slippage Limit Exceeded");
+        assert_eq!(message, "Trade slippage of 150 bps exceeded your limit of 100 bps");
+        assert_eq!(data, "{\"requestedSlippageBps\":150, \"allowedSlippageBps\":100}");
+        
+        // The leg is recorded as failed rather than failing the instruction
+        assert!(settled_success(true, 100, 100));
+        assert!(!settled_success(true, 150, 100));
+        assert!(!settled_success(false, 0, 100));
+        
+        // Without preferences the notice goes out on every channel
+        assert_eq!(
+            resolve_channels(&[], Pubkey::new_unique(), NotificationEventType::SlippageExceeded),
+            ALL_CHANNELS
+        );
+    }
+    
+    #[test]
+    fn global_pause_halts_active_agents_until_lifted() {
+        // The two constraints every trading instruction checks
+        let may_trade = |program_config: &ProgramConfig, agent_config: &AgentConfig| {
+            program_config.trading_allowed() && agent_config.accepts_trades()
+        };
+        let mut agent_config = test_agent_config(Pubkey::new_unique());
+        assert_eq!(agent_config.status, AgentStatus::Active);
+        
+        let mut program_config = ProgramConfig {
+            admin: Pubkey::new_unique(),
+            globally_paused: false,
+            bump: 255,
+        };
+        assert!(may_trade(&program_config, &agent_config));
+        
+        // An active agent is halted while the pause is on, whatever its own status
+        program_config.globally_paused = true;
+        assert!(agent_config.accepts_trades());
+        assert!(!may_trade(&program_config, &agent_config));
+        agent_config.status = AgentStatus::CloseOnly;
+        assert!(!may_trade(&program_config, &agent_config));
+        
+        // Lifting the pause restores trading for active and close-only agents only
+        program_config.globally_paused = false;
+        assert!(may_trade(&program_config, &agent_config));
+        agent_config.status = AgentStatus::Active;
+        assert!(may_trade(&program_config, &agent_config));
+        for status in [AgentStatus::Paused, AgentStatus::Inactive] {
+            agent_config.status = status;
+            assert!(!may_trade(&program_config, &agent_config));
+        }
+    }
+    
+    #[test]
+    fn transferred_agents_answer_to_the_new_owner() {
+        let old_owner = Pubkey::new_unique();
+        let new_owner = Pubkey::new_unique();
+        let mut agent_config = test_agent_config(old_owner);
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), old_owner);
+        
+        assert_eq!(
+            agent_config.transfer_ownership(&mut agent_stats, old_owner, 100).unwrap_err(),
+            error!(CommonError::InvalidParameter)
+        );
+        
+        assert_eq!(agent_config.transfer_ownership(&mut agent_stats, new_owner, 100).unwrap(), old_owner);
+        assert!(agent_config.is_owned_by(&new_owner));
+        assert!(!agent_config.is_owned_by(&old_owner));
+        assert_eq!(agent_stats.owner, new_owner);
+        assert_eq!(agent_config.updated_at, 100);
+    }
+    
+    #[test]
+    fn agents_are_addressed_by_id_not_owner() {
+        let owner = Pubkey::new_unique();
+        let agent_address = |agent_id: &[u8; 16]| {
+            Pubkey::find_program_address(&[b"agent", agent_id.as_ref()], &crate::ID).0
+        };
+        
+        // One owner may hold several agents
+        let mut first = test_agent_config(owner);
+        first.agent_id = [1; 16];
+        let mut second = test_agent_config(owner);
+        second.agent_id = [2; 16];
+        assert_ne!(agent_address(&first.agent_id), agent_address(&second.agent_id));
+        
+        // Transferring one keeps its address and leaves the other alone
+        let address = agent_address(&first.agent_id);
+        let new_owner = Pubkey::new_unique();
+        let mut stats = test_agent_stats(address, owner);
+        first.transfer_ownership(&mut stats, new_owner, 100).unwrap();
+        assert_eq!(agent_address(&first.agent_id), address);
+        assert!(first.is_owned_by(&new_owner));
+        assert!(second.is_owned_by(&owner));
+    }
+    
+    #[test]
+    fn scheduled_strategies_come_due_and_move_forward() {
+        let mut strategy = test_strategy(1);
+        assert!(!strategy.is_due(0));
+        assert_eq!(strategy.mark_executed(0).unwrap_err(), error!(ErrorCode::StrategyNotScheduled));
+        
+        strategy.interval_secs = 3_600;
+        strategy.next_run_at = 1_000;
+        assert!(!strategy.is_due(999));
+        assert_eq!(strategy.mark_executed(999).unwrap_err(), error!(ErrorCode::StrategyNotDue));
+        assert!(strategy.is_due(1_000));
+        
+        strategy.mark_executed(1_000).unwrap();
+        assert_eq!(strategy.next_run_at, 4_600);
+        assert_eq!((strategy.last_executed_at, strategy.execution_count), (1_000, 1));
+        assert!(!strategy.is_due(1_000));
+        
+        // Running two and a half intervals late skips the missed slots
+        strategy.mark_executed(4_600 + 9_000).unwrap();
+        assert_eq!(strategy.next_run_at, 4_600 + 3 * 3_600);
+        assert_eq!(strategy.execution_count, 2);
+        
+        // Paused strategies are never due
+        strategy.is_active = false;
+        assert!(!strategy.is_due(i64::MAX));
+    }
+    
+    #[test]
+    fn trade_totals_fail_cleanly_on_overflow() {
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), Pubkey::new_unique());
+        
+        agent_stats.record_trades(3, 2, 500).unwrap();
+        assert_eq!(
+            (agent_stats.total_trades, agent_stats.successful_trades, agent_stats.failed_trades, agent_stats.total_volume),
+            (3, 2, 1, 500)
+        );
+        
+        agent_stats.total_volume = u64::MAX - 10;
+        assert_eq!(agent_stats.record_trades(1, 1, 11).unwrap_err(), error!(ErrorCode::ArithmeticOverflow));
+        agent_stats.record_trades(1, 1, 10).unwrap();
+        assert_eq!(agent_stats.total_volume, u64::MAX);
+        
+        agent_stats.total_trades = u64::MAX;
+        assert_eq!(agent_stats.record_trades(1, 0, 0).unwrap_err(), error!(ErrorCode::ArithmeticOverflow));
+    }
+    
+    #[test]
+    fn authorized_programs_must_be_the_direct_cpi_caller() {
+        use solana_program::sysvar::instructions::{construct_instructions_data, BorrowedInstruction};
+        
+        let caller = Pubkey::new_unique();
+        let sysvar_key = solana_program::sysvar::instructions::ID;
+        let mut lamports = 0;
+        let mut data = construct_instructions_data(&[BorrowedInstruction {
+            program_id: &caller,
+            accounts: vec![],
+            data: &[],
+        }]);
+        let instructions_sysvar = AccountInfo::new(&sysvar_key, false, false, &mut lamports, &mut data, &sysvar_key, false, 0);
+        
+        // Only a CPI straight from the top-level instruction names its caller
+        assert_eq!(cpi_caller(&instructions_sysvar, TRANSACTION_LEVEL_STACK_HEIGHT + 1).unwrap(), Some(caller));
+        assert_eq!(cpi_caller(&instructions_sysvar, TRANSACTION_LEVEL_STACK_HEIGHT).unwrap(), None);
+        assert_eq!(cpi_caller(&instructions_sysvar, TRANSACTION_LEVEL_STACK_HEIGHT + 2).unwrap(), None);
+    }
+    
+    #[test]
+    fn authorized_programs_record_for_any_authority() {
+        let owner = Pubkey::new_unique();
+        let caller = Pubkey::new_unique();
+        let mut agent_config = test_agent_config(owner);
+        agent_config.authorized_programs = vec![caller];
+        
+        assert!(check_recorder(&agent_config, &Pubkey::new_unique(), Some(caller)).is_ok());
+        assert_eq!(
+            check_recorder(&agent_config, &Pubkey::new_unique(), Some(Pubkey::new_unique())).unwrap_err(),
+            error!(ErrorCode::UnauthorizedCaller)
+        );
+        // A nested CPI has no known caller
+        assert_eq!(
+            check_recorder(&agent_config, &Pubkey::new_unique(), None).unwrap_err(),
+            error!(ErrorCode::UnauthorizedCaller)
+        );
+        
+        // The owner can still record directly
+        assert!(check_recorder(&agent_config, &owner, None).is_ok());
+    }
+    
+    #[test]
+    fn recorded_rebalances_are_throttled() {
+        let mut agent_config = test_agent_config(Pubkey::new_unique());
+        agent_config.min_rebalance_interval_secs = 3_600;
+        agent_config.last_rebalance_at = 0;
+        
+        agent_config.record_rebalance(5_000).unwrap();
+        assert_eq!(
+            agent_config.record_rebalance(8_599).unwrap_err(),
+            error!(ErrorCode::RebalanceTooSoon)
+        );
+        assert_eq!(agent_config.last_rebalance_at, 5_000);
+        
+        agent_config.record_rebalance(8_600).unwrap();
+        assert_eq!(agent_config.last_rebalance_at, 8_600);
+    }
+    
+    #[test]
+    fn cost_basis_and_value_changes_fail_cleanly_on_overflow() {
+        let quote = Pubkey::new_unique();
+        let token = Pubkey::new_unique();
+        let mut agent_stats = test_agent_stats(Pubkey::new_unique(), Pubkey::new_unique());
+        
+        update_cost_basis(&mut agent_stats, &quote, &quote, &token, 1_000, u64::MAX - 10, 1).unwrap();
+        assert_eq!(
+            update_cost_basis(&mut agent_stats, &quote, &quote, &token, 1_000, 11, 2).unwrap_err(),
+            error!(ErrorCode::ArithmeticOverflow)
+        );
+        assert_eq!(
+            update_cost_basis(&mut agent_stats, &quote, &quote, &token, u64::MAX, 1, 3).unwrap_err(),
+            error!(ErrorCode::ArithmeticOverflow)
+        );
+        
+        assert_eq!(value_change(500, 200).unwrap(), -300);
+        assert_eq!(value_change(200, 500).unwrap(), 300);
+        assert_eq!(value_change(0, u64::MAX).unwrap_err(), error!(ErrorCode::ArithmeticOverflow));
+        assert_eq!(value_change(u64::MAX, 0).unwrap_err(), error!(ErrorCode::ArithmeticOverflow));
+    }
+    
+    #[test]
+    fn trade_action_space_matches_its_serialized_size() {
+        for reason in ["", "rebalanced after a stop loss"] {
+            let trade_action = TradeAction {
+                agent: Pubkey::new_unique(),
+                owner: Pubkey::new_unique(),
+                strategy_id: [1; 16],
+                input_mint: Pubkey::new_unique(),
+                output_mint: Pubkey::new_unique(),
+                input_amount: u64::MAX,
+                output_amount: u64::MAX,
+                slippage_bps: u16::MAX,
+                executed_at: i64::MAX,
+                transaction_signature: [2; 64],
+                success: true,
+                price_impact_bps: u16::MAX,
+                reason: reason.to_string(),
+                reason_code: TradeReason::DollarCostAverage,
+                prev_trade_hash: [3; 32],
+                direction: TradeDirection::Rebalance,
+                bump: 255,
+            };
+            assert_eq!(
+                TradeAction::space(reason.len()),
+                8 + trade_action.try_to_vec().unwrap().len()
+            );
+        }
+        assert_eq!(TradeAction::space(0), 284);
+    }
+}
@@ -4,7 +4,7 @@ use anchor_lang::prelude::*;
 use solana_program::pubkey::Pubkey;
 
 // Event types for notifications
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
 pub enum NotificationEventType {
     // Market events
     PriceAlert,
@@ -31,6 +31,15 @@ pub enum NotificationEventType {
     MaintenanceAlert,
 }
 
+// Number of NotificationEventType variants
+pub const NOTIFICATION_EVENT_TYPE_COUNT: usize = 16;
+
+// Delivery channel bits
+pub const CHANNEL_EMAIL: u8 = 1 << 0;
+pub const CHANNEL_BROWSER: u8 = 1 << 1;
+pub const CHANNEL_WEBHOOK: u8 = 1 << 2;
+pub const ALL_CHANNELS: u8 = CHANNEL_EMAIL | CHANNEL_BROWSER | CHANNEL_WEBHOOK;
+
 // Notification priority levels
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
 pub enum NotificationPriority {
@@ -40,12 +49,192 @@ pub enum NotificationPriority {
     Critical,
 }
 
+// Per-user notification sequence counter
+#[account]
+pub struct NotificationCounter {
+    // User whose notifications are counted
+    pub user: Pubkey,
+    
+    // Sequence number of the last notification emitted for the user
+    pub seq: u64,
+    
+    // Bump used for PDA
+    pub bump: u8,
+}
+
+impl NotificationCounter {
+    pub const SPACE: usize = 8 + 32 + 8 + 1;
+    
+    // Advance to and return the sequence number of the user's next notification
+    pub fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(user: Pubkey)]
+pub struct InitializeNotificationCounter<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = payer,
+        space = NotificationCounter::SPACE,
+        seeds = [b"notification-counter", user.as_ref()],
+        bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+// Per-user delivery channels for each notification type
+#[account]
+pub struct NotificationPrefs {
+    // User the preferences belong to
+    pub user: Pubkey,
+    
+    // Enabled channel bits, indexed by NotificationEventType
+    pub channels: [u8; NOTIFICATION_EVENT_TYPE_COUNT],
+    
+    // Bump used for PDA
+    pub bump: u8,
+}
+
+impl NotificationPrefs {
+    pub const SPACE: usize = 8 + 32 + NOTIFICATION_EVENT_TYPE_COUNT + 1;
+}
+
+#[derive(Accounts)]
+pub struct InitializeNotificationPrefs<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = NotificationPrefs::SPACE,
+        seeds = [b"notification-prefs", user.key().as_ref()],
+        bump
+    )]
+    pub notification_prefs: Account<'info, NotificationPrefs>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateNotificationPrefs<'info> {
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-prefs", user.key().as_ref()],
+        bump = notification_prefs.bump
+    )]
+    pub notification_prefs: Account<'info, NotificationPrefs>,
+}
+
+// Most webhooks a user may register
+pub const MAX_WEBHOOKS: usize = 5;
+
+// A registered webhook endpoint
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Webhook {
+    // Id carried by notification events
+    pub id: u32,
+    
+    // Hash of the endpoint URL and signing secret; the relay holds the plaintext
+    pub endpoint_hash: [u8; 32],
+}
+
+// Per-user webhook endpoints for off-chain delivery
+#[account]
+pub struct NotificationWebhooks {
+    // User the webhooks belong to
+    pub user: Pubkey,
+    
+    // Registered webhooks, oldest first
+    pub webhooks: Vec<Webhook>,
+    
+    // Webhook notifications are delivered to (the latest registered)
+    pub active_webhook_id: Option<u32>,
+    
+    // Id given to the next registered webhook
+    pub next_id: u32,
+    
+    // Bump used for PDA
+    pub bump: u8,
+}
+
+impl NotificationWebhooks {
+    pub const SPACE: usize = 8 + 32 + 4 + MAX_WEBHOOKS * (4 + 32) + 5 + 4 + 1;
+    
+    // Add an endpoint and make it the active one, returning its id
+    pub fn register(&mut self, endpoint_hash: [u8; 32]) -> Result<u32> {
+        require!(self.webhooks.len() < MAX_WEBHOOKS, crate::errors::CommonError::InvalidParameter);
+        
+        let id = self.next_id;
+        self.webhooks.push(Webhook { id, endpoint_hash });
+        self.active_webhook_id = Some(id);
+        self.next_id += 1;
+        Ok(id)
+    }
+    
+    // Drop an endpoint; the latest remaining one becomes active
+    pub fn remove(&mut self, webhook_id: u32) -> Result<()> {
+        let index = self.webhooks
+            .iter()
+            .position(|webhook| webhook.id == webhook_id)
+            .ok_or(crate::errors::CommonError::InvalidParameter)?;
+        self.webhooks.remove(index);
+        
+        if self.active_webhook_id == Some(webhook_id) {
+            self.active_webhook_id = self.webhooks.last().map(|webhook| webhook.id);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializeNotificationWebhooks<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = NotificationWebhooks::SPACE,
+        seeds = [b"notification-webhooks", user.key().as_ref()],
+        bump
+    )]
+    pub notification_webhooks: Account<'info, NotificationWebhooks>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateNotificationWebhooks<'info> {
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-webhooks", user.key().as_ref()],
+        bump = notification_webhooks.bump
+    )]
+    pub notification_webhooks: Account<'info, NotificationWebhooks>,
+}
+
 // Notification event emitted by the contract
 #[event]
 pub struct NotificationEvent {
     // User associated with the notification
     pub user: Pubkey,
     
+    // Per-user sequence number; a gap means a notification was missed
+    pub seq: u64,
+    
     // Type of notification
     pub event_type: NotificationEventType,
     
@@ -72,6 +261,12 @@ pub struct NotificationEvent {
     
     // Transaction signature (if applicable)
     pub tx_signature: Option<String>,
+    
+    // Channels the user wants this notification delivered on (CHANNEL_* bits)
+    pub channels: u8,
+    
+    // Webhook the relay should deliver to, when the webhook channel is enabled
+    pub webhook_id: Option<u32>,
 }
 
 // Trade notification event - specialized for trade executions
@@ -121,21 +316,147 @@ pub struct PriceAlertEvent {
     pub timestamp: i64,
 }
 
+// Create the notification counter for a user (anyone may pay for it)
+pub fn initialize_notification_counter(
+    ctx: Context<InitializeNotificationCounter>,
+    user: Pubkey,
+) -> Result<()> {
+    let bump = *ctx.bumps.get("notification_counter").unwrap();
+    init_notification_counter(&mut ctx.accounts.notification_counter, user, bump);
+    
+    Ok(())
+}
+
+// Set up a freshly created notification counter
+pub fn init_notification_counter(counter: &mut Account<NotificationCounter>, user: Pubkey, bump: u8) {
+    counter.user = user;
+    counter.seq = 0;
+    counter.bump = bump;
+}
+
+// Create a user's notification preferences with every channel enabled
+pub fn initialize_notification_prefs(ctx: Context<InitializeNotificationPrefs>) -> Result<()> {
+    let prefs = &mut ctx.accounts.notification_prefs;
+    prefs.user = ctx.accounts.user.key();
+    prefs.channels = [ALL_CHANNELS; NOTIFICATION_EVENT_TYPE_COUNT];
+    prefs.bump = *ctx.bumps.get("notification_prefs").unwrap();
+    
+    Ok(())
+}
+
+// Set the channels one notification type is delivered on (0 mutes it)
+pub fn set_notification_channels(
+    ctx: Context<UpdateNotificationPrefs>,
+    event_type: NotificationEventType,
+    channels: u8,
+) -> Result<()> {
+    require!(channels & !ALL_CHANNELS == 0, crate::errors::CommonError::InvalidParameter);
+    
+    ctx.accounts.notification_prefs.channels[event_type as usize] = channels;
+    
+    Ok(())
+}
+
+// Create a user's (empty) webhook list
+pub fn initialize_notification_webhooks(ctx: Context<InitializeNotificationWebhooks>) -> Result<()> {
+    let webhooks = &mut ctx.accounts.notification_webhooks;
+    webhooks.user = ctx.accounts.user.key();
+    webhooks.webhooks = Vec::new();
+    webhooks.active_webhook_id = None;
+    webhooks.next_id = 1;
+    webhooks.bump = *ctx.bumps.get("notification_webhooks").unwrap();
+    
+    Ok(())
+}
+
+// Register a webhook endpoint and make it the active one
+pub fn register_webhook(ctx: Context<UpdateNotificationWebhooks>, endpoint_hash: [u8; 32]) -> Result<()> {
+    let id = ctx.accounts.notification_webhooks.register(endpoint_hash)?;
+    
+    msg!("Registered webhook {}", id);
+    Ok(())
+}
+
+// Remove a webhook; the latest remaining one becomes active
+pub fn remove_webhook(ctx: Context<UpdateNotificationWebhooks>, webhook_id: u32) -> Result<()> {
+    ctx.accounts.notification_webhooks.remove(webhook_id)
+}
+
+// Active webhook for `user`, picked up from `accounts` like resolve_channels
+pub fn resolve_webhook(accounts: &[AccountInfo], user: Pubkey) -> Option<u32> {
+    accounts
+        .iter()
+        .filter(|info| *info.owner == crate::ID)
+        .filter_map(|info| Account::<NotificationWebhooks>::try_from(info).ok())
+        .find(|webhooks| webhooks.user == user)
+        .and_then(|webhooks| webhooks.active_webhook_id)
+}
+
+// Channels `user` wants `event_type` on. Prefs are picked up from `accounts`
+// (usually the instruction's remaining accounts); without them every
+// channel is enabled.
+pub fn resolve_channels(accounts: &[AccountInfo], user: Pubkey, event_type: NotificationEventType) -> u8 {
+    accounts
+        .iter()
+        .filter(|info| *info.owner == crate::ID)
+        .filter_map(|info| Account::<NotificationPrefs>::try_from(info).ok())
+        .find(|prefs| prefs.user == user)
+        .map(|prefs| prefs.channels[event_type as usize])
+        .unwrap_or(ALL_CHANNELS)
+}
+
+// Content of a notification; see NotificationEvent
+pub struct Notification {
+    pub event_type: NotificationEventType,
+    pub priority: NotificationPriority,
+    pub title: String,
+    pub message: String,
+    pub data: Option<String>,
+    pub strategy_id: Option<u64>,
+    pub token_address: Option<Pubkey>,
+    pub tx_signature: Option<String>,
+}
+
+// Details of a trade notification; see TradeNotificationEvent
+pub struct TradeNotification {
+    pub from_token: Pubkey,
+    pub to_token: Pubkey,
+    pub from_amount: u64,
+    pub to_amount: u64,
+    pub price_impact: i32,
+    pub success: bool,
+    pub tx_signature: String,
+    pub strategy_id: Option<u64>,
+}
+
 // Functions to emit notification events
 pub fn emit_notification(
-    ctx: Context<&impl Accounts>,
+    accounts: &[AccountInfo],
+    counter: &mut Account<NotificationCounter>,
     user: Pubkey,
-    event_type: NotificationEventType,
-    priority: NotificationPriority,
-    title: String,
-    message: String,
-    data: Option<String>,
-    strategy_id: Option<u64>,
-    token_address: Option<Pubkey>,
-    tx_signature: Option<String>,
+    notification: Notification,
 ) {
+    let Notification {
+        event_type,
+        priority,
+        title,
+        message,
+        data,
+        strategy_id,
+        token_address,
+        tx_signature,
+    } = notification;
+    let seq = counter.next_seq();
+    let channels = resolve_channels(accounts, user, event_type);
+    let webhook_id = if channels & CHANNEL_WEBHOOK != 0 {
+        resolve_webhook(accounts, user)
+    } else {
+        None
+    };
+    
     emit!(NotificationEvent {
         user,
+        seq,
         event_type,
         priority,
         title,
@@ -145,22 +466,22 @@ pub fn emit_notification(
         strategy_id,
         token_address,
         tx_signature,
+        channels,
+        webhook_id,
     });
 }
 
-pub fn emit_trade_notification(
-    ctx: Context<&impl Accounts>,
-    user: Pubkey,
-    event_type: NotificationEventType,
-    from_token: Pubkey,
-    to_token: Pubkey,
-    from_amount: u64,
-    to_amount: u64,
-    price_impact: i32,
-    success: bool,
-    tx_signature: String,
-    strategy_id: Option<u64>,
-) {
+pub fn emit_trade_notification(user: Pubkey, event_type: NotificationEventType, trade: TradeNotification) {
+    let TradeNotification {
+        from_token,
+        to_token,
+        from_amount,
+        to_amount,
+        price_impact,
+        success,
+        tx_signature,
+        strategy_id,
+    } = trade;
     emit!(TradeNotificationEvent {
         user,
         event_type,
@@ -177,7 +498,6 @@ pub fn emit_trade_notification(
 }
 
 pub fn emit_price_alert(
-    ctx: Context<&impl Accounts>,
     user: Pubkey,
     token_address: Pubkey,
     alert_direction: bool,
@@ -192,4 +512,111 @@ pub fn emit_price_alert(
         current_price,
         timestamp: Clock::get().unwrap().unix_timestamp,
     });
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn muted_event_types_resolve_to_no_channels() {
+        let user = Pubkey::new_unique();
+        let mut channels = [ALL_CHANNELS; NOTIFICATION_EVENT_TYPE_COUNT];
+        channels[NotificationEventType::TradeExecuted as usize] = 0;
+        let prefs = NotificationPrefs { user, channels, bump: 0 };
+        
+        let key = Pubkey::new_unique();
+        let mut lamports = 1;
+        let mut data = vec![];
+        prefs.try_serialize(&mut data).unwrap();
+        let owner = crate::ID;
+        let prefs_info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        let accounts = [prefs_info];
+        
+        assert_eq!(resolve_channels(&accounts, user, NotificationEventType::TradeExecuted), 0);
+        assert_eq!(resolve_channels(&accounts, user, NotificationEventType::PriceAlert), ALL_CHANNELS);
+        
+        // Someone else's preferences don't apply
+        assert_eq!(
+            resolve_channels(&accounts, Pubkey::new_unique(), NotificationEventType::TradeExecuted),
+            ALL_CHANNELS
+        );
+    }
+    
+    #[test]
+    fn missing_prefs_enable_every_channel() {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![];
+        let owner = solana_program::system_program::ID;
+        let uncreated = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        
+        assert_eq!(
+            resolve_channels(&[uncreated], Pubkey::new_unique(), NotificationEventType::TradeExecuted),
+            ALL_CHANNELS
+        );
+    }
+    
+    #[test]
+    fn sequence_numbers_increase_by_one_per_user() {
+        let mut alice = NotificationCounter { user: Pubkey::new_unique(), seq: 0, bump: 0 };
+        let mut bob = NotificationCounter { user: Pubkey::new_unique(), seq: 0, bump: 0 };
+        
+        assert_eq!(alice.next_seq(), 1);
+        assert_eq!(alice.next_seq(), 2);
+        assert_eq!(bob.next_seq(), 1);
+        assert_eq!(alice.next_seq(), 3);
+        assert_eq!(alice.seq, 3);
+        assert_eq!(bob.seq, 1);
+    }
+    
+    #[test]
+    fn the_latest_remaining_webhook_is_active() {
+        let user = Pubkey::new_unique();
+        let mut webhooks = NotificationWebhooks {
+            user,
+            webhooks: vec![],
+            active_webhook_id: None,
+            next_id: 1,
+            bump: 0,
+        };
+        assert_eq!(webhooks.register([1; 32]).unwrap(), 1);
+        assert_eq!(webhooks.register([2; 32]).unwrap(), 2);
+        assert_eq!(webhooks.register([3; 32]).unwrap(), 3);
+        
+        webhooks.remove(3).unwrap();
+        assert_eq!(webhooks.active_webhook_id, Some(2));
+        webhooks.remove(1).unwrap();
+        assert_eq!(webhooks.active_webhook_id, Some(2));
+        assert!(webhooks.remove(1).is_err());
+        
+        let key = Pubkey::new_unique();
+        let mut lamports = 1;
+        let mut data = vec![];
+        webhooks.try_serialize(&mut data).unwrap();
+        let owner = crate::ID;
+        let webhooks_info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        let accounts = [webhooks_info];
+        
+        assert_eq!(resolve_webhook(&accounts, user), Some(2));
+        assert_eq!(resolve_webhook(&accounts, Pubkey::new_unique()), None);
+    }
+    
+    #[test]
+    fn webhooks_are_capped() {
+        let mut webhooks = NotificationWebhooks {
+            user: Pubkey::new_unique(),
+            webhooks: vec![],
+            active_webhook_id: None,
+            next_id: 1,
+            bump: 0,
+        };
+        for _ in 0..MAX_WEBHOOKS {
+            webhooks.register([0; 32]).unwrap();
+        }
+        assert!(webhooks.register([0; 32]).is_err());
+        
+        let mut data = vec![];
+        webhooks.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), NotificationWebhooks::SPACE);
+    }
+}
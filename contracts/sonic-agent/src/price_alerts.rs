@@ -1,7 +1,149 @@
 // contracts/sonic-agent/src/price_alerts.rs
 
 use anchor_lang::prelude::*;
-use crate::notification_events::{emit_notification, emit_price_alert, NotificationEventType, NotificationPriority};
+use crate::notification_events::{emit_notification, emit_price_alert, Notification, NotificationCounter, NotificationEventType, NotificationPriority};
+pub use crate::errors::CommonError;
+use crate::ProgramConfig;
+
+// Pyth Solana receiver program, owner of PriceUpdateV2 accounts
+pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = solana_program::pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+
+// Anchor discriminator of PriceUpdateV2 accounts
+const PRICE_UPDATE_V2_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+
+// Oldest Pyth price accepted when triggering alerts
+pub const PYTH_MAX_PRICE_AGE_SECS: i64 = 60;
+
+// Pyth-triggered alerts compare thresholds against prices in micro-USD
+pub const PYTH_PRICE_DECIMALS: i32 = 6;
+
+// Pyth feed a token is priced by, set by the program admin
+#[account]
+pub struct PriceFeedMapping {
+    // Token mint
+    pub mint: Pubkey,
+    
+    // Pyth price feed id
+    pub feed_id: [u8; 32],
+    
+    // Bump used for PDA
+    pub bump: u8,
+}
+
+impl PriceFeedMapping {
+    pub const SPACE: usize = 8 + 32 + 32 + 1;
+}
+
+// Most recent price samples kept per token
+pub const MAX_PRICE_SAMPLES: usize = 64;
+
+// A sampled price in micro-USD
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct PriceSample {
+    pub timestamp: i64,
+    pub price: u64,
+}
+
+// Rolling buffer of Pyth price samples for a token, oldest first
+#[account]
+pub struct PriceSamples {
+    // Token mint
+    pub mint: Pubkey,
+    
+    // Recent samples, at most MAX_PRICE_SAMPLES
+    pub samples: Vec<PriceSample>,
+    
+    // Bump used for PDA
+    pub bump: u8,
+}
+
+impl PriceSamples {
+    pub const SPACE: usize = 8 + 32 + 4 + MAX_PRICE_SAMPLES * (8 + 8) + 1;
+    
+    // Highest and lowest price sampled at or after `since`
+    pub fn range_since(&self, since: i64) -> Option<(u64, u64)> {
+        self.samples
+            .iter()
+            .filter(|sample| sample.timestamp >= since)
+            .fold(None, |range, sample| match range {
+                None => Some((sample.price, sample.price)),
+                Some((max, min)) => Some((max.max(sample.price), min.min(sample.price))),
+            })
+    }
+    
+    // Append a sample newer than the last one, dropping the oldest when full
+    pub fn push(&mut self, sample: PriceSample) -> Result<()> {
+        if let Some(last) = self.samples.last() {
+            require!(sample.timestamp > last.timestamp, ErrorCode::StaleSample);
+        }
+        
+        self.samples.push(sample);
+        if self.samples.len() > MAX_PRICE_SAMPLES {
+            self.samples.remove(0);
+        }
+        Ok(())
+    }
+}
+
+// Short/long EMA crossover detector for one user and token
+#[account]
+pub struct TrendMonitor {
+    // User notified of reversals
+    pub user: Pubkey,
+    
+    // Token mint
+    pub mint: Pubkey,
+    
+    // EMA periods, in samples (short < long)
+    pub short_period: u16,
+    pub long_period: u16,
+    
+    // Current EMAs in micro-USD
+    pub short_ema: u64,
+    pub long_ema: u64,
+    
+    // Samples folded in so far; crossovers are ignored until long_period is reached
+    pub sample_count: u32,
+    
+    // Whether the short EMA was above the long EMA after the last sample
+    pub short_above_long: bool,
+    
+    // Bump used for PDA
+    pub bump: u8,
+}
+
+impl TrendMonitor {
+    pub const SPACE: usize = 8 + 32 + 32 + 2 + 2 + 8 + 8 + 4 + 1 + 1;
+    
+    // Fold in a price; returns the new direction when the EMAs cross
+    pub fn update(&mut self, price: u64) -> Option<bool> {
+        if self.sample_count == 0 {
+            self.short_ema = price;
+            self.long_ema = price;
+        } else {
+            self.short_ema = ema_step(self.short_ema, price, self.short_period);
+            self.long_ema = ema_step(self.long_ema, price, self.long_period);
+        }
+        self.sample_count = self.sample_count.saturating_add(1);
+        
+        let short_above_long = self.short_ema > self.long_ema;
+        let crossed = short_above_long != self.short_above_long;
+        self.short_above_long = short_above_long;
+        
+        if crossed && self.sample_count > self.long_period as u32 {
+            Some(short_above_long)
+        } else {
+            None
+        }
+    }
+}
+
+// One EMA step with smoothing 2 / (period + 1)
+fn ema_step(ema: u64, price: u64, period: u16) -> u64 {
+    let ema = ema as i128;
+    let delta = (price as i128 - ema) * 2 / (period as i128 + 1);
+    (ema + delta) as u64
+}
 
 #[account]
 #[derive(Default)]
@@ -15,7 +157,7 @@ pub struct UserPriceAlerts {
     // Total number of alerts created (used for ID generation)
     pub alert_count: u64,
     
-    // Maximum number of active alerts
+    // Maximum number of active alerts; the account is sized for exactly this many
     pub max_alerts: u8,
     
     // Bump used for PDA
@@ -42,9 +184,31 @@ pub struct PriceAlert {
     // Has this alert been triggered yet?
     pub triggered: bool,
     
+    // Timestamp after which the alert no longer fires (0 = never expires)
+    pub expires_at: i64,
+    
     // Notification preferences
     pub notify_email: bool,
     pub notify_browser: bool,
+    
+    // What the alert watches for
+    pub kind: AlertKind,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AlertKind {
+    // Price crosses `threshold` in `direction`
+    #[default]
+    Threshold,
+    
+    // Max/min price range over the last `window_secs` exceeds `threshold_bps`
+    Volatility { window_secs: u32, threshold_bps: u16 },
+}
+
+impl PriceAlert {
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at != 0 && now >= self.expires_at
+    }
 }
 
 // Space calculation for UserPriceAlerts account
@@ -62,15 +226,33 @@ impl UserPriceAlerts {
                                   1 + // direction
                                   8 + // created_at
                                   1 + // triggered
+                                  8 + // expires_at
                                   1 + // notify_email
-                                  1;  // notify_browser
+                                  1 + // notify_browser
+                                  1 + 4 + 2; // kind
+    
+    // Hard ceiling on a user's max_alerts
+    pub const MAX_ALERTS: usize = 50;
     
-    pub const MAX_ALERTS: usize = 10;
+    // Account size for a capacity of `max_alerts` alerts
+    pub fn space(max_alerts: usize) -> usize {
+        Self::BASE_SIZE + Self::ALERT_SIZE * max_alerts
+    }
     
-    pub const MAX_SIZE: usize = Self::BASE_SIZE + (Self::ALERT_SIZE * Self::MAX_ALERTS);
+    // A capacity must be positive, within the hard ceiling and hold every active alert
+    pub fn check_max_alerts(max_alerts: u8, active: usize) -> Result<()> {
+        require!(
+            max_alerts > 0
+                && max_alerts as usize <= Self::MAX_ALERTS
+                && max_alerts as usize >= active,
+            ErrorCode::InvalidMaxAlerts
+        );
+        Ok(())
+    }
 }
 
 #[derive(Accounts)]
+#[instruction(max_alerts: u8)]
 pub struct InitializePriceAlerts<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
@@ -78,13 +260,20 @@ pub struct InitializePriceAlerts<'info> {
     #[account(
         init,
         payer = user,
-        space = UserPriceAlerts::MAX_SIZE,
+        space = UserPriceAlerts::space(max_alerts as usize),
         seeds = [b"price_alerts", user.key().as_ref()],
         bump
     )]
     pub price_alerts: Account<'info, UserPriceAlerts>,
     
     pub system_program: Program<'info, System>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", user.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
 }
 
 #[derive(Accounts)]
@@ -101,10 +290,37 @@ pub struct CreatePriceAlert<'info> {
     )]
     pub price_alerts: Account<'info, UserPriceAlerts>,
     
-    #[account(init, payer = user, space = 8)]
+    // Fresh keypair whose address becomes the alert id
     pub price_alert: Signer<'info>,
     
     pub system_program: Program<'info, System>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", user.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+}
+
+#[derive(Accounts)]
+#[instruction(max_alerts: u8)]
+pub struct ResizePriceAlerts<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"price_alerts", user.key().as_ref()],
+        bump = price_alerts.bump,
+        constraint = price_alerts.user == user.key(),
+        realloc = UserPriceAlerts::space(max_alerts as usize),
+        realloc::payer = user,
+        realloc::zero = false
+    )]
+    pub price_alerts: Account<'info, UserPriceAlerts>,
+    
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
@@ -119,6 +335,13 @@ pub struct DeletePriceAlert<'info> {
         constraint = price_alerts.user == user.key()
     )]
     pub price_alerts: Account<'info, UserPriceAlerts>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", user.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
 }
 
 #[derive(Accounts)]
@@ -138,7 +361,166 @@ pub struct TriggerPriceAlert<'info> {
     pub user: AccountInfo<'info>,
 }
 
-#[error_code]
+#[derive(Accounts)]
+pub struct PruneExpiredAlerts<'info> {
+    #[account(
+        mut,
+        seeds = [b"price_alerts", user.key().as_ref()],
+        bump = price_alerts.bump
+    )]
+    pub price_alerts: Account<'info, UserPriceAlerts>,
+    
+    /// CHECK: This is not a contract account
+    pub user: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(mint: Pubkey)]
+pub struct SetPriceFeedMapping<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    
+    #[account(
+        seeds = [b"program-config"],
+        bump = program_config.bump,
+        constraint = program_config.admin == admin.key() @ CommonError::Unauthorized
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    
+    #[account(
+        init,
+        payer = admin,
+        space = PriceFeedMapping::SPACE,
+        seeds = [b"price-feed", mint.as_ref()],
+        bump
+    )]
+    pub price_feed: Account<'info, PriceFeedMapping>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TriggerPriceAlertPyth<'info> {
+    #[account(
+        mut,
+        seeds = [b"price_alerts", user.key().as_ref()],
+        bump = price_alerts.bump
+    )]
+    pub price_alerts: Account<'info, UserPriceAlerts>,
+    
+    /// CHECK: This is not a contract account
+    pub user: AccountInfo<'info>,
+    
+    #[account(
+        seeds = [b"price-feed", price_feed.mint.as_ref()],
+        bump = price_feed.bump
+    )]
+    pub price_feed: Account<'info, PriceFeedMapping>,
+    
+    /// CHECK: Owner, discriminator and feed id are checked when the price is read
+    #[account(owner = PYTH_RECEIVER_PROGRAM_ID)]
+    pub price_update: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializePriceSamples<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    #[account(
+        seeds = [b"price-feed", price_feed.mint.as_ref()],
+        bump = price_feed.bump
+    )]
+    pub price_feed: Account<'info, PriceFeedMapping>,
+    
+    #[account(
+        init,
+        payer = payer,
+        space = PriceSamples::SPACE,
+        seeds = [b"price-samples", price_feed.mint.as_ref()],
+        bump
+    )]
+    pub price_samples: Account<'info, PriceSamples>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RecordPriceSample<'info> {
+    #[account(
+        seeds = [b"price-feed", price_feed.mint.as_ref()],
+        bump = price_feed.bump
+    )]
+    pub price_feed: Account<'info, PriceFeedMapping>,
+    
+    /// CHECK: Owner, discriminator and feed id are checked when the price is read
+    #[account(owner = PYTH_RECEIVER_PROGRAM_ID)]
+    pub price_update: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"price-samples", price_feed.mint.as_ref()],
+        bump = price_samples.bump
+    )]
+    pub price_samples: Account<'info, PriceSamples>,
+    
+    // Passed together to also update a trend monitor on this token
+    #[account(
+        mut,
+        seeds = [b"trend-monitor", trend_monitor.user.as_ref(), price_feed.mint.as_ref()],
+        bump = trend_monitor.bump
+    )]
+    pub trend_monitor: Option<Account<'info, TrendMonitor>>,
+    
+    #[account(mut)]
+    pub notification_counter: Option<Account<'info, NotificationCounter>>,
+}
+
+#[derive(Accounts)]
+#[instruction(mint: Pubkey)]
+pub struct InitializeTrendMonitor<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = TrendMonitor::SPACE,
+        seeds = [b"trend-monitor", user.key().as_ref(), mint.as_ref()],
+        bump
+    )]
+    pub trend_monitor: Account<'info, TrendMonitor>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CheckVolatility<'info> {
+    #[account(
+        mut,
+        seeds = [b"price_alerts", user.key().as_ref()],
+        bump = price_alerts.bump
+    )]
+    pub price_alerts: Account<'info, UserPriceAlerts>,
+    
+    /// CHECK: This is not a contract account
+    pub user: AccountInfo<'info>,
+    
+    #[account(
+        seeds = [b"price-samples", price_samples.mint.as_ref()],
+        bump = price_samples.bump
+    )]
+    pub price_samples: Account<'info, PriceSamples>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", user.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+}
+
+#[error_code(offset = 6500)]
 pub enum ErrorCode {
     #[msg("Maximum number of alerts exceeded")]
     MaxAlertsExceeded,
@@ -146,34 +528,78 @@ pub enum ErrorCode {
     #[msg("Alert not found")]
     AlertNotFound,
     
-    #[msg("Unauthorized")]
-    Unauthorized,
+    #[msg("Invalid alert capacity")]
+    InvalidMaxAlerts,
+    
+    #[msg("Invalid Pyth price update account")]
+    InvalidPriceUpdate,
+    
+    #[msg("Pyth price is too old")]
+    StalePrice,
+    
+    #[msg("Alert expiry must be in the future")]
+    InvalidExpiry,
+    
+    #[msg("Price sample is not newer than the last one")]
+    StaleSample,
+    
+    #[msg("Invalid volatility window or threshold")]
+    InvalidVolatilityAlert,
+    
+    #[msg("Short EMA period must be positive and below the long period")]
+    InvalidEmaPeriods,
 }
 
-// Initialize price alerts account for a user
-pub fn initialize_price_alerts(ctx: Context<InitializePriceAlerts>) -> Result<()> {
-    let price_alerts = &mut ctx.accounts.price_alerts;
+// Initialize price alerts account for a user, sized for `max_alerts` alerts
+pub fn initialize_price_alerts(ctx: Context<InitializePriceAlerts>, max_alerts: u8) -> Result<()> {
     let bump = *ctx.bumps.get("price_alerts").unwrap();
+    init_price_alerts(&mut ctx.accounts.price_alerts, ctx.accounts.user.key(), max_alerts, bump)?;
+    
+    // Emit notification for account creation
+    emit_notification(
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
+        ctx.accounts.user.key(),
+        Notification {
+            event_type: NotificationEventType::AgentDeployed,
+            priority: NotificationPriority::Low,
+            title: "Price Alerts Initialized".to_string(),
+            message: "Your price alert system has been set up successfully.".to_string(),
+            data: None,
+            strategy_id: None,
+            token_address: None,
+            tx_signature: None,
+        },
+    );
+    
+    Ok(())
+}
+
+// Set up an empty alert list; shared with the lib-level onboarding instruction
+pub fn init_price_alerts(
+    price_alerts: &mut Account<UserPriceAlerts>,
+    user: Pubkey,
+    max_alerts: u8,
+    bump: u8,
+) -> Result<()> {
+    UserPriceAlerts::check_max_alerts(max_alerts, 0)?;
     
-    price_alerts.user = ctx.accounts.user.key();
+    price_alerts.user = user;
     price_alerts.alerts = Vec::new();
     price_alerts.alert_count = 0;
-    price_alerts.max_alerts = UserPriceAlerts::MAX_ALERTS as u8;
+    price_alerts.max_alerts = max_alerts;
     price_alerts.bump = bump;
     
-    // Emit notification for account creation
-    emit_notification(
-        ctx.to_account_infos(), 
-        ctx.accounts.user.key(), 
-        NotificationEventType::AgentDeployed, 
-        NotificationPriority::Low, 
-        "Price Alerts Initialized".to_string(), 
-        "Your price alert system has been set up successfully.".to_string(), 
-        None, 
-        None, 
-        None, 
-        None
-    );
+    Ok(())
+}
+
+// Grow or shrink a user's alert capacity; shrinking refunds the freed rent
+pub fn resize_price_alerts(ctx: Context<ResizePriceAlerts>, max_alerts: u8) -> Result<()> {
+    let price_alerts = &mut ctx.accounts.price_alerts;
+    
+    UserPriceAlerts::check_max_alerts(max_alerts, price_alerts.alerts.len())?;
+    
+    price_alerts.max_alerts = max_alerts;
     
     Ok(())
 }
@@ -186,19 +612,25 @@ pub fn create_price_alert(
     direction: bool,
     notify_email: bool,
     notify_browser: bool,
+    expires_at: i64,
 ) -> Result<()> {
     let price_alerts = &mut ctx.accounts.price_alerts;
     let alert_id = ctx.accounts.price_alert.key();
+    let now = Clock::get()?.unix_timestamp;
+    
+    require!(expires_at == 0 || expires_at > now, ErrorCode::InvalidExpiry);
     
     let alert = PriceAlert {
         id: alert_id,
         token,
         threshold,
         direction,
-        created_at: Clock::get()?.unix_timestamp,
+        created_at: now,
         triggered: false,
+        expires_at,
         notify_email,
         notify_browser,
+        kind: AlertKind::Threshold,
     };
     
     price_alerts.alerts.push(alert);
@@ -208,16 +640,19 @@ pub fn create_price_alert(
     let direction_str = if direction { "above" } else { "below" };
     
     emit_notification(
-        ctx.to_account_infos(), 
-        ctx.accounts.user.key(), 
-        NotificationEventType::PriceAlert, 
-        NotificationPriority::Low, 
-        "Price Alert Created".to_string(), 
-        format!("You will be notified when price goes {} {}", direction_str, threshold), 
-        Some(format!(r#"{{"token":"{}", "threshold":{}, "direction":{}}}"#, token, threshold, direction)),
-        None, 
-        Some(token), 
-        None
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
+        ctx.accounts.user.key(),
+        Notification {
+            event_type: NotificationEventType::PriceAlert,
+            priority: NotificationPriority::Low,
+            title: "Price Alert Created".to_string(),
+            message: format!("You will be notified when price goes {} {}", direction_str, threshold),
+            data: Some(format!(r#"{{"token":"{}", "threshold":{}, "direction":{}}}"#, token, threshold, direction)),
+            strategy_id: None,
+            token_address: Some(token),
+            tx_signature: None,
+        },
     );
     
     Ok(())
@@ -238,16 +673,19 @@ pub fn delete_price_alert(ctx: Context<DeletePriceAlert>, alert_id: Pubkey) -> R
     
     // Emit notification for alert deletion
     emit_notification(
-        ctx.to_account_infos(), 
-        ctx.accounts.user.key(), 
-        NotificationEventType::PriceAlert, 
-        NotificationPriority::Low, 
-        "Price Alert Deleted".to_string(), 
-        format!("Your price alert for token {} has been deleted", alert.token), 
-        Some(format!(r#"{{"token":"{}"}}"#, alert.token)),
-        None, 
-        Some(alert.token), 
-        None
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
+        ctx.accounts.user.key(),
+        Notification {
+            event_type: NotificationEventType::PriceAlert,
+            priority: NotificationPriority::Low,
+            title: "Price Alert Deleted".to_string(),
+            message: format!("Your price alert for token {} has been deleted", alert.token),
+            data: Some(format!(r#"{{"token":"{}"}}"#, alert.token)),
+            strategy_id: None,
+            token_address: Some(alert.token),
+            tx_signature: None,
+        },
     );
     
     Ok(())
@@ -259,46 +697,607 @@ pub fn trigger_price_alert(
     token: Pubkey, 
     current_price: u64,
     oracle_authority: Pubkey,
+    prune_expired: bool,
 ) -> Result<()> {
     // Verify the caller is an authorized oracle
-    require!(ctx.accounts.authority.key() == oracle_authority, ErrorCode::Unauthorized);
+    require!(ctx.accounts.authority.key() == oracle_authority, CommonError::Unauthorized);
+    
+    let now = Clock::get()?.unix_timestamp;
     
     let price_alerts = &mut ctx.accounts.price_alerts;
     let user = ctx.accounts.user.key();
     
     // Find alerts to trigger
-    let mut triggered_indices = Vec::new();
+    let triggered_indices = matching_alerts(&price_alerts.alerts, token, current_price, now);
     
-    for (i, alert) in price_alerts.alerts.iter().enumerate() {
-        if alert.token == token && !alert.triggered {
-            let should_trigger = if alert.direction {
+    for &index in triggered_indices.iter() {
+        let alert = &price_alerts.alerts[index];
+        
+        // Emit price alert event
+        emit_price_alert(
+            user,
+            token,
+            alert.direction,
+            alert.threshold,
+            current_price
+        );
+    }
+    
+    // Mark triggered alerts
+    for &index in triggered_indices.iter().rev() {
+        price_alerts.alerts[index].triggered = true;
+    }
+    
+    if prune_expired {
+        price_alerts.alerts.retain(|alert| !alert.is_expired(now));
+    }
+    
+    Ok(())
+}
+
+// Map a token to the Pyth feed that prices it (admin only)
+pub fn set_price_feed_mapping(
+    ctx: Context<SetPriceFeedMapping>,
+    mint: Pubkey,
+    feed_id: [u8; 32],
+) -> Result<()> {
+    let price_feed = &mut ctx.accounts.price_feed;
+    
+    price_feed.mint = mint;
+    price_feed.feed_id = feed_id;
+    price_feed.bump = *ctx.bumps.get("price_feed").unwrap();
+    
+    Ok(())
+}
+
+// Trigger price alerts from the on-chain Pyth price (callable by anyone).
+// Thresholds are compared in micro-USD.
+pub fn trigger_price_alert_pyth(ctx: Context<TriggerPriceAlertPyth>, prune_expired: bool) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let token = ctx.accounts.price_feed.mint;
+    let (current_price, _) = read_pyth_price(
+        &ctx.accounts.price_update,
+        &ctx.accounts.price_feed.feed_id,
+        now,
+    )?;
+    
+    let price_alerts = &mut ctx.accounts.price_alerts;
+    let user = ctx.accounts.user.key();
+    
+    // Find alerts to trigger
+    let triggered_indices = matching_alerts(&price_alerts.alerts, token, current_price, now);
+    
+    for &index in triggered_indices.iter() {
+        let alert = &price_alerts.alerts[index];
+        
+        // Emit price alert event
+        emit_price_alert(
+            user,
+            token,
+            alert.direction,
+            alert.threshold,
+            current_price
+        );
+    }
+    
+    // Mark triggered alerts
+    for &index in triggered_indices.iter().rev() {
+        price_alerts.alerts[index].triggered = true;
+    }
+    
+    if prune_expired {
+        price_alerts.alerts.retain(|alert| !alert.is_expired(now));
+    }
+    
+    Ok(())
+}
+
+// Remove expired alerts from any user's account (callable by anyone)
+pub fn prune_expired_alerts(ctx: Context<PruneExpiredAlerts>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let price_alerts = &mut ctx.accounts.price_alerts;
+    
+    let before = price_alerts.alerts.len();
+    price_alerts.alerts.retain(|alert| !alert.is_expired(now));
+    msg!("Pruned {} expired alerts", before - price_alerts.alerts.len());
+    
+    Ok(())
+}
+
+// Create an alert that fires when the price range over `window_secs`
+// exceeds `threshold_bps` of the window's low
+pub fn create_volatility_alert(
+    ctx: Context<CreatePriceAlert>,
+    token: Pubkey,
+    window_secs: u32,
+    threshold_bps: u16,
+    notify_email: bool,
+    notify_browser: bool,
+    expires_at: i64,
+) -> Result<()> {
+    let price_alerts = &mut ctx.accounts.price_alerts;
+    let alert_id = ctx.accounts.price_alert.key();
+    let now = Clock::get()?.unix_timestamp;
+    
+    require!(expires_at == 0 || expires_at > now, ErrorCode::InvalidExpiry);
+    require!(window_secs > 0 && threshold_bps > 0, ErrorCode::InvalidVolatilityAlert);
+    
+    price_alerts.alerts.push(PriceAlert {
+        id: alert_id,
+        token,
+        threshold: 0,
+        direction: false,
+        created_at: now,
+        triggered: false,
+        expires_at,
+        notify_email,
+        notify_browser,
+        kind: AlertKind::Volatility { window_secs, threshold_bps },
+    });
+    price_alerts.alert_count += 1;
+    
+    emit_notification(
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
+        ctx.accounts.user.key(),
+        Notification {
+            event_type: NotificationEventType::VolatilityAlert,
+            priority: NotificationPriority::Low,
+            title: "Volatility Alert Created".to_string(),
+            message: format!("You will be notified when price moves {} bps within {} seconds", threshold_bps, window_secs),
+            data: Some(format!(r#"{{"token":"{}", "windowSecs":{}, "thresholdBps":{}}}"#, token, window_secs, threshold_bps)),
+            strategy_id: None,
+            token_address: Some(token),
+            tx_signature: None,
+        },
+    );
+    
+    Ok(())
+}
+
+// Create the price sample buffer for a token with a Pyth feed (anyone may pay)
+pub fn initialize_price_samples(ctx: Context<InitializePriceSamples>) -> Result<()> {
+    let price_samples = &mut ctx.accounts.price_samples;
+    
+    price_samples.mint = ctx.accounts.price_feed.mint;
+    price_samples.samples = Vec::new();
+    price_samples.bump = *ctx.bumps.get("price_samples").unwrap();
+    
+    Ok(())
+}
+
+// Append the current Pyth price to a token's sample buffer (callable by anyone)
+pub fn record_price_sample(ctx: Context<RecordPriceSample>) -> Result<()> {
+    let (price, publish_time) = read_pyth_price(
+        &ctx.accounts.price_update,
+        &ctx.accounts.price_feed.feed_id,
+        Clock::get()?.unix_timestamp,
+    )?;
+    
+    // One sample per Pyth update, so the buffer can't be flooded with repeats
+    ctx.accounts.price_samples.push(PriceSample { timestamp: publish_time, price })?;
+    
+    if let Some(trend_monitor) = ctx.accounts.trend_monitor.as_mut() {
+        let counter = ctx.accounts.notification_counter
+            .as_mut()
+            .ok_or(CommonError::InvalidParameter)?;
+        require!(counter.user == trend_monitor.user, CommonError::InvalidParameter);
+        
+        if let Some(bullish) = trend_monitor.update(price) {
+            let (title, direction) = if bullish {
+                ("Golden Cross", "above")
+            } else {
+                ("Death Cross", "below")
+            };
+            
+            emit_notification(
+                ctx.remaining_accounts,
+                counter,
+                trend_monitor.user,
+                Notification {
+                    event_type: NotificationEventType::TrendReversalDetected,
+                    priority: NotificationPriority::Medium,
+                    title: format!("Trend Reversal: {}", title),
+                    message: format!(
+                        "The {}-sample EMA crossed {} the {}-sample EMA",
+                        trend_monitor.short_period, direction, trend_monitor.long_period
+                    ),
+                    data: Some(format!(
+                        r#"{{"token":"{}", "shortEma":{}, "longEma":{}, "bullish":{}}}"#,
+                        trend_monitor.mint, trend_monitor.short_ema, trend_monitor.long_ema, bullish
+                    )),
+                    strategy_id: None,
+                    token_address: Some(trend_monitor.mint),
+                    tx_signature: None,
+                },
+            );
+        }
+    }
+    
+    Ok(())
+}
+
+// Start watching a token for short/long EMA crossovers
+pub fn initialize_trend_monitor(
+    ctx: Context<InitializeTrendMonitor>,
+    mint: Pubkey,
+    short_period: u16,
+    long_period: u16,
+) -> Result<()> {
+    require!(short_period > 0 && short_period < long_period, ErrorCode::InvalidEmaPeriods);
+    
+    let trend_monitor = &mut ctx.accounts.trend_monitor;
+    trend_monitor.user = ctx.accounts.user.key();
+    trend_monitor.mint = mint;
+    trend_monitor.short_period = short_period;
+    trend_monitor.long_period = long_period;
+    trend_monitor.short_ema = 0;
+    trend_monitor.long_ema = 0;
+    trend_monitor.sample_count = 0;
+    trend_monitor.short_above_long = false;
+    trend_monitor.bump = *ctx.bumps.get("trend_monitor").unwrap();
+    
+    Ok(())
+}
+
+// Fire a user's volatility alerts on the sampled token whose window range
+// exceeds their threshold (callable by anyone)
+pub fn check_volatility(ctx: Context<CheckVolatility>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let token = ctx.accounts.price_samples.mint;
+    let user = ctx.accounts.user.key();
+    
+    for index in 0..ctx.accounts.price_alerts.alerts.len() {
+        let alert = &ctx.accounts.price_alerts.alerts[index];
+        if alert.token != token || alert.triggered || alert.is_expired(now) {
+            continue;
+        }
+        let (window_secs, threshold_bps) = match alert.kind {
+            AlertKind::Volatility { window_secs, threshold_bps } => (window_secs, threshold_bps),
+            AlertKind::Threshold => continue,
+        };
+        
+        let (max, min) = match ctx.accounts.price_samples.range_since(now - window_secs as i64) {
+            Some(range) => range,
+            None => continue,
+        };
+        let range_bps = range_bps(max, min);
+        if range_bps < threshold_bps as u128 {
+            continue;
+        }
+        
+        ctx.accounts.price_alerts.alerts[index].triggered = true;
+        
+        emit_notification(
+            ctx.remaining_accounts,
+            &mut ctx.accounts.notification_counter,
+            user,
+            Notification {
+                event_type: NotificationEventType::VolatilityAlert,
+                priority: NotificationPriority::Medium,
+                title: "Volatility Alert".to_string(),
+                message: format!("Price moved {} bps within {} seconds", range_bps, window_secs),
+                data: Some(format!(
+                    r#"{{"token":"{}", "high":{}, "low":{}, "rangeBps":{}}}"#,
+                    token, max, min, range_bps
+                )),
+                strategy_id: None,
+                token_address: Some(token),
+                tx_signature: None,
+            },
+        );
+    }
+    
+    Ok(())
+}
+
+// High-low range as bps of the low
+fn range_bps(max: u64, min: u64) -> u128 {
+    (max - min) as u128 * 10000 / min.max(1) as u128
+}
+
+// Indices of live, untriggered alerts on `token` that `current_price` crosses
+fn matching_alerts(alerts: &[PriceAlert], token: Pubkey, current_price: u64, now: i64) -> Vec<usize> {
+    alerts
+        .iter()
+        .enumerate()
+        .filter(|(_, alert)| alert.kind == AlertKind::Threshold)
+        .filter(|(_, alert)| alert.token == token && !alert.triggered && !alert.is_expired(now))
+        .filter(|(_, alert)| {
+            if alert.direction {
                 // Alert for price above threshold
                 current_price >= alert.threshold
             } else {
                 // Alert for price below threshold
                 current_price <= alert.threshold
-            };
-            
-            if should_trigger {
-                triggered_indices.push(i);
-                
-                // Emit price alert event
-                emit_price_alert(
-                    ctx.to_account_infos(),
-                    user,
-                    token,
-                    alert.direction,
-                    alert.threshold,
-                    current_price
-                );
             }
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+// Price message of a Pyth PriceUpdateV2 account, in the feed's 10^exponent units
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PythPriceMessage {
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+    pub ema_price: i64,
+    pub ema_conf: u64,
+}
+
+// Read the price message of a fully verified Pyth PriceUpdateV2 account for `feed_id`
+// published at most `max_age_secs` before `now`. Layout after the discriminator: write
+// authority (32), verification level (Borsh enum: [1] for Full, [0, n] for Partial),
+// then the price message: feed id (32), price (8), conf (8), exponent (4),
+// publish time (8), previous publish time (8), EMA price (8), EMA conf (8), ...
+pub fn read_pyth_message(
+    price_update: &AccountInfo,
+    feed_id: &[u8; 32],
+    now: i64,
+    max_age_secs: i64,
+) -> Result<PythPriceMessage> {
+    let data = price_update.try_borrow_data()?;
+    require!(
+        data.len() >= 8 + 32 + 1 + 32 + 8 + 8 + 4 + 8 + 8 + 8 + 8
+            && data[..8] == PRICE_UPDATE_V2_DISCRIMINATOR,
+        ErrorCode::InvalidPriceUpdate
+    );
+    
+    // Only fully verified updates are trusted
+    let mut offset = 8 + 32;
+    require!(data[offset] == 1, ErrorCode::InvalidPriceUpdate);
+    offset += 1;
+    
+    require!(data[offset..offset + 32] == feed_id[..], ErrorCode::InvalidPriceUpdate);
+    offset += 32;
+    
+    let mut read = |len: usize| {
+        let bytes = &data[offset..offset + len];
+        offset += len;
+        bytes
+    };
+    let price = i64::from_le_bytes(read(8).try_into().unwrap());
+    let conf = u64::from_le_bytes(read(8).try_into().unwrap());
+    let exponent = i32::from_le_bytes(read(4).try_into().unwrap());
+    let publish_time = i64::from_le_bytes(read(8).try_into().unwrap());
+    read(8); // previous publish time
+    let ema_price = i64::from_le_bytes(read(8).try_into().unwrap());
+    let ema_conf = u64::from_le_bytes(read(8).try_into().unwrap());
+    
+    require!(now - publish_time <= max_age_secs, ErrorCode::StalePrice);
+    
+    Ok(PythPriceMessage {
+        price,
+        conf,
+        exponent,
+        publish_time,
+        ema_price,
+        ema_conf,
+    })
+}
+
+// Read a fully verified Pyth PriceUpdateV2 account and return its price in
+// micro-USD with its publish time
+pub fn read_pyth_price(price_update: &AccountInfo, feed_id: &[u8; 32], now: i64) -> Result<(u64, i64)> {
+    let message = read_pyth_message(price_update, feed_id, now, PYTH_MAX_PRICE_AGE_SECS)?;
+    require!(message.price > 0, ErrorCode::InvalidPriceUpdate);
+    
+    // Rescale from 10^exponent to micro-USD
+    let shift = message.exponent + PYTH_PRICE_DECIMALS;
+    let scaled = if shift >= 0 {
+        (message.price as u128).checked_mul(10u128.pow(shift as u32))
+    } else {
+        Some(message.price as u128 / 10u128.pow(shift.unsigned_abs()))
+    };
+    
+    let price = scaled
+        .and_then(|value| u64::try_from(value).ok())
+        .ok_or_else(|| error!(ErrorCode::InvalidPriceUpdate))?;
+    
+    Ok((price, message.publish_time))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    
+    #[test]
+    fn alert_capacity_is_bounded_and_holds_active_alerts() {
+        assert!(UserPriceAlerts::check_max_alerts(1, 0).is_ok());
+        assert!(UserPriceAlerts::check_max_alerts(UserPriceAlerts::MAX_ALERTS as u8, 0).is_ok());
+        for (max_alerts, active) in [(0, 0), (UserPriceAlerts::MAX_ALERTS as u8 + 1, 0), (3, 4)] {
+            assert_eq!(
+                UserPriceAlerts::check_max_alerts(max_alerts, active).unwrap_err(),
+                error!(ErrorCode::InvalidMaxAlerts)
+            );
         }
     }
     
-    // Mark triggered alerts
-    for &index in triggered_indices.iter().rev() {
-        price_alerts.alerts[index].triggered = true;
+    #[test]
+    fn full_alert_lists_serialize_to_their_declared_space() {
+        let max_alerts = 5;
+        let price_alerts = UserPriceAlerts {
+            user: Pubkey::new_unique(),
+            alerts: vec![
+                PriceAlert {
+                    kind: AlertKind::Volatility { window_secs: 3600, threshold_bps: 500 },
+                    ..PriceAlert::default()
+                };
+                max_alerts
+            ],
+            alert_count: 0,
+            max_alerts: max_alerts as u8,
+            bump: 0,
+        };
+        let mut data = Vec::new();
+        price_alerts.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), UserPriceAlerts::space(max_alerts));
     }
     
-    Ok(())
-}
\ No newline at end of file
+    pub(crate) fn price_update_data(verification_level: &[u8], feed_id: [u8; 32], price: i64, exponent: i32, publish_time: i64) -> Vec<u8> {
+        let mut data = PRICE_UPDATE_V2_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&[0; 32]);
+        data.extend_from_slice(verification_level);
+        data.extend_from_slice(&feed_id);
+        data.extend_from_slice(&price.to_le_bytes());
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data.extend_from_slice(&exponent.to_le_bytes());
+        data.extend_from_slice(&publish_time.to_le_bytes());
+        data.extend_from_slice(&publish_time.to_le_bytes());
+        data.extend_from_slice(&price.to_le_bytes());
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data
+    }
+    
+    #[test]
+    fn pyth_prices_are_rescaled_to_micro_usd() {
+        let key = Pubkey::new_unique();
+        let feed_id = [7; 32];
+        let mut lamports = 0;
+        let mut data = price_update_data(&[1], feed_id, 12_345_678_900, -8, 1_000);
+        let price_update = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &PYTH_RECEIVER_PROGRAM_ID, false, 0);
+        
+        assert_eq!(read_pyth_price(&price_update, &feed_id, 1_000).unwrap(), (123_456_789, 1_000));
+        assert_eq!(
+            read_pyth_message(&price_update, &feed_id, 1_030, 30).unwrap(),
+            PythPriceMessage {
+                price: 12_345_678_900,
+                conf: 0,
+                exponent: -8,
+                publish_time: 1_000,
+                ema_price: 12_345_678_900,
+                ema_conf: 0,
+            }
+        );
+        assert_eq!(
+            read_pyth_message(&price_update, &feed_id, 1_031, 30).unwrap_err(),
+            error!(ErrorCode::StalePrice)
+        );
+        assert_eq!(
+            read_pyth_price(&price_update, &[8; 32], 1_000).unwrap_err(),
+            error!(ErrorCode::InvalidPriceUpdate)
+        );
+        assert_eq!(
+            read_pyth_price(&price_update, &feed_id, 1_000 + PYTH_MAX_PRICE_AGE_SECS + 1).unwrap_err(),
+            error!(ErrorCode::StalePrice)
+        );
+    }
+    
+    #[test]
+    fn partially_verified_pyth_updates_are_rejected() {
+        let key = Pubkey::new_unique();
+        let feed_id = [7; 32];
+        let mut lamports = 0;
+        let mut data = price_update_data(&[0, 3], feed_id, 100, -2, 1_000);
+        let price_update = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &PYTH_RECEIVER_PROGRAM_ID, false, 0);
+        
+        assert_eq!(
+            read_pyth_price(&price_update, &feed_id, 1_000).unwrap_err(),
+            error!(ErrorCode::InvalidPriceUpdate)
+        );
+    }
+    
+    #[test]
+    fn expired_alerts_never_match() {
+        let token = Pubkey::new_unique();
+        let alert = |threshold, direction, expires_at| PriceAlert {
+            token,
+            threshold,
+            direction,
+            expires_at,
+            ..PriceAlert::default()
+        };
+        let alerts = vec![alert(100, true, 0), alert(100, true, 500), alert(200, false, 0), alert(50, true, 2_000)];
+        
+        assert!(!alerts[0].is_expired(i64::MAX));
+        assert!(alerts[1].is_expired(500));
+        assert_eq!(matching_alerts(&alerts, token, 150, 499), vec![0, 1, 2, 3]);
+        assert_eq!(matching_alerts(&alerts, token, 150, 500), vec![0, 2, 3]);
+        assert!(matching_alerts(&alerts, Pubkey::new_unique(), 150, 0).is_empty());
+    }
+    
+    #[test]
+    fn volatility_uses_the_range_inside_the_window() {
+        let mut price_samples = PriceSamples {
+            mint: Pubkey::new_unique(),
+            samples: vec![],
+            bump: 0,
+        };
+        for (timestamp, price) in [(100, 50), (200, 100), (300, 110), (400, 105)] {
+            price_samples.push(PriceSample { timestamp, price }).unwrap();
+        }
+        assert_eq!(
+            price_samples.push(PriceSample { timestamp: 400, price: 1 }).unwrap_err(),
+            error!(ErrorCode::StaleSample)
+        );
+        
+        assert_eq!(price_samples.range_since(200), Some((110, 100)));
+        assert_eq!(price_samples.range_since(401), None);
+        assert_eq!(range_bps(110, 100), 1000);
+        assert_eq!(range_bps(110, 50), 12000);
+    }
+    
+    #[test]
+    fn price_samples_keep_the_newest_window() {
+        let mut price_samples = PriceSamples {
+            mint: Pubkey::new_unique(),
+            samples: vec![],
+            bump: 0,
+        };
+        for timestamp in 0..MAX_PRICE_SAMPLES as i64 + 3 {
+            price_samples.push(PriceSample { timestamp, price: 1 }).unwrap();
+        }
+        
+        assert_eq!(price_samples.samples.len(), MAX_PRICE_SAMPLES);
+        assert_eq!(price_samples.samples[0].timestamp, 3);
+        
+        let mut data = vec![];
+        price_samples.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), PriceSamples::SPACE);
+    }
+    
+    #[test]
+    fn ema_crossovers_signal_trend_reversals() {
+        let mut trend_monitor = TrendMonitor {
+            user: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            short_period: 2,
+            long_period: 4,
+            short_ema: 0,
+            long_ema: 0,
+            sample_count: 0,
+            short_above_long: false,
+            bump: 0,
+        };
+        let signals: Vec<Option<bool>> = [100, 90, 80, 70, 60, 80, 100, 120, 140, 100, 60]
+            .into_iter()
+            .map(|price| trend_monitor.update(price))
+            .collect();
+        
+        assert_eq!(signals[6], Some(true));
+        assert_eq!(signals[10], Some(false));
+        assert_eq!(signals.iter().filter(|signal| signal.is_some()).count(), 2);
+        assert_eq!((trend_monitor.short_ema, trend_monitor.long_ema), (77, 90));
+    }
+    
+    #[test]
+    fn crossovers_are_ignored_while_warming_up() {
+        let mut trend_monitor = TrendMonitor {
+            user: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            short_period: 2,
+            long_period: 4,
+            short_ema: 0,
+            long_ema: 0,
+            sample_count: 0,
+            short_above_long: false,
+            bump: 0,
+        };
+        assert_eq!(trend_monitor.update(100), None);
+        assert_eq!(trend_monitor.update(110), None);
+        assert!(trend_monitor.short_above_long);
+        assert_eq!(ema_step(100, 110, 4), 104);
+    }
+}
@@ -1,8 +1,16 @@
 // contracts/sonic-agent/src/strategy_manager.rs
 
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
-use crate::notification_events::{emit_notification, NotificationEventType, NotificationPriority};
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use crate::notification_events::{emit_notification, Notification, NotificationCounter, NotificationEventType, NotificationPriority};
+pub use crate::errors::CommonError;
+
+// Unit a strategy's investment amounts are denominated in
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum InvestmentUnit {
+    Lamports,
+    UsdCents,
+}
 
 #[account]
 #[derive(Default)]
@@ -19,6 +27,15 @@ pub struct StrategyRegistry {
     // Protocol fee recipient
     pub fee_recipient: Pubkey,
     
+    // Ceiling on management_fee_bps + performance_fee_bps / 10 for any strategy
+    pub max_combined_fee_score: u16,
+    
+    // Largest drop a single value update may apply without explicit confirmation (in basis points)
+    pub max_single_loss_bps: u16,
+    
+    // Only creators in the CreatorRegistry may create strategies
+    pub creator_allowlist_enabled: bool,
+    
     // Bump seed for PDA
     pub bump: u8,
 }
@@ -46,6 +63,9 @@ pub struct AIStrategy {
     // AI model types (bitmap)
     pub ai_models: u32,
     
+    // Category tags (bitmap, bit names live in the TagRegistry)
+    pub tags_bitmap: u64,
+    
     // Token support type (0 = Major Only, 1 = Major & Medium, 2 = Wide Coverage, 3 = Custom Basket)
     pub token_support: u8,
     
@@ -55,18 +75,57 @@ pub struct AIStrategy {
     // Performance fee in basis points (e.g. 2000 = 20%)
     pub performance_fee_bps: u16,
     
-    // Minimum investment in lamports
+    // Share of the management fee paid to a subscriber's referrer, in basis points
+    pub referral_fee_bps: u16,
+    
+    // Discounted management fees for larger subscriptions, by ascending threshold
+    pub volume_tiers: Vec<VolumeTier>,
+    
+    // Unit of min_investment, tvl, max_tvl and subscription amounts
+    pub unit: InvestmentUnit,
+    
+    // Token mint deposits and fees are paid in
+    pub deposit_mint: Pubkey,
+    
+    // Minimum investment, in `unit`
     pub min_investment: u64,
     
-    // Total value locked in lamports
+    // Total value locked, in `unit`
     pub tvl: u64,
     
+    // Maximum TVL the strategy accepts, in `unit` (0 = uncapped)
+    pub max_tvl: u64,
+    
     // Number of active subscribers
     pub subscriber_count: u64,
     
     // Total cumulative returns in basis points (for statistical purposes)
     pub total_returns_bps: i32,
     
+    // Value history appended by update_strategy_value, oldest first
+    pub performance_history: Vec<StrategyPerfPoint>,
+    
+    // Number of history points kept (at most MAX_STRATEGY_PERF_POINTS)
+    pub perf_retention: u8,
+    
+    // Largest change a single value update may apply, in basis points (0 = uncapped)
+    pub max_value_change_bps: u16,
+    
+    // Minimum seconds between value updates of a subscription (0 = no limit)
+    pub min_value_update_interval_secs: u32,
+    
+    // Minimum seconds a subscription must be held before unsubscribing (0 = no minimum)
+    pub min_holding_secs: u32,
+    
+    // Fee on exits inside the holding period, in basis points, when early exits are allowed
+    pub early_exit_fee_bps: u16,
+    
+    // Allow exits inside the holding period for early_exit_fee_bps instead of rejecting them
+    pub early_exit_penalty_enabled: bool,
+    
+    // Minimum seconds between auto-harvests of a subscription
+    pub harvest_interval_secs: u32,
+    
     // Strategy creation timestamp
     pub created_at: i64,
     
@@ -76,13 +135,299 @@ pub struct AIStrategy {
     // Strategy status (0 = Active, 1 = Paused, 2 = Deprecated)
     pub status: u8,
     
+    // After this timestamp the authority may force out remaining subscribers (0 = not deprecated)
+    pub deprecation_deadline: i64,
+    
     // Verification status (true = verified)
     pub verified: bool,
     
+    // Creator and registry index the PDA was derived from; fixed across ownership transfers
+    pub seed_creator: Pubkey,
+    pub seed_index: u64,
+    
+    // Strategy this one was cloned from, if any
+    pub cloned_from: Option<Pubkey>,
+    
+    // Bump seed for PDA
+    pub bump: u8,
+}
+
+impl AIStrategy {
+    pub const SPACE: usize = 8 + 64 + 32 + 64 + 64 + 1 + 1 + 4 + 8 + 1 + 2 + 2 + 2 + (4 + MAX_VOLUME_TIERS * VolumeTier::SIZE) + 1 + 32 + 8 + 8 + 8 + 8 + 4 + (4 + MAX_STRATEGY_PERF_POINTS * StrategyPerfPoint::SIZE) + 1 + 2 + 4 + 4 + 2 + 1 + 4 + 8 + 8 + 1 + 8 + 1 + 32 + 8 + 33 + 1;
+    
+    // True when the strategy carries every tag in `mask`
+    pub fn has_tags(&self, mask: u64) -> bool {
+        self.tags_bitmap & mask == mask
+    }
+    
+    // Management fee for a subscription of `value`: the highest tier it
+    // reaches, never above the base fee
+    pub fn effective_management_fee_bps(&self, value: u64) -> u16 {
+        self.volume_tiers
+            .iter()
+            .rev()
+            .find(|tier| value >= tier.min_value)
+            .map_or(self.management_fee_bps, |tier| tier.fee_bps.min(self.management_fee_bps))
+    }
+    
+    // Tiers must ascend in threshold with fees no higher than the last tier's
+    // or the base fee
+    pub fn check_volume_tiers(&self, tiers: &[VolumeTier]) -> Result<()> {
+        require!(tiers.len() <= MAX_VOLUME_TIERS, CommonError::InvalidParameter);
+        for (index, tier) in tiers.iter().enumerate() {
+            require!(tier.fee_bps <= self.management_fee_bps, CommonError::InvalidParameter);
+            if index > 0 {
+                let previous = &tiers[index - 1];
+                require!(
+                    tier.min_value > previous.min_value && tier.fee_bps <= previous.fee_bps,
+                    CommonError::InvalidParameter
+                );
+            }
+        }
+        Ok(())
+    }
+    
+    // Amounts are only comparable in the strategy's own unit, so reject a
+    // mismatched unit before checking the minimum
+    pub fn check_min_investment(&self, amount: u64, unit: InvestmentUnit) -> Result<()> {
+        require!(unit == self.unit, ErrorCode::InvestmentUnitMismatch);
+        require!(amount >= self.min_investment, ErrorCode::BelowMinimumInvestment);
+        Ok(())
+    }
+    
+    // Remaining subscribers may only be forced out once the grace period ends
+    pub fn check_deprecation_deadline(&self, now: i64) -> Result<()> {
+        require!(now >= self.deprecation_deadline, ErrorCode::DeprecationDeadlineNotReached);
+        Ok(())
+    }
+    
+    // Room left before max_tvl, or None when uncapped
+    pub fn remaining_capacity(&self) -> Option<u64> {
+        if self.max_tvl > 0 {
+            Some(self.max_tvl.saturating_sub(self.tvl))
+        } else {
+            None
+        }
+    }
+    
+    // Reject adding `amount` to the TVL when it would pass max_tvl
+    pub fn check_capacity(&self, amount: u64) -> Result<()> {
+        if let Some(remaining) = self.remaining_capacity() {
+            require!(amount <= remaining, ErrorCode::StrategyAtCapacity);
+        }
+        Ok(())
+    }
+    
+    // Checks every new position of `amount` must pass, whether subscribed or
+    // migrated in: the subscriber's verified-only preference, the minimum
+    // investment and the remaining capacity
+    pub fn check_new_position(
+        &self,
+        prefs: Option<&SubscriptionPrefs>,
+        amount: u64,
+        unit: InvestmentUnit
+    ) -> Result<()> {
+        if let Some(prefs) = prefs {
+            prefs.check_strategy(self)?;
+        }
+        self.check_min_investment(amount, unit)?;
+        self.check_capacity(amount)
+    }
+    
+    // Reject value updates that come too soon after `last_update_at` or move
+    // the value by more than max_value_change_bps; zero disables either limit
+    pub fn check_value_update(&self, last_update_at: i64, old_value: u64, new_value: u64, now: i64) -> Result<()> {
+        if self.min_value_update_interval_secs > 0 {
+            require!(
+                now - last_update_at >= self.min_value_update_interval_secs as i64,
+                ErrorCode::ValueUpdateTooSoon
+            );
+        }
+        if self.max_value_change_bps > 0 && old_value > 0 {
+            let change_bps = (new_value as i128 - old_value as i128).unsigned_abs() * 10000 / old_value as u128;
+            require!(
+                change_bps <= self.max_value_change_bps as u128,
+                ErrorCode::ValueChangeTooLarge
+            );
+        }
+        Ok(())
+    }
+    
+    // Append a history point, dropping the oldest beyond the retention
+    pub fn record_perf_point(&mut self, point: StrategyPerfPoint) {
+        self.performance_history.push(point);
+        while self.performance_history.len() > self.perf_retention as usize {
+            self.performance_history.remove(0);
+        }
+    }
+}
+
+// One point in a strategy's value history
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct StrategyPerfPoint {
+    pub timestamp: i64,
+    pub tvl: u64,
+    pub cumulative_return_bps: i32,
+}
+
+impl StrategyPerfPoint {
+    pub const SIZE: usize = 8 + 8 + 4;
+}
+
+// Management fee applied to subscriptions worth at least `min_value`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct VolumeTier {
+    // Threshold in the strategy's unit
+    pub min_value: u64,
+    
+    // Management fee in basis points
+    pub fee_bps: u16,
+}
+
+impl VolumeTier {
+    pub const SIZE: usize = 8 + 2;
+}
+
+// Configuration of a new strategy; see create_strategy
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CreateStrategyParams {
+    pub id: String,
+    pub name: String,
+    pub description_hash: String,
+    pub risk_level: u8,
+    pub time_horizon: u8,
+    pub ai_models: u32,
+    pub token_support: u8,
+    pub management_fee_bps: u16,
+    pub performance_fee_bps: u16,
+    pub min_investment: u64,
+    pub referral_fee_bps: u16,
+    pub max_tvl: u64,
+    pub unit: InvestmentUnit,
+    pub tags_bitmap: u64,
+}
+
+// Optional changes to an existing strategy; see update_strategy
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct UpdateStrategyParams {
+    pub name: Option<String>,
+    pub description_hash: Option<String>,
+    pub risk_level: Option<u8>,
+    pub time_horizon: Option<u8>,
+    pub ai_models: Option<u32>,
+    pub token_support: Option<u8>,
+    pub management_fee_bps: Option<u16>,
+    pub performance_fee_bps: Option<u16>,
+    pub min_investment: Option<u64>,
+    pub status: Option<u8>,
+    pub referral_fee_bps: Option<u16>,
+    pub tags_bitmap: Option<u64>,
+    pub perf_retention: Option<u8>,
+    pub max_value_change_bps: Option<u16>,
+    pub min_value_update_interval_secs: Option<u32>,
+    pub min_holding_secs: Option<u32>,
+    pub early_exit_fee_bps: Option<u16>,
+    pub early_exit_penalty_enabled: Option<bool>,
+    pub harvest_interval_secs: Option<u32>,
+}
+
+// Identity of a cloned strategy; see clone_strategy
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CloneStrategyParams {
+    pub id: String,
+    pub name: String,
+    pub description_hash: String,
+}
+
+// Most volume tiers a strategy can configure
+pub const MAX_VOLUME_TIERS: usize = 4;
+
+// Most history points a strategy account has room for
+pub const MAX_STRATEGY_PERF_POINTS: usize = 30;
+
+// Well-known tag bits
+pub const TAG_DEFI: u64 = 1 << 0;
+pub const TAG_MOMENTUM: u64 = 1 << 1;
+pub const TAG_STABLECOIN: u64 = 1 << 2;
+
+pub const MAX_TAGS: usize = 64;
+pub const MAX_TAG_NAME_LEN: usize = 32;
+
+#[account]
+pub struct TagRegistry {
+    // Human-readable name of each tag bit; index = bit
+    pub names: Vec<String>,
+    
+    // Bump seed for PDA
+    pub bump: u8,
+}
+
+impl TagRegistry {
+    pub const SPACE: usize = 8 + 4 + MAX_TAGS * (4 + MAX_TAG_NAME_LEN) + 1;
+    
+    // Bits that have a name and may be set on a strategy
+    pub fn defined_mask(&self) -> u64 {
+        if self.names.len() >= MAX_TAGS {
+            u64::MAX
+        } else {
+            (1u64 << self.names.len()) - 1
+        }
+    }
+    
+    pub fn validate(&self, tags_bitmap: u64) -> Result<()> {
+        require!(tags_bitmap & !self.defined_mask() == 0, ErrorCode::InvalidTag);
+        Ok(())
+    }
+}
+
+pub const MAX_APPROVED_CREATORS: usize = 100;
+
+#[account]
+pub struct CreatorRegistry {
+    // Creators allowed to create strategies while the allowlist is enabled
+    pub creators: Vec<Pubkey>,
+    
     // Bump seed for PDA
     pub bump: u8,
 }
 
+impl CreatorRegistry {
+    pub const SPACE: usize = 8 + 4 + MAX_APPROVED_CREATORS * 32 + 1;
+    
+    // Fails unless the allowlist is off or `creator` is on it
+    pub fn check_creator(
+        registry: &StrategyRegistry,
+        creator_registry: Option<&CreatorRegistry>,
+        creator: &Pubkey
+    ) -> Result<()> {
+        if !registry.creator_allowlist_enabled {
+            return Ok(());
+        }
+        
+        let creator_registry = creator_registry.ok_or(ErrorCode::CreatorNotApproved)?;
+        require!(creator_registry.creators.contains(creator), ErrorCode::CreatorNotApproved);
+        Ok(())
+    }
+    
+    // Add `creator` to the allowlist
+    pub fn approve(&mut self, creator: Pubkey) -> Result<()> {
+        require!(!self.creators.contains(&creator), CommonError::InvalidParameter);
+        require!(self.creators.len() < MAX_APPROVED_CREATORS, CommonError::InvalidParameter);
+        self.creators.push(creator);
+        Ok(())
+    }
+    
+    // Take `creator` off the allowlist
+    pub fn revoke(&mut self, creator: &Pubkey) -> Result<()> {
+        let index = self.creators
+            .iter()
+            .position(|c| c == creator)
+            .ok_or(ErrorCode::CreatorNotApproved)?;
+        self.creators.remove(index);
+        Ok(())
+    }
+}
+
 #[account]
 pub struct StrategySubscription {
     // Strategy ID
@@ -91,10 +436,10 @@ pub struct StrategySubscription {
     // Subscriber wallet address
     pub subscriber: Pubkey,
     
-    // Investment amount in lamports
+    // Investment amount in the strategy's unit
     pub investment_amount: u64,
     
-    // Current value in lamports
+    // Current value in the strategy's unit
     pub current_value: u64,
     
     // Subscription timestamp
@@ -106,10 +451,113 @@ pub struct StrategySubscription {
     // High water mark for performance fee calculation
     pub high_water_mark: u64,
     
+    // Harvest and compound gains inline whenever the value is updated
+    pub auto_harvest_on_update: bool,
+    
+    // Last harvest timestamp
+    pub last_harvest_at: i64,
+    
+    // Wallet that referred this subscriber, if any
+    pub referrer: Option<Pubkey>,
+    
+    // Total management fees credited to the referrer, in the strategy's unit
+    pub referral_fees_earned: u64,
+    
+    // Paused by the subscriber: value updates are skipped and no fees accrue
+    pub paused: bool,
+    
+    // When the subscription was paused, or fees were last skipped while paused
+    pub paused_at: i64,
+    
+    // Last time update_strategy_value changed this subscription's value
+    pub last_value_update_at: i64,
+    
+    // Bump seed for PDA
+    pub bump: u8,
+}
+
+impl StrategySubscription {
+    // A paused subscription's value isn't updated, so exiting at it would pay
+    // out a stale value; subscribers resume and let the value catch up first
+    pub fn check_not_paused(&self) -> Result<()> {
+        require!(!self.paused, ErrorCode::SubscriptionPaused);
+        Ok(())
+    }
+}
+
+// Platform-wide totals across every strategy
+#[account]
+#[derive(Default)]
+pub struct PlatformStats {
+    // Sum of all strategy TVL
+    pub total_tvl: u64,
+    
+    // Number of strategies ever created
+    pub total_strategies: u64,
+    
+    // Number of open subscriptions
+    pub total_active_subscriptions: u64,
+    
+    // Cumulative deposits and withdrawals
+    pub total_volume: u64,
+    
+    // Bump seed for PDA
+    pub bump: u8,
+}
+
+impl PlatformStats {
+    pub fn record_strategy_created(&mut self) -> Result<()> {
+        self.total_strategies = self.total_strategies.checked_add(1).ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+    
+    pub fn record_subscription(&mut self, amount: u64) -> Result<()> {
+        self.total_tvl = self.total_tvl.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_volume = self.total_volume.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_active_subscriptions = self.total_active_subscriptions
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+    
+    // Saturates rather than failing: these totals are bookkeeping and must
+    // never stand between a subscriber and their withdrawal
+    pub fn record_unsubscription(&mut self, amount: u64) {
+        self.total_tvl = self.total_tvl.saturating_sub(amount);
+        self.total_volume = self.total_volume.saturating_add(amount);
+        self.total_active_subscriptions = self.total_active_subscriptions.saturating_sub(1);
+    }
+    
+    pub fn record_value_change(&mut self, old_value: u64, new_value: u64) -> Result<()> {
+        self.total_tvl = self.total_tvl
+            .checked_sub(old_value)
+            .and_then(|tvl| tvl.checked_add(new_value))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+// Per-user subscription safety preferences
+#[account]
+pub struct SubscriptionPrefs {
+    // Wallet these preferences belong to
+    pub user: Pubkey,
+    
+    // Only allow subscribing to verified strategies
+    pub verified_only: bool,
+    
     // Bump seed for PDA
     pub bump: u8,
 }
 
+impl SubscriptionPrefs {
+    // Fails when the user only wants verified strategies and `strategy` isn't
+    pub fn check_strategy(&self, strategy: &AIStrategy) -> Result<()> {
+        require!(!self.verified_only || strategy.verified, ErrorCode::StrategyNotVerified);
+        Ok(())
+    }
+}
+
 #[derive(Accounts)]
 pub struct InitializeRegistry<'info> {
     #[account(mut)]
@@ -118,7 +566,7 @@ pub struct InitializeRegistry<'info> {
     #[account(
         init,
         payer = authority,
-        space = 8 + 32 + 8 + 2 + 32 + 1, // discriminator + authority + strategy_count + protocol_fee_bps + fee_recipient + bump
+        space = 8 + 32 + 8 + 2 + 32 + 2 + 2 + 1 + 1, // discriminator + authority + strategy_count + protocol_fee_bps + fee_recipient + max_combined_fee_score + max_single_loss_bps + creator_allowlist_enabled + bump
         seeds = [b"strategy-registry"],
         bump
     )]
@@ -128,198 +576,985 @@ pub struct InitializeRegistry<'info> {
 }
 
 #[derive(Accounts)]
-pub struct CreateStrategy<'info> {
+pub struct InitializePlatformStats<'info> {
     #[account(mut)]
-    pub creator: Signer<'info>,
+    pub authority: Signer<'info>,
     
     #[account(
-        mut,
         seeds = [b"strategy-registry"],
-        bump = registry.bump
+        bump = registry.bump,
+        constraint = authority.key() == registry.authority @ CommonError::Unauthorized
     )]
     pub registry: Account<'info, StrategyRegistry>,
     
     #[account(
         init,
-        payer = creator,
-        space = 8 + 64 + 32 + 64 + 64 + 1 + 1 + 4 + 1 + 2 + 2 + 8 + 8 + 8 + 4 + 8 + 8 + 1 + 1 + 1, // Add space for all fields
-        seeds = [b"strategy", creator.key().as_ref(), registry.strategy_count.to_le_bytes().as_ref()],
+        payer = authority,
+        space = 8 + 8 + 8 + 8 + 8 + 1, // discriminator + total_tvl + total_strategies + total_active_subscriptions + total_volume + bump
+        seeds = [b"platform-stats"],
         bump
     )]
-    pub strategy: Account<'info, AIStrategy>,
+    pub platform_stats: Account<'info, PlatformStats>,
     
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateStrategy<'info> {
+pub struct UpdateRegistry<'info> {
     #[account(mut)]
-    pub creator: Signer<'info>,
+    pub authority: Signer<'info>,
     
     #[account(
         mut,
-        seeds = [b"strategy", creator.key().as_ref(), strategy.id.as_bytes()],
-        bump = strategy.bump,
-        constraint = creator.key() == strategy.creator @ ErrorCode::Unauthorized
+        seeds = [b"strategy-registry"],
+        bump = registry.bump,
+        constraint = authority.key() == registry.authority @ CommonError::Unauthorized
     )]
-    pub strategy: Account<'info, AIStrategy>,
+    pub registry: Account<'info, StrategyRegistry>,
 }
 
 #[derive(Accounts)]
-pub struct VerifyStrategy<'info> {
+pub struct InitializeTagRegistry<'info> {
     #[account(mut)]
     pub authority: Signer<'info>,
     
     #[account(
         seeds = [b"strategy-registry"],
         bump = registry.bump,
-        constraint = authority.key() == registry.authority @ ErrorCode::Unauthorized
+        constraint = authority.key() == registry.authority @ CommonError::Unauthorized
     )]
     pub registry: Account<'info, StrategyRegistry>,
     
-    #[account(mut)]
-    pub strategy: Account<'info, AIStrategy>,
+    #[account(
+        init,
+        payer = authority,
+        space = TagRegistry::SPACE,
+        seeds = [b"tag-registry"],
+        bump
+    )]
+    pub tag_registry: Account<'info, TagRegistry>,
+    
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct SubscribeToStrategy<'info> {
+pub struct InitializeCreatorRegistry<'info> {
     #[account(mut)]
-    pub subscriber: Signer<'info>,
+    pub authority: Signer<'info>,
     
     #[account(
-        mut,
-        constraint = strategy.status == 0 @ ErrorCode::StrategyNotActive
+        seeds = [b"strategy-registry"],
+        bump = registry.bump,
+        constraint = authority.key() == registry.authority @ CommonError::Unauthorized
     )]
-    pub strategy: Account<'info, AIStrategy>,
+    pub registry: Account<'info, StrategyRegistry>,
     
     #[account(
         init,
-        payer = subscriber,
-        space = 8 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 1, // Add space for all fields
-        seeds = [b"subscription", strategy.key().as_ref(), subscriber.key().as_ref()],
+        payer = authority,
+        space = CreatorRegistry::SPACE,
+        seeds = [b"creator-registry"],
         bump
     )]
-    pub subscription: Account<'info, StrategySubscription>,
-    
-    #[account(mut)]
-    pub subscriber_token_account: Account<'info, TokenAccount>,
+    pub creator_registry: Account<'info, CreatorRegistry>,
     
-    #[account(mut)]
-    pub strategy_token_account: Account<'info, TokenAccount>,
-    
-    pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct UnsubscribeFromStrategy<'info> {
-    #[account(mut)]
-    pub subscriber: Signer<'info>,
+pub struct UpdateCreatorRegistry<'info> {
+    pub authority: Signer<'info>,
     
-    pub strategy: Account<'info, AIStrategy>,
+    #[account(
+        seeds = [b"strategy-registry"],
+        bump = registry.bump,
+        constraint = authority.key() == registry.authority @ CommonError::Unauthorized
+    )]
+    pub registry: Account<'info, StrategyRegistry>,
     
     #[account(
         mut,
-        close = subscriber,
-        seeds = [b"subscription", strategy.key().as_ref(), subscriber.key().as_ref()],
-        bump = subscription.bump,
-        constraint = subscriber.key() == subscription.subscriber @ ErrorCode::Unauthorized
+        seeds = [b"creator-registry"],
+        bump = creator_registry.bump
     )]
-    pub subscription: Account<'info, StrategySubscription>,
-    
-    #[account(mut)]
-    pub subscriber_token_account: Account<'info, TokenAccount>,
-    
-    #[account(mut)]
-    pub strategy_token_account: Account<'info, TokenAccount>,
-    
-    pub token_program: Program<'info, Token>,
+    pub creator_registry: Account<'info, CreatorRegistry>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateStrategyValue<'info> {
-    #[account(mut)]
+pub struct SetTagName<'info> {
     pub authority: Signer<'info>,
     
     #[account(
         seeds = [b"strategy-registry"],
         bump = registry.bump,
-        constraint = authority.key() == registry.authority @ ErrorCode::Unauthorized
+        constraint = authority.key() == registry.authority @ CommonError::Unauthorized
     )]
     pub registry: Account<'info, StrategyRegistry>,
     
-    #[account(mut)]
-    pub strategy: Account<'info, AIStrategy>,
-    
     #[account(
         mut,
-        seeds = [b"subscription", strategy.key().as_ref(), subscription.subscriber.as_ref()],
-        bump = subscription.bump
+        seeds = [b"tag-registry"],
+        bump = tag_registry.bump
     )]
-    pub subscription: Account<'info, StrategySubscription>,
+    pub tag_registry: Account<'info, TagRegistry>,
 }
 
-#[error_code]
-pub enum ErrorCode {
-    #[msg("Unauthorized access")]
-    Unauthorized,
-    
-    #[msg("Invalid parameter")]
-    InvalidParameter,
+#[derive(Accounts)]
+pub struct CreateStrategy<'info> {
+    #[account(mut)]
+    pub creator: Signer<'info>,
     
-    #[msg("Strategy not active")]
-    StrategyNotActive,
+    #[account(
+        mut,
+        seeds = [b"strategy-registry"],
+        bump = registry.bump
+    )]
+    pub registry: Account<'info, StrategyRegistry>,
     
-    #[msg("Investment below minimum")]
-    BelowMinimumInvestment,
+    #[account(
+        seeds = [b"tag-registry"],
+        bump = tag_registry.bump
+    )]
+    pub tag_registry: Account<'info, TagRegistry>,
     
-    #[msg("Insufficient funds")]
-    InsufficientFunds,
-}
-
-// Initialize the strategy registry
-pub fn initialize_registry(
-    ctx: Context<InitializeRegistry>, 
-    protocol_fee_bps: u16,
-    fee_recipient: Pubkey
-) -> Result<()> {
-    let registry = &mut ctx.accounts.registry;
+    // Only required while the creator allowlist is enabled
+    #[account(
+        seeds = [b"creator-registry"],
+        bump = creator_registry.bump
+    )]
+    pub creator_registry: Option<Account<'info, CreatorRegistry>>,
     
-    // Validate input
-    require!(protocol_fee_bps <= 1000, ErrorCode::InvalidParameter); // Max 10%
+    pub deposit_mint: Account<'info, Mint>,
     
-    registry.authority = ctx.accounts.authority.key();
-    registry.strategy_count = 0;
-    registry.protocol_fee_bps = protocol_fee_bps;
-    registry.fee_recipient = fee_recipient;
-    registry.bump = *ctx.bumps.get("registry").unwrap();
+    #[account(
+        init,
+        payer = creator,
+        space = AIStrategy::SPACE,
+        seeds = [b"strategy", creator.key().as_ref(), registry.strategy_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub strategy: Account<'info, AIStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"platform-stats"],
+        bump = platform_stats.bump
+    )]
+    pub platform_stats: Account<'info, PlatformStats>,
+    
+    pub system_program: Program<'info, System>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", creator.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+}
+
+#[derive(Accounts)]
+pub struct CloneStrategy<'info> {
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"strategy-registry"],
+        bump = registry.bump
+    )]
+    pub registry: Account<'info, StrategyRegistry>,
+    
+    #[account(
+        seeds = [b"strategy", source_strategy.seed_creator.as_ref(), source_strategy.seed_index.to_le_bytes().as_ref()],
+        bump = source_strategy.bump
+    )]
+    pub source_strategy: Account<'info, AIStrategy>,
+    
+    // Only required while the creator allowlist is enabled
+    #[account(
+        seeds = [b"creator-registry"],
+        bump = creator_registry.bump
+    )]
+    pub creator_registry: Option<Account<'info, CreatorRegistry>>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = AIStrategy::SPACE,
+        seeds = [b"strategy", creator.key().as_ref(), registry.strategy_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub strategy: Account<'info, AIStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"platform-stats"],
+        bump = platform_stats.bump
+    )]
+    pub platform_stats: Account<'info, PlatformStats>,
+    
+    pub system_program: Program<'info, System>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", creator.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateStrategy<'info> {
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    #[account(
+        seeds = [b"strategy-registry"],
+        bump = registry.bump
+    )]
+    pub registry: Account<'info, StrategyRegistry>,
+    
+    #[account(
+        seeds = [b"tag-registry"],
+        bump = tag_registry.bump
+    )]
+    pub tag_registry: Account<'info, TagRegistry>,
+    
+    #[account(
+        mut,
+        seeds = [b"strategy", strategy.seed_creator.as_ref(), strategy.seed_index.to_le_bytes().as_ref()],
+        bump = strategy.bump,
+        constraint = creator.key() == strategy.creator @ CommonError::Unauthorized
+    )]
+    pub strategy: Account<'info, AIStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", creator.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+}
+
+#[derive(Accounts)]
+#[instruction(new_owner: Pubkey)]
+pub struct TransferStrategyOwnership<'info> {
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"strategy", strategy.seed_creator.as_ref(), strategy.seed_index.to_le_bytes().as_ref()],
+        bump = strategy.bump,
+        constraint = creator.key() == strategy.creator @ CommonError::Unauthorized
+    )]
+    pub strategy: Account<'info, AIStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", creator.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", new_owner.as_ref()],
+        bump = new_owner_notification_counter.bump
+    )]
+    pub new_owner_notification_counter: Account<'info, NotificationCounter>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyStrategy<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"strategy-registry"],
+        bump = registry.bump,
+        constraint = authority.key() == registry.authority @ CommonError::Unauthorized
+    )]
+    pub registry: Account<'info, StrategyRegistry>,
+    
+    #[account(mut)]
+    pub strategy: Account<'info, AIStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", strategy.creator.as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+}
+
+#[derive(Accounts)]
+pub struct SubscribeToStrategy<'info> {
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+    
+    #[account(
+        mut,
+        constraint = strategy.status == 0 @ CommonError::StrategyNotActive
+    )]
+    pub strategy: Account<'info, AIStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"platform-stats"],
+        bump = platform_stats.bump
+    )]
+    pub platform_stats: Account<'info, PlatformStats>,
+    
+    #[account(
+        init,
+        payer = subscriber,
+        space = 8 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 1 + 8 + 33 + 8 + 1 + 8 + 8 + 1, // Add space for all fields
+        seeds = [b"subscription", strategy.key().as_ref(), subscriber.key().as_ref()],
+        bump
+    )]
+    pub subscription: Account<'info, StrategySubscription>,
+    
+    #[account(mut)]
+    pub subscriber_token_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub strategy_token_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", subscriber.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+    
+    // Only passed by users who have set up subscription preferences
+    #[account(
+        seeds = [b"subscription-prefs", subscriber.key().as_ref()],
+        bump = subscription_prefs.bump
+    )]
+    pub subscription_prefs: Option<Account<'info, SubscriptionPrefs>>,
+    
+    // Referrer's token account for their share of fees; required with a referrer
+    #[account(
+        constraint = referrer_token_account.mint == strategy.deposit_mint @ ErrorCode::MintMismatch
+    )]
+    pub referrer_token_account: Option<Account<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct UnsubscribeFromStrategy<'info> {
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"strategy", strategy.seed_creator.as_ref(), strategy.seed_index.to_le_bytes().as_ref()],
+        bump = strategy.bump
+    )]
+    pub strategy: Account<'info, AIStrategy>,
+    
+    #[account(
+        seeds = [b"strategy-registry"],
+        bump = registry.bump
+    )]
+    pub registry: Account<'info, StrategyRegistry>,
+    
+    #[account(
+        mut,
+        seeds = [b"platform-stats"],
+        bump = platform_stats.bump
+    )]
+    pub platform_stats: Account<'info, PlatformStats>,
+    
+    #[account(
+        mut,
+        close = subscriber,
+        seeds = [b"subscription", strategy.key().as_ref(), subscriber.key().as_ref()],
+        bump = subscription.bump,
+        constraint = subscriber.key() == subscription.subscriber @ CommonError::Unauthorized
+    )]
+    pub subscription: Account<'info, StrategySubscription>,
+    
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscriber.key() @ CommonError::Unauthorized
+    )]
+    pub subscriber_token_account: Account<'info, TokenAccount>,
+    
+    // Owned by the strategy PDA, which signs the withdrawal
+    #[account(
+        mut,
+        constraint = strategy_token_account.owner == strategy.key() @ CommonError::Unauthorized
+    )]
+    pub strategy_token_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", subscriber.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+    
+    // Protocol fee recipient's token account; required when an early exit fee is charged
+    #[account(
+        mut,
+        constraint = fee_recipient.mint == strategy.deposit_mint @ ErrorCode::MintMismatch,
+        constraint = fee_recipient.owner == registry.fee_recipient @ CommonError::Unauthorized
+    )]
+    pub fee_recipient: Option<Account<'info, TokenAccount>>,
+    
+    // Creator's token account for their share of an early exit fee
+    #[account(
+        mut,
+        constraint = creator_fee_account.mint == strategy.deposit_mint @ ErrorCode::MintMismatch,
+        constraint = creator_fee_account.owner == strategy.creator @ CommonError::Unauthorized
+    )]
+    pub creator_fee_account: Option<Account<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateStrategyValue<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"strategy-registry"],
+        bump = registry.bump,
+        constraint = authority.key() == registry.authority @ CommonError::Unauthorized
+    )]
+    pub registry: Account<'info, StrategyRegistry>,
+    
+    #[account(mut)]
+    pub strategy: Account<'info, AIStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"platform-stats"],
+        bump = platform_stats.bump
+    )]
+    pub platform_stats: Account<'info, PlatformStats>,
+    
+    #[account(
+        mut,
+        seeds = [b"subscription", strategy.key().as_ref(), subscription.subscriber.as_ref()],
+        bump = subscription.bump
+    )]
+    pub subscription: Account<'info, StrategySubscription>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", subscription.subscriber.as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+    
+    // Only required when the subscription has a referrer to credit
+    #[account(
+        mut,
+        seeds = [b"notification-counter", subscription.referrer.unwrap_or_default().as_ref()],
+        bump = referrer_notification_counter.bump
+    )]
+    pub referrer_notification_counter: Option<Account<'info, NotificationCounter>>,
+    
+    // Protocol fee recipient's token account; must hold the strategy's deposit mint
+    #[account(
+        mut,
+        constraint = fee_recipient.mint == strategy.deposit_mint @ ErrorCode::MintMismatch,
+        constraint = fee_recipient.owner == registry.fee_recipient @ CommonError::Unauthorized
+    )]
+    pub fee_recipient: Option<Account<'info, TokenAccount>>,
+    
+    // Creator's token account for their share of fees
+    #[account(
+        mut,
+        constraint = creator_fee_account.mint == strategy.deposit_mint @ ErrorCode::MintMismatch,
+        constraint = creator_fee_account.owner == strategy.creator @ CommonError::Unauthorized
+    )]
+    pub creator_fee_account: Option<Account<'info, TokenAccount>>,
+    
+    // Strategy's deposit account fees are paid out of; required when a fee is collected
+    #[account(
+        mut,
+        constraint = strategy_token_account.mint == strategy.deposit_mint @ ErrorCode::MintMismatch,
+        constraint = strategy_token_account.owner == strategy.key() @ CommonError::Unauthorized
+    )]
+    pub strategy_token_account: Option<Account<'info, TokenAccount>>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+    
+    // Referrer's token account; required when a referral fee is paid
+    #[account(
+        mut,
+        constraint = referrer_token_account.mint == strategy.deposit_mint @ ErrorCode::MintMismatch,
+        constraint = Some(referrer_token_account.owner) == subscription.referrer @ CommonError::Unauthorized
+    )]
+    pub referrer_token_account: Option<Account<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSubscriptionSettings<'info> {
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+    
+    pub strategy: Account<'info, AIStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"subscription", strategy.key().as_ref(), subscriber.key().as_ref()],
+        bump = subscription.bump,
+        constraint = subscriber.key() == subscription.subscriber @ CommonError::Unauthorized
+    )]
+    pub subscription: Account<'info, StrategySubscription>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeSubscriptionPrefs<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 32 + 1 + 1, // discriminator + user + verified_only + bump
+        seeds = [b"subscription-prefs", user.key().as_ref()],
+        bump
+    )]
+    pub subscription_prefs: Account<'info, SubscriptionPrefs>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSubscriptionPrefs<'info> {
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"subscription-prefs", user.key().as_ref()],
+        bump = subscription_prefs.bump,
+        constraint = subscription_prefs.user == user.key() @ CommonError::Unauthorized
+    )]
+    pub subscription_prefs: Account<'info, SubscriptionPrefs>,
+}
+
+#[derive(Accounts)]
+pub struct TransferSubscription<'info> {
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+    
+    // Signing accepts the position and pays for its account
+    #[account(mut)]
+    pub new_subscriber: Signer<'info>,
+    
+    #[account(
+        seeds = [b"strategy", strategy.seed_creator.as_ref(), strategy.seed_index.to_le_bytes().as_ref()],
+        bump = strategy.bump
+    )]
+    pub strategy: Account<'info, AIStrategy>,
+    
+    #[account(
+        mut,
+        close = subscriber,
+        seeds = [b"subscription", strategy.key().as_ref(), subscriber.key().as_ref()],
+        bump = subscription.bump,
+        constraint = subscriber.key() == subscription.subscriber @ CommonError::Unauthorized
+    )]
+    pub subscription: Account<'info, StrategySubscription>,
+    
+    #[account(
+        init,
+        payer = new_subscriber,
+        space = 8 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 1 + 8 + 33 + 8 + 1 + 8 + 8 + 1, // Add space for all fields
+        seeds = [b"subscription", strategy.key().as_ref(), new_subscriber.key().as_ref()],
+        bump
+    )]
+    pub new_subscription: Account<'info, StrategySubscription>,
+    
+    pub system_program: Program<'info, System>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", new_subscriber.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateSubscription<'info> {
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"strategy", source_strategy.seed_creator.as_ref(), source_strategy.seed_index.to_le_bytes().as_ref()],
+        bump = source_strategy.bump,
+        constraint = source_strategy.status == 2 @ ErrorCode::StrategyNotDeprecated
+    )]
+    pub source_strategy: Account<'info, AIStrategy>,
+    
+    #[account(
+        mut,
+        close = subscriber,
+        seeds = [b"subscription", source_strategy.key().as_ref(), subscriber.key().as_ref()],
+        bump = source_subscription.bump,
+        constraint = subscriber.key() == source_subscription.subscriber @ CommonError::Unauthorized
+    )]
+    pub source_subscription: Account<'info, StrategySubscription>,
+    
+    #[account(
+        mut,
+        constraint = target_strategy.status == 0 @ CommonError::StrategyNotActive,
+        constraint = target_strategy.unit == source_strategy.unit @ ErrorCode::InvestmentUnitMismatch
+    )]
+    pub target_strategy: Account<'info, AIStrategy>,
+    
+    #[account(
+        init,
+        payer = subscriber,
+        space = 8 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 1 + 8 + 33 + 8 + 1 + 8 + 8 + 1, // Add space for all fields
+        seeds = [b"subscription", target_strategy.key().as_ref(), subscriber.key().as_ref()],
+        bump
+    )]
+    pub target_subscription: Account<'info, StrategySubscription>,
+    
+    #[account(
+        mut,
+        constraint = source_strategy_token_account.owner == source_strategy.key() @ CommonError::Unauthorized
+    )]
+    pub source_strategy_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = target_strategy_token_account.owner == target_strategy.key() @ CommonError::Unauthorized
+    )]
+    pub target_strategy_token_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", subscriber.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+    
+    #[account(
+        seeds = [b"strategy-registry"],
+        bump = registry.bump
+    )]
+    pub registry: Account<'info, StrategyRegistry>,
+    
+    // Only required while the creator allowlist is enabled
+    #[account(
+        seeds = [b"creator-registry"],
+        bump = creator_registry.bump
+    )]
+    pub creator_registry: Option<Account<'info, CreatorRegistry>>,
+    
+    // Only passed by users who have set up subscription preferences
+    #[account(
+        seeds = [b"subscription-prefs", subscriber.key().as_ref()],
+        bump = subscription_prefs.bump
+    )]
+    pub subscription_prefs: Option<Account<'info, SubscriptionPrefs>>,
+}
+
+#[derive(Accounts)]
+pub struct ForceUnsubscribe<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"strategy-registry"],
+        bump = registry.bump,
+        constraint = authority.key() == registry.authority @ CommonError::Unauthorized
+    )]
+    pub registry: Account<'info, StrategyRegistry>,
+    
+    #[account(
+        mut,
+        seeds = [b"strategy", strategy.seed_creator.as_ref(), strategy.seed_index.to_le_bytes().as_ref()],
+        bump = strategy.bump,
+        constraint = strategy.status == 2 @ ErrorCode::StrategyNotDeprecated
+    )]
+    pub strategy: Account<'info, AIStrategy>,
+    
+    #[account(
+        mut,
+        seeds = [b"platform-stats"],
+        bump = platform_stats.bump
+    )]
+    pub platform_stats: Account<'info, PlatformStats>,
+    
+    #[account(
+        mut,
+        close = subscriber,
+        seeds = [b"subscription", strategy.key().as_ref(), subscriber.key().as_ref()],
+        bump = subscription.bump
+    )]
+    pub subscription: Account<'info, StrategySubscription>,
+    
+    /// CHECK: Receives the subscription's rent; must be the subscriber
+    #[account(mut, constraint = subscriber.key() == subscription.subscriber @ CommonError::Unauthorized)]
+    pub subscriber: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscriber.key() @ CommonError::Unauthorized
+    )]
+    pub subscriber_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = strategy_token_account.owner == strategy.key() @ CommonError::Unauthorized
+    )]
+    pub strategy_token_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    #[account(
+        mut,
+        seeds = [b"notification-counter", subscriber.key().as_ref()],
+        bump = notification_counter.bump
+    )]
+    pub notification_counter: Account<'info, NotificationCounter>,
+}
+
+#[derive(Accounts)]
+pub struct StrategyCapacity<'info> {
+    pub strategy: Account<'info, AIStrategy>,
+}
+
+#[error_code(offset = 6300)]
+pub enum ErrorCode {
+    #[msg("Investment below minimum")]
+    BelowMinimumInvestment,
+    
+    #[msg("Insufficient funds")]
+    InsufficientFunds,
+    
+    #[msg("Combined management and performance fees too high")]
+    FeesTooHigh,
+    
+    #[msg("Value drop exceeds the single-update limit without confirmation")]
+    LargeLossNotConfirmed,
+    
+    #[msg("Subscribers cannot refer themselves")]
+    SelfReferral,
+    
+    #[msg("Strategy is at capacity")]
+    StrategyAtCapacity,
+    
+    #[msg("Investment unit does not match the strategy's unit")]
+    InvestmentUnitMismatch,
+    
+    #[msg("Strategy is not deprecated")]
+    StrategyNotDeprecated,
+    
+    #[msg("Deprecation grace period has not ended")]
+    DeprecationDeadlineNotReached,
+    
+    #[msg("Tag bit is out of range or unnamed")]
+    InvalidTag,
+    
+    #[msg("Strategy is not verified")]
+    StrategyNotVerified,
+    
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+    
+    #[msg("Token account mint does not match the strategy's deposit mint")]
+    MintMismatch,
+    
+    #[msg("Value change exceeds the strategy's per-update cap")]
+    ValueChangeTooLarge,
+    
+    #[msg("Value was updated too recently")]
+    ValueUpdateTooSoon,
+    
+    #[msg("Creator is not approved to create strategies")]
+    CreatorNotApproved,
+    
+    #[msg("Fee split exceeds the gross fee")]
+    FeeSplitExceedsGross,
+    
+    #[msg("Subscription has not been held for the minimum holding period")]
+    MinHoldingPeriodNotMet,
+    
+    #[msg("Referrer must have a token account for the strategy's deposit mint")]
+    InvalidReferrer,
+    
+    #[msg("Subscription is paused; resume it before exiting")]
+    SubscriptionPaused,
+}
+
+// Time subscribers have to migrate out of a deprecated strategy (30 days)
+pub const DEPRECATION_GRACE_PERIOD: i64 = 30 * 86400;
+
+// Harvest interval new strategies start with (1 day)
+pub const DEFAULT_HARVEST_INTERVAL_SECS: u32 = 86400;
+
+// Remaining room in a strategy before it reaches max_tvl
+#[event]
+pub struct StrategyCapacityEvent {
+    pub strategy: Pubkey,
+    pub tvl: u64,
+    pub max_tvl: u64,
+    pub remaining_capacity: Option<u64>, // None when uncapped
+}
+
+// A collected fee split between the protocol and the strategy creator
+#[event]
+pub struct FeeDistributedEvent {
+    pub strategy: Pubkey,
+    pub subscription: Pubkey,
+    pub gross_fee: u64,
+    pub protocol_amount: u64,
+    pub creator_amount: u64,
+}
+
+// Latest point appended to a strategy's value history
+#[event]
+pub struct StrategyPerfPointEvent {
+    pub strategy: Pubkey,
+    pub point: StrategyPerfPoint,
+}
+
+// Initialize the strategy registry
+pub fn initialize_registry(
+    ctx: Context<InitializeRegistry>, 
+    protocol_fee_bps: u16,
+    fee_recipient: Pubkey,
+    max_combined_fee_score: u16
+) -> Result<()> {
+    let registry = &mut ctx.accounts.registry;
+    
+    // Validate input
+    require!(protocol_fee_bps <= 1000, CommonError::InvalidParameter); // Max 10%
+    require!(max_combined_fee_score > 0, CommonError::InvalidParameter);
+    
+    registry.authority = ctx.accounts.authority.key();
+    registry.strategy_count = 0;
+    registry.protocol_fee_bps = protocol_fee_bps;
+    registry.fee_recipient = fee_recipient;
+    registry.max_combined_fee_score = max_combined_fee_score;
+    registry.max_single_loss_bps = 3000; // 30%
+    registry.creator_allowlist_enabled = false;
+    registry.bump = *ctx.bumps.get("registry").unwrap();
+    
+    Ok(())
+}
+
+// Initialize the tag registry with the well-known tags
+pub fn initialize_tag_registry(ctx: Context<InitializeTagRegistry>) -> Result<()> {
+    let tag_registry = &mut ctx.accounts.tag_registry;
+    
+    tag_registry.names = vec![
+        "DeFi".to_string(),       // TAG_DEFI
+        "Momentum".to_string(),   // TAG_MOMENTUM
+        "Stablecoin".to_string(), // TAG_STABLECOIN
+    ];
+    tag_registry.bump = *ctx.bumps.get("tag_registry").unwrap();
+    
+    Ok(())
+}
+
+// Name a new tag bit or rename an existing one (admin only)
+pub fn set_tag_name(ctx: Context<SetTagName>, bit: u8, name: String) -> Result<()> {
+    let tag_registry = &mut ctx.accounts.tag_registry;
+    let bit = bit as usize;
+    
+    // Bits are assigned in order, so the next new bit is names.len()
+    require!(bit < MAX_TAGS && bit <= tag_registry.names.len(), ErrorCode::InvalidTag);
+    require!(!name.is_empty() && name.len() <= MAX_TAG_NAME_LEN, CommonError::InvalidParameter);
+    
+    if bit == tag_registry.names.len() {
+        tag_registry.names.push(name);
+    } else {
+        tag_registry.names[bit] = name;
+    }
+    
+    Ok(())
+}
+
+// Initialize the creator allowlist, empty (admin only)
+pub fn initialize_creator_registry(ctx: Context<InitializeCreatorRegistry>) -> Result<()> {
+    let creator_registry = &mut ctx.accounts.creator_registry;
+    
+    creator_registry.creators = Vec::new();
+    creator_registry.bump = *ctx.bumps.get("creator_registry").unwrap();
+    
+    Ok(())
+}
+
+// Allow a creator to create strategies while the allowlist is enabled (admin only)
+pub fn add_approved_creator(ctx: Context<UpdateCreatorRegistry>, creator: Pubkey) -> Result<()> {
+    ctx.accounts.creator_registry.approve(creator)
+}
+
+// Revoke a creator's approval; their existing strategies are unaffected (admin only)
+pub fn remove_approved_creator(ctx: Context<UpdateCreatorRegistry>, creator: Pubkey) -> Result<()> {
+    ctx.accounts.creator_registry.revoke(&creator)
+}
+
+// Initialize the platform-wide stats account
+pub fn initialize_platform_stats(ctx: Context<InitializePlatformStats>) -> Result<()> {
+    let platform_stats = &mut ctx.accounts.platform_stats;
+    
+    platform_stats.total_tvl = 0;
+    platform_stats.total_strategies = 0;
+    platform_stats.total_active_subscriptions = 0;
+    platform_stats.total_volume = 0;
+    platform_stats.bump = *ctx.bumps.get("platform_stats").unwrap();
     
     Ok(())
 }
 
 // Create a new AI strategy
-pub fn create_strategy(
-    ctx: Context<CreateStrategy>,
-    id: String,
-    name: String,
-    description_hash: String,
-    risk_level: u8,
-    time_horizon: u8,
-    ai_models: u32,
-    token_support: u8,
-    management_fee_bps: u16,
-    performance_fee_bps: u16,
-    min_investment: u64
-) -> Result<()> {
+pub fn create_strategy(ctx: Context<CreateStrategy>, params: CreateStrategyParams) -> Result<()> {
+    let CreateStrategyParams {
+        id,
+        name,
+        description_hash,
+        risk_level,
+        time_horizon,
+        ai_models,
+        token_support,
+        management_fee_bps,
+        performance_fee_bps,
+        min_investment,
+        referral_fee_bps,
+        max_tvl,
+        unit,
+        tags_bitmap,
+    } = params;
     let strategy = &mut ctx.accounts.strategy;
     let registry = &mut ctx.accounts.registry;
     
     // Validate inputs
-    require!(risk_level <= 3, ErrorCode::InvalidParameter);
-    require!(time_horizon <= 2, ErrorCode::InvalidParameter);
-    require!(token_support <= 3, ErrorCode::InvalidParameter);
-    require!(management_fee_bps <= 500, ErrorCode::InvalidParameter); // Max 5%
-    require!(performance_fee_bps <= 3000, ErrorCode::InvalidParameter); // Max 30%
+    CreatorRegistry::check_creator(
+        registry,
+        ctx.accounts.creator_registry.as_deref(),
+        &ctx.accounts.creator.key()
+    )?;
+    require!(risk_level <= 3, CommonError::InvalidParameter);
+    require!(time_horizon <= 2, CommonError::InvalidParameter);
+    require!(token_support <= 3, CommonError::InvalidParameter);
+    require!(management_fee_bps <= 500, CommonError::InvalidParameter); // Max 5%
+    require!(performance_fee_bps <= 3000, CommonError::InvalidParameter); // Max 30%
+    require!(referral_fee_bps <= 2000, CommonError::InvalidParameter); // Max 20% of the management fee
+    ctx.accounts.tag_registry.validate(tags_bitmap)?;
+    require!(
+        combined_fee_score(management_fee_bps, performance_fee_bps) <= registry.max_combined_fee_score as u32,
+        ErrorCode::FeesTooHigh
+    );
     
     // Set strategy data
     strategy.id = id;
@@ -329,63 +1564,150 @@ pub fn create_strategy(
     strategy.risk_level = risk_level;
     strategy.time_horizon = time_horizon;
     strategy.ai_models = ai_models;
+    strategy.tags_bitmap = tags_bitmap;
     strategy.token_support = token_support;
     strategy.management_fee_bps = management_fee_bps;
     strategy.performance_fee_bps = performance_fee_bps;
+    strategy.referral_fee_bps = referral_fee_bps;
+    strategy.volume_tiers = Vec::new();
+    strategy.unit = unit;
     strategy.min_investment = min_investment;
     strategy.tvl = 0;
+    strategy.max_tvl = max_tvl;
     strategy.subscriber_count = 0;
     strategy.total_returns_bps = 0;
     strategy.created_at = Clock::get()?.unix_timestamp;
     strategy.updated_at = Clock::get()?.unix_timestamp;
     strategy.status = 0; // Active
+    strategy.deprecation_deadline = 0;
     strategy.verified = false;
+    strategy.deposit_mint = ctx.accounts.deposit_mint.key();
+    strategy.performance_history = Vec::new();
+    strategy.perf_retention = MAX_STRATEGY_PERF_POINTS as u8;
+    strategy.max_value_change_bps = 0;
+    strategy.min_value_update_interval_secs = 0;
+    strategy.min_holding_secs = 0;
+    strategy.early_exit_fee_bps = 0;
+    strategy.early_exit_penalty_enabled = false;
+    strategy.harvest_interval_secs = DEFAULT_HARVEST_INTERVAL_SECS;
+    strategy.seed_creator = ctx.accounts.creator.key();
+    strategy.seed_index = registry.strategy_count;
+    strategy.cloned_from = None;
     strategy.bump = *ctx.bumps.get("strategy").unwrap();
     
     // Increment strategy count in registry
     registry.strategy_count += 1;
+    ctx.accounts.platform_stats.record_strategy_created()?;
     
     // Emit notification
     emit_notification(
-        ctx.to_account_infos(),
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
+        ctx.accounts.creator.key(),
+        Notification {
+            event_type: NotificationEventType::StrategyUpdated,
+            priority: NotificationPriority::Low,
+            title: "Strategy Created".to_string(),
+            message: format!("Your strategy '{}' has been created successfully", strategy.name),
+            data: Some(format!("{{\"strategyId\":\"{}\"}}", strategy.id)),
+            strategy_id: None,
+            token_address: None,
+            tx_signature: None,
+        },
+    );
+    
+    Ok(())
+}
+
+// Fork an existing strategy into a new one owned by the caller. The copy keeps
+// the source's configuration and fees but starts empty and unverified.
+pub fn clone_strategy(ctx: Context<CloneStrategy>, params: CloneStrategyParams) -> Result<()> {
+    let source = &ctx.accounts.source_strategy;
+    let strategy = &mut ctx.accounts.strategy;
+    let registry = &mut ctx.accounts.registry;
+    let now = Clock::get()?.unix_timestamp;
+    
+    CreatorRegistry::check_creator(
+        registry,
+        ctx.accounts.creator_registry.as_deref(),
+        &ctx.accounts.creator.key()
+    )?;
+    
+    // Fee limits may have tightened since the source was created
+    require!(
+        combined_fee_score(source.management_fee_bps, source.performance_fee_bps) <= registry.max_combined_fee_score as u32,
+        ErrorCode::FeesTooHigh
+    );
+    
+    strategy.set_inner(cloned_strategy(
+        source,
+        source.key(),
+        ctx.accounts.creator.key(),
+        params,
+        registry.strategy_count,
+        now,
+        *ctx.bumps.get("strategy").unwrap(),
+    ));
+    
+    registry.strategy_count += 1;
+    ctx.accounts.platform_stats.record_strategy_created()?;
+    
+    emit_notification(
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
         ctx.accounts.creator.key(),
-        NotificationEventType::StrategyUpdated,
-        NotificationPriority::Low,
-        "Strategy Created".to_string(),
-        format!("Your strategy '{}' has been created successfully", strategy.name),
-        Some(format!("{{\"strategyId\":\"{}\"}}", strategy.id)),
-        None,
-        None,
-        None
+        Notification {
+            event_type: NotificationEventType::StrategyUpdated,
+            priority: NotificationPriority::Low,
+            title: "Strategy Cloned".to_string(),
+            message: format!("Your strategy '{}' has been cloned from '{}'", strategy.name, source.name),
+            data: Some(format!(
+                "{{\"strategyId\":\"{}\", \"clonedFrom\":\"{}\"}}",
+                strategy.id,
+                source.key()
+            )),
+            strategy_id: None,
+            token_address: None,
+            tx_signature: None,
+        },
     );
     
     Ok(())
 }
 
 // Update an existing strategy
-pub fn update_strategy(
-    ctx: Context<UpdateStrategy>,
-    name: Option<String>,
-    description_hash: Option<String>,
-    risk_level: Option<u8>,
-    time_horizon: Option<u8>,
-    ai_models: Option<u32>,
-    token_support: Option<u8>,
-    management_fee_bps: Option<u16>,
-    performance_fee_bps: Option<u16>,
-    min_investment: Option<u64>,
-    status: Option<u8>
-) -> Result<()> {
+pub fn update_strategy(ctx: Context<UpdateStrategy>, params: UpdateStrategyParams) -> Result<()> {
+    let UpdateStrategyParams {
+        name,
+        description_hash,
+        risk_level,
+        time_horizon,
+        ai_models,
+        token_support,
+        management_fee_bps,
+        performance_fee_bps,
+        min_investment,
+        status,
+        referral_fee_bps,
+        tags_bitmap,
+        perf_retention,
+        max_value_change_bps,
+        min_value_update_interval_secs,
+        min_holding_secs,
+        early_exit_fee_bps,
+        early_exit_penalty_enabled,
+        harvest_interval_secs,
+    } = params;
     let strategy = &mut ctx.accounts.strategy;
     
     // Validate inputs
     if let Some(risk) = risk_level {
-        require!(risk <= 3, ErrorCode::InvalidParameter);
+        require!(risk <= 3, CommonError::InvalidParameter);
         strategy.risk_level = risk;
     }
     
     if let Some(horizon) = time_horizon {
-        require!(horizon <= 2, ErrorCode::InvalidParameter);
+        require!(horizon <= 2, CommonError::InvalidParameter);
         strategy.time_horizon = horizon;
     }
     
@@ -393,27 +1715,88 @@ pub fn update_strategy(
         strategy.ai_models = models;
     }
     
+    if let Some(tags) = tags_bitmap {
+        ctx.accounts.tag_registry.validate(tags)?;
+        strategy.tags_bitmap = tags;
+    }
+    
     if let Some(support) = token_support {
-        require!(support <= 3, ErrorCode::InvalidParameter);
+        require!(support <= 3, CommonError::InvalidParameter);
         strategy.token_support = support;
     }
     
     if let Some(fee) = management_fee_bps {
-        require!(fee <= 500, ErrorCode::InvalidParameter); // Max 5%
+        require!(fee <= 500, CommonError::InvalidParameter); // Max 5%
         strategy.management_fee_bps = fee;
     }
     
     if let Some(fee) = performance_fee_bps {
-        require!(fee <= 3000, ErrorCode::InvalidParameter); // Max 30%
+        require!(fee <= 3000, CommonError::InvalidParameter); // Max 30%
         strategy.performance_fee_bps = fee;
     }
     
+    // Check the resulting fee pair against the platform ceiling
+    require!(
+        combined_fee_score(strategy.management_fee_bps, strategy.performance_fee_bps)
+            <= ctx.accounts.registry.max_combined_fee_score as u32,
+        ErrorCode::FeesTooHigh
+    );
+    
     if let Some(min) = min_investment {
         strategy.min_investment = min;
     }
     
+    if let Some(fee) = referral_fee_bps {
+        require!(fee <= 2000, CommonError::InvalidParameter); // Max 20% of the management fee
+        strategy.referral_fee_bps = fee;
+    }
+    
+    if let Some(retention) = perf_retention {
+        require!(
+            retention > 0 && retention as usize <= MAX_STRATEGY_PERF_POINTS,
+            CommonError::InvalidParameter
+        );
+        strategy.perf_retention = retention;
+        while strategy.performance_history.len() > retention as usize {
+            strategy.performance_history.remove(0);
+        }
+    }
+    
+    if let Some(max_change) = max_value_change_bps {
+        require!(max_change <= 10000, CommonError::InvalidParameter);
+        strategy.max_value_change_bps = max_change;
+    }
+    
+    if let Some(interval) = min_value_update_interval_secs {
+        strategy.min_value_update_interval_secs = interval;
+    }
+    
+    if let Some(holding) = min_holding_secs {
+        strategy.min_holding_secs = holding;
+    }
+    
+    if let Some(fee) = early_exit_fee_bps {
+        require!(fee <= 1000, CommonError::InvalidParameter); // Max 10%
+        strategy.early_exit_fee_bps = fee;
+    }
+    
+    if let Some(enabled) = early_exit_penalty_enabled {
+        strategy.early_exit_penalty_enabled = enabled;
+    }
+    
+    if let Some(interval) = harvest_interval_secs {
+        strategy.harvest_interval_secs = interval;
+    }
+    
     if let Some(new_status) = status {
-        require!(new_status <= 2, ErrorCode::InvalidParameter);
+        require!(new_status <= 2, CommonError::InvalidParameter);
+        
+        // Deprecation starts the grace period for subscribers to migrate out
+        if new_status == 2 && strategy.status != 2 {
+            strategy.deprecation_deadline = Clock::get()?.unix_timestamp + DEPRECATION_GRACE_PERIOD;
+        } else if new_status != 2 {
+            strategy.deprecation_deadline = 0;
+        }
         strategy.status = new_status;
     }
     
@@ -430,21 +1813,36 @@ pub fn update_strategy(
     
     // Emit notification
     emit_notification(
-        ctx.to_account_infos(),
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
         ctx.accounts.creator.key(),
-        NotificationEventType::StrategyUpdated,
-        NotificationPriority::Low,
-        "Strategy Updated".to_string(),
-        format!("Your strategy '{}' has been updated successfully", strategy.name),
-        Some(format!("{{\"strategyId\":\"{}\"}}", strategy.id)),
-        None,
-        None,
-        None
+        Notification {
+            event_type: NotificationEventType::StrategyUpdated,
+            priority: NotificationPriority::Low,
+            title: "Strategy Updated".to_string(),
+            message: format!("Your strategy '{}' has been updated successfully", strategy.name),
+            data: Some(format!("{{\"strategyId\":\"{}\"}}", strategy.id)),
+            strategy_id: None,
+            token_address: None,
+            tx_signature: None,
+        },
     );
     
     Ok(())
 }
 
+// Replace a strategy's volume fee tiers. Thresholds must ascend and fees must
+// not rise with volume; an empty list charges every subscriber the base fee.
+pub fn set_volume_tiers(ctx: Context<UpdateStrategy>, tiers: Vec<VolumeTier>) -> Result<()> {
+    let strategy = &mut ctx.accounts.strategy;
+    strategy.check_volume_tiers(&tiers)?;
+    
+    strategy.volume_tiers = tiers;
+    strategy.updated_at = Clock::get()?.unix_timestamp;
+    
+    Ok(())
+}
+
 // Verify a strategy (admin only)
 pub fn verify_strategy(ctx: Context<VerifyStrategy>, verified: bool) -> Result<()> {
     let strategy = &mut ctx.accounts.strategy;
@@ -454,20 +1852,23 @@ pub fn verify_strategy(ctx: Context<VerifyStrategy>, verified: bool) -> Result<(
     
     // Emit notification to strategy creator
     emit_notification(
-        ctx.to_account_infos(),
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
         strategy.creator,
-        NotificationEventType::StrategyUpdated,
-        NotificationPriority::Medium,
-        "Strategy Verification Update".to_string(),
-        format!(
-            "Your strategy '{}' has been {} verification", 
-            strategy.name, 
-            if verified { "granted" } else { "denied" }
-        ),
-        Some(format!("{{\"strategyId\":\"{}\", \"verified\":{}}}", strategy.id, verified)),
-        None,
-        None,
-        None
+        Notification {
+            event_type: NotificationEventType::StrategyUpdated,
+            priority: NotificationPriority::Medium,
+            title: "Strategy Verification Update".to_string(),
+            message: format!(
+                "Your strategy '{}' has been {} verification", 
+                strategy.name, 
+                if verified { "granted" } else { "denied" }
+            ),
+            data: Some(format!("{{\"strategyId\":\"{}\", \"verified\":{}}}", strategy.id, verified)),
+            strategy_id: None,
+            token_address: None,
+            tx_signature: None,
+        },
     );
     
     Ok(())
@@ -476,30 +1877,51 @@ pub fn verify_strategy(ctx: Context<VerifyStrategy>, verified: bool) -> Result<(
 // Subscribe to a strategy
 pub fn subscribe_to_strategy(
     ctx: Context<SubscribeToStrategy>,
-    investment_amount: u64
+    investment_amount: u64,
+    referrer: Option<Pubkey>,
+    unit: InvestmentUnit
 ) -> Result<()> {
     let strategy = &mut ctx.accounts.strategy;
     let subscription = &mut ctx.accounts.subscription;
     
-    // Verify minimum investment
     require!(
-        investment_amount >= strategy.min_investment,
-        ErrorCode::BelowMinimumInvestment
+        referrer != Some(ctx.accounts.subscriber.key()),
+        ErrorCode::SelfReferral
     );
     
+    // A referrer must be able to receive their share in the deposit mint
+    if let Some(referrer) = referrer {
+        let referrer_token_account = ctx.accounts.referrer_token_account
+            .as_ref()
+            .ok_or(ErrorCode::InvalidReferrer)?;
+        require!(referrer_token_account.owner == referrer, ErrorCode::InvalidReferrer);
+    }
+    
+    // Verified-only preference, minimum investment in the strategy's own unit,
+    // and room for the investment
+    strategy.check_new_position(ctx.accounts.subscription_prefs.as_deref(), investment_amount, unit)?;
+    
     // Set subscription data
-    subscription.strategy = ctx.accounts.strategy.key();
+    subscription.strategy = strategy.key();
     subscription.subscriber = ctx.accounts.subscriber.key();
     subscription.investment_amount = investment_amount;
     subscription.current_value = investment_amount; // Initially same as investment
     subscription.subscribed_at = Clock::get()?.unix_timestamp;
     subscription.last_fee_collection = Clock::get()?.unix_timestamp;
     subscription.high_water_mark = investment_amount;
+    subscription.auto_harvest_on_update = false;
+    subscription.last_harvest_at = Clock::get()?.unix_timestamp;
+    subscription.referrer = referrer;
+    subscription.referral_fees_earned = 0;
+    subscription.paused = false;
+    subscription.paused_at = 0;
+    subscription.last_value_update_at = 0;
     subscription.bump = *ctx.bumps.get("subscription").unwrap();
     
     // Update strategy stats
     strategy.tvl = strategy.tvl.checked_add(investment_amount).unwrap();
     strategy.subscriber_count = strategy.subscriber_count.checked_add(1).unwrap();
+    ctx.accounts.platform_stats.record_subscription(investment_amount)?;
     
     // Transfer funds from subscriber to strategy account
     let transfer_instruction = Transfer {
@@ -517,20 +1939,23 @@ pub fn subscribe_to_strategy(
     
     // Emit notification
     emit_notification(
-        ctx.to_account_infos(),
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
         ctx.accounts.subscriber.key(),
-        NotificationEventType::StrategyUpdated,
-        NotificationPriority::Medium,
-        "Strategy Subscription".to_string(),
-        format!("You have successfully subscribed to '{}' strategy", strategy.name),
-        Some(format!(
-            "{{\"strategyId\":\"{}\", \"investmentAmount\":{}}}", 
-            strategy.id, 
-            investment_amount
-        )),
-        Some(strategy.id.parse::<u64>().unwrap_or(0)),
-        None,
-        None
+        Notification {
+            event_type: NotificationEventType::StrategyUpdated,
+            priority: NotificationPriority::Medium,
+            title: "Strategy Subscription".to_string(),
+            message: format!("You have successfully subscribed to '{}' strategy", strategy.name),
+            data: Some(format!(
+                "{{\"strategyId\":\"{}\", \"investmentAmount\":{}}}", 
+                strategy.id, 
+                investment_amount
+            )),
+            strategy_id: Some(strategy.id.parse::<u64>().unwrap_or(0)),
+            token_address: None,
+            tx_signature: None,
+        },
     );
     
     Ok(())
@@ -540,55 +1965,70 @@ pub fn subscribe_to_strategy(
 pub fn unsubscribe_from_strategy(ctx: Context<UnsubscribeFromStrategy>) -> Result<()> {
     let strategy = &mut ctx.accounts.strategy;
     let subscription = &ctx.accounts.subscription;
+    subscription.check_not_paused()?;
     
     // Calculate current value (in a real implementation, this would be based on actual strategy performance)
     let current_value = subscription.current_value;
     
+    // Exits inside the holding period are rejected, or charged the strategy's
+    // early exit fee when it allows early exits. The fee is paid out like a
+    // management fee, so it leaves TVL with the rest of the subscription.
+    let held_secs = Clock::get()?.unix_timestamp - subscription.subscribed_at;
+    let early_exit_fee = early_exit_fee(
+        current_value,
+        held_secs,
+        strategy.min_holding_secs,
+        strategy.early_exit_penalty_enabled,
+        strategy.early_exit_fee_bps,
+    )?;
+    let withdrawn_amount = current_value - early_exit_fee;
+    
     // Update strategy stats
-    strategy.tvl = strategy.tvl.checked_sub(current_value).unwrap_or(0);
-    strategy.subscriber_count = strategy.subscriber_count.checked_sub(1).unwrap_or(0);
+    strategy.tvl = strategy.tvl.saturating_sub(current_value);
+    strategy.subscriber_count = strategy.subscriber_count.saturating_sub(1);
+    ctx.accounts.platform_stats.record_unsubscription(current_value);
     
     // Transfer funds from strategy to subscriber account
-    let transfer_instruction = Transfer {
-        from: ctx.accounts.strategy_token_account.to_account_info(),
-        to: ctx.accounts.subscriber_token_account.to_account_info(),
-        authority: strategy.to_account_info(),
-    };
-    
-    // This would normally require a PDA signer, simplified for this example
-    // In a real implementation, we would create a proper PDA signer for the strategy
-    let seeds = &[
-        b"strategy",
-        strategy.creator.as_ref(),
-        strategy.id.as_bytes(),
-        &[strategy.bump],
-    ];
-    let signer = &[&seeds[..]];
-    
-    let cpi_ctx = CpiContext::new_with_signer(
-        ctx.accounts.token_program.to_account_info(),
-        transfer_instruction,
-        signer,
-    );
-    
-    token::transfer(cpi_ctx, current_value)?;
+    transfer_from_strategy(
+        &ctx.accounts.token_program,
+        strategy,
+        &ctx.accounts.strategy_token_account,
+        &ctx.accounts.subscriber_token_account,
+        withdrawn_amount,
+    )?;
+    pay_fee(
+        &ctx.accounts.token_program,
+        &ctx.accounts.registry,
+        strategy,
+        ctx.accounts.subscription.key(),
+        &ctx.accounts.strategy_token_account,
+        FeeAccounts {
+            fee_recipient: ctx.accounts.fee_recipient.as_ref(),
+            creator_fee_account: ctx.accounts.creator_fee_account.as_ref(),
+        },
+        early_exit_fee,
+    )?;
     
     // Emit notification
     emit_notification(
-        ctx.to_account_infos(),
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
         ctx.accounts.subscriber.key(),
-        NotificationEventType::StrategyUpdated,
-        NotificationPriority::Medium,
-        "Strategy Unsubscription".to_string(),
-        format!("You have successfully unsubscribed from '{}' strategy", strategy.name),
-        Some(format!(
-            "{{\"strategyId\":\"{}\", \"withdrawnAmount\":{}}}", 
-            strategy.id, 
-            current_value
-        )),
-        Some(strategy.id.parse::<u64>().unwrap_or(0)),
-        None,
-        None
+        Notification {
+            event_type: NotificationEventType::StrategyUpdated,
+            priority: NotificationPriority::Medium,
+            title: "Strategy Unsubscription".to_string(),
+            message: format!("You have successfully unsubscribed from '{}' strategy", strategy.name),
+            data: Some(format!(
+                "{{\"strategyId\":\"{}\", \"withdrawnAmount\":{}, \"earlyExitFee\":{}}}", 
+                strategy.id, 
+                withdrawn_amount,
+                early_exit_fee
+            )),
+            strategy_id: Some(strategy.id.parse::<u64>().unwrap_or(0)),
+            token_address: None,
+            tx_signature: None,
+        },
     );
     
     Ok(())
@@ -598,28 +2038,74 @@ pub fn unsubscribe_from_strategy(ctx: Context<UnsubscribeFromStrategy>) -> Resul
 pub fn update_strategy_value(
     ctx: Context<UpdateStrategyValue>,
     new_value: u64,
-    returns_bps: i32
+    returns_bps: i32,
+    confirm_large_loss: bool
 ) -> Result<()> {
     let strategy = &mut ctx.accounts.strategy;
     let subscription = &mut ctx.accounts.subscription;
+    let now = Clock::get()?.unix_timestamp;
     
-    // Update subscription value
-    let old_value = subscription.current_value;
-    subscription.current_value = new_value;
-    
-    // Update high water mark if necessary
-    if new_value > subscription.high_water_mark {
-        subscription.high_water_mark = new_value;
+    // Paused subscriptions keep their last value
+    if subscription.paused {
+        msg!("Subscription is paused, skipping value update");
+        return Ok(());
     }
     
+    let (old_value, harvested_amount) = apply_value_update(
+        strategy,
+        subscription,
+        new_value,
+        ctx.accounts.registry.max_single_loss_bps,
+        confirm_large_loss,
+        now,
+    )?;
+    
     // Update strategy TVL
-    strategy.tvl = strategy.tvl.checked_sub(old_value).unwrap_or(0);
+    strategy.tvl = strategy.tvl.saturating_sub(old_value);
     strategy.tvl = strategy.tvl.checked_add(new_value).unwrap();
+    ctx.accounts.platform_stats.record_value_change(old_value, new_value)?;
     
     // Update strategy returns (simple average for demo purposes)
     // In real implementation, this would be a weighted average based on TVL
     strategy.total_returns_bps = ((strategy.total_returns_bps as i64 + returns_bps as i64) / 2) as i32;
     
+    let point = StrategyPerfPoint {
+        timestamp: now,
+        tvl: strategy.tvl,
+        cumulative_return_bps: strategy.total_returns_bps,
+    };
+    strategy.record_perf_point(point.clone());
+    emit!(StrategyPerfPointEvent {
+        strategy: strategy.key(),
+        point,
+    });
+    
+    if harvested_amount > 0 {
+        emit_notification(
+            ctx.remaining_accounts,
+            &mut ctx.accounts.notification_counter,
+            subscription.subscriber,
+            Notification {
+                event_type: NotificationEventType::PortfolioRebalanced,
+                priority: NotificationPriority::Low,
+                title: "Rewards Compounded".to_string(),
+                message: format!(
+                    "{} lamports of gains in '{}' strategy were compounded into your principal", 
+                    harvested_amount, 
+                    strategy.name
+                ),
+                data: Some(format!(
+                    "{{\"strategyId\":\"{}\", \"harvestedAmount\":{}}}", 
+                    strategy.id, 
+                    harvested_amount
+                )),
+                strategy_id: Some(strategy.id.parse::<u64>().unwrap_or(0)),
+                token_address: None,
+                tx_signature: None,
+            },
+        );
+    }
+    
     // Calculate if notification should be sent
     let value_change_pct = if old_value > 0 {
         ((new_value as f64 - old_value as f64) / old_value as f64) * 100.0
@@ -636,29 +2122,177 @@ pub fn update_strategy_value(
         };
         
         emit_notification(
-            ctx.to_account_infos(),
+            ctx.remaining_accounts,
+            &mut ctx.accounts.notification_counter,
             subscription.subscriber,
-            notification_type,
-            priority,
-            "Strategy Performance Update".to_string(),
-            format!(
-                "Your investment in '{}' strategy has changed by {:.2}%", 
-                strategy.name, 
-                value_change_pct
-            ),
-            Some(format!(
-                "{{\"strategyId\":\"{}\", \"changePercent\":{}, \"newValue\":{}}}", 
-                strategy.id, 
-                value_change_pct, 
-                new_value
-            )),
-            Some(strategy.id.parse::<u64>().unwrap_or(0)),
-            None,
-            None
+            Notification {
+                event_type: notification_type,
+                priority,
+                title: "Strategy Performance Update".to_string(),
+                message: format!(
+                    "Your investment in '{}' strategy has changed by {:.2}%", 
+                    strategy.name, 
+                    value_change_pct
+                ),
+                data: Some(format!(
+                    "{{\"strategyId\":\"{}\", \"changePercent\":{}, \"newValue\":{}}}", 
+                    strategy.id, 
+                    value_change_pct, 
+                    new_value
+                )),
+                strategy_id: Some(strategy.id.parse::<u64>().unwrap_or(0)),
+                token_address: None,
+                tx_signature: None,
+            },
         );
     }
     
-    Ok(())
+    Ok(())
+}
+
+// Turn the creator allowlist on or off; off lets anyone create strategies
+pub fn set_creator_allowlist_enabled(
+    ctx: Context<UpdateRegistry>,
+    enabled: bool
+) -> Result<()> {
+    ctx.accounts.registry.creator_allowlist_enabled = enabled;
+    
+    Ok(())
+}
+
+// Set the largest value drop a single update may apply without confirmation
+pub fn set_max_single_loss(
+    ctx: Context<UpdateRegistry>,
+    max_single_loss_bps: u16
+) -> Result<()> {
+    require!(max_single_loss_bps <= 10000, CommonError::InvalidParameter);
+    
+    ctx.accounts.registry.max_single_loss_bps = max_single_loss_bps;
+    
+    Ok(())
+}
+
+// Enable or disable auto-harvesting on value updates
+pub fn set_auto_harvest(
+    ctx: Context<UpdateSubscriptionSettings>,
+    auto_harvest_on_update: bool
+) -> Result<()> {
+    let subscription = &mut ctx.accounts.subscription;
+    
+    subscription.auto_harvest_on_update = auto_harvest_on_update;
+    
+    Ok(())
+}
+
+// Create a user's subscription preferences
+pub fn initialize_subscription_prefs(
+    ctx: Context<InitializeSubscriptionPrefs>,
+    verified_only: bool
+) -> Result<()> {
+    let prefs = &mut ctx.accounts.subscription_prefs;
+    
+    prefs.user = ctx.accounts.user.key();
+    prefs.verified_only = verified_only;
+    prefs.bump = *ctx.bumps.get("subscription_prefs").unwrap();
+    
+    Ok(())
+}
+
+// Toggle verified-only subscriptions
+pub fn set_verified_only(
+    ctx: Context<UpdateSubscriptionPrefs>,
+    verified_only: bool
+) -> Result<()> {
+    ctx.accounts.subscription_prefs.verified_only = verified_only;
+    
+    Ok(())
+}
+
+// Pause or resume a subscription's value updates and fee accrual
+pub fn toggle_subscription_pause(ctx: Context<UpdateSubscriptionSettings>) -> Result<()> {
+    toggle_pause(&mut ctx.accounts.subscription, Clock::get()?.unix_timestamp);
+    
+    Ok(())
+}
+
+// Pause a running subscription, or resume a paused one without charging
+// for the time it spent paused
+fn toggle_pause(subscription: &mut StrategySubscription, now: i64) {
+    if subscription.paused {
+        skip_paused_fees(subscription, now);
+        subscription.paused = false;
+    } else {
+        subscription.paused = true;
+        subscription.paused_at = now;
+    }
+}
+
+// Move the fee clock past the time spent paused so that interval is never charged
+fn skip_paused_fees(subscription: &mut StrategySubscription, now: i64) {
+    if subscription.paused {
+        subscription.last_fee_collection += now - subscription.paused_at;
+        subscription.paused_at = now;
+    }
+}
+
+// Annual management fee of `fee_bps` on `value`, pro-rated over `seconds_elapsed`
+fn management_fee(value: u64, fee_bps: u16, seconds_elapsed: i64) -> u64 {
+    let fee_ratio = (fee_bps as f64) / 10000.0; // Convert basis points to ratio
+    let time_ratio = (seconds_elapsed as f64) / (365.0 * 86400.0); // Fraction of a year
+    (value as f64 * fee_ratio * time_ratio) as u64
+}
+
+// Fold gains above the invested principal back into the principal.
+// Returns the harvested amount.
+fn harvest_and_compound(subscription: &mut StrategySubscription, now: i64) -> Result<u64> {
+    let gain = subscription.current_value.saturating_sub(subscription.investment_amount);
+    
+    subscription.investment_amount = subscription.investment_amount
+        .checked_add(gain)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    subscription.last_harvest_at = now;
+    
+    Ok(gain)
+}
+
+// Harvest if due, then move `subscription` to `new_value`. Returns the
+// previous value and the amount harvested.
+fn apply_value_update(
+    strategy: &AIStrategy,
+    subscription: &mut StrategySubscription,
+    new_value: u64,
+    max_single_loss_bps: u16,
+    confirm_large_loss: bool,
+    now: i64,
+) -> Result<(u64, u64)> {
+    // Fold accrued gains into principal before recording the new value
+    // (at most once per update, and only once the strategy's harvest interval has passed)
+    let mut harvested_amount = 0;
+    if subscription.auto_harvest_on_update
+        && now - subscription.last_harvest_at >= strategy.harvest_interval_secs as i64
+    {
+        harvested_amount = harvest_and_compound(subscription, now)?;
+    }
+    
+    let old_value = subscription.current_value;
+    
+    // Rate limit value updates so the authority can't paint performance
+    strategy.check_value_update(subscription.last_value_update_at, old_value, new_value, now)?;
+    
+    // Large drops in one update are more likely a keeper bug than a real loss,
+    // so they need explicit confirmation from the registry authority
+    if !confirm_large_loss {
+        check_single_loss(old_value, new_value, max_single_loss_bps)?;
+    }
+    subscription.current_value = new_value;
+    subscription.last_value_update_at = now;
+    
+    // Update high water mark if necessary
+    if new_value > subscription.high_water_mark {
+        subscription.high_water_mark = new_value;
+    }
+    
+    Ok((old_value, harvested_amount))
 }
 
 // Collect management fees (simplified implementation)
@@ -670,6 +2304,13 @@ pub fn collect_management_fees(
     
     // Calculate time elapsed since last fee collection
     let now = Clock::get()?.unix_timestamp;
+    
+    // Paused time is skipped rather than charged
+    if subscription.paused {
+        skip_paused_fees(subscription, now);
+        return Ok(());
+    }
+    
     let seconds_elapsed = now - subscription.last_fee_collection;
     
     // Only collect fees if at least a day has passed
@@ -677,16 +2318,65 @@ pub fn collect_management_fees(
         return Ok(());
     }
     
-    // Calculate annual fee pro-rated by time
-    let fee_ratio = (strategy.management_fee_bps as f64) / 10000.0; // Convert basis points to ratio
-    let time_ratio = (seconds_elapsed as f64) / (365.0 * 86400.0); // Fraction of a year
-    let fee_amount = (subscription.current_value as f64 * fee_ratio * time_ratio) as u64;
+    // Calculate annual fee pro-rated by time, at the subscription's volume tier
+    let fee_bps = strategy.effective_management_fee_bps(subscription.current_value);
+    let fee_amount = management_fee(subscription.current_value, fee_bps, seconds_elapsed);
     
     // Update subscription value and last fee collection timestamp
     subscription.current_value = subscription.current_value.checked_sub(fee_amount).unwrap_or(subscription.current_value);
     subscription.last_fee_collection = now;
     
-    Ok(())
+    // Pay the referrer's share out of the fee; the rest is split by distribute_fee
+    let mut referral_amount = 0;
+    if let Some(referrer) = subscription.referrer {
+        referral_amount = referral_share(fee_amount, strategy.referral_fee_bps)?;
+        
+        if referral_amount > 0 {
+            subscription.referral_fees_earned = subscription.referral_fees_earned
+                .checked_add(referral_amount)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            
+            let token_program = ctx.accounts.token_program.as_ref().ok_or(CommonError::InvalidParameter)?;
+            let strategy_token_account = ctx.accounts.strategy_token_account
+                .as_ref()
+                .ok_or(CommonError::InvalidParameter)?;
+            let referrer_token_account = ctx.accounts.referrer_token_account
+                .as_ref()
+                .ok_or(CommonError::InvalidParameter)?;
+            transfer_from_strategy(
+                token_program,
+                strategy,
+                strategy_token_account,
+                referrer_token_account,
+                referral_amount,
+            )?;
+            
+            // The notification is best effort; the referrer is paid either way
+            if let Some(referrer_counter) = ctx.accounts.referrer_notification_counter.as_mut() {
+                emit_notification(
+                    ctx.remaining_accounts,
+                    referrer_counter,
+                    referrer,
+                    Notification {
+                        event_type: NotificationEventType::StrategyUpdated,
+                        priority: NotificationPriority::Low,
+                        title: "Referral Fee Earned".to_string(),
+                        message: format!("You earned {} lamports from a referral to '{}'", referral_amount, strategy.name),
+                        data: Some(format!(
+                            "{{\"strategyId\":\"{}\", \"referralAmount\":{}}}",
+                            strategy.id,
+                            referral_amount
+                        )),
+                        strategy_id: Some(strategy.id.parse::<u64>().unwrap_or(0)),
+                        token_address: None,
+                        tx_signature: None,
+                    },
+                );
+            }
+        }
+    }
+    
+    distribute_fee(ctx.accounts, fee_amount - referral_amount)
 }
 
 // Collect performance fees (simplified implementation)
@@ -696,8 +2386,8 @@ pub fn collect_performance_fees(
     let strategy = &ctx.accounts.strategy;
     let subscription = &mut ctx.accounts.subscription;
     
-    // Check if current value exceeds high water mark
-    if subscription.current_value <= subscription.high_water_mark {
+    // Check if current value exceeds high water mark (paused subscriptions are skipped)
+    if subscription.paused || subscription.current_value <= subscription.high_water_mark {
         return Ok(());
     }
     
@@ -712,25 +2402,26 @@ pub fn collect_performance_fees(
     subscription.current_value = subscription.current_value.checked_sub(fee_amount).unwrap_or(subscription.current_value);
     subscription.high_water_mark = subscription.current_value;
     
-    Ok(())
+    distribute_fee(ctx.accounts, fee_amount)
 }
 
 // Update protocol fees
 pub fn update_protocol_fees(
     ctx: Context<InitializeRegistry>,
     protocol_fee_bps: u16,
-    fee_recipient: Option<Pubkey>
+    fee_recipient: Option<Pubkey>,
+    max_combined_fee_score: Option<u16>
 ) -> Result<()> {
     let registry = &mut ctx.accounts.registry;
     
     // Only authority can update fees
     require!(
         ctx.accounts.authority.key() == registry.authority,
-        ErrorCode::Unauthorized
+        CommonError::Unauthorized
     );
     
     // Validate input
-    require!(protocol_fee_bps <= 1000, ErrorCode::InvalidParameter); // Max 10%
+    require!(protocol_fee_bps <= 1000, CommonError::InvalidParameter); // Max 10%
     
     registry.protocol_fee_bps = protocol_fee_bps;
     
@@ -738,12 +2429,17 @@ pub fn update_protocol_fees(
         registry.fee_recipient = recipient;
     }
     
+    if let Some(score) = max_combined_fee_score {
+        require!(score > 0, CommonError::InvalidParameter);
+        registry.max_combined_fee_score = score;
+    }
+    
     Ok(())
 }
 
 // Transfer strategy ownership
 pub fn transfer_strategy_ownership(
-    ctx: Context<UpdateStrategy>,
+    ctx: Context<TransferStrategyOwnership>,
     new_owner: Pubkey
 ) -> Result<()> {
     let strategy = &mut ctx.accounts.strategy;
@@ -751,7 +2447,7 @@ pub fn transfer_strategy_ownership(
     // Only current owner can transfer ownership
     require!(
         ctx.accounts.creator.key() == strategy.creator,
-        ErrorCode::Unauthorized
+        CommonError::Unauthorized
     );
     
     // Update creator
@@ -759,31 +2455,1182 @@ pub fn transfer_strategy_ownership(
     
     // Emit notification
     emit_notification(
-        ctx.to_account_infos(),
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
         ctx.accounts.creator.key(),
-        NotificationEventType::PermissionsChanged,
-        NotificationPriority::High,
-        "Strategy Ownership Transferred".to_string(),
-        format!("Ownership of '{}' strategy has been transferred", strategy.name),
-        Some(format!("{{\"strategyId\":\"{}\", \"newOwner\":\"{}\"}}", strategy.id, new_owner)),
-        Some(strategy.id.parse::<u64>().unwrap_or(0)),
-        None,
-        None
+        Notification {
+            event_type: NotificationEventType::PermissionsChanged,
+            priority: NotificationPriority::High,
+            title: "Strategy Ownership Transferred".to_string(),
+            message: format!("Ownership of '{}' strategy has been transferred", strategy.name),
+            data: Some(format!("{{\"strategyId\":\"{}\", \"newOwner\":\"{}\"}}", strategy.id, new_owner)),
+            strategy_id: Some(strategy.id.parse::<u64>().unwrap_or(0)),
+            token_address: None,
+            tx_signature: None,
+        },
     );
     
     // Also notify the new owner
     emit_notification(
-        ctx.to_account_infos(),
+        ctx.remaining_accounts,
+        &mut ctx.accounts.new_owner_notification_counter,
         new_owner,
-        NotificationEventType::PermissionsChanged,
-        NotificationPriority::High,
-        "Strategy Ownership Received".to_string(),
-        format!("You are now the owner of '{}' strategy", strategy.name),
-        Some(format!("{{\"strategyId\":\"{}\"}}", strategy.id)),
-        Some(strategy.id.parse::<u64>().unwrap_or(0)),
-        None,
-        None
+        Notification {
+            event_type: NotificationEventType::PermissionsChanged,
+            priority: NotificationPriority::High,
+            title: "Strategy Ownership Received".to_string(),
+            message: format!("You are now the owner of '{}' strategy", strategy.name),
+            data: Some(format!("{{\"strategyId\":\"{}\"}}", strategy.id)),
+            strategy_id: Some(strategy.id.parse::<u64>().unwrap_or(0)),
+            token_address: None,
+            tx_signature: None,
+        },
+    );
+    
+    Ok(())
+}
+
+// Hand a subscription to another wallet. Both wallets sign; the position,
+// fee schedule and high water mark carry over, but the referral does not.
+pub fn transfer_subscription(ctx: Context<TransferSubscription>) -> Result<()> {
+    let source = &mut ctx.accounts.subscription;
+    let new_subscriber = ctx.accounts.new_subscriber.key();
+    require!(new_subscriber != source.subscriber, CommonError::InvalidParameter);
+    
+    let new_subscription = transferred_subscription(
+        source,
+        new_subscriber,
+        *ctx.bumps.get("new_subscription").unwrap(),
+    );
+    let current_value = new_subscription.current_value;
+    ctx.accounts.new_subscription.set_inner(new_subscription);
+    let source = &ctx.accounts.subscription;
+    
+    let strategy = &ctx.accounts.strategy;
+    emit_notification(
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
+        new_subscriber,
+        Notification {
+            event_type: NotificationEventType::StrategyUpdated,
+            priority: NotificationPriority::Medium,
+            title: "Subscription Received".to_string(),
+            message: format!("You now hold a position in '{}' strategy", strategy.name),
+            data: Some(format!(
+                "{{\"strategyId\":\"{}\", \"from\":\"{}\", \"currentValue\":{}}}",
+                strategy.id,
+                source.subscriber,
+                current_value
+            )),
+            strategy_id: Some(strategy.id.parse::<u64>().unwrap_or(0)),
+            token_address: None,
+            tx_signature: None,
+        },
+    );
+    
+    Ok(())
+}
+
+// Move a subscription out of a deprecated strategy into an active one
+pub fn migrate_subscription(ctx: Context<MigrateSubscription>) -> Result<()> {
+    let source_strategy = &mut ctx.accounts.source_strategy;
+    let target_strategy = &mut ctx.accounts.target_strategy;
+    let source = &ctx.accounts.source_subscription;
+    source.check_not_paused()?;
+    let current_value = source.current_value;
+    
+    // The migrated position must pass the same checks as a new subscription,
+    // and the target's creator must still be approved
+    target_strategy.check_new_position(
+        ctx.accounts.subscription_prefs.as_deref(),
+        current_value,
+        source_strategy.unit
+    )?;
+    CreatorRegistry::check_creator(
+        &ctx.accounts.registry,
+        ctx.accounts.creator_registry.as_deref(),
+        &target_strategy.creator
+    )?;
+    
+    transfer_from_strategy(
+        &ctx.accounts.token_program,
+        source_strategy,
+        &ctx.accounts.source_strategy_token_account,
+        &ctx.accounts.target_strategy_token_account,
+        current_value,
+    )?;
+    
+    source_strategy.tvl = source_strategy.tvl.saturating_sub(current_value);
+    source_strategy.subscriber_count = source_strategy.subscriber_count.saturating_sub(1);
+    target_strategy.tvl = target_strategy.tvl.checked_add(current_value).unwrap();
+    target_strategy.subscriber_count = target_strategy.subscriber_count.checked_add(1).unwrap();
+    
+    // Carry the position over; the high water mark keeps performance fees fair
+    let now = Clock::get()?.unix_timestamp;
+    ctx.accounts.target_subscription.set_inner(migrated_subscription(
+        source,
+        target_strategy.key(),
+        now,
+        *ctx.bumps.get("target_subscription").unwrap(),
+    ));
+    
+    emit_notification(
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
+        ctx.accounts.subscriber.key(),
+        Notification {
+            event_type: NotificationEventType::StrategyUpdated,
+            priority: NotificationPriority::Medium,
+            title: "Subscription Migrated".to_string(),
+            message: format!(
+                "Your position in deprecated strategy '{}' has moved to '{}'",
+                source_strategy.name,
+                target_strategy.name
+            ),
+            data: Some(format!(
+                "{{\"fromStrategyId\":\"{}\", \"toStrategyId\":\"{}\", \"amount\":{}}}",
+                source_strategy.id,
+                target_strategy.id,
+                current_value
+            )),
+            strategy_id: Some(target_strategy.id.parse::<u64>().unwrap_or(0)),
+            token_address: None,
+            tx_signature: None,
+        },
+    );
+    
+    Ok(())
+}
+
+// Return a remaining subscriber's funds once a deprecated strategy's grace period has ended
+pub fn force_unsubscribe(ctx: Context<ForceUnsubscribe>) -> Result<()> {
+    let strategy = &mut ctx.accounts.strategy;
+    let current_value = ctx.accounts.subscription.current_value;
+    
+    strategy.check_deprecation_deadline(Clock::get()?.unix_timestamp)?;
+    
+    transfer_from_strategy(
+        &ctx.accounts.token_program,
+        strategy,
+        &ctx.accounts.strategy_token_account,
+        &ctx.accounts.subscriber_token_account,
+        current_value,
+    )?;
+    
+    strategy.tvl = strategy.tvl.saturating_sub(current_value);
+    strategy.subscriber_count = strategy.subscriber_count.saturating_sub(1);
+    ctx.accounts.platform_stats.record_unsubscription(current_value);
+    
+    emit_notification(
+        ctx.remaining_accounts,
+        &mut ctx.accounts.notification_counter,
+        ctx.accounts.subscriber.key(),
+        Notification {
+            event_type: NotificationEventType::StrategyUpdated,
+            priority: NotificationPriority::High,
+            title: "Subscription Closed".to_string(),
+            message: format!(
+                "Deprecated strategy '{}' has been wound down and your funds returned",
+                strategy.name
+            ),
+            data: Some(format!(
+                "{{\"strategyId\":\"{}\", \"withdrawnAmount\":{}}}",
+                strategy.id,
+                current_value
+            )),
+            strategy_id: Some(strategy.id.parse::<u64>().unwrap_or(0)),
+            token_address: None,
+            tx_signature: None,
+        },
+    );
+    
+    Ok(())
+}
+
+// Report how much more capital a strategy can take
+pub fn strategy_capacity(ctx: Context<StrategyCapacity>) -> Result<()> {
+    let strategy = &ctx.accounts.strategy;
+    
+    emit!(StrategyCapacityEvent {
+        strategy: strategy.key(),
+        tvl: strategy.tvl,
+        max_tvl: strategy.max_tvl,
+        remaining_capacity: strategy.remaining_capacity(),
+    });
+    
+    Ok(())
+}
+
+// Seeds the strategy PDA was derived from at creation. They use seed_creator
+// rather than creator, so they stay valid across ownership transfers.
+fn strategy_signer_seeds<'a>(
+    strategy: &'a AIStrategy,
+    seed_index: &'a [u8; 8],
+    bump: &'a [u8; 1],
+) -> [&'a [u8]; 4] {
+    [b"strategy", strategy.seed_creator.as_ref(), seed_index, bump]
+}
+
+// Transfer tokens out of a strategy's token account, signed by the strategy PDA
+fn transfer_from_strategy<'info>(
+    token_program: &Program<'info, Token>,
+    strategy: &Account<'info, AIStrategy>,
+    from: &Account<'info, TokenAccount>,
+    to: &Account<'info, TokenAccount>,
+    amount: u64,
+) -> Result<()> {
+    // Sign with the seeds the strategy was created under
+    let seed_index = strategy.seed_index.to_le_bytes();
+    let bump = [strategy.bump];
+    let seeds = strategy_signer_seeds(strategy, &seed_index, &bump);
+    let signer = &[&seeds[..]];
+    
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        Transfer {
+            from: from.to_account_info(),
+            to: to.to_account_info(),
+            authority: strategy.to_account_info(),
+        },
+        signer,
     );
     
+    token::transfer(cpi_ctx, amount)
+}
+
+// Pay a fee collected on a value update out of the strategy's deposit account
+fn distribute_fee<'info>(accounts: &UpdateStrategyValue<'info>, fee_amount: u64) -> Result<()> {
+    if fee_amount == 0 {
+        return Ok(());
+    }
+    
+    let token_program = accounts.token_program.as_ref().ok_or(CommonError::InvalidParameter)?;
+    let strategy_token_account = accounts.strategy_token_account
+        .as_ref()
+        .ok_or(CommonError::InvalidParameter)?;
+    
+    pay_fee(
+        token_program,
+        &accounts.registry,
+        &accounts.strategy,
+        accounts.subscription.key(),
+        strategy_token_account,
+        FeeAccounts {
+            fee_recipient: accounts.fee_recipient.as_ref(),
+            creator_fee_account: accounts.creator_fee_account.as_ref(),
+        },
+        fee_amount,
+    )
+}
+
+// Token accounts a fee is paid into; each is only required when its share
+// of the fee is non-zero
+struct FeeAccounts<'a, 'info> {
+    fee_recipient: Option<&'a Account<'info, TokenAccount>>,
+    creator_fee_account: Option<&'a Account<'info, TokenAccount>>,
+}
+
+// Pay a fee out of the strategy's deposit account: the protocol takes
+// protocol_fee_bps of the gross and the creator gets the remainder
+fn pay_fee<'info>(
+    token_program: &Program<'info, Token>,
+    registry: &StrategyRegistry,
+    strategy: &Account<'info, AIStrategy>,
+    subscription: Pubkey,
+    strategy_token_account: &Account<'info, TokenAccount>,
+    fee_accounts: FeeAccounts<'_, 'info>,
+    fee_amount: u64,
+) -> Result<()> {
+    if fee_amount == 0 {
+        return Ok(());
+    }
+    let FeeAccounts { fee_recipient, creator_fee_account } = fee_accounts;
+    
+    let (protocol_amount, creator_amount) = split_fee(fee_amount, registry.protocol_fee_bps)?;
+    
+    if protocol_amount > 0 {
+        let fee_recipient = fee_recipient.ok_or(CommonError::InvalidParameter)?;
+        check_fee_account(fee_recipient, &strategy.deposit_mint, &registry.fee_recipient)?;
+        transfer_from_strategy(
+            token_program,
+            strategy,
+            strategy_token_account,
+            fee_recipient,
+            protocol_amount,
+        )?;
+    }
+    
+    if creator_amount > 0 {
+        let creator_fee_account = creator_fee_account.ok_or(CommonError::InvalidParameter)?;
+        check_fee_account(creator_fee_account, &strategy.deposit_mint, &strategy.creator)?;
+        transfer_from_strategy(
+            token_program,
+            strategy,
+            strategy_token_account,
+            creator_fee_account,
+            creator_amount,
+        )?;
+    }
+    
+    emit!(FeeDistributedEvent {
+        strategy: strategy.key(),
+        subscription,
+        gross_fee: fee_amount,
+        protocol_amount,
+        creator_amount,
+    });
+    
+    Ok(())
+}
+
+// A fee-receiving token account must hold the strategy's deposit mint and
+// belong to the party the fee is owed to
+fn check_fee_account(token_account: &TokenAccount, deposit_mint: &Pubkey, owner: &Pubkey) -> Result<()> {
+    require!(token_account.mint == *deposit_mint, ErrorCode::MintMismatch);
+    require!(token_account.owner == *owner, CommonError::Unauthorized);
+    Ok(())
+}
+
+// Protocol and creator shares of a gross fee
+fn split_fee(fee_amount: u64, protocol_fee_bps: u16) -> Result<(u64, u64)> {
+    let protocol_amount = (fee_amount as u128 * protocol_fee_bps as u128 / 10000) as u64;
+    let creator_amount = fee_amount
+        .checked_sub(protocol_amount)
+        .ok_or(ErrorCode::FeeSplitExceedsGross)?;
+    Ok((protocol_amount, creator_amount))
+}
+
+// Rejects an unconfirmed drop from `old_value` to `new_value` larger than
+// `max_single_loss_bps`
+fn check_single_loss(old_value: u64, new_value: u64, max_single_loss_bps: u16) -> Result<()> {
+    if new_value < old_value {
+        let loss_bps = (old_value - new_value) as u128 * 10000 / old_value as u128;
+        require!(
+            loss_bps <= max_single_loss_bps as u128,
+            ErrorCode::LargeLossNotConfirmed
+        );
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+// Move `source`'s position to `new_subscriber`, leaving `source` empty until
+// its account is closed; referral and auto-harvest settings belong to the
+// old owner and are dropped
+fn transferred_subscription(
+    source: &mut StrategySubscription,
+    new_subscriber: Pubkey,
+    bump: u8,
+) -> StrategySubscription {
+    let transferred = StrategySubscription {
+        strategy: source.strategy,
+        subscriber: new_subscriber,
+        investment_amount: source.investment_amount,
+        current_value: source.current_value,
+        subscribed_at: source.subscribed_at,
+        last_fee_collection: source.last_fee_collection,
+        high_water_mark: source.high_water_mark,
+        auto_harvest_on_update: false,
+        last_harvest_at: source.last_harvest_at,
+        referrer: None,
+        referral_fees_earned: 0,
+        paused: source.paused,
+        paused_at: source.paused_at,
+        last_value_update_at: source.last_value_update_at,
+        bump,
+    };
+    
+    source.investment_amount = 0;
+    source.current_value = 0;
+    source.high_water_mark = 0;
+    transferred
+}
+
+// A subscription to `target_strategy` carrying over `source`'s position and
+// high water mark; referral and pause state start fresh
+fn migrated_subscription(
+    source: &StrategySubscription,
+    target_strategy: Pubkey,
+    now: i64,
+    bump: u8,
+) -> StrategySubscription {
+    StrategySubscription {
+        strategy: target_strategy,
+        subscriber: source.subscriber,
+        investment_amount: source.investment_amount,
+        current_value: source.current_value,
+        subscribed_at: now,
+        last_fee_collection: now,
+        high_water_mark: source.high_water_mark,
+        auto_harvest_on_update: source.auto_harvest_on_update,
+        last_harvest_at: source.last_harvest_at,
+        referrer: None,
+        referral_fees_earned: 0,
+        paused: false,
+        paused_at: 0,
+        last_value_update_at: source.last_value_update_at,
+        bump,
+    }
+}
+
+// A fresh copy of `source`'s configuration owned by `creator`: TVL,
+// subscribers, returns and verification start over
+fn cloned_strategy(
+    source: &AIStrategy,
+    source_key: Pubkey,
+    creator: Pubkey,
+    params: CloneStrategyParams,
+    seed_index: u64,
+    now: i64,
+    bump: u8,
+) -> AIStrategy {
+    let CloneStrategyParams { id, name, description_hash } = params;
+    AIStrategy {
+        id,
+        creator,
+        name,
+        description_hash,
+        risk_level: source.risk_level,
+        time_horizon: source.time_horizon,
+        ai_models: source.ai_models,
+        tags_bitmap: source.tags_bitmap,
+        token_support: source.token_support,
+        management_fee_bps: source.management_fee_bps,
+        performance_fee_bps: source.performance_fee_bps,
+        referral_fee_bps: source.referral_fee_bps,
+        volume_tiers: source.volume_tiers.clone(),
+        unit: source.unit,
+        deposit_mint: source.deposit_mint,
+        min_investment: source.min_investment,
+        tvl: 0,
+        max_tvl: source.max_tvl,
+        subscriber_count: 0,
+        total_returns_bps: 0,
+        performance_history: Vec::new(),
+        perf_retention: source.perf_retention,
+        max_value_change_bps: source.max_value_change_bps,
+        min_value_update_interval_secs: source.min_value_update_interval_secs,
+        min_holding_secs: source.min_holding_secs,
+        early_exit_fee_bps: source.early_exit_fee_bps,
+        early_exit_penalty_enabled: source.early_exit_penalty_enabled,
+        harvest_interval_secs: source.harvest_interval_secs,
+        created_at: now,
+        updated_at: now,
+        status: 0, // Active
+        deprecation_deadline: 0,
+        verified: false,
+        seed_creator: creator,
+        seed_index,
+        cloned_from: Some(source_key),
+        bump,
+    }
+}
+
+// Fee charged on leaving after `held_secs`: exits inside the holding period
+// pay early_exit_fee_bps when the strategy allows them and fail otherwise
+fn early_exit_fee(
+    current_value: u64,
+    held_secs: i64,
+    min_holding_secs: u32,
+    penalty_enabled: bool,
+    fee_bps: u16,
+) -> Result<u64> {
+    if held_secs >= min_holding_secs as i64 {
+        return Ok(0);
+    }
+    
+    require!(penalty_enabled, ErrorCode::MinHoldingPeriodNotMet);
+    Ok((current_value as u128 * fee_bps as u128 / 10000) as u64)
+}
+
+// Referrer's share of a management fee, never more than the fee itself
+fn referral_share(fee_amount: u64, referral_fee_bps: u16) -> Result<u64> {
+    let referral_amount = (fee_amount as u128 * referral_fee_bps as u128 / 10000) as u64;
+    require!(referral_amount <= fee_amount, ErrorCode::FeeSplitExceedsGross);
+    Ok(referral_amount)
+}
+
+// Fee fairness score: management fee plus a tenth of the performance fee
+fn combined_fee_score(management_fee_bps: u16, performance_fee_bps: u16) -> u32 {
+    management_fee_bps as u32 + performance_fee_bps as u32 / 10
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn performance_fees_weigh_a_tenth_of_management_fees() {
+        assert_eq!(combined_fee_score(200, 2000), 400);
+        assert_eq!(combined_fee_score(500, 0), combined_fee_score(0, 5000));
+        // Both fees at their individual maximums
+        assert_eq!(combined_fee_score(500, 3000), 800);
+    }
+    
+    #[test]
+    fn referral_share_comes_out_of_the_fee() {
+        assert_eq!(referral_share(10_000, 2500).unwrap(), 2_500);
+        assert_eq!(referral_share(10_000, 0).unwrap(), 0);
+        assert_eq!(referral_share(10_000, 10000).unwrap(), 10_000);
+        assert_eq!(
+            referral_share(10_000, 10001).unwrap_err(),
+            error!(ErrorCode::FeeSplitExceedsGross)
+        );
+    }
+    
+    #[test]
+    fn early_exits_are_rejected_or_charged() {
+        assert_eq!(
+            early_exit_fee(10_000, 100, 3600, false, 500).unwrap_err(),
+            error!(ErrorCode::MinHoldingPeriodNotMet)
+        );
+        assert_eq!(early_exit_fee(10_000, 100, 3600, true, 500).unwrap(), 500);
+        assert_eq!(early_exit_fee(10_000, 100, 3600, true, 0).unwrap(), 0);
+        assert_eq!(early_exit_fee(10_000, 3600, 3600, false, 500).unwrap(), 0);
+    }
+    
+    #[test]
+    fn harvest_compounds_gains_into_principal() {
+        let mut subscription = StrategySubscription {
+            strategy: Pubkey::new_unique(),
+            subscriber: Pubkey::new_unique(),
+            investment_amount: 1_000,
+            current_value: 1_250,
+            subscribed_at: 0,
+            last_fee_collection: 0,
+            high_water_mark: 1_000,
+            auto_harvest_on_update: true,
+            last_harvest_at: 0,
+            referrer: None,
+            referral_fees_earned: 0,
+            paused: false,
+            paused_at: 0,
+            last_value_update_at: 0,
+            bump: 0,
+        };
+        
+        assert_eq!(harvest_and_compound(&mut subscription, 100).unwrap(), 250);
+        assert_eq!(subscription.investment_amount, 1_250);
+        assert_eq!(subscription.last_harvest_at, 100);
+        assert_eq!(harvest_and_compound(&mut subscription, 200).unwrap(), 0);
+    }
+    
+    #[test]
+    fn value_updates_harvest_once_the_interval_has_passed() {
+        let mut strategy = test_strategy();
+        strategy.harvest_interval_secs = 86400;
+        let mut subscription = StrategySubscription {
+            strategy: Pubkey::new_unique(),
+            subscriber: Pubkey::new_unique(),
+            investment_amount: 1_000,
+            current_value: 1_250,
+            subscribed_at: 0,
+            last_fee_collection: 0,
+            high_water_mark: 1_250,
+            auto_harvest_on_update: true,
+            last_harvest_at: 0,
+            referrer: None,
+            referral_fees_earned: 0,
+            paused: false,
+            paused_at: 0,
+            last_value_update_at: 0,
+            bump: 0,
+        };
+        
+        // Before the interval the gain stays unharvested
+        assert_eq!(apply_value_update(&strategy, &mut subscription, 1_300, 0, false, 86399).unwrap(), (1_250, 0));
+        assert_eq!(subscription.investment_amount, 1_000);
+        assert_eq!((subscription.current_value, subscription.high_water_mark), (1_300, 1_300));
+        assert_eq!(subscription.last_harvest_at, 0);
+        
+        // Once it has passed, the gain so far is compounded before the new value lands
+        assert_eq!(apply_value_update(&strategy, &mut subscription, 1_400, 0, false, 86400).unwrap(), (1_300, 300));
+        assert_eq!(subscription.investment_amount, 1_300);
+        assert_eq!((subscription.current_value, subscription.high_water_mark), (1_400, 1_400));
+        assert_eq!((subscription.last_harvest_at, subscription.last_value_update_at), (86400, 86400));
+        
+        // And not again until another interval passes
+        assert_eq!(apply_value_update(&strategy, &mut subscription, 1_500, 0, false, 2 * 86400 - 1).unwrap(), (1_400, 0));
+        assert_eq!(subscription.investment_amount, 1_300);
+        
+        // Without auto-harvest the principal never moves
+        subscription.auto_harvest_on_update = false;
+        assert_eq!(apply_value_update(&strategy, &mut subscription, 1_600, 0, false, 10 * 86400).unwrap(), (1_500, 0));
+        assert_eq!(subscription.investment_amount, 1_300);
+    }
+    
+    #[test]
+    fn large_single_losses_need_confirmation() {
+        assert!(check_single_loss(10_000, 9_000, 1000).is_ok());
+        assert_eq!(
+            check_single_loss(10_000, 8_999, 1000).unwrap_err(),
+            error!(ErrorCode::LargeLossNotConfirmed)
+        );
+        // Gains and unchanged values are never limited
+        assert!(check_single_loss(10_000, 50_000, 0).is_ok());
+        assert!(check_single_loss(0, 0, 0).is_ok());
+    }
+    
+    fn test_strategy() -> AIStrategy {
+        AIStrategy {
+            id: "1".to_string(),
+            creator: Pubkey::new_unique(),
+            name: String::new(),
+            description_hash: String::new(),
+            risk_level: 1,
+            time_horizon: 0,
+            ai_models: 0,
+            tags_bitmap: 0,
+            token_support: 0,
+            management_fee_bps: 200,
+            performance_fee_bps: 2000,
+            referral_fee_bps: 0,
+            volume_tiers: vec![],
+            unit: InvestmentUnit::Lamports,
+            deposit_mint: Pubkey::new_unique(),
+            min_investment: 0,
+            tvl: 0,
+            max_tvl: 0,
+            subscriber_count: 0,
+            total_returns_bps: 0,
+            performance_history: vec![],
+            perf_retention: MAX_STRATEGY_PERF_POINTS as u8,
+            max_value_change_bps: 0,
+            min_value_update_interval_secs: 0,
+            min_holding_secs: 0,
+            early_exit_fee_bps: 0,
+            early_exit_penalty_enabled: false,
+            harvest_interval_secs: 0,
+            created_at: 0,
+            updated_at: 0,
+            status: 0,
+            deprecation_deadline: 0,
+            verified: false,
+            seed_creator: Pubkey::new_unique(),
+            seed_index: 0,
+            cloned_from: None,
+            bump: 0,
+        }
+    }
+    
+    #[test]
+    fn capped_strategies_reject_deposits_past_max_tvl() {
+        let mut strategy = test_strategy();
+        strategy.tvl = 9_000;
+        assert_eq!(strategy.remaining_capacity(), None);
+        assert!(strategy.check_capacity(u64::MAX).is_ok());
+        
+        strategy.max_tvl = 10_000;
+        assert_eq!(strategy.remaining_capacity(), Some(1_000));
+        assert!(strategy.check_capacity(1_000).is_ok());
+        assert_eq!(
+            strategy.check_capacity(1_001).unwrap_err(),
+            error!(ErrorCode::StrategyAtCapacity)
+        );
+        
+        // Lowering the cap below the TVL leaves no room rather than underflowing
+        strategy.max_tvl = 5_000;
+        assert_eq!(strategy.remaining_capacity(), Some(0));
+    }
+    
+    #[test]
+    fn minimum_investments_are_checked_in_the_strategy_unit() {
+        let mut lamports_strategy = test_strategy();
+        lamports_strategy.min_investment = 1_000_000_000;
+        assert!(lamports_strategy.check_min_investment(1_000_000_000, InvestmentUnit::Lamports).is_ok());
+        assert_eq!(
+            lamports_strategy.check_min_investment(999_999_999, InvestmentUnit::Lamports).unwrap_err(),
+            error!(ErrorCode::BelowMinimumInvestment)
+        );
+        
+        let mut usd_strategy = test_strategy();
+        usd_strategy.unit = InvestmentUnit::UsdCents;
+        usd_strategy.min_investment = 10_000;
+        assert!(usd_strategy.check_min_investment(10_000, InvestmentUnit::UsdCents).is_ok());
+        assert_eq!(
+            usd_strategy.check_min_investment(9_999, InvestmentUnit::UsdCents).unwrap_err(),
+            error!(ErrorCode::BelowMinimumInvestment)
+        );
+        
+        // A lamport amount clearing the USD minimum is still rejected
+        assert_eq!(
+            usd_strategy.check_min_investment(1_000_000_000, InvestmentUnit::Lamports).unwrap_err(),
+            error!(ErrorCode::InvestmentUnitMismatch)
+        );
+    }
+    
+    #[test]
+    fn migration_keeps_the_position_and_high_water_mark() {
+        let source = StrategySubscription {
+            strategy: Pubkey::new_unique(),
+            subscriber: Pubkey::new_unique(),
+            investment_amount: 1_000,
+            current_value: 900,
+            subscribed_at: 0,
+            last_fee_collection: 0,
+            high_water_mark: 1_200,
+            auto_harvest_on_update: true,
+            last_harvest_at: 50,
+            referrer: Some(Pubkey::new_unique()),
+            referral_fees_earned: 10,
+            paused: true,
+            paused_at: 60,
+            last_value_update_at: 70,
+            bump: 0,
+        };
+        let target_strategy = Pubkey::new_unique();
+        
+        let target = migrated_subscription(&source, target_strategy, 100, 7);
+        assert_eq!(target.strategy, target_strategy);
+        assert_eq!(target.subscriber, source.subscriber);
+        assert_eq!(target.current_value, 900);
+        assert_eq!(target.high_water_mark, 1_200);
+        assert_eq!(target.subscribed_at, 100);
+        assert_eq!(target.referrer, None);
+        assert!(!target.paused);
+        assert_eq!(target.bump, 7);
+    }
+    
+    #[test]
+    fn forced_exits_wait_for_the_deprecation_deadline() {
+        let mut strategy = test_strategy();
+        strategy.status = 2;
+        strategy.deprecation_deadline = 1_000 + DEPRECATION_GRACE_PERIOD;
+        
+        assert_eq!(
+            strategy.check_deprecation_deadline(1_000).unwrap_err(),
+            error!(ErrorCode::DeprecationDeadlineNotReached)
+        );
+        assert!(strategy.check_deprecation_deadline(1_000 + DEPRECATION_GRACE_PERIOD).is_ok());
+    }
+    
+    #[test]
+    fn signer_seeds_resolve_to_the_strategy_pda() {
+        let mut strategy = test_strategy();
+        strategy.seed_index = 3;
+        let seed_index = strategy.seed_index.to_le_bytes();
+        let (address, bump) = Pubkey::find_program_address(
+            &[b"strategy", strategy.seed_creator.as_ref(), seed_index.as_ref()],
+            &crate::ID,
+        );
+        strategy.bump = bump;
+        
+        // Ownership transfers change the creator but not the PDA's seeds
+        strategy.creator = Pubkey::new_unique();
+        let bump = [strategy.bump];
+        let seeds = strategy_signer_seeds(&strategy, &seed_index, &bump);
+        assert_eq!(Pubkey::create_program_address(&seeds, &crate::ID).unwrap(), address);
+    }
+    
+    #[test]
+    fn tag_bits_round_trip_and_undefined_bits_are_rejected() {
+        let tag_registry = TagRegistry {
+            names: vec!["DeFi".to_string(), "Momentum".to_string(), "Stablecoin".to_string()],
+            bump: 0,
+        };
+        let tags_bitmap = TAG_DEFI | TAG_STABLECOIN;
+        assert!(tag_registry.validate(tags_bitmap).is_ok());
+        assert_eq!(
+            tag_registry.validate(tags_bitmap | (1 << 3)).unwrap_err(),
+            error!(ErrorCode::InvalidTag)
+        );
+        
+        let mut strategy = test_strategy();
+        strategy.tags_bitmap = tags_bitmap;
+        assert!(strategy.has_tags(TAG_DEFI));
+        assert!(strategy.has_tags(TAG_DEFI | TAG_STABLECOIN));
+        assert!(!strategy.has_tags(TAG_DEFI | TAG_MOMENTUM));
+        
+        // A full registry defines every bit
+        let full_registry = TagRegistry { names: vec![String::new(); MAX_TAGS], bump: 0 };
+        assert!(full_registry.validate(u64::MAX).is_ok());
+    }
+    
+    #[test]
+    fn verified_only_users_skip_unverified_strategies() {
+        let mut prefs = SubscriptionPrefs { user: Pubkey::new_unique(), verified_only: true, bump: 0 };
+        let mut strategy = test_strategy();
+        
+        assert_eq!(
+            prefs.check_strategy(&strategy).unwrap_err(),
+            error!(ErrorCode::StrategyNotVerified)
+        );
+        strategy.verified = true;
+        assert!(prefs.check_strategy(&strategy).is_ok());
+        
+        // Users who haven't opted in can still subscribe to unverified strategies
+        prefs.verified_only = false;
+        strategy.verified = false;
+        assert!(prefs.check_strategy(&strategy).is_ok());
+    }
+    
+    #[test]
+    fn platform_counters_track_the_sum_of_subscriptions() {
+        let mut platform_stats = PlatformStats {
+            total_tvl: 0,
+            total_strategies: 0,
+            total_active_subscriptions: 0,
+            total_volume: 0,
+            bump: 0,
+        };
+        
+        platform_stats.record_strategy_created().unwrap();
+        platform_stats.record_strategy_created().unwrap();
+        platform_stats.record_subscription(1_000).unwrap();
+        platform_stats.record_subscription(500).unwrap();
+        platform_stats.record_value_change(1_000, 1_200).unwrap();
+        
+        // Subscriptions now worth 1_200 and 500
+        assert_eq!(platform_stats.total_strategies, 2);
+        assert_eq!(platform_stats.total_active_subscriptions, 2);
+        assert_eq!(platform_stats.total_tvl, 1_700);
+        
+        platform_stats.record_unsubscription(500);
+        assert_eq!(platform_stats.total_active_subscriptions, 1);
+        assert_eq!(platform_stats.total_tvl, 1_200);
+        assert_eq!(platform_stats.total_volume, 2_000);
+        
+        // Withdrawals the totals can't account for still go through
+        platform_stats.record_unsubscription(5_000);
+        assert_eq!(platform_stats.total_active_subscriptions, 0);
+        assert_eq!(platform_stats.total_tvl, 0);
+        assert_eq!(platform_stats.total_volume, 7_000);
+        platform_stats.record_unsubscription(1);
+        assert_eq!(platform_stats.total_active_subscriptions, 0);
+    }
+    
+    #[test]
+    fn paused_windows_accrue_no_management_fees() {
+        let mut subscription = StrategySubscription {
+            strategy: Pubkey::new_unique(),
+            subscriber: Pubkey::new_unique(),
+            investment_amount: 10_000_000,
+            current_value: 10_000_000,
+            subscribed_at: 0,
+            last_fee_collection: 0,
+            high_water_mark: 10_000_000,
+            auto_harvest_on_update: false,
+            last_harvest_at: 0,
+            referrer: None,
+            referral_fees_earned: 0,
+            paused: false,
+            paused_at: 0,
+            last_value_update_at: 0,
+            bump: 0,
+        };
+        let day = 86400;
+        
+        // Paused for ten days starting on day one; exits wait for the resume
+        toggle_pause(&mut subscription, day);
+        assert!(subscription.paused);
+        assert_eq!(subscription.check_not_paused().unwrap_err(), error!(ErrorCode::SubscriptionPaused));
+        toggle_pause(&mut subscription, 11 * day);
+        assert!(!subscription.paused);
+        subscription.check_not_paused().unwrap();
+        assert_eq!(subscription.last_fee_collection, 10 * day);
+        
+        // Two days later only the three unpaused days are charged, at 1% a year
+        let seconds_elapsed = 13 * day - subscription.last_fee_collection;
+        assert_eq!(seconds_elapsed, 3 * day);
+        assert_eq!(management_fee(subscription.current_value, 100, seconds_elapsed), 821);
+        assert_eq!(management_fee(subscription.current_value, 100, 13 * day), 3_561);
+    }
+    
+    #[test]
+    fn perf_history_keeps_only_the_retained_points() {
+        let mut strategy = test_strategy();
+        strategy.perf_retention = 3;
+        for timestamp in 0..5 {
+            strategy.record_perf_point(StrategyPerfPoint {
+                timestamp,
+                tvl: 0,
+                cumulative_return_bps: 0,
+            });
+        }
+        
+        let timestamps: Vec<i64> = strategy.performance_history.iter().map(|p| p.timestamp).collect();
+        assert_eq!(timestamps, vec![2, 3, 4]);
+    }
+    
+    #[test]
+    fn clones_copy_the_configuration_but_start_unverified() {
+        let mut source = test_strategy();
+        let source_creator = source.creator;
+        source.risk_level = 4;
+        source.ai_models = 0b101;
+        source.token_support = 0b11;
+        source.management_fee_bps = 150;
+        source.performance_fee_bps = 1500;
+        source.volume_tiers = vec![VolumeTier { min_value: 1_000, fee_bps: 100 }];
+        source.max_tvl = 50_000;
+        source.tvl = 5_000;
+        source.subscriber_count = 3;
+        source.total_returns_bps = 1200;
+        source.verified = true;
+        source.record_perf_point(StrategyPerfPoint { timestamp: 1, tvl: 5_000, cumulative_return_bps: 1200 });
+        let (source_key, creator) = (Pubkey::new_unique(), Pubkey::new_unique());
+        
+        let mut copy = cloned_strategy(
+            &source,
+            source_key,
+            creator,
+            CloneStrategyParams { id: "copy".to_string(), name: "Copy".to_string(), description_hash: String::new() },
+            7,
+            100,
+            0,
+        );
+        
+        // Configuration is copied
+        assert_eq!((copy.risk_level, copy.ai_models, copy.token_support), (4, 0b101, 0b11));
+        assert_eq!((copy.management_fee_bps, copy.performance_fee_bps), (150, 1500));
+        assert_eq!(copy.volume_tiers.len(), 1);
+        assert_eq!((copy.volume_tiers[0].min_value, copy.volume_tiers[0].fee_bps), (1_000, 100));
+        assert_eq!(copy.max_tvl, 50_000);
+        
+        // Ownership, TVL, subscribers, returns and verification start over
+        assert_ne!(copy.creator, source_creator);
+        assert_eq!((copy.creator, copy.seed_creator, copy.seed_index), (creator, creator, 7));
+        assert_eq!(copy.cloned_from, Some(source_key));
+        assert_eq!((copy.tvl, copy.subscriber_count, copy.total_returns_bps), (0, 0, 0));
+        assert!(copy.performance_history.is_empty());
+        assert!(!copy.verified);
+        assert_eq!((copy.id.as_str(), copy.name.as_str()), ("copy", "Copy"));
+        assert_eq!((copy.created_at, copy.updated_at), (100, 100));
+        
+        // Changing the copy leaves the source alone
+        copy.management_fee_bps = 300;
+        copy.volume_tiers[0].fee_bps = 50;
+        copy.volume_tiers.push(VolumeTier { min_value: 10_000, fee_bps: 25 });
+        copy.tvl = 9_000;
+        copy.record_perf_point(StrategyPerfPoint { timestamp: 2, tvl: 9_000, cumulative_return_bps: 10 });
+        assert_eq!(source.management_fee_bps, 150);
+        assert_eq!(source.volume_tiers.len(), 1);
+        assert_eq!(source.volume_tiers[0].fee_bps, 100);
+        assert_eq!((source.tvl, source.subscriber_count), (5_000, 3));
+        assert_eq!(source.performance_history.len(), 1);
+        assert_eq!(source.performance_history[0].timestamp, 1);
+        assert_eq!(source.creator, source_creator);
+        assert!(source.verified);
+    }
+    
+    #[test]
+    fn value_updates_are_rate_limited() {
+        let mut strategy = test_strategy();
+        strategy.max_value_change_bps = 1000;
+        strategy.min_value_update_interval_secs = 3600;
+        
+        assert!(strategy.check_value_update(0, 10_000, 11_000, 3600).is_ok());
+        assert!(strategy.check_value_update(0, 10_000, 9_000, 3600).is_ok());
+        assert_eq!(
+            strategy.check_value_update(0, 10_000, 11_001, 3600).unwrap_err(),
+            error!(ErrorCode::ValueChangeTooLarge)
+        );
+        assert_eq!(
+            strategy.check_value_update(0, 10_000, 10_500, 3599).unwrap_err(),
+            error!(ErrorCode::ValueUpdateTooSoon)
+        );
+        
+        // Both limits are off at zero
+        strategy.max_value_change_bps = 0;
+        strategy.min_value_update_interval_secs = 0;
+        assert!(strategy.check_value_update(0, 10_000, 1, 0).is_ok());
+    }
+    
+    #[test]
+    fn high_volume_subscribers_pay_the_tier_fee() {
+        let mut strategy = test_strategy();
+        strategy.management_fee_bps = 200;
+        assert_eq!(strategy.effective_management_fee_bps(5_000_000), 200);
+        
+        let tiers = vec![
+            VolumeTier { min_value: 10_000, fee_bps: 150 },
+            VolumeTier { min_value: 100_000, fee_bps: 100 },
+            VolumeTier { min_value: 1_000_000, fee_bps: 50 },
+        ];
+        assert!(strategy.check_volume_tiers(&tiers).is_ok());
+        strategy.volume_tiers = tiers;
+        
+        assert_eq!(strategy.effective_management_fee_bps(9_999), 200);
+        assert_eq!(strategy.effective_management_fee_bps(10_000), 150);
+        assert_eq!(strategy.effective_management_fee_bps(999_999), 100);
+        assert_eq!(strategy.effective_management_fee_bps(1_000_000), 50);
+    }
+    
+    #[test]
+    fn volume_tiers_must_ascend_with_falling_fees() {
+        let strategy = test_strategy();
+        let tier = |min_value, fee_bps| VolumeTier { min_value, fee_bps };
+        
+        for tiers in [
+            vec![tier(10_000, strategy.management_fee_bps + 1)],
+            vec![tier(10_000, 100), tier(10_000, 50)],
+            vec![tier(10_000, 50), tier(100_000, 100)],
+        ] {
+            assert_eq!(
+                strategy.check_volume_tiers(&tiers).unwrap_err(),
+                error!(CommonError::InvalidParameter)
+            );
+        }
+    }
+    
+    #[test]
+    fn only_approved_creators_publish_while_the_allowlist_is_on() {
+        let mut registry = StrategyRegistry {
+            authority: Pubkey::new_unique(),
+            strategy_count: 0,
+            protocol_fee_bps: 0,
+            fee_recipient: Pubkey::new_unique(),
+            max_combined_fee_score: u16::MAX,
+            max_single_loss_bps: 10000,
+            creator_allowlist_enabled: false,
+            bump: 0,
+        };
+        let mut creator_registry = CreatorRegistry { creators: vec![], bump: 0 };
+        let (approved, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        
+        // With the allowlist off anyone may create, even without the registry
+        assert!(CreatorRegistry::check_creator(&registry, None, &other).is_ok());
+        
+        registry.creator_allowlist_enabled = true;
+        creator_registry.approve(approved).unwrap();
+        assert!(creator_registry.approve(approved).is_err());
+        assert!(CreatorRegistry::check_creator(&registry, Some(&creator_registry), &approved).is_ok());
+        assert_eq!(
+            CreatorRegistry::check_creator(&registry, Some(&creator_registry), &other).unwrap_err(),
+            error!(ErrorCode::CreatorNotApproved)
+        );
+        assert_eq!(
+            CreatorRegistry::check_creator(&registry, None, &approved).unwrap_err(),
+            error!(ErrorCode::CreatorNotApproved)
+        );
+        
+        creator_registry.revoke(&approved).unwrap();
+        assert_eq!(
+            CreatorRegistry::check_creator(&registry, Some(&creator_registry), &approved).unwrap_err(),
+            error!(ErrorCode::CreatorNotApproved)
+        );
+    }
+    
+    #[test]
+    fn transfers_hand_the_position_to_the_new_owner() {
+        let strategy = Pubkey::new_unique();
+        let (old_owner, new_owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut source = StrategySubscription {
+            strategy,
+            subscriber: old_owner,
+            investment_amount: 1_000,
+            current_value: 900,
+            subscribed_at: 10,
+            last_fee_collection: 20,
+            high_water_mark: 1_200,
+            auto_harvest_on_update: true,
+            last_harvest_at: 50,
+            referrer: Some(Pubkey::new_unique()),
+            referral_fees_earned: 10,
+            paused: true,
+            paused_at: 60,
+            last_value_update_at: 70,
+            bump: 3,
+        };
+        
+        let target = transferred_subscription(&mut source, new_owner, 7);
+        
+        // Owner changed, same strategy, fresh bump
+        assert_eq!(target.subscriber, new_owner);
+        assert_eq!(target.strategy, strategy);
+        assert_eq!(target.bump, 7);
+        
+        // Amounts, fee clocks and pause state are preserved
+        assert_eq!(target.investment_amount, 1_000);
+        assert_eq!(target.current_value, 900);
+        assert_eq!(target.high_water_mark, 1_200);
+        assert_eq!((target.subscribed_at, target.last_fee_collection), (10, 20));
+        assert_eq!((target.last_harvest_at, target.last_value_update_at), (50, 70));
+        assert_eq!((target.paused, target.paused_at), (true, 60));
+        
+        // The old owner's referral and auto-harvest settings don't carry over
+        assert_eq!(target.referrer, None);
+        assert_eq!(target.referral_fees_earned, 0);
+        assert!(!target.auto_harvest_on_update);
+        
+        // The source no longer holds the position
+        assert_eq!(source.subscriber, old_owner);
+        assert_eq!(source.investment_amount, 0);
+        assert_eq!(source.current_value, 0);
+        assert_eq!(source.high_water_mark, 0);
+    }
+    
+    #[test]
+    fn fees_split_between_protocol_and_creator() {
+        assert_eq!(split_fee(10_000, 1000).unwrap(), (1_000, 9_000));
+        assert_eq!(split_fee(10_000, 0).unwrap(), (0, 10_000));
+        assert_eq!(split_fee(10_000, 10000).unwrap(), (10_000, 0));
+        
+        // Rounding favours the creator and the shares always sum to the gross
+        let (protocol_amount, creator_amount) = split_fee(999, 1000).unwrap();
+        assert_eq!((protocol_amount, creator_amount), (99, 900));
+        
+        assert_eq!(
+            split_fee(10_000, 10001).unwrap_err(),
+            error!(ErrorCode::FeeSplitExceedsGross)
+        );
+    }
+    
+    #[test]
+    fn fee_accounts_must_hold_the_deposit_mint() {
+        use anchor_spl::token::spl_token;
+        use solana_program::program_pack::Pack;
+        
+        let token_account = |mint, owner| {
+            let account = spl_token::state::Account {
+                mint,
+                owner,
+                state: spl_token::state::AccountState::Initialized,
+                ..Default::default()
+            };
+            let mut data = vec![0; spl_token::state::Account::LEN];
+            spl_token::state::Account::pack(account, &mut data).unwrap();
+            TokenAccount::try_deserialize_unchecked(&mut data.as_slice()).unwrap()
+        };
+        let (deposit_mint, recipient) = (Pubkey::new_unique(), Pubkey::new_unique());
+        
+        assert!(check_fee_account(&token_account(deposit_mint, recipient), &deposit_mint, &recipient).is_ok());
+        assert_eq!(
+            check_fee_account(&token_account(Pubkey::new_unique(), recipient), &deposit_mint, &recipient).unwrap_err(),
+            error!(ErrorCode::MintMismatch)
+        );
+        assert_eq!(
+            check_fee_account(&token_account(deposit_mint, Pubkey::new_unique()), &deposit_mint, &recipient).unwrap_err(),
+            error!(CommonError::Unauthorized)
+        );
+    }
+    
+    #[test]
+    fn migrated_positions_pass_the_subscribe_checks() {
+        let mut target = test_strategy();
+        target.min_investment = 1_000;
+        target.max_tvl = 10_000;
+        target.tvl = 8_000;
+        let prefs = SubscriptionPrefs { user: Pubkey::new_unique(), verified_only: true, bump: 0 };
+        
+        assert_eq!(
+            target.check_new_position(None, 999, InvestmentUnit::Lamports).unwrap_err(),
+            error!(ErrorCode::BelowMinimumInvestment)
+        );
+        assert_eq!(
+            target.check_new_position(None, 2_001, InvestmentUnit::Lamports).unwrap_err(),
+            error!(ErrorCode::StrategyAtCapacity)
+        );
+        assert_eq!(
+            target.check_new_position(None, 2_000, InvestmentUnit::UsdCents).unwrap_err(),
+            error!(ErrorCode::InvestmentUnitMismatch)
+        );
+        assert_eq!(
+            target.check_new_position(Some(&prefs), 2_000, InvestmentUnit::Lamports).unwrap_err(),
+            error!(ErrorCode::StrategyNotVerified)
+        );
+        target.check_new_position(None, 2_000, InvestmentUnit::Lamports).unwrap();
+        
+        target.verified = true;
+        target.check_new_position(Some(&prefs), 1_000, InvestmentUnit::Lamports).unwrap();
+    }
+}